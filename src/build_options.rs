@@ -0,0 +1,45 @@
+use std::env;
+
+/// Options from the `DEB_BUILD_OPTIONS` environment variable, as set by sbuild and other Debian build tools.
+///
+/// See [Debian policy](https://www.debian.org/doc/debian-policy/ch-source.html#debian-rules-and-deb-build-options).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebBuildOptions {
+    /// Don't strip debug symbols from binaries
+    pub nostrip: bool,
+    /// Don't run tests
+    pub nocheck: bool,
+    /// Number of parallel build jobs
+    pub parallel: Option<u32>,
+}
+
+impl DebBuildOptions {
+    #[must_use]
+    pub fn from_env() -> Self {
+        env::var("DEB_BUILD_OPTIONS").map(|options| Self::parse(&options)).unwrap_or_default()
+    }
+
+    /// Options are separated by spaces (or commas). Unknown options are ignored.
+    #[must_use]
+    pub fn parse(options: &str) -> Self {
+        let mut parsed = Self::default();
+        for option in options.split(|c: char| c.is_whitespace() || c == ',').filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                None if option == "nostrip" => parsed.nostrip = true,
+                None if option == "nocheck" => parsed.nocheck = true,
+                Some(("parallel", jobs)) => parsed.parallel = jobs.parse().ok().filter(|&jobs| jobs > 0),
+                _ => {},
+            }
+        }
+        parsed
+    }
+}
+
+#[test]
+fn parse_build_options() {
+    assert_eq!(DebBuildOptions::default(), DebBuildOptions::parse(""));
+    assert_eq!(DebBuildOptions { nostrip: true, nocheck: true, parallel: Some(4) },
+        DebBuildOptions::parse("nocheck  parallel=4 noopt,nostrip"));
+    assert_eq!(None, DebBuildOptions::parse("parallel=x").parallel);
+    assert_eq!(None, DebBuildOptions::parse("parallel=0").parallel);
+}
@@ -0,0 +1,110 @@
+use crate::error::*;
+use crate::listener::Listener;
+use crate::manifest::Config;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Verifies that all packages named in `Depends`, `Pre-Depends` and `Recommends`
+/// are known to apt, to catch typos and names that don't exist in the target distro.
+///
+/// Uses the local apt cache, or the given `Packages` index file (e.g. downloaded for another distro).
+pub fn check_dependencies(options: &Config, packages_file: Option<&Path>, listener: &dyn Listener) -> CDResult<()> {
+    let index = match packages_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| CargoDebError::IoFile("unable to read Packages index", e, path.to_owned()))?,
+        None => apt_cache_dumpavail()?,
+    };
+    let known = index_package_names(&index);
+    if known.is_empty() {
+        return Err("no packages found in the apt package index. Run `apt update` or specify a Packages file with --check-deps=<path>".into());
+    }
+
+    let depends = options.get_dependencies(listener)?;
+    let pre_depends = options.relationship_field(options.pre_depends.as_deref(), listener)?;
+    let recommends = options.relationship_field(options.recommends.as_deref(), listener)?;
+    let fields = [Some(depends.as_str()), pre_depends.as_deref(), recommends.as_deref()];
+    let mut missing = Vec::new();
+    for alternatives in fields.iter().flatten().flat_map(|field| relationship_names(field)) {
+        if !alternatives.iter().any(|name| known.contains(name)) {
+            missing.push(alternatives.join(" | "));
+        }
+    }
+    if !missing.is_empty() {
+        return Err(CargoDebError::DependenciesNotFound(missing.join(", ")));
+    }
+    listener.info(format!("All dependencies found among {} known packages", known.len()));
+    Ok(())
+}
+
+/// The local apt cache in the `Packages` index format
+fn apt_cache_dumpavail() -> CDResult<String> {
+    const APT_CACHE_COMMAND: &str = "apt-cache";
+    let output = Command::new(APT_CACHE_COMMAND)
+        .arg("dumpavail")
+        .output()
+        .map_err(|e| CargoDebError::CommandFailed(e, APT_CACHE_COMMAND))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError(APT_CACHE_COMMAND, "dumpavail".to_owned(), output.stderr));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Names of real and virtual packages listed in a `Packages` index
+fn index_package_names(index: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for line in index.lines() {
+        if let Some(name) = line.strip_prefix("Package:") {
+            names.insert(name.trim().to_owned());
+        } else if let Some(provides) = line.strip_prefix("Provides:") {
+            names.extend(relationship_names(provides).into_iter().flatten());
+        }
+    }
+    names
+}
+
+/// Splits a relationship field like `foo (>= 1.0) | bar [amd64], baz:any`
+/// into groups of alternative package names, without versions and architecture qualifiers.
+fn relationship_names(field: &str) -> Vec<Vec<String>> {
+    field.split(',')
+        .map(|group| {
+            group.split('|')
+                .filter_map(|alternative| {
+                    let name = alternative.trim()
+                        .split(|c: char| c == '(' || c == '[' || c == '<' || c.is_whitespace())
+                        .next()
+                        .unwrap_or("");
+                    let name = name.split(':').next().unwrap_or("");
+                    // Substitution variables can't be checked
+                    if name.is_empty() || name.contains('$') {
+                        None
+                    } else {
+                        Some(name.to_owned())
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+#[test]
+fn relationship_field_names() {
+    assert_eq!(relationship_names("libc6 (>= 2.28), libssl3 | libssl1.1 [amd64], python3:any"), vec![
+        vec!["libc6".to_owned()],
+        vec!["libssl3".to_owned(), "libssl1.1".to_owned()],
+        vec!["python3".to_owned()],
+    ]);
+    assert!(relationship_names(" , ").is_empty());
+    assert!(relationship_names("$auto").is_empty());
+}
+
+#[test]
+fn packages_index_names() {
+    let names = index_package_names("Package: exim4\nVersion: 4.96\nProvides: mail-transport-agent, exim (= 4.96)\n\nPackage: libc6\nArchitecture: amd64\n");
+    for name in &["exim4", "mail-transport-agent", "exim", "libc6"] {
+        assert!(names.contains(*name), "{}", name);
+    }
+    assert_eq!(4, names.len());
+}
@@ -0,0 +1,269 @@
+//! The command-line options of `cargo deb`, and `cargo deb completions <shell>`, which prints a completion script for them
+use crate::error::*;
+use std::fmt::Write as _;
+
+const SUBCOMMANDS: [&str; 5] = ["verify", "verify-installed", "extract", "init", "completions"];
+const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+/// Whether an option takes a value, and whether it can be repeated, like in getopts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Flag,
+    /// A flag that can be repeated, e.g. `-vv`
+    FlagMulti,
+    /// A flag with an optional value, e.g. `--check-deps[=Packages]`
+    FlagOpt,
+    Opt,
+    OptMulti,
+}
+
+/// A command-line option of `cargo deb`, e.g. `-o, --output path`
+#[derive(Debug, PartialEq, Eq)]
+pub struct CliOption {
+    /// The letter of the short option, or `""`
+    pub short: &'static str,
+    pub long: &'static str,
+    pub help: &'static str,
+    /// Name of the value, e.g. `path` or `toml|json`, or `""` for flags
+    pub hint: &'static str,
+    pub kind: OptionKind,
+}
+
+impl CliOption {
+    const fn new(long: &'static str, kind: OptionKind, help: &'static str, hint: &'static str) -> Self {
+        Self { short: "", long, help, hint, kind }
+    }
+
+    const fn short(mut self, short: &'static str) -> Self {
+        self.short = short;
+        self
+    }
+
+    fn takes_value(&self) -> bool {
+        !matches!(self.kind, OptionKind::Flag | OptionKind::FlagMulti)
+    }
+
+    fn value_is_optional(&self) -> bool {
+        self.kind == OptionKind::FlagOpt
+    }
+
+    /// Values given as `a|b`
+    fn choices(&self) -> Vec<&str> {
+        if self.hint.contains('|') && !self.hint.contains(' ') { self.hint.split('|').collect() } else { Vec::new() }
+    }
+}
+
+const fn flag(long: &'static str, help: &'static str) -> CliOption {
+    CliOption::new(long, OptionKind::Flag, help, "")
+}
+
+const fn opt(long: &'static str, help: &'static str, hint: &'static str) -> CliOption {
+    CliOption::new(long, OptionKind::Opt, help, hint)
+}
+
+const fn multi(long: &'static str, help: &'static str, hint: &'static str) -> CliOption {
+    CliOption::new(long, OptionKind::OptMulti, help, hint)
+}
+
+/// All options of `cargo deb`, for its argument parser and the completion scripts, in the order of `--help`
+pub const CLI_OPTIONS: &[CliOption] = &[
+    flag("no-build", "Assume project is already built"),
+    flag("no-strip", "Do not strip debug symbols from the binary"),
+    flag("separate-debug-symbols", "Strip debug symbols into a separate .debug file"),
+    flag("fast", "Use faster compression, which yields larger archive"),
+    flag("install", "Immediately install created package"),
+    CliOption::new("check-deps", OptionKind::FlagOpt, "Check that dependencies exist in the apt cache or the given Packages index", "Packages"),
+    flag("explain-deps", "Show which binaries and libraries caused each dependency"),
+    opt("graph", "Write a graph of the package's relationships next to the package", "dot|json"),
+    CliOption::new("print-config", OptionKind::FlagOpt, "Print the configuration after merging all sources, and exit", "toml|json"),
+    flag("hardening", "Show which binaries are built with PIE, RELRO, BIND_NOW and stack protector"),
+    flag("run-tests", "Run cargo test before packaging, and stop if tests fail"),
+    opt("sbom", "Install a software bill of materials in the package", "cyclonedx|spdx"),
+    flag("sbom-sidecar", "Also write the SBOM next to the package"),
+    flag("build-ids", "Install build-ids.json with GNU build-ids of the binaries, and write it next to the package"),
+    opt("xz-threads", "Number of threads for xz compression (default: one per CPU core)", "N"),
+    opt("xz-memory-limit", "Use fewer xz compression threads to stay under this memory usage", "MiB"),
+    opt("split-size", "Also split the package into dpkg-split parts of this size", "KiB"),
+    flag("provenance", "Write in-toto provenance of the package to <package>.intoto.jsonl"),
+    flag("timings", "Print how long each step of making the package took"),
+    multi("bin", "Package only this binary (can be repeated)", "name"),
+    multi("example", "Build and package this example (can be repeated)", "name"),
+    multi("features", "Cargo features to enable in addition to the `features` setting, separated by commas or spaces", "features").short("F"),
+    opt("target", "Rust target for cross-compilation", "triple"),
+    opt("profile", "Cargo profile to build with", "release"),
+    opt("target-dir", "Directory for all generated artifacts, like in cargo build", "target"),
+    flag("offline", "Run cargo without accessing the network"),
+    flag("locked", "Require Cargo.lock to be up to date"),
+    flag("frozen", "Require Cargo.lock to be up to date, and don't access the network"),
+    multi("variant", "Alternative configuration section to use (can be repeated to make several packages)", "name"),
+    opt("manifest-path", "Cargo project file location", "./Cargo.toml"),
+    opt("config", "Take packaging settings from this file, overriding [package.metadata.deb]", "deb.toml"),
+    opt("package", "Select one of packages belonging to a workspace", "name").short("p"),
+    opt("output", "Write .deb to this file or directory", "path").short("o"),
+    flag("quiet", "Don't print warnings").short("q"),
+    CliOption::new("verbose", OptionKind::FlagMulti, "Print progress. Use -vv to also make cargo verbose", "").short("v"),
+    multi("deny", "Treat these warnings as errors, or all with 'warnings'", "code,...").short("D"),
+    multi("allow", "Don't print these warnings, even if they're denied", "code,...").short("A"),
+    flag("help", "Print this help menu").short("h"),
+    flag("version", "Show the version of cargo-deb"),
+    opt("deb-version", "Alternate version string for package", "version"),
+    flag("deb-version-append-git", "Append +git<date>.<commit> to the package version"),
+    opt("deb-suffix", "Append this to the package version and file name, e.g. for builds for different distros", "~bookworm1"),
+    opt("backport", "Build a backport to the suite: appends ~bpo12+1 or similar to the version, and sets the changelog distribution", "bookworm"),
+    opt("changelog-distribution", "Distribution of the generated changelog entry", "unstable"),
+    opt("distro-profile", "Use defaults of the distribution: compression, changelog distribution and control fields", "debian|ubuntu"),
+    opt("changelog-urgency", "Urgency of the generated changelog entry", "medium"),
+    opt("format", "Package format to make: deb (default) or ipk", "deb|ipk"),
+    opt("compat", "Make packages installable with old versions of dpkg", "oldstable"),
+    multi("cargo-build-flags", "Extra flags for cargo build, separated by spaces", "flags"),
+    multi("substvar", "Set a ${name} substitution variable for dependency fields", "name=value"),
+];
+
+/// The completion script for `bash`, `zsh` or `fish`
+pub fn completions(shell: &str) -> CDResult<String> {
+    match shell {
+        "bash" => Ok(bash(CLI_OPTIONS)),
+        "zsh" => Ok(zsh(CLI_OPTIONS)),
+        "fish" => Ok(fish(CLI_OPTIONS)),
+        other => Err(CargoDebError::UnknownShell(other.to_owned())),
+    }
+}
+
+fn short_flag(option: &CliOption) -> Option<String> {
+    Some(format!("-{}", option.short)).filter(|_| !option.short.is_empty())
+}
+
+fn bash(options: &[CliOption]) -> String {
+    let flags: Vec<String> = options.iter()
+        .flat_map(|o| short_flag(o).into_iter().chain([format!("--{}", o.long)]))
+        .collect();
+    let mut script = String::new();
+    script.push_str("_cargo_deb() {\n");
+    script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    script.push_str("    case \"$prev\" in\n");
+    let _ = writeln!(script, "        completions) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return;;", SHELLS.join(" "));
+    for option in options.iter().filter(|o| o.takes_value() && !o.value_is_optional()) {
+        let names = short_flag(option).map(|s| format!("{}|", s)).unwrap_or_default();
+        let choices = option.choices();
+        if choices.is_empty() {
+            let _ = writeln!(script, "        {}--{}) COMPREPLY=($(compgen -f -- \"$cur\")); return;;", names, option.long);
+        } else {
+            let _ = writeln!(script, "        {}--{}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return;;", names, option.long, choices.join(" "));
+        }
+    }
+    script.push_str("    esac\n");
+    script.push_str("    if [[ \"$cur\" == -* ]]; then\n");
+    let _ = writeln!(script, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", flags.join(" "));
+    script.push_str("    elif [[ $COMP_CWORD -eq 1 || ( $COMP_CWORD -eq 2 && \"${COMP_WORDS[1]}\" == deb ) ]]; then\n");
+    let _ = writeln!(script, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))", SUBCOMMANDS.join(" "));
+    script.push_str("    else\n");
+    script.push_str("        COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+    script.push_str("    fi\n");
+    script.push_str("}\n");
+    script.push_str("complete -F _cargo_deb cargo-deb\n\n");
+    // `cargo deb …` is completed too, and other cargo subcommands by cargo's own completion
+    script.push_str("_cargo_deb_cargo() {\n");
+    script.push_str("    if [[ $COMP_CWORD -ge 2 && \"${COMP_WORDS[1]}\" == deb ]]; then\n");
+    script.push_str("        _cargo_deb\n");
+    script.push_str("        return\n");
+    script.push_str("    fi\n");
+    script.push_str("    if ! declare -F _cargo >/dev/null && declare -F _completion_loader >/dev/null; then\n");
+    script.push_str("        _completion_loader cargo\n");
+    script.push_str("        complete -F _cargo_deb_cargo cargo\n");
+    script.push_str("    fi\n");
+    script.push_str("    if declare -F _cargo >/dev/null; then\n");
+    script.push_str("        _cargo \"$@\"\n");
+    script.push_str("    fi\n");
+    script.push_str("}\n");
+    script.push_str("complete -F _cargo_deb_cargo cargo\n");
+    script
+}
+
+/// Inside `'…'`, for zsh and fish
+fn single_quoted(s: &str, escaped_quote: &str) -> String {
+    s.replace('\'', escaped_quote)
+}
+
+fn zsh(options: &[CliOption]) -> String {
+    let mut script = String::new();
+    script.push_str("#compdef cargo-deb\n\n");
+    script.push_str("_cargo-deb() {\n");
+    script.push_str("    _arguments -s \\\n");
+    for option in options {
+        let help = single_quoted(&option.help.replace('[', "\\[").replace(']', "\\]"), "'\\''");
+        // ends inside the quotes
+        let names = match short_flag(option) {
+            Some(short) => format!("'*'{{{},--{}}}'", short, option.long),
+            None => format!("'*--{}", option.long),
+        };
+        let value = if option.takes_value() {
+            let choices = option.choices();
+            let action = if choices.is_empty() { "_files".to_owned() } else { format!("({})", choices.join(" ")) };
+            let colons = if option.value_is_optional() { "::" } else { ":" };
+            format!("{}{}:{}", colons, single_quoted(&option.hint.replace(':', "\\:"), "'\\''"), action)
+        } else {
+            String::new()
+        };
+        let equals = match (option.takes_value(), option.value_is_optional()) {
+            (false, _) => "",
+            (true, false) => "=",
+            (true, true) => "=-",
+        };
+        let _ = writeln!(script, "        {}{}[{}]{}' \\", names, equals, help, value);
+    }
+    let _ = writeln!(script, "        '1::command:({})' \\", SUBCOMMANDS.join(" "));
+    script.push_str("        '*:file:_files'\n");
+    script.push_str("}\n\n");
+    script.push_str("_cargo-deb \"$@\"\n");
+    script
+}
+
+fn fish(options: &[CliOption]) -> String {
+    let mut script = String::new();
+    // `cargo deb …` is completed too, after the subcommand
+    for command in ["-c cargo-deb", "-c cargo -n '__fish_seen_subcommand_from deb'"] {
+        let _ = writeln!(script, "complete {} -f -a '{}'", command, SUBCOMMANDS.join(" "));
+        for option in options {
+            let mut line = format!("complete {}", command);
+            if !option.short.is_empty() {
+                let _ = write!(line, " -s {}", option.short);
+            }
+            let _ = write!(line, " -l {}", option.long);
+            if option.takes_value() && !option.value_is_optional() {
+                line.push_str(" -r");
+            }
+            let choices = option.choices();
+            if !choices.is_empty() {
+                let _ = write!(line, " -f -a '{}'", choices.join(" "));
+            }
+            let _ = write!(line, " -d '{}'", single_quoted(&option.help.replace('\\', "\\\\"), "\\'"));
+            script.push_str(&line);
+            script.push('\n');
+        }
+    }
+    script
+}
+
+#[test]
+fn completion_scripts() {
+    let mut longs: Vec<_> = CLI_OPTIONS.iter().map(|o| o.long).collect();
+    longs.sort_unstable();
+    longs.dedup();
+    assert_eq!(CLI_OPTIONS.len(), longs.len());
+
+    let bash = completions("bash").unwrap();
+    assert!(bash.contains("compgen -W \"--no-build --no-strip "), "{}", bash);
+    assert!(bash.contains(" -o --output -q --quiet "), "{}", bash);
+    assert!(bash.contains("-o|--output) COMPREPLY=($(compgen -f"), "{}", bash);
+    assert!(bash.contains("--format) COMPREPLY=($(compgen -W \"deb ipk\""), "{}", bash);
+    assert!(!bash.contains("--print-config)"), "{}", bash);
+    assert!(bash.contains("complete -F _cargo_deb cargo-deb\n"), "{}", bash);
+    assert!(bash.ends_with("complete -F _cargo_deb_cargo cargo\n"), "{}", bash);
+    let zsh = completions("zsh").unwrap();
+    assert!(zsh.contains("'*'{-q,--quiet}'[Don'\\''t print warnings]' \\\n"), "{}", zsh);
+    assert!(zsh.contains("'*--print-config=-[Print the configuration after merging all sources, and exit]::toml|json:(toml json)' \\\n"), "{}", zsh);
+    let fish = completions("fish").unwrap();
+    assert!(fish.contains("complete -c cargo-deb -s o -l output -r -d 'Write .deb to this file or directory'\n"), "{}", fish);
+    assert!(fish.contains("complete -c cargo -n '__fish_seen_subcommand_from deb' -s q -l quiet -d 'Don\\'t print warnings'\n"), "{}", fish);
+    assert!(completions("powershell").is_err());
+}
@@ -1,46 +1,224 @@
+use std::fs;
 use std::ops;
+use std::path::Path;
 use crate::error::*;
+use crate::manifest::CompressType;
+use crate::sha256::sha256_hex;
 
-pub enum Compressed {
-    Gz(Vec<u8>),
-    Xz(Vec<u8>),
+/// Compressed data, and the file extension of its format
+pub struct Compressed {
+    data: Vec<u8>,
+    extension: &'static str,
 }
 
 impl ops::Deref for Compressed {
     type Target = Vec<u8>;
 
     fn deref(&self) -> &Self::Target {
-        match self {
-            Self::Gz(data) |
-            Self::Xz(data) => &data,
-        }
+        &self.data
     }
 }
 
 impl Compressed {
+    /// e.g. `gz`, or empty if the data isn't compressed
     pub fn extension(&self) -> &'static str {
-        match self {
-            Self::Gz(_) => "gz",
-            Self::Xz(_) => "xz",
+        self.extension
+    }
+
+    /// File name with the extension, e.g. `data.tar.xz`
+    pub fn file_name(&self, name: &str) -> String {
+        if self.extension.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", name, self.extension)
         }
     }
 }
 
+/// A compression method for the control and data tarballs.
+///
+/// Library users can implement it to use a different encoder, e.g. a hardware-accelerated zlib.
+pub trait Compressor: Send + Sync {
+    /// File extension of the format, e.g. `gz`, or empty for no compression
+    fn extension(&self) -> &'static str;
+
+    /// Distinguishes results of different compression settings in the cache, e.g. `xz-fast`
+    fn cache_key(&self) -> String;
+
+    /// `progress` is called with the number of bytes compressed so far, if the compressor supports it
+    fn compress(&self, data: &[u8], progress: &dyn Fn(usize)) -> CDResult<Vec<u8>>;
+}
+
+/// Gzip, using Zopfli or, if `fast`, a quick encoder
+pub struct Gz {
+    pub fast: bool,
+}
+
+impl Compressor for Gz {
+    fn extension(&self) -> &'static str { "gz" }
+
+    fn cache_key(&self) -> String {
+        if self.fast { "gz-fast".into() } else { "gz".into() }
+    }
+
+    fn compress(&self, data: &[u8], _progress: &dyn Fn(usize)) -> CDResult<Vec<u8>> {
+        Ok(gz(data, self.fast)?.data)
+    }
+}
+
+/// Multithreaded xz, using liblzma. Needs the `lzma` feature.
+#[cfg(feature = "lzma")]
+pub struct Xz {
+    pub fast: bool,
+    pub limits: XzLimits,
+}
+
+#[cfg(feature = "lzma")]
+impl Compressor for Xz {
+    fn extension(&self) -> &'static str { "xz" }
+
+    fn cache_key(&self) -> String {
+        if self.fast { "xz-fast".into() } else { "xz".into() }
+    }
+
+    fn compress(&self, data: &[u8], progress: &dyn Fn(usize)) -> CDResult<Vec<u8>> {
+        xz(data, self.fast, self.limits, progress)
+    }
+}
+
+/// Zstandard, using the `zstd` command, which needs to be installed. dpkg supports it since version 1.21.18.
+pub struct Zstd {
+    pub fast: bool,
+}
+
+impl Compressor for Zstd {
+    fn extension(&self) -> &'static str { "zst" }
+
+    fn cache_key(&self) -> String {
+        if self.fast { "zst-fast".into() } else { "zst".into() }
+    }
+
+    fn compress(&self, data: &[u8], _progress: &dyn Fn(usize)) -> CDResult<Vec<u8>> {
+        run_filter("zstd", &["-q", "-c", "-T0", if self.fast { "-3" } else { "-19" }], data)
+    }
+}
+
+/// Uncompressed tarballs
+pub struct NoCompression;
+
+impl Compressor for NoCompression {
+    fn extension(&self) -> &'static str { "" }
+
+    fn cache_key(&self) -> String { "none".into() }
+
+    fn compress(&self, data: &[u8], _progress: &dyn Fn(usize)) -> CDResult<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+/// The compressor for the compression type. Xz is an error when cargo-deb is built without the `lzma` feature.
+pub fn compressor(compress_type: CompressType, fast: bool, xz_limits: XzLimits) -> CDResult<Box<dyn Compressor>> {
+    Ok(match compress_type {
+        CompressType::Gz => Box::new(Gz { fast }),
+        #[cfg(feature = "lzma")]
+        CompressType::Xz => Box::new(Xz { fast, limits: xz_limits }),
+        #[cfg(not(feature = "lzma"))]
+        CompressType::Xz => {
+            let _ = xz_limits;
+            return Err(CargoDebError::XzNotSupported);
+        },
+        CompressType::Zstd => Box::new(Zstd { fast }),
+        CompressType::None => Box::new(NoCompression),
+    })
+}
+
+/// The cached result of compressing data with the SHA-256 `data_hash`
+pub fn cache_lookup(cache_dir: &Path, compressor: &dyn Compressor, data_hash: &str) -> Option<Compressed> {
+    let data = fs::read(cache_dir.join(format!("{}.{}", data_hash, compressor.cache_key()))).ok()?;
+    Some(Compressed { data, extension: compressor.extension() })
+}
+
+pub fn compress_with(compressor: &dyn Compressor, data: &[u8], progress: &dyn Fn(usize)) -> CDResult<Compressed> {
+    Ok(Compressed { data: compressor.compress(data, progress)?, extension: compressor.extension() })
+}
+
+/// Pipes the data through the command, e.g. `zstd -c`
+pub(crate) fn run_filter(program: &'static str, args: &[&str], data: &[u8]) -> CDResult<Vec<u8>> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(program).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CargoDebError::CommandFailed(e, program))?;
+    let mut stdin = child.stdin.take().ok_or("stdin")?;
+    let output = std::thread::scope(|s| {
+        // Writing while reading avoids a deadlock when the pipe buffers fill up
+        let writer = s.spawn(move || stdin.write_all(data));
+        let output = child.wait_with_output();
+        (writer.join(), output)
+    });
+    let output = match output {
+        (Ok(Ok(())), Ok(output)) if output.status.success() => output,
+        (_, Ok(output)) => return Err(CargoDebError::CommandError("compression failed", program.to_owned(), output.stderr)),
+        (_, Err(e)) => return Err(CargoDebError::CommandFailed(e, program)),
+    };
+    Ok(output.stdout)
+}
+
+/// Reuses the result of compressing the same data in an earlier run, found by the hash of the data.
+///
+/// Results of different compressors are distinguished by their `cache_key`. Only the latest entry is kept in `cache_dir`.
+pub fn cached(cache_dir: &Path, compressor: &dyn Compressor, data: &[u8], progress: &dyn Fn(usize)) -> CDResult<Compressed> {
+    let data_hash = sha256_hex(data);
+    if let Some(compressed) = cache_lookup(cache_dir, compressor, &data_hash) {
+        return Ok(compressed);
+    }
+    let cache_path = cache_dir.join(format!("{}.{}", data_hash, compressor.cache_key()));
+
+    let compressed = compress_with(compressor, data, progress)?;
+    // The cache is only an optimization, so failing to write it is fine
+    let _ = fs::remove_dir_all(cache_dir);
+    if fs::create_dir_all(cache_dir).is_ok() {
+        let temp_path = cache_path.with_extension("tmp");
+        if fs::write(&temp_path, &*compressed).is_ok() {
+            let _ = fs::rename(&temp_path, &cache_path);
+        }
+    }
+    Ok(compressed)
+}
+
 /// Compresses data using the [native Rust implementation of Zopfli](https://github.com/carols10cents/zopfli).
-#[cfg(not(feature = "lzma"))]
-pub fn xz_or_gz(data: &[u8], _fast: bool) -> CDResult<Compressed> {
+///
+/// Zopfli makes the smallest files, but is very slow on large ones. With `fast` a plain gzip encoder is used instead.
+pub fn gz(data: &[u8], fast: bool) -> CDResult<Compressed> {
     use zopfli::{self, Format, Options};
 
+    if fast {
+        return Ok(Compressed { data: crate::deflate::gzip_fast(data), extension: "gz" });
+    }
+
     // Compressed data is typically half to a third the original size
     let mut compressed = Vec::with_capacity(data.len() >> 1);
     zopfli::compress(&Options::default(), &Format::Gzip, data, &mut compressed)?;
 
-    Ok(Compressed::Gz(compressed))
+    Ok(Compressed { data: compressed, extension: "gz" })
+}
+
+/// Resource limits for multithreaded xz compression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XzLimits {
+    /// Number of threads. `None` means one per CPU core.
+    pub threads: Option<u32>,
+    /// Fewer threads are used if compression would need more memory than this (in bytes)
+    pub memory_limit: Option<u64>,
 }
 
 /// Compresses data using the xz2 library
+///
+/// `progress` is called with the number of bytes compressed so far.
 #[cfg(feature = "lzma")]
-pub fn xz_or_gz(data: &[u8], fast: bool) -> CDResult<Compressed> {
+fn xz(data: &[u8], fast: bool, limits: XzLimits, progress: &dyn Fn(usize)) -> CDResult<Vec<u8>> {
     use std::io::Write;
     use xz2::stream;
     use xz2::write::XzEncoder;
@@ -49,16 +227,69 @@ pub fn xz_or_gz(data: &[u8], fast: bool) -> CDResult<Compressed> {
     let buf = Vec::with_capacity(data.len() >> 1);
 
     // Compression level 6 is a good trade off between size and [ridiculously] long compression time
-    let encoder = stream::MtStreamBuilder::new()
-        .threads(num_cpus::get() as u32)
-        .preset(if fast { 1 } else { 6 })
-        .encoder()
-        .map_err(|e| CargoDebError::LzmaCompressionError(e))?;
+    let mut builder = stream::MtStreamBuilder::new();
+    builder.preset(if fast { 1 } else { 6 });
+    let mut threads = limits.threads.unwrap_or(num_cpus::get() as u32).max(1);
+    builder.threads(threads);
+    // Each thread needs its own buffers, so fewer threads need less memory
+    if let Some(memory_limit) = limits.memory_limit {
+        while threads > 1 && builder.memusage() > memory_limit {
+            threads -= 1;
+            builder.threads(threads);
+        }
+    }
+    let encoder = builder.encoder()
+        .map_err(CargoDebError::LzmaCompressionError)?;
 
     let mut writer = XzEncoder::new_stream(buf, encoder);
-    writer.write_all(data).map_err(|e| CargoDebError::Io(e))?;
+    let mut done = 0;
+    for chunk in data.chunks(1 << 20) {
+        writer.write_all(chunk).map_err(CargoDebError::Io)?;
+        done += chunk.len();
+        progress(done);
+    }
 
-    let compressed = writer.finish().map_err(|e| CargoDebError::Io(e))?;
+    writer.finish().map_err(CargoDebError::Io)
+}
 
-    Ok(Compressed::Xz(compressed))
+#[test]
+#[cfg(feature = "lzma")]
+fn xz_memory_limit() {
+    let data = vec![0; 1000];
+    let limited = xz(&data, true, XzLimits { threads: Some(64), memory_limit: Some(1) }, &|_| {}).unwrap();
+    assert!(limited.starts_with(b"\xFD7zXZ\0"));
+    assert_eq!(limited, xz(&data, true, XzLimits { threads: Some(1), memory_limit: None }, &|_| {}).unwrap());
+}
+
+#[test]
+fn cache_hit() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let cache_dir = cache_dir.path().join("foo");
+    struct NotCached;
+    impl Compressor for NotCached {
+        fn extension(&self) -> &'static str { "gz" }
+        fn cache_key(&self) -> String { "gz".into() }
+        fn compress(&self, _: &[u8], _: &dyn Fn(usize)) -> CDResult<Vec<u8>> { panic!("not cached") }
+    }
+    let first = cached(&cache_dir, &Gz { fast: false }, b"data", &|_| {}).unwrap();
+    let second = cached(&cache_dir, &NotCached, b"data", &|_| {}).unwrap();
+    assert_eq!(*first, *second);
+    assert_eq!("data.tar.gz", second.file_name("data.tar"));
+
+    cached(&cache_dir, &Gz { fast: true }, b"other data", &|_| {}).unwrap();
+    assert_eq!(1, fs::read_dir(&cache_dir).unwrap().count());
+    assert_eq!("data.tar", cached(&cache_dir, &NoCompression, b"data", &|_| {}).unwrap().file_name("data.tar"));
+}
+
+#[test]
+fn compressor_for_type() {
+    let xz = compressor(CompressType::Xz, true, XzLimits::default());
+    if cfg!(feature = "lzma") {
+        assert_eq!("xz-fast", xz.unwrap().cache_key());
+    } else {
+        assert!(xz.is_err());
+    }
+    assert_eq!("zst", compressor(CompressType::Zstd, false, XzLimits::default()).unwrap().extension());
+    let none = compressor(CompressType::None, false, XzLimits::default()).unwrap();
+    assert_eq!(b"data", &none.compress(b"data", &|_| {}).unwrap()[..]);
 }
@@ -16,7 +16,7 @@ impl CargoConfig {
 
     #[allow(deprecated)]
     fn new_(project_path: &Path) -> CDResult<Option<Self>> {
-        let mut project_path = project_path.as_ref();
+        let mut project_path = project_path;
         loop {
             if let Some(conf) = Self::try_parse(project_path)? {
                 return Ok(Some(conf));
@@ -73,7 +73,7 @@ impl CargoConfig {
         }
         if let Some(linker) = self.linker_command(target_triple) {
             if linker.contains('/') {
-                let strip_path = Path::new(linker).with_file_name("strip");
+                let strip_path = Path::new(&*linker).with_file_name("strip");
                 if strip_path.exists() {
                     return Some(Cow::Owned((*strip_path.to_string_lossy()).to_owned()));
                 }
@@ -90,13 +90,28 @@ impl CargoConfig {
         &self.path
     }
 
-    fn linker_command(&self, target_triple: &str) -> Option<&str> {
+    /// `CARGO_TARGET_<TRIPLE>_LINKER` takes precedence over the config, like in Cargo
+    pub(crate) fn linker_command(&self, target_triple: &str) -> Option<Cow<'_, str>> {
+        if let Some(linker) = env_linker(target_triple) {
+            return Some(Cow::Owned(linker));
+        }
         if let Some(target) = self.target_conf(target_triple) {
-            return target.get("linker").and_then(|l| l.as_str());
+            return target.get("linker").and_then(|l| l.as_str()).map(Cow::Borrowed);
         }
         None
     }
 
+    /// `target.<triple>.rustflags`, or else `build.rustflags`, as a string or an array
+    pub(crate) fn rustflags(&self, target_triple: &str) -> Option<Vec<String>> {
+        let flags = self.target_conf(target_triple).and_then(|target| target.get("rustflags"))
+            .or_else(|| self.config.get("build")?.get("rustflags"))?;
+        match flags {
+            toml::Value::String(flags) => Some(flags.split_whitespace().map(String::from).collect()),
+            toml::Value::Array(flags) => Some(flags.iter().filter_map(|flag| flag.as_str()).map(String::from).collect()),
+            _ => None,
+        }
+    }
+
     pub fn objcopy_command(&self, target_triple: &str) -> Option<Cow<'_, str>> {
         if let Some(target) = self.target_conf(target_triple) {
             let objcopy_config = target.get("objcopy").and_then(|top| {
@@ -109,7 +124,7 @@ impl CargoConfig {
         }
         if let Some(linker) = self.linker_command(target_triple) {
             if linker.contains('/') {
-                let objcopy_path = Path::new(linker).with_file_name("objcopy");
+                let objcopy_path = Path::new(&*linker).with_file_name("objcopy");
                 if objcopy_path.exists() {
                     return Some(Cow::Owned((*objcopy_path.to_string_lossy()).to_owned()));
                 }
@@ -119,6 +134,52 @@ impl CargoConfig {
     }
 }
 
+/// Cargo's environment variable for a `target.<triple>.<key>` setting
+pub(crate) fn target_env_var(target_triple: &str, key: &str) -> String {
+    format!("CARGO_TARGET_{}_{}", target_triple.to_ascii_uppercase().replace(['-', '.'], "_"), key.to_ascii_uppercase())
+}
+
+pub(crate) fn env_linker(target_triple: &str) -> Option<String> {
+    env::var(target_env_var(target_triple, "linker")).ok().filter(|linker| !linker.is_empty())
+}
+
+/// The rustflags from the environment, which take precedence over the config, like in Cargo
+pub(crate) fn env_rustflags(target_triple: &str) -> Option<Vec<String>> {
+    if let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        return Some(flags.split('\x1f').filter(|flag| !flag.is_empty()).map(String::from).collect());
+    }
+    let flags = env::var("RUSTFLAGS").or_else(|_| env::var(target_env_var(target_triple, "rustflags"))).ok()?;
+    Some(flags.split_whitespace().map(String::from).collect())
+}
+
+/// The linker from `-C linker=…` in rustc flags. The last one wins.
+pub(crate) fn linker_in_rustflags(flags: &[String]) -> Option<&str> {
+    let mut linker = None;
+    let mut flags = flags.iter().map(String::as_str);
+    while let Some(flag) = flags.next() {
+        let codegen = match flag {
+            "-C" | "--codegen" => flags.next(),
+            flag => flag.strip_prefix("-C").or_else(|| flag.strip_prefix("--codegen=")),
+        };
+        if let Some(value) = codegen.and_then(|option| option.strip_prefix("linker=")) {
+            linker = Some(value);
+        }
+    }
+    linker
+}
+
+#[test]
+fn parse_linker() {
+    let c = CargoConfig::from_str(r#"
+[target.thumbv7neon-linux-androideabi]
+linker = "/opt/ndk/bin/clang"
+"#, ".".into()).unwrap();
+
+    assert_eq!("CARGO_TARGET_THUMBV7NEON_LINUX_ANDROIDEABI_LINKER", target_env_var("thumbv7neon-linux-androideabi", "linker"));
+    assert_eq!("/opt/ndk/bin/clang", c.linker_command("thumbv7neon-linux-androideabi").unwrap());
+    assert_eq!(None, c.linker_command("aarch64-unknown-linux-gnu"));
+}
+
 #[test]
 fn parse_strip() {
     let c = CargoConfig::from_str(r#"
@@ -150,3 +211,21 @@ objcopy = { path = "objcopy2" }
     assert_eq!("objcopy2", c.objcopy_command("foo").unwrap());
     assert_eq!(None, c.objcopy_command("bar"));
 }
+
+#[test]
+fn parse_rustflags_linker() {
+    let c = CargoConfig::from_str(r#"
+[build]
+rustflags = ["-C", "target-cpu=native"]
+
+[target.aarch64-unknown-linux-gnu]
+rustflags = "-Clinker=aarch64-linux-gnu-gcc -C opt-level=3"
+"#, ".".into()).unwrap();
+
+    let flags = c.rustflags("aarch64-unknown-linux-gnu").unwrap();
+    assert_eq!(Some("aarch64-linux-gnu-gcc"), linker_in_rustflags(&flags));
+    let flags = c.rustflags("riscv64gc-unknown-linux-gnu").unwrap();
+    assert_eq!(None, linker_in_rustflags(&flags));
+    let flags = ["--codegen=linker=a", "-C", "linker=b"].map(String::from);
+    assert_eq!(Some("b"), linker_in_rustflags(&flags));
+}
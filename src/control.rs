@@ -1,31 +1,29 @@
+use crate::data::{same_source_content, sha256_manifest_len};
 use crate::dh_installsystemd;
 use crate::dh_lib;
 use crate::error::*;
-use crate::listener::Listener;
-use crate::manifest::Config;
+use crate::listener::{Listener, Warning};
+use crate::manifest::{Asset, Config, DistroProfile, InstalledSizeMethod, PackageFormat};
 use crate::pathbytes::*;
 use crate::tararchive::Archive;
 use crate::util::{is_path_file, read_file_to_bytes};
 use crate::wordsplit::WordSplit;
 use dh_lib::ScriptFragments;
 use md5::Digest;
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
 /// Generates an uncompressed tar archive with `control`, `md5sums`, and others
 pub fn generate_archive(options: &Config, time: u64, asset_hashes: HashMap<PathBuf, Digest>, listener: &mut dyn Listener) -> CDResult<Vec<u8>> {
-    let mut archive = Archive::new(time);
-    generate_md5sums(&mut archive, options, asset_hashes)?;
-    generate_control(&mut archive, options, listener)?;
+    let mut archive = Archive::new(time, options.tar_format);
+    generate_md5sums(&mut archive, options, &asset_hashes)?;
+    generate_control(&mut archive, options, &asset_hashes, listener)?;
     if let Some(ref files) = options.conf_files {
         generate_conf_files(&mut archive, files)?;
     }
     generate_scripts(&mut archive, options, listener)?;
-    if let Some(ref file) = options.triggers_file {
-        generate_triggers_file(&mut archive, file)?;
-    }
+    generate_triggers_file(&mut archive, options)?;
     Ok(archive.into_inner()?)
 }
 
@@ -38,71 +36,110 @@ pub fn generate_archive(options: &Config, time: u64, asset_hashes: HashMap<PathB
 /// files" (quoting man 1 dh_installsystemd) will replace the `#DEBHELPER#`
 /// token in the provided maintainer scripts.
 ///
+/// Paths in `remove_on_purge` get a `postrm` fragment deleting them on purge,
+/// and assets with an owner get `dpkg-statoverride` calls in `postinst` and `postrm`.
+/// These don't need `maintainer_scripts`.
+///
 /// If a shell fragment cannot be inserted because the target script is missing
 /// then the entire script will be generated and appended to the archive.
 ///
 /// # Requirements
 ///
-/// When `systemd_units` is configured, user supplied `maintainer_scripts` must
+/// When `systemd_units`, `remove_on_purge` or asset owners are configured, user supplied `maintainer_scripts` must
 /// contain a `#DEBHELPER#` token at the point where shell script fragments
 /// should be inserted.
 fn generate_scripts(archive: &mut Archive, option: &Config, listener: &mut dyn Listener) -> CDResult<()> {
-    if let Some(ref maintainer_scripts_dir) = option.maintainer_scripts {
-        let mut scripts;
-
-        if let Some(systemd_units_config) = &option.systemd_units {
-            // Select and populate autoscript templates relevant to the unit
-            // file(s) in this package and the configuration settings chosen.
-            scripts = dh_installsystemd::generate(
-                &option.name,
-                &option.assets.resolved,
-                &dh_installsystemd::Options::from(systemd_units_config),
-                listener)?;
-
-            // Get Option<&str> from Option<String>
-            let unit_name = systemd_units_config.unit_name
-                .as_deref();
-
-            // Replace the #DEBHELPER# token in the users maintainer scripts
-            // and/or generate maintainer scripts from scratch as needed.
-            dh_lib::apply(
-                &maintainer_scripts_dir,
-                &mut scripts,
-                &option.name,
-                unit_name,
-                listener)?;
-        } else {
-            scripts = ScriptFragments::with_capacity(0);
-        }
+    let maintainer_scripts_dir = option.maintainer_scripts.as_ref().map(|dir| option.path_in_workspace(dir));
+    let maintainer_scripts_dir = maintainer_scripts_dir.as_deref();
+    let systemd_units_config = option.systemd_units.as_ref().filter(|_| maintainer_scripts_dir.is_some());
+    let owned_assets: Vec<_> = option.assets.resolved.iter()
+        .filter_map(|asset| Some((asset, asset.owner.as_ref()?)))
+        .collect();
+    let has_fragments = systemd_units_config.is_some() || !option.remove_on_purge.is_empty() || !owned_assets.is_empty();
+    if maintainer_scripts_dir.is_none() && !has_fragments {
+        return Ok(());
+    }
 
-        // Add maintainer scripts to the archive, either those supplied by the
-        // user or if available prefer modified versions generated above.
-        for name in &["config", "preinst", "postinst", "prerm", "postrm", "templates"] {
-            let mut script = scripts.remove(&name.to_string());
+    let mut scripts = if let Some(systemd_units_config) = systemd_units_config {
+        // Select and populate autoscript templates relevant to the unit
+        // file(s) in this package and the configuration settings chosen.
+        dh_installsystemd::generate(
+            &option.name,
+            &option.assets.resolved,
+            &dh_installsystemd::Options::from(systemd_units_config),
+            &*option.fs,
+            listener)?
+    } else {
+        ScriptFragments::with_capacity(0)
+    };
+
+    if !option.remove_on_purge.is_empty() {
+        let paths = option.remove_on_purge.iter()
+            .map(|path| shell_quote(path))
+            .collect::<Vec<_>>().join(" ");
+        let replace = HashMap::from([("PATHS", paths)]);
+        dh_lib::autoscript(&mut scripts, &option.name, "postrm", "postrm-purge", &replace, false, listener)?;
+    }
 
-            if script.is_none() {
-                let script_path = maintainer_scripts_dir.join(name);
-                if is_path_file(&script_path) {
-                    script = Some(read_file_to_bytes(&script_path)?);
-                }
-            }
+    // Unlike ownership in the tarball, overrides survive reinstallation, and can be changed by the admin
+    for (asset, owner) in owned_assets {
+        let path = shell_quote(&format!("/{}", asset.target_path.to_string_lossy()));
+        let replace = HashMap::from([
+            ("PATH", path),
+            ("USER", owner.user.clone()),
+            ("GROUP", owner.group.clone()),
+            ("MODE", format!("{:04o}", asset.chmod)),
+        ]);
+        dh_lib::autoscript(&mut scripts, &option.name, "postinst", "postinst-statoverride", &replace, false, listener)?;
+        dh_lib::autoscript(&mut scripts, &option.name, "postrm", "postrm-statoverride", &replace, false, listener)?;
+    }
+
+    if has_fragments {
+        // Get Option<&str> from Option<String>
+        let unit_name = systemd_units_config.and_then(|c| c.unit_name.as_deref());
+
+        // Replace the #DEBHELPER# token in the users maintainer scripts
+        // and/or generate maintainer scripts from scratch as needed.
+        dh_lib::apply(
+            maintainer_scripts_dir,
+            &mut scripts,
+            &option.name,
+            unit_name,
+            &option.generated_scripts,
+            listener)?;
+    }
+
+    // Add maintainer scripts to the archive, either those supplied by the
+    // user or if available prefer modified versions generated above.
+    for name in &["config", "preinst", "postinst", "prerm", "postrm", "templates"] {
+        let mut script = scripts.remove(*name);
 
-            if let Some(contents) = script {
-                // The config, postinst, postrm, preinst, and prerm
-                // control files should use mode 0755; all other control files should use 0644.
-                // See Debian Policy Manual section 10.9
-                // and lintian tag control-file-has-bad-permissions
-                let permissions = if *name == "templates" { 0o644 } else { 0o755 };
-                archive.file(name, &contents, permissions)?;
+        if let (None, Some(maintainer_scripts_dir)) = (&script, maintainer_scripts_dir) {
+            let script_path = maintainer_scripts_dir.join(name);
+            if is_path_file(&script_path) {
+                script = Some(read_file_to_bytes(&script_path)?);
             }
         }
+
+        if let Some(contents) = script {
+            // The config, postinst, postrm, preinst, and prerm
+            // control files should use mode 0755; all other control files should use 0644.
+            // See Debian Policy Manual section 10.9
+            // and lintian tag control-file-has-bad-permissions
+            let permissions = if *name == "templates" { 0o644 } else { 0o755 };
+            archive.file(name, &contents, permissions)?;
+        }
     }
 
     Ok(())
 }
 
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
 /// Creates the md5sums file which contains a list of all contained files and the md5sums of each.
-fn generate_md5sums(archive: &mut Archive, options: &Config, asset_hashes: HashMap<PathBuf, Digest>) -> CDResult<()> {
+fn generate_md5sums(archive: &mut Archive, options: &Config, asset_hashes: &HashMap<PathBuf, Digest>) -> CDResult<()> {
     let mut md5sums: Vec<u8> = Vec::new();
 
     // Collect md5sums from each asset in the archive (excludes symlinks).
@@ -112,7 +149,7 @@ fn generate_md5sums(archive: &mut Archive, options: &Config, asset_hashes: HashM
             md5sums.write_all(b"  ")?;
 
             md5sums.write_all(&asset.target_path.as_path().as_unix_path())?;
-            md5sums.write_all(&[b'\n'])?;
+            md5sums.write_all(b"\n")?;
         }
     }
 
@@ -122,14 +159,14 @@ fn generate_md5sums(archive: &mut Archive, options: &Config, asset_hashes: HashM
 }
 
 /// Generates the control file that obtains all the important information about the package.
-fn generate_control(archive: &mut Archive, options: &Config, listener: &mut dyn Listener) -> CDResult<()> {
+fn generate_control(archive: &mut Archive, options: &Config, asset_hashes: &HashMap<PathBuf, Digest>, listener: &mut dyn Listener) -> CDResult<()> {
     // Create and return the handle to the control file with write access.
     let mut control: Vec<u8> = Vec::with_capacity(1024);
 
     // Write all of the lines required by the control file.
     writeln!(&mut control, "Package: {}", options.deb_name)?;
     writeln!(&mut control, "Version: {}", options.deb_version)?;
-    writeln!(&mut control, "Architecture: {}", options.architecture)?;
+    writeln!(&mut control, "Architecture: {}", options.package_architecture())?;
     if let Some(ref repo) = options.repository {
         if repo.starts_with("http") {
             writeln!(&mut control, "Vcs-Browser: {}", repo)?;
@@ -141,16 +178,27 @@ fn generate_control(archive: &mut Archive, options: &Config, listener: &mut dyn
     if let Some(homepage) = options.homepage.as_ref().or(options.documentation.as_ref()) {
         writeln!(&mut control, "Homepage: {}", homepage)?;
     }
+    if let Some(ref origin) = options.origin {
+        writeln!(&mut control, "Origin: {}", origin)?;
+    }
+    if let Some(ref bugs) = options.bugs {
+        writeln!(&mut control, "Bugs: {}", bugs)?;
+    }
     if let Some(ref section) = options.section {
         writeln!(&mut control, "Section: {}", section)?;
     }
     writeln!(&mut control, "Priority: {}", options.priority)?;
     writeln!(&mut control, "Maintainer: {}", options.maintainer)?;
+    if let Some(ref uploaders) = options.uploaders {
+        writeln!(&mut control, "Uploaders: {}", uploaders)?;
+    }
 
-    let installed_size = options.assets.resolved
-        .iter()
-        .map(|m| (m.source.len().unwrap_or(0)+2047)/1024) // assume 1KB of fs overhead per file
-        .sum::<u64>();
+    let installed_size = installed_size(options, asset_hashes)?;
+    // opkg expects the size in bytes
+    let installed_size = match options.package_format {
+        PackageFormat::Deb => installed_size,
+        PackageFormat::Ipk => installed_size * 1024,
+    };
 
     writeln!(&mut control, "Installed-Size: {}", installed_size)?;
 
@@ -159,66 +207,126 @@ fn generate_control(archive: &mut Archive, options: &Config, listener: &mut dyn
         writeln!(&mut control, "Depends: {}", deps)?;
     }
 
-    if let Some(ref pre_depends) = options.pre_depends {
-        let pre_depends_normalized = pre_depends.trim();
-
-        if !pre_depends_normalized.is_empty() {
-            writeln!(&mut control, "Pre-Depends: {}", pre_depends_normalized)?;
+    let relationships = [
+        ("Pre-Depends", &options.pre_depends),
+        ("Recommends", &options.recommends),
+        ("Suggests", &options.suggests),
+        ("Enhances", &options.enhances),
+        ("Conflicts", &options.conflicts),
+        ("Breaks", &options.breaks),
+        ("Replaces", &options.replaces),
+        ("Provides", &options.provides),
+    ];
+    for (field, value) in relationships {
+        if let Some(value) = options.relationship_field(value.as_deref(), listener)? {
+            writeln!(&mut control, "{}: {}", field, value)?;
         }
     }
 
-    if let Some(ref recommends) = options.recommends {
-        let recommends_normalized = recommends.trim();
-
-        if !recommends_normalized.is_empty() {
-            writeln!(&mut control, "Recommends: {}", recommends_normalized)?;
+    for (field, value) in &options.user_defined_fields {
+        // Ubuntu keeps the maintainer of the Debian package it's based on
+        if field.eq_ignore_ascii_case("Original-Maintainer") && options.distro_profile == Some(DistroProfile::Debian) {
+            listener.warning(Warning::Policy, format!("the {} field is only for Ubuntu, and is left out of the Debian package", field));
+            continue;
         }
+        writeln!(&mut control, "{}: {}", field, value)?;
     }
 
-    if let Some(ref suggests) = options.suggests {
-        let suggests_normalized = suggests.trim();
-
-        if !suggests_normalized.is_empty() {
-            writeln!(&mut control, "Suggests: {}", suggests_normalized)?;
-        }
-    }
+    check_policy(options, listener);
+    control.extend_from_slice(description_field(&options.description, options.extended_description.as_deref())?.as_bytes());
+    control.push(10);
 
-    if let Some(ref enhances) = options.enhances {
-        let enhances_normalized = enhances.trim();
+    // Add the control file to the tar archive.
+    archive.file("./control", &control, 0o644)?;
+    Ok(())
+}
 
-        if !enhances_normalized.is_empty() {
-            writeln!(&mut control, "Enhances: {}", enhances_normalized)?;
+/// Warns about fields that Lintian would complain about, which `--deny policy` makes errors
+fn check_policy(options: &Config, listener: &dyn Listener) {
+    let synopsis = options.description.trim();
+    let mut problems = Vec::new();
+    if synopsis.chars().count() > 80 {
+        problems.push("the description (synopsis) is longer than 80 characters".to_owned());
+    }
+    if synopsis.ends_with('.') && !synopsis.ends_with("...") {
+        problems.push("the description (synopsis) should be a phrase, not a sentence ending with a period".to_owned());
+    }
+    if synopsis.split_whitespace().next().is_some_and(|first| first.eq_ignore_ascii_case(&options.deb_name)) {
+        problems.push("the description (synopsis) shouldn't start with the package name".to_owned());
+    }
+    if synopsis.split_whitespace().next().is_some_and(|first| ["a", "an", "the"].contains(&first.to_ascii_lowercase().as_str())) {
+        problems.push("the description (synopsis) shouldn't start with an article".to_owned());
+    }
+    if let Some(extended) = options.extended_description.as_deref() {
+        if extended.trim() == synopsis {
+            problems.push("the extended description repeats the synopsis".to_owned());
+        }
+        // Indented lines are displayed verbatim, and aren't reflowed
+        if let Some(line) = extended.lines().find(|line| line.starts_with(' ') && line.chars().count() > 80) {
+            problems.push(format!("the verbatim line of the extended description '{}' is longer than 80 characters", line.trim()));
         }
     }
-
-    if let Some(ref conflicts) = options.conflicts {
-        writeln!(&mut control, "Conflicts: {}", conflicts)?;
+    if options.homepage.is_none() && options.documentation.is_none() {
+        problems.push("there's no homepage field in Cargo.toml for the Homepage".to_owned());
     }
-    if let Some(ref breaks) = options.breaks {
-        writeln!(&mut control, "Breaks: {}", breaks)?;
+    for problem in problems {
+        listener.warning(Warning::Policy, problem);
     }
-    if let Some(ref replaces) = options.replaces {
-        writeln!(&mut control, "Replaces: {}", replaces)?;
+}
+
+/// `Description:` with the synopsis, and the extended description reflowed to 80 columns.
+/// Continuation lines are indented with a space, and blank lines are written as ` .`.
+fn description_field(description: &str, extended_description: Option<&str>) -> CDResult<String> {
+    // The synopsis is one line, and the next one would be taken as the extended description
+    let synopsis = description.trim();
+    if let Some(line) = synopsis.lines().next().filter(|_| synopsis.contains('\n')) {
+        return Err(CargoDebError::InvalidDescription(line.to_owned(), "is the synopsis, which has to be one line"));
     }
-    if let Some(ref provides) = options.provides {
-        writeln!(&mut control, "Provides: {}", provides)?;
+    let mut field = String::from("Description:");
+    let lines = synopsis.split_by_chars(79).into_iter()
+        .chain(extended_description.into_iter().flat_map(|desc| desc.split_by_chars(79)));
+    for line in lines {
+        field.push(' ');
+        field.push_str(&line);
+        field.push('\n');
     }
 
-    write!(&mut control, "Description:")?;
-    for line in options.description.split_by_chars(79) {
-        writeln!(&mut control, " {}", line)?;
+    // Lines are indented and blank ones are ` .`, but control characters would still end the field early or make dpkg reject the package
+    if let Some(line) = field.lines().find(|line| line.contains(|c: char| c.is_control())) {
+        return Err(CargoDebError::InvalidDescription(line.trim().to_owned(), "contains a control character"));
     }
+    Ok(field)
+}
 
-    if let Some(ref desc) = options.extended_description {
-        for line in desc.split_by_chars(79) {
-            writeln!(&mut control, " {}", line)?;
+/// Estimated disk usage in KiB
+fn installed_size(options: &Config, asset_hashes: &HashMap<PathBuf, Digest>) -> CDResult<u64> {
+    // hardlinks don't take any space. They're the same duplicates as in `data::archive_files`.
+    let mut linked: HashMap<([u8; 16], u32), &Asset> = HashMap::new();
+    let mut files = Vec::with_capacity(options.assets.resolved.len());
+    let manifest_path = options.sha256_manifest_path();
+    for asset in &options.assets.resolved {
+        if let Some(hash) = asset_hashes.get(&asset.target_path).filter(|_| options.hardlink_duplicates) {
+            match linked.get(&(hash.0, asset.chmod)) {
+                Some(original) if same_source_content(&*options.fs, &original.source, &asset.source)? => continue,
+                Some(_) => {},
+                None => { linked.insert((hash.0, asset.chmod), asset); },
+            }
         }
+        let len = match &manifest_path {
+            Some(manifest_path) if asset.target_path == *manifest_path => sha256_manifest_len(options, manifest_path, asset_hashes),
+            _ => asset.source.len(&*options.fs).unwrap_or(0),
+        };
+        files.push(len);
     }
-    control.push(10);
-
-    // Add the control file to the tar archive.
-    archive.file("./control", &control, 0o644)?;
-    Ok(())
+    let directories = options.assets.resolved.iter()
+        .flat_map(|asset| asset.target_path.ancestors().skip(1))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect::<HashSet<_>>()
+        .len() as u64;
+    Ok(match options.installed_size_method {
+        InstalledSizeMethod::Dpkg => files.iter().map(|size| size.div_ceil(1024)).sum::<u64>() + directories,
+        InstalledSizeMethod::Blocks => (files.iter().map(|size| size.div_ceil(4096)).sum::<u64>() + directories) * 4,
+    })
 }
 
 /// If configuration files are required, the conffiles file will be created.
@@ -230,19 +338,64 @@ fn generate_conf_files(archive: &mut Archive, files: &str) -> CDResult<()> {
     Ok(())
 }
 
-fn generate_triggers_file(archive: &mut Archive, path: &Path) -> CDResult<()> {
-    if let Ok(content) = fs::read(path) {
-        archive.file("./triggers", &content, 0o644)?;
+/// Directories watched by file triggers of other packages, which update caches of their contents
+const TRIGGER_DIRS: [&str; 4] = [
+    "/usr/share/mime/packages", // update-mime-database
+    "/usr/share/info", // install-info
+    "/usr/share/icons/hicolor", // gtk-update-icon-cache
+    "/usr/share/applications", // update-desktop-database
+];
+
+/// Triggers from the `triggers_file`, plus activation of triggers for files
+/// that need to be processed by other packages, such as MIME type definitions, info pages, icons,
+/// desktop entries, and shared libraries that need `ldconfig` to update the library cache
+fn generate_triggers_file(archive: &mut Archive, options: &Config) -> CDResult<()> {
+    let mut content = options.triggers_file.as_ref()
+        .and_then(|path| options.fs.read_to_string(&options.path_in_workspace(path)).ok())
+        .unwrap_or_default();
+
+    for dir in TRIGGER_DIRS {
+        if options.assets.resolved.iter().any(|asset| asset.target_path.starts_with(&dir[1..])) {
+            activate_trigger(&mut content, dir);
+        }
+    }
+    if options.assets.resolved.iter().any(|asset| is_shared_library(&asset.target_path)) {
+        activate_trigger(&mut content, "ldconfig");
+    }
+
+    if !content.is_empty() {
+        archive.file("./triggers", content.as_bytes(), 0o644)?;
     }
     Ok(())
 }
 
+/// Adds `activate-noawait` for the trigger, unless the triggers file already mentions it
+fn activate_trigger(content: &mut String, name: &str) {
+    if content.split_whitespace().any(|word| word == name) {
+        return;
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str("activate-noawait ");
+    content.push_str(name);
+    content.push('\n');
+}
+
+/// `lib*.so*` files anywhere in `/lib` or `/usr/lib`, which are in the `ldconfig` cache
+fn is_shared_library(path: &Path) -> bool {
+    let is_library_name = path.file_name().and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("lib") && (name.ends_with(".so") || name.contains(".so.")));
+    is_library_name && (path.starts_with("usr/lib") || path.starts_with("lib"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::manifest::{Asset, AssetSource, SystemdUnitsConfig};
+    use crate::manifest::{Asset, AssetSource, SystemdUnitsConfig, TarFormat};
     use crate::util::tests::{add_test_fs_paths, set_test_fs_path_content};
     use std::io::prelude::Read;
+    use std::path::Path;
 
     fn decode_name<R>(entry: &tar::Entry<R>) -> String where R: Read {
         std::str::from_utf8(&entry.path_bytes()).unwrap().to_string()
@@ -266,16 +419,175 @@ mod tests {
     }
 
     fn prepare() -> (Config, crate::listener::MockListener, Archive) {
-        let mut mock_listener = crate::listener::MockListener::new();
-        mock_listener.expect_info().return_const(());
 
-        let config = Config::from_manifest(Path::new("Cargo.toml"), None, None, None, None, None, &mut mock_listener).unwrap();
+        let (config, mock_listener) = crate::util::tests::test_config();
 
-        let ar = Archive::new(0);
+        let ar = Archive::new(0, TarFormat::Gnu);
 
         (config, mock_listener, ar)
     }
 
+    #[test]
+    fn installed_size_methods() {
+        let (mut config, _, _) = prepare();
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![0; 5000]), PathBuf::from("usr/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![0; 10]), PathBuf::from("usr/share/doc/foo/copyright"), 0o644, false),
+        ];
+        // usr, usr/bin, usr/share, usr/share/doc, usr/share/doc/foo
+        let hashes = HashMap::new();
+        assert_eq!(5 + 1 + 5, installed_size(&config, &hashes).unwrap());
+        config.installed_size_method = InstalledSizeMethod::Blocks;
+        assert_eq!((2 + 1 + 5) * 4, installed_size(&config, &hashes).unwrap());
+    }
+
+    #[test]
+    fn installed_size_of_hardlinks() {
+        let (mut config, _, _) = prepare();
+        config.hardlink_duplicates = true;
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![1; 5000]), PathBuf::from("usr/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![1; 5000]), PathBuf::from("usr/bin/foo-alias"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![2; 5000]), PathBuf::from("usr/bin/foo-other"), 0o755, false),
+        ];
+        // same MD5, as if the last one was a collision
+        let hashes = config.assets.resolved.iter().map(|asset| (asset.target_path.clone(), md5::compute(b"foo"))).collect();
+        // usr, usr/bin
+        assert_eq!(5 + 5 + 2, installed_size(&config, &hashes).unwrap());
+        config.hardlink_duplicates = false;
+        assert_eq!(5 + 5 + 5 + 2, installed_size(&config, &hashes).unwrap());
+    }
+
+    #[test]
+    fn vendor_fields() {
+        let (mut config, mut mock_listener, mut in_ar) = prepare();
+        config.depends = String::new();
+        config.origin = Some("Example".into());
+        config.bugs = Some("https://example.com/issues".into());
+        config.uploaders = Some("A <a@example.com>, B <b@example.com>".into());
+        generate_control(&mut in_ar, &config, &HashMap::new(), &mut mock_listener).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        let control = &contents["control"];
+        assert!(control.contains("\nOrigin: Example\nBugs: https://example.com/issues\n"), "{}", control);
+        assert!(control.contains("\nUploaders: A <a@example.com>, B <b@example.com>\n"), "{}", control);
+    }
+
+    #[test]
+    fn distro_profile_fields() {
+        let (mut config, _, mut in_ar) = prepare();
+        config.depends = String::new();
+        config.user_defined_fields = vec![("Original-Maintainer".into(), "Debian Foo Team <foo@example.org>".into()), ("Foo-Support".into(), "yes".into())];
+        config.distro_profile = Some(DistroProfile::Ubuntu);
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().withf(|code, _| *code != Warning::Policy).return_const(());
+        generate_control(&mut in_ar, &config, &HashMap::new(), &mut mock_listener).unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&in_ar.into_inner().unwrap()[..]));
+        assert!(contents["control"].contains("\nOriginal-Maintainer: Debian Foo Team <foo@example.org>\nFoo-Support: yes\n"), "{}", contents["control"]);
+
+        let (_, _, mut in_ar) = prepare();
+        config.distro_profile = Some(DistroProfile::Debian);
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().withf(|code, msg| *code == Warning::Policy && msg.contains("Original-Maintainer")).times(1).return_const(());
+        mock_listener.expect_warning().return_const(());
+        generate_control(&mut in_ar, &config, &HashMap::new(), &mut mock_listener).unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&in_ar.into_inner().unwrap()[..]));
+        assert!(!contents["control"].contains("Original-Maintainer"));
+        assert!(contents["control"].contains("\nFoo-Support: yes\n"));
+    }
+
+    #[test]
+    fn policy_warnings() {
+        let (mut config, _, _) = prepare();
+        config.homepage = Some("https://example.com".into());
+        config.description = "Make Debian packages from Cargo projects".into();
+        config.extended_description = Some(format!("Details.\n  short verbatim line\n {}", "x".repeat(79)));
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().times(0).return_const(());
+        check_policy(&config, &mock_listener);
+
+        config.extended_description = Some(format!("Details.\n {}", "x".repeat(80)));
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().withf(|_, msg| msg.contains("longer than 80")).times(1).return_const(());
+        check_policy(&config, &mock_listener);
+
+        config.homepage = None;
+        config.documentation = None;
+        config.description = "The cargo-deb tool.".into();
+        config.extended_description = Some("The cargo-deb tool.".into());
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().withf(|code, _| *code == Warning::Policy).times(4).return_const(());
+        check_policy(&config, &mock_listener);
+    }
+
+    #[test]
+    fn description_reflow() {
+        let long = "word ".repeat(30);
+        let field = description_field("short", Some(&format!("{}\n\n  indented\tline", long))).unwrap();
+        let lines: Vec<_> = field.lines().collect();
+        assert_eq!("Description: short", lines[0]);
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ') && l.chars().count() <= 80), "{}", field);
+        assert_eq!(&[" .", "   indented  line"], &lines[lines.len() - 2..]);
+        assert!(description_field("short", Some("carriage\rreturn")).is_err());
+        assert!(description_field("bell\u{7}", None).is_err());
+        assert!(description_field("two\nlines", Some("extended")).is_err());
+        assert!(description_field("trailing newline\n", None).is_ok());
+    }
+
+    #[test]
+    fn generate_triggers_for_mime_types() {
+        let (mut config, _, mut in_ar) = prepare();
+        generate_triggers_file(&mut in_ar, &config).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        assert!(decode_names(&mut tar::Archive::new(&archive_bytes[..])).is_empty());
+
+        config.assets.resolved.push(Asset::new(
+            AssetSource::Data(Vec::new()),
+            PathBuf::from("usr/share/mime/packages/cargo-deb.xml"),
+            0o644,
+            false,
+        ));
+        let mut in_ar = Archive::new(0, TarFormat::Gnu);
+        generate_triggers_file(&mut in_ar, &config).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!("activate-noawait /usr/share/mime/packages\n", contents["triggers"]);
+    }
+
+    #[test]
+    fn generate_triggers_for_shared_libraries() {
+        assert!(is_shared_library(Path::new("usr/lib/x86_64-linux-gnu/libfoo.so.1.2")));
+        assert!(is_shared_library(Path::new("usr/lib/libfoo.so")));
+        assert!(!is_shared_library(Path::new("usr/lib/foo/plugin.so")));
+        assert!(!is_shared_library(Path::new("usr/share/doc/libfoo.so.txt")));
+
+        let (mut config, _, _) = prepare();
+        config.assets.resolved.push(Asset::new(
+            AssetSource::Data(Vec::new()),
+            PathBuf::from("usr/lib/x86_64-linux-gnu/libcargo_deb.so.1"),
+            0o644,
+            false,
+        ));
+        let mut in_ar = Archive::new(0, TarFormat::Gnu);
+        generate_triggers_file(&mut in_ar, &config).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!("activate-noawait ldconfig\n", contents["triggers"]);
+    }
+
+    #[test]
+    fn generate_triggers_for_icons_and_desktop_entries() {
+        let (mut config, _, _) = prepare();
+        for path in ["usr/share/icons/hicolor/48x48/apps/cargo-deb.png", "usr/share/applications/cargo-deb.desktop"] {
+            config.assets.resolved.push(Asset::new(AssetSource::Data(Vec::new()), PathBuf::from(path), 0o644, false));
+        }
+        let mut in_ar = Archive::new(0, TarFormat::Gnu);
+        generate_triggers_file(&mut in_ar, &config).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!("activate-noawait /usr/share/icons/hicolor\nactivate-noawait /usr/share/applications\n", contents["triggers"]);
+    }
+
     #[test]
     fn generate_scripts_does_nothing_if_maintainer_scripts_is_not_set() {
         let (config, mut mock_listener, mut in_ar) = prepare();
@@ -299,7 +611,7 @@ mod tests {
 
     #[test]
     fn generate_scripts_archives_user_supplied_maintainer_scripts() {
-        let maintainer_script_names = &vec!["config", "preinst", "postinst", "prerm", "postrm", "templates"];
+        let maintainer_script_names = &["config", "preinst", "postinst", "prerm", "postrm", "templates"];
 
         let (mut config, mut mock_listener, mut in_ar) = prepare();
 
@@ -313,7 +625,8 @@ mod tests {
         }
 
         // look in the current (virtual) dir for the maintainer script we just
-        // "added"
+        // "added", which the relative dir is resolved against
+        config.manifest_dir = PathBuf::new();
         config.maintainer_scripts.get_or_insert(PathBuf::new());
 
         // generate scripts and store them in the given archive
@@ -337,7 +650,7 @@ mod tests {
         // verify that the content we supplied was faithfully archived
         for script in maintainer_script_names.iter() {
             let expected_content = &format!("some contents: {}", script);
-            let actual_content = archived_content.get(&script.to_string()).unwrap();
+            let actual_content = archived_content.get(*script).unwrap();
             assert_eq!(expected_content, actual_content);
         }
     }
@@ -379,10 +692,46 @@ mod tests {
 
         // don't check the file content, generation of correct files and content
         // is tested at a lower level, we're only testing the higher level.
-        let expected_maintainer_scripts = vec!["postinst", "postrm", "prerm"]
+        let expected_maintainer_scripts = ["postinst", "postrm", "prerm"]
             .iter()
             .map(|i| i.to_string())
             .collect::<Vec<String>>();
         assert_eq!(expected_maintainer_scripts, archived_file_names);
     }
+
+    #[test]
+    fn generate_scripts_removes_paths_on_purge() {
+        let (mut config, mut mock_listener, mut in_ar) = prepare();
+
+        // no maintainer_scripts, the postrm is generated from scratch
+        config.remove_on_purge = vec!["/var/lib/cargo-deb".into(), "/var/log/cargo deb".into()];
+
+        generate_scripts(&mut in_ar, &config, &mut mock_listener).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+
+        assert_eq!(1, archived_content.len());
+        let postrm = &archived_content["postrm"];
+        assert!(postrm.starts_with("#!/bin/sh\nset -e\n"), "{}", postrm);
+        assert!(postrm.contains("if [ \"$1\" = \"purge\" ]; then\n\trm -rf -- '/var/lib/cargo-deb' '/var/log/cargo deb'\nfi\n"), "{}", postrm);
+    }
+
+    #[test]
+    fn generate_scripts_adds_statoverrides() {
+        let (mut config, mut mock_listener, mut in_ar) = prepare();
+
+        let mut asset = Asset::new(AssetSource::Data(vec![]), PathBuf::from("var/lib/foo/it's.key"), 0o640, false);
+        asset.owner = Some("foo:ssl-cert".parse().unwrap());
+        config.assets.resolved.push(asset);
+
+        generate_scripts(&mut in_ar, &config, &mut mock_listener).unwrap();
+        let archive_bytes = in_ar.into_inner().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+
+        let postinst = &archived_content["postinst"];
+        assert!(postinst.contains("dpkg-statoverride --update --add foo ssl-cert 0640 '/var/lib/foo/it'\\''s.key'\n"), "{}", postinst);
+        assert!(archived_content["postrm"].contains("\tdpkg-statoverride --remove '/var/lib/foo/it'\\''s.key'\n"));
+        assert!("Foo:bar".parse::<crate::manifest::AssetOwner>().is_err());
+        assert!("foo:bar baz".parse::<crate::manifest::AssetOwner>().is_err());
+    }
 }
@@ -1,40 +1,378 @@
 use crate::error::*;
-use crate::listener::Listener;
-use crate::manifest::{Asset, Config};
+use crate::listener::{Listener, Warning};
+use crate::manifest::{Asset, AssetSource, Config, DocBaseConfig};
+use crate::elf::Hardening;
 use crate::tararchive::Archive;
+use crate::vfs::FileSystem;
+use crate::dh_lib::pkgfile;
+use crate::pathbytes::{path_from_unix_bytes, AsUnixPathBytes};
+use crate::util::{clamped_timestamp, rfc2822_date};
+use crate::wordsplit::WordSplit;
 use md5::Digest;
+use rayon::prelude::*;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 use std::fs;
-use std::io::Write;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use zopfli::{self, Format, Options};
 
 /// Generates an uncompressed tar archive and hashes of its files
-pub fn generate_archive(options: &Config, time: u64, listener: &mut dyn Listener) -> CDResult<(Vec<u8>, HashMap<PathBuf, Digest>)> {
-    let mut archive = Archive::new(time);
-    let copy_hashes = archive_files(&mut archive, options, listener)?;
+pub fn generate_archive(options: &Config, time: u64, contents: &[AssetContent<'_>], listener: &mut dyn Listener) -> CDResult<(Vec<u8>, HashMap<PathBuf, Digest>)> {
+    let mut archive = Archive::new(time, options.tar_format);
+    let copy_hashes = archive_files(&mut archive, options, contents, listener)?;
     Ok((archive.into_inner()?, copy_hashes))
 }
 
-/// Generates compressed changelog file
-pub(crate) fn generate_changelog_asset(options: &Config) -> CDResult<Option<Vec<u8>>> {
-    if let Some(ref path) = options.changelog {
-        let changelog = fs::read(options.path_in_workspace(path))
-            .and_then(|content| {
-                // The input is plaintext, but the debian package should contain gzipped one.
-                let mut compressed = Vec::with_capacity(content.len());
-                zopfli::compress(&Options::default(), &Format::Gzip, &content, &mut compressed)?;
-                compressed.shrink_to_fit();
-                Ok(compressed)
-            })
-            .map_err(|e| CargoDebError::IoFile("unable to read changelog file", e, path.into()))?;
-        Ok(Some(changelog))
+/// An asset read ahead of building the archive
+pub enum AssetContent<'a> {
+    /// Kept as a link, because of `preserve-symlinks`
+    Symlink(PathBuf),
+    /// Data that is already in memory, from `AssetSource::Data` or generated, like the SHA-256 manifest
+    File(Cow<'a, [u8]>, Digest),
+    /// A file that is only hashed, and read again when it's added to the archive,
+    /// so that the files don't have to be in memory all at once. The uncompressed `data.tar` still is.
+    Streamed(Digest, u64),
+}
+
+/// Hashes the assets on all cores, which helps with thousands of small files.
+/// The contents are in the same order as `options.assets.resolved`.
+///
+/// With `sha256-manifest`, SHA-256 checksums are computed in the same pass, and become the contents of the manifest asset.
+pub fn read_assets(options: &Config) -> CDResult<Vec<AssetContent<'_>>> {
+    let manifest_path = options.sha256_manifest_path();
+    let with_sha256 = manifest_path.is_some();
+    let (mut contents, sha256s): (Vec<_>, Vec<_>) = options.assets.resolved.par_iter().map(|asset| {
+        crate::pathbytes::check_representable(&asset.target_path)?;
+        if options.preserve_symlinks {
+            if let Some(source_path) = asset.source.path() {
+                if let Some(link) = options.fs.read_link(source_path)? {
+                    return Ok((AssetContent::Symlink(link), None));
+                }
+            }
+        }
+        if let Some(path) = asset.source.path() {
+            let (hash, sha256, len) = hash_file(&*options.fs, path, with_sha256)
+                .map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, path.to_owned()))?;
+            return Ok((AssetContent::Streamed(hash, len), sha256));
+        }
+        let data = asset.source.data(&*options.fs)?;
+        let hash = md5::compute(&data);
+        let sha256 = with_sha256.then(|| crate::sha256::sha256(&data));
+        Ok((AssetContent::File(data, hash), sha256))
+    }).collect::<CDResult<Vec<_>>>()?.into_iter().unzip();
+
+    if let Some(manifest_path) = manifest_path {
+        let manifest = sha256_manifest(options, &manifest_path, &sha256s)?;
+        if let Some(index) = options.assets.resolved.iter().position(|asset| asset.target_path == manifest_path) {
+            let hash = md5::compute(&manifest);
+            contents[index] = AssetContent::File(Cow::Owned(manifest), hash);
+        }
+    }
+    Ok(contents)
+}
+
+/// `sha256sum -c` format, with absolute paths, so that it can be checked from any directory
+fn sha256_manifest(options: &Config, manifest_path: &Path, sha256s: &[Option<[u8; 32]>]) -> CDResult<Vec<u8>> {
+    let mut manifest = Vec::new();
+    for (asset, sha256) in options.assets.resolved.iter().zip(sha256s) {
+        let Some(sha256) = sha256.filter(|_| asset.target_path != manifest_path) else { continue };
+        for byte in sha256 {
+            write!(manifest, "{:02x}", byte)?;
+        }
+        manifest.write_all(b"  /")?;
+        manifest.write_all(&asset.target_path.as_unix_path())?;
+        manifest.write_all(b"\n")?;
+    }
+    Ok(manifest)
+}
+
+/// Length of the `sha256_manifest` of the files in `hashes`. `Installed-Size` needs it
+/// after the contents of the assets are gone, and the manifest asset is still empty in the config.
+pub(crate) fn sha256_manifest_len(options: &Config, manifest_path: &Path, hashes: &HashMap<PathBuf, Digest>) -> u64 {
+    options.assets.resolved.iter()
+        .filter(|asset| asset.target_path != manifest_path && hashes.contains_key(&asset.target_path))
+        .map(|asset| 64 + "  /".len() as u64 + asset.target_path.as_unix_path().len() as u64 + 1)
+        .sum()
+}
+
+fn hash_file(fs: &dyn FileSystem, path: &Path, with_sha256: bool) -> io::Result<(Digest, Option<[u8; 32]>, u64)> {
+    let mut md5 = md5::Context::new();
+    let mut sha256 = with_sha256.then(crate::sha256::Sha256::new);
+    let mut file = fs.open(path)?;
+    let mut buf = vec![0; 1 << 16];
+    let mut len = 0;
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        md5.consume(&buf[..n]);
+        if let Some(sha256) = &mut sha256 {
+            sha256.update(&buf[..n]);
+        }
+        len += n as u64;
+    }
+    Ok((md5.compute(), sha256.map(crate::sha256::Sha256::finish), len))
+}
+
+/// Identifies the contents of `data.tar` by the hashes of the assets, so that it can be reused if nothing changed
+#[must_use]
+pub fn assets_fingerprint(options: &Config, time: u64, contents: &[AssetContent<'_>]) -> String {
+    let mut fingerprint = format!("{:?} {} {}\n", options.tar_format, options.preserve_symlinks, options.hardlink_duplicates).into_bytes();
+    // Without SOURCE_DATE_EPOCH the timestamps are arbitrary, so the ones from the previous run are fine
+    if std::env::var_os("SOURCE_DATE_EPOCH").is_some() {
+        fingerprint.extend_from_slice(format!("time {}\n", time).as_bytes());
+    }
+    for (asset, content) in options.assets.resolved.iter().zip(contents) {
+        match content {
+            AssetContent::Symlink(link) => {
+                fingerprint.extend_from_slice(b"L ");
+                push_escaped(&mut fingerprint, &asset.target_path.as_unix_path());
+                fingerprint.push(b' ');
+                push_escaped(&mut fingerprint, &link.as_unix_path());
+            },
+            AssetContent::File(_, hash) | AssetContent::Streamed(hash, _) => {
+                fingerprint.extend_from_slice(format!("F {:o} {:x} ", asset.chmod, hash).as_bytes());
+                push_escaped(&mut fingerprint, &asset.target_path.as_unix_path());
+            },
+        }
+        fingerprint.push(b'\n');
+    }
+    crate::sha256::sha256_hex(&fingerprint)
+}
+
+/// Paths are written as bytes, because they don't have to be UTF-8, with `\` and newlines escaped
+fn push_escaped(out: &mut Vec<u8>, path: &[u8]) {
+    for &b in path {
+        match b {
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b => out.push(b),
+        }
+    }
+}
+
+fn unescape(escaped: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.iter();
+    while let Some(&b) = bytes.next() {
+        out.push(match b {
+            b'\\' => match bytes.next()? {
+                b'\\' => b'\\',
+                b'n' => b'\n',
+                _ => return None,
+            },
+            b => b,
+        });
+    }
+    Some(out)
+}
+
+/// What's needed to reuse the compressed `data.tar` from the previous run
+pub struct DataArchiveManifest {
+    /// From `assets_fingerprint`
+    pub fingerprint: String,
+    /// SHA-256 of the uncompressed `data.tar`, which is its key in the compression cache
+    pub tar_hash: String,
+    pub tar_len: usize,
+    /// MD5 of the files, for `md5sums`
+    pub hashes: HashMap<PathBuf, Digest>,
+}
+
+const DATA_ARCHIVE_MANIFEST: &str = "data.tar.manifest";
+
+impl DataArchiveManifest {
+    #[must_use]
+    pub fn new(fingerprint: String, data_archive: &[u8], hashes: HashMap<PathBuf, Digest>) -> Self {
+        Self { fingerprint, tar_hash: crate::sha256::sha256_hex(data_archive), tar_len: data_archive.len(), hashes }
+    }
+
+    /// Saved in the compression cache. Reading it back is only an optimization, so errors are ignored.
+    pub fn read(cache_dir: &Path) -> Option<Self> {
+        let data = fs::read(cache_dir.join(DATA_ARCHIVE_MANIFEST)).ok()?;
+        let mut lines = data.strip_suffix(b"\n")?.split(|&b| b == b'\n');
+        let mut text_line = || String::from_utf8(lines.next()?.to_vec()).ok();
+        let fingerprint = text_line()?;
+        let tar_hash = text_line()?;
+        let tar_len = text_line()?.parse().ok()?;
+        let hashes = lines.map(|line| {
+            let hex = std::str::from_utf8(line.get(..32)?).ok()?;
+            let path = unescape(line.get(32..)?.strip_prefix(b"  ")?)?;
+            let mut digest = [0; 16];
+            for (i, byte) in digest.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+            }
+            Some((path_from_unix_bytes(&path).ok()?.into_owned(), Digest(digest)))
+        }).collect::<Option<_>>()?;
+        Some(Self { fingerprint, tar_hash, tar_len, hashes })
+    }
+
+    pub fn write(&self, cache_dir: &Path) {
+        let mut data = format!("{}\n{}\n{}\n", self.fingerprint, self.tar_hash, self.tar_len).into_bytes();
+        for (path, digest) in &self.hashes {
+            data.extend_from_slice(format!("{:x}  ", digest).as_bytes());
+            push_escaped(&mut data, &path.as_unix_path());
+            data.push(b'\n');
+        }
+        let _ = fs::write(cache_dir.join(DATA_ARCHIVE_MANIFEST), data);
+    }
+}
+
+/// Generates compressed changelog file.
+/// If the package doesn't have a changelog, a minimal one is made from the current version.
+pub(crate) fn generate_changelog_asset(options: &Config) -> CDResult<Vec<u8>> {
+    let content = if let Some(ref path) = options.changelog {
+        options.fs.read(&options.path_in_workspace(path))
+            .map_err(|e| CargoDebError::IoFile("unable to read changelog file", e, path.into()))?
+    } else {
+        generate_default_changelog(options, clamped_timestamp())?
+    };
+    // The input is plaintext, but the debian package should contain gzipped one.
+    Ok(gzipped(&content)?)
+}
+
+/// Generates compressed NEWS.Debian file, if the package has one
+pub(crate) fn generate_news_asset(options: &Config) -> CDResult<Option<Vec<u8>>> {
+    let path = match options.news {
+        Some(ref path) => options.path_in_workspace(path),
+        None => match options.maintainer_scripts {
+            Some(ref dir) => match pkgfile(&options.path_in_workspace(dir), &options.name, &options.name, "NEWS", None) {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        },
+    };
+    let content = options.fs.read(&path)
+        .map_err(|e| CargoDebError::IoFile("unable to read NEWS file", e, path.clone()))?;
+    Ok(Some(gzipped(&content)?))
+}
+
+/// Generates compressed copy of the `Cargo.lock` used for the build
+pub(crate) fn generate_cargo_lock_asset(options: &Config) -> CDResult<Vec<u8>> {
+    let path = options.workspace_root.join("Cargo.lock");
+    let content = options.fs.read(&path)
+        .map_err(|e| CargoDebError::IoFile("unable to read Cargo.lock", e, path.clone()))?;
+    Ok(gzipped(&content)?)
+}
+
+/// JSON with the GNU build-id of every ELF binary and library, and its separate debug file,
+/// for crash reporting services that look up debug symbols by the build-id
+pub(crate) fn generate_build_ids(options: &Config) -> CDResult<Vec<u8>> {
+    let mut binaries = Vec::new();
+    // the debug files have the same build-ids
+    let assets = options.assets.resolved.iter().filter(|asset| !asset.target_path.starts_with("usr/lib/debug"));
+    for asset in assets.filter(|asset| is_elf(&*options.fs, &asset.source)) {
+        let Some(build_id) = crate::elf::build_id(&asset.source.data(&*options.fs)?) else { continue };
+        let debug_file = asset.debug_target()
+            .filter(|debug| options.assets.resolved.iter().any(|a| Path::new("/").join(&a.target_path) == *debug));
+        binaries.push(serde_json::json!({
+            "path": Path::new("/").join(&asset.target_path).display().to_string(),
+            "build-id": build_id,
+            "debug-file": debug_file.map(|path| path.display().to_string()),
+        }));
+    }
+    let mut json = serde_json::to_vec_pretty(&serde_json::json!({
+        "package": options.deb_name,
+        "version": options.deb_version,
+        "architecture": options.package_architecture(),
+        "binaries": binaries,
+    }))?;
+    json.push(b'\n');
+    Ok(json)
+}
+
+/// Copy of `build-ids.json` next to the package, for uploading debug symbols without unpacking it
+pub fn write_build_ids_sidecar(options: &Config, package_path: &Path) -> CDResult<PathBuf> {
+    let target_path = Path::new("usr/share/doc").join(&options.deb_name).join("build-ids.json");
+    let build_ids = options.assets.resolved.iter()
+        .find(|asset| asset.target_path == target_path)
+        .ok_or("the package has no build-ids.json")?
+        .source.data(&*options.fs)?;
+    let path = package_path.with_extension("build-ids.json");
+    fs::write(&path, build_ids).map_err(|e| CargoDebError::IoFile("unable to write build-ids.json", e, path.clone()))?;
+    Ok(path)
+}
+
+/// Generates the doc-base control file registering the package's documentation
+///
+/// See <https://www.debian.org/doc/packaging-manuals/doc-base/ch2.html>
+pub(crate) fn generate_doc_base_asset(options: &Config, doc_base: &DocBaseConfig) -> CDResult<Vec<u8>> {
+    if doc_base.formats.is_empty() {
+        return Err(CargoDebError::InvalidDocBase("needs at least one entry in `formats`"));
+    }
+    let mut control = Vec::new();
+    writeln!(control, "Document: {}", doc_base.document.as_deref().unwrap_or(&options.deb_name))?;
+    writeln!(control, "Title: {}", doc_base.title.as_deref().unwrap_or(&options.description))?;
+    if let Some(ref author) = doc_base.author {
+        writeln!(control, "Author: {}", author)?;
+    }
+    if let Some(ref abstract_) = doc_base.abstract_ {
+        write!(control, "Abstract:")?;
+        for line in abstract_.split_by_chars(79) {
+            writeln!(control, " {}", line)?;
+        }
+    }
+    writeln!(control, "Section: {}", doc_base.section.as_deref().unwrap_or("Programming"))?;
+
+    for format in &doc_base.formats {
+        writeln!(control)?;
+        writeln!(control, "Format: {}", format.format)?;
+        match format.index {
+            Some(ref index) => writeln!(control, "Index: {}", absolute_path(index))?,
+            None if format.format.eq_ignore_ascii_case("html") => {
+                return Err(CargoDebError::InvalidDocBase("HTML format requires the `index` file"));
+            },
+            None => {},
+        }
+        writeln!(control, "Files: {}", absolute_path(&format.files))?;
+    }
+    Ok(control)
+}
+
+fn absolute_path(path: &str) -> Cow<'_, str> {
+    if path.starts_with('/') {
+        Cow::Borrowed(path)
     } else {
-        Ok(None)
+        Cow::Owned(format!("/{}", path))
     }
 }
 
+fn gzipped(content: &[u8]) -> io::Result<Vec<u8>> {
+    let mut compressed = Vec::with_capacity(content.len());
+    zopfli::compress(&Options::default(), &Format::Gzip, content, &mut compressed)?;
+    compressed.shrink_to_fit();
+    Ok(compressed)
+}
+
+/// A single changelog entry for the version being packaged
+///
+/// See <https://www.debian.org/doc/debian-policy/ch-source.html#debian-changelog-debian-changelog>
+fn generate_default_changelog(options: &Config, timestamp: u64) -> CDResult<Vec<u8>> {
+    const URGENCIES: [&str; 5] = ["low", "medium", "high", "emergency", "critical"];
+    if !URGENCIES.contains(&options.changelog_urgency.as_str()) {
+        return Err(CargoDebError::InvalidChangelogUrgency(options.changelog_urgency.clone()));
+    }
+    let distribution = options.changelog_distribution();
+    if distribution.is_empty() || distribution.contains(|c: char| c.is_whitespace() || c == ';') {
+        return Err(CargoDebError::InvalidChangelogDistribution(distribution.to_owned()));
+    }
+    let mut changelog = Vec::new();
+    writeln!(changelog, "{} ({}) {}; urgency={}", options.deb_name, options.deb_version, distribution, options.changelog_urgency)?;
+    writeln!(changelog)?;
+    writeln!(changelog, "  * Release {}.", options.deb_version)?;
+    if let Some(ref commit) = options.git_commit {
+        writeln!(changelog, "  * Built from git commit {}.", commit)?;
+    }
+    writeln!(changelog)?;
+    writeln!(changelog, " -- {}  {}", options.maintainer, rfc2822_date(timestamp))?;
+    Ok(changelog)
+}
+
 fn append_copyright_metadata(copyright: &mut Vec<u8>, options: &Config) -> Result<(), CargoDebError> {
     writeln!(copyright, "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/")?;
     writeln!(copyright, "Upstream-Name: {}", options.name)?;
@@ -52,7 +390,7 @@ fn append_copyright_metadata(copyright: &mut Vec<u8>, options: &Config) -> Resul
 pub(crate) fn generate_copyright_asset(options: &Config) -> CDResult<Vec<u8>> {
     let mut copyright: Vec<u8> = Vec::new();
     if let Some(ref path) = options.license_file {
-        let license_string = fs::read_to_string(options.path_in_workspace(path))
+        let license_string = options.fs.read_to_string(&options.path_in_workspace(path))
             .map_err(|e| CargoDebError::IoFile("unable to read license file", e, path.to_owned()))?;
         if !has_copyright_metadata(&license_string) {
             append_copyright_metadata(&mut copyright, options)?;
@@ -91,27 +429,40 @@ pub fn compress_assets(options: &mut Config, listener: &dyn Listener) -> CDResul
     let mut indices_to_remove = Vec::new();
     let mut new_assets = Vec::new();
 
-    fn needs_compression(path: &str) -> bool {
+    fn needs_compression(path: &str, len: u64) -> bool {
         !path.ends_with(".gz")
             && (path.starts_with("usr/share/man/")
                 || (path.starts_with("usr/share/doc/")
                     && (path.ends_with("/NEWS") || path.ends_with("/changelog")))
-                || (path.starts_with("usr/share/info/") && path.ends_with(".info")))
+                || (path.starts_with("usr/share/info/") && is_info_file(path))
+                || (is_doc_example(path) && len > 4096))
+    }
+
+    /// Examples are compressed like `dh_compress` does, except small files and formats that are compressed already
+    fn is_doc_example(path: &str) -> bool {
+        path.strip_prefix("usr/share/doc/").and_then(|p| p.split_once('/')).is_some_and(|(_, rest)| rest.starts_with("examples/"))
+            && !path.rsplit_once('.').is_some_and(|(_, ext)| ["bz2", "xz", "zst", "zip", "jar", "png", "jpg", "jpeg", "gif", "svgz", "pdf"].contains(&ext))
+    }
+
+    /// `foo.info`, and parts of split documents, `foo.info-1`
+    fn is_info_file(path: &str) -> bool {
+        path.ends_with(".info") || path.rsplit_once(".info-").is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
     }
 
     for (idx, asset) in options.assets.resolved.iter().enumerate() {
         let target_path_str = asset.target_path.to_string_lossy();
-        if needs_compression(&target_path_str) {
+        if needs_compression(&target_path_str, asset.source.len(&*options.fs).unwrap_or(0)) {
             listener.info(format!("Compressing '{}'", asset.source.path().unwrap_or(Path::new("-")).display()));
 
-            let content = asset.source.data()?;
-            let mut compressed = Vec::with_capacity(content.len());
-            zopfli::compress(&Options::default(), &Format::Gzip, &content, &mut compressed)?;
-            compressed.shrink_to_fit();
+            let compressed = gzipped(&asset.source.data(&*options.fs)?)?;
 
             new_assets.push(Asset::new(
                 crate::manifest::AssetSource::Data(compressed),
-                Path::new(&format!("{}.gz", target_path_str)).into(),
+                {
+                    let mut target_path = asset.target_path.clone().into_os_string();
+                    target_path.push(".gz");
+                    target_path.into()
+                },
                 asset.chmod,
                 false,
             ));
@@ -129,50 +480,553 @@ pub fn compress_assets(options: &mut Config, listener: &dyn Listener) -> CDResul
     Ok(())
 }
 
+/// Warns about RPATH and RUNPATH of ELF files that Lintian would flag, and removes them with `scrub-rpath`
+pub fn check_runpaths(options: &mut Config, listener: &dyn Listener) -> CDResult<()> {
+    let build_dirs = [options.workspace_root.clone(), options.target_dir.clone()];
+    let is_suspicious = |dir: &str| {
+        if dir.starts_with("$ORIGIN") || dir.starts_with("${ORIGIN}") {
+            return None;
+        }
+        if dir.is_empty() || !dir.starts_with('/') {
+            return Some("is relative to the current directory");
+        }
+        let dir = Path::new(dir);
+        if build_dirs.iter().any(|build_dir| dir.starts_with(build_dir)) || dir.starts_with("/tmp") || dir.starts_with("/home") {
+            return Some("points into the build tree");
+        }
+        None
+    };
+    for asset in &mut options.assets.resolved {
+        if !is_elf(&*options.fs, &asset.source) {
+            continue;
+        }
+        let data = asset.source.data(&*options.fs)?;
+        let mut found = false;
+        for runpath in crate::elf::runpaths(&data) {
+            for (dir, problem) in runpath.split(':').filter_map(|dir| Some((dir, is_suspicious(dir)?))) {
+                found = true;
+                if !options.scrub_rpath {
+                    listener.warning(Warning::Rpath, format!("/{} has RPATH {}, which {}. Set scrub-rpath = true to remove it", asset.target_path.display(), dir, problem));
+                }
+            }
+        }
+        if found && options.scrub_rpath {
+            let mut data = data.into_owned();
+            if crate::elf::scrub_runpaths(&mut data, &|dir| is_suspicious(dir).is_none()) {
+                listener.info(format!("Removed RPATH from /{}", asset.target_path.display()));
+                asset.source = AssetSource::Data(data);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warns about ELF executables without PIE, RELRO, or immediate binding (`-z now`).
+/// Returns hardening features of every ELF file, which `--hardening` prints like `hardening-check`.
+pub fn check_hardening(options: &Config, listener: &dyn Listener) -> CDResult<Vec<(PathBuf, Hardening)>> {
+    let mut report = Vec::new();
+    for asset in options.assets.resolved.iter().filter(|asset| is_elf(&*options.fs, &asset.source)) {
+        let h = match crate::elf::hardening(&asset.source.data(&*options.fs)?) {
+            Some(h) => h,
+            None => continue,
+        };
+        let path = Path::new("/").join(&asset.target_path);
+        if h.pie.is_some() {
+            // libraries are only reported
+            let missing: Vec<_> = [(h.pie == Some(false), "PIE"), (!h.relro, "RELRO"), (!h.bind_now, "BIND_NOW")]
+                .iter().filter(|(missing, _)| *missing).map(|(_, name)| *name).collect();
+            if !missing.is_empty() {
+                listener.warning(Warning::Hardening, format!("{} is built without {}. See `--hardening`", path.display(), missing.join(", ")));
+            }
+        }
+        report.push((path, h));
+    }
+    Ok(report)
+}
+
+/// Warns about binaries of a debug build, when the package is made with another profile, e.g. assets
+/// from `target/debug/` in a release package. They're many times larger and slower.
+/// Only the directory is checked, because release profiles can have debug info and overflow checks too.
+pub fn check_debug_binaries(options: &Config, listener: &dyn Listener) {
+    if options.profile_dir_name() == "debug" {
+        return;
+    }
+    let debug_dir = options.target_dir.join("debug");
+    for asset in &options.assets.resolved {
+        if matches!(&asset.source, AssetSource::Path(path) if path.starts_with(&debug_dir)) && is_elf(&*options.fs, &asset.source) {
+            listener.warning(Warning::DebugBinary, format!("/{} is a debug build, but the package is for the {} profile. Check the asset's source path, or use `--profile dev` if it's intended",
+                asset.target_path.display(), options.profile));
+        }
+    }
+}
+
+/// Reads only the magic number of files
+fn is_elf(fs: &dyn FileSystem, source: &AssetSource) -> bool {
+    let mut magic = [0; 4];
+    match source {
+        AssetSource::Path(path) => fs.open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && magic == *b"\x7fELF",
+        AssetSource::Data(data) => data.starts_with(b"\x7fELF"),
+    }
+}
+
 /// Copies all the files to be packaged into the tar archive.
 /// Returns MD5 hashes of files copied
-fn archive_files(archive: &mut Archive, options: &Config, listener: &mut dyn Listener) -> CDResult<HashMap<PathBuf, Digest>> {
+fn archive_files(archive: &mut Archive, options: &Config, contents: &[AssetContent<'_>], listener: &mut dyn Listener) -> CDResult<HashMap<PathBuf, Digest>> {
     let mut hashes = HashMap::new();
-    for asset in &options.assets.resolved {
-        let out_data = asset.source.data()?;
-
+    // content and mode of files that duplicates can be hardlinked to
+    let mut linked: HashMap<([u8; 16], u32), (&Asset, &AssetContent<'_>)> = HashMap::new();
+    for (asset, content) in options.assets.resolved.iter().zip(contents) {
         let mut log_line = format!(
             "{} -> {}",
             asset.source.path().unwrap_or_else(|| Path::new("-")).display(),
             asset.target_path.display()
         );
-        if let Some(len) = asset.source.len() {
+        if let Some(len) = asset.source.len(&*options.fs) {
             let (size, unit) = human_size(len);
             let _ = fmt::Write::write_fmt(&mut log_line, format_args!(" ({}{})", size, unit));
         }
         listener.info(log_line);
 
-        let mut archived = false;
-        if options.preserve_symlinks {
-            if let Some(source_path) = asset.source.path() {
-                let md = fs::symlink_metadata(source_path)?;
-                if md.file_type().is_symlink() {
-                    archived = true;
-                    let link_name = fs::read_link(source_path)?;
-                    archive.symlink(&asset.target_path, &link_name)?;
+        let hash = match *content {
+            AssetContent::Symlink(ref link_name) => {
+                archive.symlink(&asset.target_path, link_name)?;
+                continue;
+            },
+            AssetContent::File(_, hash) | AssetContent::Streamed(hash, _) => hash,
+        };
+        hashes.insert(asset.target_path.clone(), hash);
+        // MD5 only finds the candidates, because its collisions are easy to make
+        let original = match linked.get(&(hash.0, asset.chmod)) {
+            Some(&(original, original_content)) if same_content(&*options.fs, (original, original_content), (asset, content))? => Some(original),
+            _ => None,
+        };
+        match original {
+            Some(original) => {
+                listener.info(format!("{} is the same as {}, so it's a hardlink", asset.target_path.display(), original.target_path.display()));
+                archive.hardlink(&asset.target_path, &original.target_path, asset.chmod)?;
+            },
+            None => {
+                if let AssetContent::File(ref out_data, _) = *content {
+                    archive.file(&asset.target_path, out_data, asset.chmod)?;
+                } else if let (AssetContent::Streamed(_, len), Some(path)) = (content, asset.source.path()) {
+                    let file = options.fs.open(path)
+                        .map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, path.to_owned()))?;
+                    archive.file_from_reader(&asset.target_path, file, *len, asset.chmod)?;
                 }
-            }
+                if options.hardlink_duplicates {
+                    linked.entry((hash.0, asset.chmod)).or_insert((asset, content));
+                }
+            },
         }
+    }
+    Ok(hashes)
+}
 
-        if !archived {
-            hashes.insert(asset.target_path.clone(), md5::compute(&out_data));
-            archive.file(&asset.target_path, &out_data, asset.chmod)?;
+/// Compares the bytes of two assets
+fn same_content(fs: &dyn FileSystem, (a, a_content): (&Asset, &AssetContent<'_>), (b, b_content): (&Asset, &AssetContent<'_>)) -> CDResult<bool> {
+    match (a_content, b_content) {
+        (AssetContent::File(a_data, _), AssetContent::File(b_data, _)) => Ok(a_data == b_data),
+        (AssetContent::Streamed(_, a_len), AssetContent::Streamed(_, b_len)) if a_len == b_len => same_source_content(fs, &a.source, &b.source),
+        (AssetContent::File(data, _), AssetContent::Streamed(_, len)) | (AssetContent::Streamed(_, len), AssetContent::File(data, _)) if data.len() as u64 == *len => {
+            same_source_content(fs, &a.source, &b.source)
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Compares the bytes of the sources of two assets, without reading whole files into memory.
+/// `Installed-Size` uses it to find the same hardlinks as `archive_files`.
+pub(crate) fn same_source_content<'a>(fs: &'a dyn FileSystem, a: &'a AssetSource, b: &'a AssetSource) -> CDResult<bool> {
+    let open = |source: &'a AssetSource| -> CDResult<Box<dyn Read + Send + 'a>> {
+        match *source {
+            AssetSource::Data(ref data) => Ok(Box::new(&data[..])),
+            AssetSource::Path(ref path) => fs.open(path).map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, path.clone())),
+        }
+    };
+    let (mut a_file, mut b_file) = (open(a)?, open(b)?);
+    let (mut a_buf, mut b_buf) = (vec![0; 1 << 16], vec![0; 1 << 16]);
+    loop {
+        let a_n = read_chunk(&mut a_file, &mut a_buf)?;
+        let b_n = read_chunk(&mut b_file, &mut b_buf)?;
+        if a_buf[..a_n] != b_buf[..b_n] {
+            return Ok(false);
+        }
+        if a_n == 0 {
+            return Ok(true);
         }
     }
-    Ok(hashes)
+}
+
+/// Fills the buffer, unless the end of the file comes first
+fn read_chunk(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
 }
 
 fn human_size(len: u64) -> (u64, &'static str) {
     if len < 1000 {
         return (len, "B");
     }
-    if len < 1000_000 {
-        return ((len + 999) / 1000, "KB");
+    if len < 1_000_000 {
+        return (len.div_ceil(1000), "KB");
+    }
+    (len.div_ceil(1_000_000), "MB")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{AssetSource, DocBaseFormat};
+    use crate::util::tests::set_test_fs_path_content;
+
+    #[test]
+    fn default_changelog() {
+        let (config, _) = crate::util::tests::test_config();
+
+        let changelog = String::from_utf8(generate_default_changelog(&config, 0).unwrap()).unwrap();
+        let expected = format!("cargo-deb ({0}) unstable; urgency=medium\n\n  * Release {0}.\n\n -- {1}  Thu, 01 Jan 1970 00:00:00 +0000\n",
+            config.deb_version, config.maintainer);
+        assert_eq!(expected, changelog);
+    }
+
+    #[test]
+    fn cargo_lock_is_gzipped() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.workspace_root = PathBuf::from("/lock-test");
+        set_test_fs_path_content("/lock-test/Cargo.lock", "version = 3\n".into());
+        let compressed = generate_cargo_lock_asset(&config).unwrap();
+        assert_eq!(b"version = 3\n", &crate::inflate::gunzip(&compressed).unwrap()[..]);
+    }
+
+    #[test]
+    fn data_archive_manifest_roundtrip() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        config.assets.resolved = vec![Asset::new(AssetSource::Data(b"one".to_vec()), "usr/share/foo/one".into(), 0o644, false)];
+        let contents = read_assets(&config).unwrap();
+        let fingerprint = assets_fingerprint(&config, 0, &contents);
+        let (tarball, hashes) = generate_archive(&config, 0, &contents, &mut mock_listener).unwrap();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        DataArchiveManifest::new(fingerprint.clone(), &tarball, hashes.clone()).write(cache_dir.path());
+        let manifest = DataArchiveManifest::read(cache_dir.path()).unwrap();
+        assert_eq!(fingerprint, manifest.fingerprint);
+        assert_eq!(tarball.len(), manifest.tar_len);
+        assert_eq!(hashes, manifest.hashes);
+
+        config.assets.resolved[0].chmod = 0o600;
+        assert_ne!(fingerprint, assets_fingerprint(&config, 0, &read_assets(&config).unwrap()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn data_archive_manifest_of_unusual_names() {
+        let names: [&[u8]; 4] = [b"usr/share/foo/new\nline", b"usr/share/foo/caf\xe9", b"usr/share/foo/back\\slash\\n", b"usr/share/foo/  two spaces"];
+        let hashes: HashMap<_, _> = names.iter()
+            .map(|name| (path_from_unix_bytes(name).unwrap().into_owned(), md5::compute(name)))
+            .collect();
+        let cache_dir = tempfile::tempdir().unwrap();
+        DataArchiveManifest::new("f".into(), b"tar", hashes.clone()).write(cache_dir.path());
+        assert_eq!(hashes, DataArchiveManifest::read(cache_dir.path()).unwrap().hashes);
+    }
+
+    #[test]
+    fn info_pages_are_gzipped() {
+        let (mut config, mock_listener) = crate::util::tests::test_config();
+        config.assets.resolved.clear();
+        for path in ["usr/share/info/foo.info", "usr/share/info/foo.info-1", "usr/share/info/foo.info-x"] {
+            config.assets.resolved.push(Asset::new(AssetSource::Data(b"info".to_vec()), path.into(), 0o644, false));
+        }
+        compress_assets(&mut config, &mock_listener).unwrap();
+        let paths: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/share/info/foo.info-x", "usr/share/info/foo.info.gz", "usr/share/info/foo.info-1.gz"], paths);
+    }
+
+    #[test]
+    fn large_doc_examples_are_gzipped() {
+        let (mut config, mock_listener) = crate::util::tests::test_config();
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![b'x'; 5000]), "usr/share/doc/foo/examples/big.rs".into(), 0o644, false),
+            Asset::new(AssetSource::Data(vec![b'x'; 5000]), "usr/share/doc/foo/examples/logo.png".into(), 0o644, false),
+            Asset::new(AssetSource::Data(b"fn main() {}".to_vec()), "usr/share/doc/foo/examples/small.rs".into(), 0o644, false),
+            Asset::new(AssetSource::Data(vec![b'x'; 5000]), "usr/share/doc/foo/README.md".into(), 0o644, false),
+        ];
+        compress_assets(&mut config, &mock_listener).unwrap();
+        let paths: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/share/doc/foo/examples/logo.png", "usr/share/doc/foo/examples/small.rs", "usr/share/doc/foo/README.md", "usr/share/doc/foo/examples/big.rs.gz"], paths);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn build_ids_json() {
+        let (mut config, _) = crate::util::tests::test_config();
+        // assets are read from a test filesystem
+        let exe = fs::read(std::env::current_exe().unwrap()).unwrap();
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(exe.clone()), PathBuf::from("usr/bin/foo"), 0o755, true),
+            Asset::new(AssetSource::Data(exe.clone()), PathBuf::from("usr/lib/debug/usr/bin/foo.debug"), 0o644, false),
+            Asset::new(AssetSource::Data(b"#!/bin/sh\n".to_vec()), PathBuf::from("usr/bin/foo-helper"), 0o755, false),
+        ];
+        config.build_ids = true;
+        config.add_build_ids_asset().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&config.assets.resolved[3].source.data(&*config.fs).unwrap()).unwrap();
+        assert_eq!("usr/share/doc/cargo-deb/build-ids.json", config.assets.resolved[3].target_path.to_str().unwrap());
+        let binaries = json["binaries"].as_array().unwrap();
+        assert_eq!(1, binaries.len());
+        assert_eq!("/usr/bin/foo", binaries[0]["path"]);
+        assert_eq!(crate::elf::build_id(&exe).unwrap(), binaries[0]["build-id"]);
+        assert_eq!("/usr/lib/debug/usr/bin/foo.debug", binaries[0]["debug-file"]);
+
+        config.assets.resolved.truncate(1);
+        let json: serde_json::Value = serde_json::from_slice(&generate_build_ids(&config).unwrap()).unwrap();
+        assert_eq!(serde_json::Value::Null, json["binaries"][0]["debug-file"]);
+    }
+
+    #[test]
+    fn sha256_manifest_lists_other_files() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(b"abc".to_vec()), PathBuf::from("usr/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(Vec::new()), PathBuf::from("usr/share/doc/cargo-deb/copyright"), 0o644, false),
+        ];
+        config.sha256_manifest = true;
+        config.add_sha256_manifest_asset();
+        assert_eq!("usr/share/cargo-deb/manifest.sha256", config.assets.resolved[2].target_path.to_str().unwrap());
+
+        let contents = read_assets(&config).unwrap();
+        let AssetContent::File(manifest, hash) = &contents[2] else { panic!() };
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  /usr/bin/foo\n\
+            e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  /usr/share/doc/cargo-deb/copyright\n",
+            std::str::from_utf8(manifest).unwrap());
+        assert_eq!(md5::compute(manifest), *hash);
+
+        // Installed-Size counts it, although the asset is still empty
+        let (_, hashes) = generate_archive(&config, 0, &contents, &mut mock_listener).unwrap();
+        let manifest_path = config.sha256_manifest_path().unwrap();
+        assert_eq!(manifest.len() as u64, sha256_manifest_len(&config, &manifest_path, &hashes));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn debug_binaries_in_release_packages() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        config.assets.resolved = vec![Asset::new(AssetSource::Path(std::env::current_exe().unwrap()), PathBuf::from("usr/bin/foo"), 0o755, true)];
+        mock_listener.expect_warning().withf(|code, msg| *code == Warning::DebugBinary && msg.starts_with("/usr/bin/foo is a debug build")).times(1).return_const(());
+        check_debug_binaries(&config, &mock_listener);
+
+        config.profile = "dev".into();
+        let mock_listener = crate::listener::MockListener::new();
+        check_debug_binaries(&config, &mock_listener);
+    }
+
+    #[test]
+    fn default_changelog_distribution_and_urgency() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.changelog_distribution = Some("bookworm-backports".into());
+        config.changelog_urgency = "high".into();
+
+        let changelog = String::from_utf8(generate_default_changelog(&config, 0).unwrap()).unwrap();
+        assert!(changelog.starts_with(&format!("cargo-deb ({}) bookworm-backports; urgency=high\n", config.deb_version)), "{}", changelog);
+
+        config.changelog_urgency = "urgent".into();
+        assert!(generate_default_changelog(&config, 0).is_err());
+        config.changelog_urgency = "low".into();
+        config.changelog_distribution = Some("jammy; urgency=high".into());
+        assert!(generate_default_changelog(&config, 0).is_err());
+    }
+
+    #[test]
+    fn default_changelog_with_git_commit() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.git_commit = Some("0123abcdef".into());
+
+        let changelog = String::from_utf8(generate_default_changelog(&config, 0).unwrap()).unwrap();
+        assert!(changelog.contains("\n  * Built from git commit 0123abcdef.\n\n -- "), "{}", changelog);
+    }
+
+    #[test]
+    fn duplicates_are_hardlinked() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        config.hardlink_duplicates = true;
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(b"bin".to_vec()), "usr/bin/foo".into(), 0o755, false),
+            Asset::new(AssetSource::Data(b"bin".to_vec()), "usr/bin/foo-alias".into(), 0o755, false),
+            Asset::new(AssetSource::Data(b"bin".to_vec()), "usr/share/foo/data".into(), 0o644, false),
+        ];
+        let mut archive = Archive::new(0, crate::manifest::TarFormat::Gnu);
+        let contents = read_assets(&config).unwrap();
+        let hashes = archive_files(&mut archive, &config, &contents, &mut mock_listener).unwrap();
+        assert_eq!(3, hashes.len());
+
+        let tarball = archive.into_inner().unwrap();
+        let entries = crate::reader::tar_entries(&tarball).unwrap();
+        let alias = entries.iter().find(|e| e.path == "usr/bin/foo-alias").unwrap();
+        assert_eq!(tar::EntryType::Link, alias.entry_type);
+        assert_eq!(Some("usr/bin/foo"), alias.link_name.as_deref());
+        let data = entries.iter().find(|e| e.path == "usr/share/foo/data").unwrap();
+        assert_eq!(tar::EntryType::Regular, data.entry_type);
+
+        // same MD5, as if it was a collision
+        config.assets.resolved.truncate(2);
+        let contents = [
+            AssetContent::File(Cow::Borrowed(b"bin"), md5::compute(b"bin")),
+            AssetContent::File(Cow::Borrowed(b"evil"), md5::compute(b"bin")),
+        ];
+        let mut archive = Archive::new(0, crate::manifest::TarFormat::Gnu);
+        archive_files(&mut archive, &config, &contents, &mut mock_listener).unwrap();
+        let entries = crate::reader::tar_entries(&archive.into_inner().unwrap()).unwrap();
+        let alias = entries.iter().find(|e| e.path == "usr/bin/foo-alias").unwrap();
+        assert_eq!(tar::EntryType::Regular, alias.entry_type);
+        assert_eq!(b"evil", &alias.data[..]);
+    }
+
+    #[test]
+    fn assets_are_read_in_order() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.assets.resolved = (0..500).map(|i| {
+            Asset::new(AssetSource::Data(i.to_string().into_bytes()), format!("usr/share/foo/{}", i).into(), 0o644, false)
+        }).collect();
+        let contents = read_assets(&config).unwrap();
+        for (i, content) in contents.iter().enumerate() {
+            let AssetContent::File(data, hash) = content else { panic!() };
+            assert_eq!(i.to_string().as_bytes(), &data[..]);
+            assert_eq!(md5::compute(i.to_string()), *hash);
+        }
+    }
+
+    #[test]
+    fn files_are_streamed() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        let dir = tempfile::tempdir().unwrap();
+        let model = dir.path().join("model.bin");
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        fs::write(&model, &data).unwrap();
+        config.assets.resolved = vec![Asset::new(AssetSource::Path(model), "usr/share/foo/model.bin".into(), 0o644, false)];
+
+        let contents = read_assets(&config).unwrap();
+        assert!(matches!(contents[0], AssetContent::Streamed(hash, len) if hash == md5::compute(&data) && len == data.len() as u64));
+        let (tarball, _) = generate_archive(&config, 0, &contents, &mut mock_listener).unwrap();
+        let entries = crate::reader::tar_entries(&tarball).unwrap();
+        assert!(entries.iter().any(|e| e.path == "usr/share/foo/model.bin" && e.data == data));
+    }
+
+    #[test]
+    fn golden_archive_from_memory_fs() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        let mut fs = crate::vfs::MemoryFs::new();
+        fs.add_file("/src/web/index.html", "<html>")
+            .add_file("/src/web/js/app.js", "app()")
+            .add_file("/src/web/copy.html", "<html>")
+            .add_symlink("/src/web/start.html", "index.html")
+            .add_file(config.path_in_workspace(config.readme.as_ref().unwrap()), "# cargo-deb");
+        config.fs = Box::new(fs);
+        config.preserve_symlinks = true;
+        config.hardlink_duplicates = true;
+        config.assets.unresolved = vec![crate::manifest::UnresolvedAsset {
+            source_path: "/src/web/**/*".into(),
+            target_path: "usr/share/foo/".into(),
+            chmod: 0o644,
+            is_built: false,
+            condition: Default::default(),
+            dependencies: Default::default(),
+            owner: None,
+            strip_keep: Default::default(),
+        }];
+        config.resolve_assets(&mock_listener).unwrap();
+
+        let contents = read_assets(&config).unwrap();
+        let (tarball, _) = generate_archive(&config, 0, &contents, &mut mock_listener).unwrap();
+        let listing: Vec<_> = crate::reader::tar_entries(&tarball).unwrap().into_iter()
+            .filter(|e| e.path.starts_with("usr/share/foo/"))
+            .map(|e| format!("{:?} {:o} {} {}", e.entry_type, e.mode, e.path, e.link_name.as_deref().map_or_else(|| String::from_utf8_lossy(&e.data), Into::into)))
+            .collect();
+        assert_eq!(vec![
+            "Directory 755 usr/share/foo/ ",
+            "Regular 644 usr/share/foo/copy.html <html>",
+            "Link 644 usr/share/foo/index.html usr/share/foo/copy.html",
+            "Directory 755 usr/share/foo/js/ ",
+            "Regular 644 usr/share/foo/js/app.js app()",
+            "Symlink 777 usr/share/foo/start.html index.html",
+        ], listing);
+    }
+
+    #[test]
+    fn package_from_memory_fs() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        mock_listener.expect_warning().return_const(());
+        let mut fs = crate::vfs::MemoryFs::new();
+        fs.add_file("/src/target/release/foo", "#!/bin/sh\n")
+            .add_file("/src/debian/foo.service", "[Service]\nExecStart=/usr/bin/foo\n[Install]\nWantedBy=multi-user.target\n")
+            .add_file("/src/LICENSE", "Only in memory\n")
+            .add_file("/src/debian/changelog", "foo (1.0) unstable; urgency=low\n")
+            .add_file("/src/debian/triggers", "interest-noawait /usr/share/foo\n")
+            .add_file("/src/README.md", "# Foo");
+        config.fs = Box::new(fs);
+        config.license_file = Some("/src/LICENSE".into());
+        config.license_file_skip_lines = 0;
+        config.changelog = Some("/src/debian/changelog".into());
+        config.triggers_file = Some("/src/debian/triggers".into());
+        config.maintainer_scripts = Some("/src/no-such-dir".into());
+        config.systemd_units = Some(crate::manifest::SystemdUnitsConfig::default());
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Path("/src/target/release/foo".into()), "usr/bin/foo".into(), 0o755, false),
+            Asset::new(AssetSource::Path("/src/debian/foo.service".into()), "usr/lib/systemd/system/foo.service".into(), 0o644, false),
+        ];
+        config.add_copyright_asset().unwrap();
+        assert!(crate::inflate::gunzip(&generate_changelog_asset(&config).unwrap()).unwrap().starts_with(b"foo (1.0)"));
+
+        let contents = read_assets(&config).unwrap();
+        let (tarball, hashes) = generate_archive(&config, 0, &contents, &mut mock_listener).unwrap();
+        let data = crate::reader::tar_entries(&tarball).unwrap();
+        let copyright = data.iter().find(|e| e.path.ends_with("/copyright")).unwrap();
+        assert!(String::from_utf8_lossy(&copyright.data).contains("Only in memory"));
+
+        let control = crate::control::generate_archive(&config, 0, hashes, &mut mock_listener).unwrap();
+        let control = crate::reader::tar_entries(&control).unwrap();
+        let file = |name: &str| String::from_utf8(control.iter().find(|e| e.path == name).unwrap().data.clone()).unwrap();
+        assert!(file("postinst").contains("foo.service"), "{}", file("postinst"));
+        assert!(file("triggers").starts_with("interest-noawait /usr/share/foo\n"), "{}", file("triggers"));
+    }
+
+    #[test]
+    fn news_from_maintainer_scripts_dir() {
+        let (mut config, _) = crate::util::tests::test_config();
+        assert!(generate_news_asset(&config).unwrap().is_none());
+
+        config.manifest_dir = PathBuf::from("/news-test");
+        set_test_fs_path_content("/news-test/debian/cargo-deb.NEWS", "upgrade notes".into());
+        config.maintainer_scripts = Some(PathBuf::from("debian"));
+
+        let news = generate_news_asset(&config).unwrap().expect("NEWS");
+        assert_eq!(&[0x1F, 0x8B], &news[..2]);
+    }
+
+    #[test]
+    fn doc_base_control() {
+        let (config, _) = crate::util::tests::test_config();
+
+        let mut doc_base = DocBaseConfig {
+            document: None,
+            title: Some("Manual".into()),
+            author: None,
+            abstract_: Some("How to use it".into()),
+            section: None,
+            formats: vec![
+                DocBaseFormat { format: "HTML".into(), index: Some("usr/share/doc/cargo-deb/html/index.html".into()), files: "/usr/share/doc/cargo-deb/html/*.html".into() },
+                DocBaseFormat { format: "PDF".into(), index: None, files: "/usr/share/doc/cargo-deb/manual.pdf".into() },
+            ],
+        };
+        let control = String::from_utf8(generate_doc_base_asset(&config, &doc_base).unwrap()).unwrap();
+        assert_eq!("Document: cargo-deb\nTitle: Manual\nAbstract: How to use it\nSection: Programming\n\n\
+            Format: HTML\nIndex: /usr/share/doc/cargo-deb/html/index.html\nFiles: /usr/share/doc/cargo-deb/html/*.html\n\n\
+            Format: PDF\nFiles: /usr/share/doc/cargo-deb/manual.pdf\n", control);
+
+        doc_base.formats[0].index = None;
+        assert!(generate_doc_base_asset(&config, &doc_base).is_err());
     }
-    return ((len + 999_999) / 1000_000, "MB");
 }
@@ -1,57 +1,162 @@
 use crate::error::CDResult;
-use crate::manifest::Config;
+use crate::manifest::{Config, PackageFormat};
 use crate::pathbytes::*;
 use ar::{Builder, Header};
 use std::fs;
 use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+/// `.deb` is an `ar` archive. opkg's `.ipk` has the same members, but in a gzipped tarball.
+enum Container {
+    Ar(Builder<File>),
+    TarGz(tar::Builder<Vec<u8>>, File),
+}
+
 pub struct DebArchive {
     out_abspath: PathBuf,
     prefix: PathBuf,
-    ar_builder: Builder<File>,
+    container: Container,
 }
 
 impl DebArchive {
     pub fn new(config: &Config) -> CDResult<Self> {
-        let out_filename = format!("{}_{}_{}.deb", config.deb_name, config.deb_version, config.architecture);
+        let out_filename = format!("{}_{}_{}.{}", config.deb_name, config.deb_version, config.package_architecture(), config.package_format.extension());
         let prefix = config.deb_temp_dir();
         let out_abspath = config.deb_output_path(&out_filename);
         {
             let deb_dir = out_abspath.parent().ok_or("invalid dir")?;
             let _ = fs::create_dir_all(deb_dir);
         }
-        let ar_builder = Builder::new(File::create(&out_abspath)?);
+        let file = File::create(&out_abspath)?;
+        let container = match config.package_format {
+            PackageFormat::Deb => Container::Ar(Builder::new(file)),
+            PackageFormat::Ipk => Container::TarGz(tar::Builder::new(Vec::new()), file),
+        };
 
         Ok(DebArchive {
             out_abspath,
             prefix,
-            ar_builder,
+            container,
         })
     }
 
     pub(crate) fn filename_glob(config: &Config) -> String {
-        format!("{}_*_{}.deb", config.deb_name, config.architecture)
+        format!("{}_*_{}.{}", config.deb_name, config.package_architecture(), config.package_format.extension())
     }
 
     pub fn add_path(&mut self, path: &Path) -> CDResult<()> {
         let dest_path = path.strip_prefix(&self.prefix).map_err(|_| "invalid path")?;
-        let mut file = File::open(&path)?;
-        self.ar_builder.append_file(&dest_path.as_unix_path(), &mut file)?;
+        match self.container {
+            Container::Ar(ref mut ar_builder) => {
+                let mut file = File::open(path)?;
+                ar_builder.append_file(&dest_path.as_unix_path(), &mut file)?;
+            },
+            Container::TarGz(ref mut tar_builder, _) => {
+                tar_builder.append_path_with_name(path, Path::new(".").join(dest_path))?;
+            },
+        }
         Ok(())
     }
 
     pub fn add_data(&mut self, dest_path: &str, mtime_timestamp: u64, data: &[u8]) -> CDResult<()> {
-        let mut header = Header::new(dest_path.as_bytes().to_owned(), data.len() as u64);
-        header.set_mode(0o644);
-        header.set_mtime(mtime_timestamp);
-        header.set_uid(0);
-        header.set_gid(0);
-        self.ar_builder.append(&header, data)?;
+        match self.container {
+            Container::Ar(ref mut ar_builder) => {
+                let mut header = Header::new(dest_path.as_bytes().to_owned(), data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(mtime_timestamp);
+                header.set_uid(0);
+                header.set_gid(0);
+                ar_builder.append(&header, data)?;
+            },
+            Container::TarGz(ref mut tar_builder, _) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_path(format!("./{}", dest_path))?;
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(mtime_timestamp);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_cksum();
+                tar_builder.append(&header, data)?;
+            },
+        }
         Ok(())
     }
 
     pub fn finish(self) -> CDResult<PathBuf> {
+        if let Container::TarGz(tar_builder, mut file) = self.container {
+            let tarball = tar_builder.into_inner()?;
+            // The members are compressed already, so zopfli wouldn't gain anything,
+            // and the fast encoder stores what doesn't shrink
+            file.write_all(&crate::compress::gz(&tarball, true)?)?;
+        }
         Ok(self.out_abspath)
     }
 }
+
+/// dpkg-split reserves this much of each part for the headers
+const SPLIT_HEADER_ALLOWANCE: u64 = 1024;
+
+/// Splits the package into `<package>.<N>of<M>.deb` parts of at most `max_part_kib` KiB each,
+/// the same way as `dpkg-split --split`, so they can be put together with `dpkg-split --join`.
+///
+/// Returns no parts if the package already fits in one.
+pub fn split_package(config: &Config, package_path: &Path, max_part_kib: u64, mtime_timestamp: u64) -> CDResult<Vec<PathBuf>> {
+    let part_size = max_part_kib.checked_mul(1024)
+        .filter(|&size| size > SPLIT_HEADER_ALLOWANCE)
+        .ok_or("split size must be at least 2 KiB")? - SPLIT_HEADER_ALLOWANCE;
+    let package = fs::read(package_path)?;
+    if package.len() as u64 <= part_size {
+        return Ok(Vec::new());
+    }
+    let parts = split_parts(&package, &config.deb_name, &config.deb_version, config.package_architecture(), part_size as usize, mtime_timestamp)?;
+    let stem = package_path.file_stem().ok_or("invalid package path")?.to_string_lossy();
+    let total = parts.len();
+    parts.into_iter().enumerate().map(|(i, part)| {
+        let path = package_path.with_file_name(format!("{}.{}of{}.deb", stem, i + 1, total));
+        fs::write(&path, part)?;
+        Ok(path)
+    }).collect()
+}
+
+fn split_parts(package: &[u8], name: &str, version: &str, architecture: &str, part_size: usize, mtime_timestamp: u64) -> CDResult<Vec<Vec<u8>>> {
+    let md5 = format!("{:x}", md5::compute(package));
+    let chunks: Vec<_> = package.chunks(part_size).collect();
+    chunks.iter().enumerate().map(|(i, chunk)| {
+        let info = format!("2.1\n{}\n{}\n{}\n{}\n{}\n{}/{}\n{}\n", name, version, md5, package.len(), part_size, i + 1, chunks.len(), architecture);
+        let mut ar_builder = Builder::new(Vec::new());
+        for (member, data) in [("debian-split".to_owned(), info.as_bytes()), (format!("data.{}", i + 1), chunk)] {
+            let mut header = Header::new(member.into_bytes(), data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime_timestamp);
+            header.set_uid(0);
+            header.set_gid(0);
+            ar_builder.append(&header, data)?;
+        }
+        Ok(ar_builder.into_inner()?)
+    }).collect()
+}
+
+#[test]
+fn split_into_parts() {
+    let package: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+    let parts = split_parts(&package, "foo", "1.0-1", "amd64", 1000, 0).unwrap();
+    assert_eq!(3, parts.len());
+
+    let mut joined = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let mut archive = ar::Archive::new(&part[..]);
+        let mut info = String::new();
+        std::io::Read::read_to_string(&mut archive.next_entry().unwrap().unwrap(), &mut info).unwrap();
+        let lines: Vec<_> = info.lines().collect();
+        assert_eq!(vec!["2.1", "foo", "1.0-1"], lines[..3]);
+        assert_eq!(format!("{:x}", md5::compute(&package)), lines[3]);
+        assert_eq!(["2500", "1000"], lines[4..6]);
+        assert_eq!(format!("{}/3", i + 1), lines[6]);
+        let mut data = archive.next_entry().unwrap().unwrap();
+        assert_eq!(format!("data.{}", i + 1).as_bytes(), data.header().identifier());
+        std::io::Read::read_to_end(&mut data, &mut joined).unwrap();
+    }
+    assert_eq!(package, joined);
+}
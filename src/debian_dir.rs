@@ -0,0 +1,235 @@
+use crate::error::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Settings found in an existing `debian/` directory of a package built with `dpkg-buildpackage`
+#[derive(Debug, Default)]
+pub(crate) struct DebianDir {
+    /// Fields of the binary package's paragraph of `debian/control`, on top of the source paragraph's.
+    /// Names are lowercase.
+    pub fields: HashMap<String, String>,
+    /// `XB-` and other user-defined fields that dpkg copies to the binary package, with names after the prefix
+    pub user_defined_fields: Vec<(String, String)>,
+    /// Lines of `debian/install`, as sources and the destination directory
+    pub install: Vec<(Vec<String>, String)>,
+    /// There are `*.service` or other systemd unit files
+    pub has_units: bool,
+    /// There are `postinst` or other maintainer scripts
+    pub has_scripts: bool,
+}
+
+/// `config` is the debconf script that asks the questions from `templates`
+const MAINTAINER_SCRIPTS: [&str; 6] = ["config", "preinst", "postinst", "prerm", "postrm", "templates"];
+const UNIT_EXTENSIONS: [&str; 6] = ["service", "socket", "timer", "path", "mount", "tmpfile"];
+
+impl DebianDir {
+    /// `package` selects the binary package when `debian/control` has more than one
+    pub fn read(dir: &Path, package: &str) -> CDResult<Self> {
+        let control_path = dir.join("control");
+        let control = fs::read_to_string(&control_path)
+            .map_err(|e| CargoDebError::IoFile("unable to read debian/control", e, control_path.clone()))?;
+        let paragraph = package_paragraph(&control, package).ok_or_else(|| CargoDebError::PackageNotInDebianControl(package.to_owned(), control_path))?;
+        let fields = lowercase_fields(&paragraph);
+        let user_defined_fields = binary_user_defined_fields(&paragraph);
+
+        let install_path = [format!("{}.install", package), "install".to_owned()].iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists());
+        let install = match install_path {
+            Some(path) => parse_install(&fs::read_to_string(&path)
+                .map_err(|e| CargoDebError::IoFile("unable to read debian/install", e, path.clone()))?),
+            None => Vec::new(),
+        };
+
+        let mut has_units = false;
+        let mut has_scripts = false;
+        for entry in fs::read_dir(dir).map_err(|e| CargoDebError::IoFile("unable to read debian/ directory", e, dir.to_owned()))? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            has_units |= name.rsplit_once('.').is_some_and(|(_, ext)| UNIT_EXTENSIONS.contains(&ext));
+            has_scripts |= MAINTAINER_SCRIPTS.iter().any(|script| name == *script || name == format!("{}.{}", package, script));
+        }
+        Ok(Self { fields, user_defined_fields, install, has_units, has_scripts })
+    }
+
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.fields.get(name).cloned()
+    }
+
+    /// The first line of `Description`, and the rest as paragraphs
+    pub fn description(&self) -> (Option<String>, Option<String>) {
+        let description = match self.fields.get("description") {
+            Some(d) => d,
+            None => return (None, None),
+        };
+        let mut lines = description.lines();
+        let short = lines.next().map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+        let long: Vec<_> = lines.map(|line| {
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            if line.trim() == "." { "" } else { line }
+        }).collect();
+        let long = Some(long.join("\n")).filter(|s| !s.trim().is_empty());
+        (short, long)
+    }
+}
+
+/// The file exists, and has an executable bit set
+#[cfg(unix)]
+pub(crate) fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+/// Paragraphs of a deb822 file, e.g. `debian/control`. Continuation lines are kept with their leading space.
+/// Field names are as written.
+fn parse_paragraphs(text: &str) -> Vec<Vec<(String, String)>> {
+    let mut paragraphs: Vec<Vec<(String, String)>> = vec![Vec::new()];
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if paragraphs.last().is_some_and(|p| !p.is_empty()) {
+                paragraphs.push(Vec::new());
+            }
+            continue;
+        }
+        let paragraph = paragraphs.last_mut().unwrap();
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = paragraph.last_mut() {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            paragraph.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    paragraphs.retain(|p| !p.is_empty());
+    paragraphs
+}
+
+/// Fields of the source paragraph, followed by the binary package's
+fn package_paragraph(control: &str, package: &str) -> Option<Vec<(String, String)>> {
+    let paragraphs = parse_paragraphs(control);
+    let has_field = |p: &Vec<(String, String)>, field: &str| p.iter().any(|(name, _)| name.eq_ignore_ascii_case(field));
+    let source = paragraphs.iter().find(|p| has_field(p, "source"));
+    let binaries: Vec<_> = paragraphs.iter().filter(|p| has_field(p, "package")).collect();
+    let binary: &Vec<_> = match binaries.iter().find(|p| p.iter().any(|(name, value)| name.eq_ignore_ascii_case("package") && value == package)) {
+        Some(binary) => binary,
+        None if binaries.len() == 1 => binaries[0],
+        None => return None,
+    };
+    Some(source.into_iter().chain(Some(binary)).flatten().cloned().collect())
+}
+
+/// Binary package fields take precedence
+fn lowercase_fields(paragraph: &[(String, String)]) -> HashMap<String, String> {
+    paragraph.iter().map(|(name, value)| (name.to_ascii_lowercase(), value.clone())).collect()
+}
+
+/// `X[SBC]*-` fields go to the outputs whose letters are in the prefix, e.g. `XB-Foo` is `Foo` in the binary package
+fn binary_user_defined_fields(paragraph: &[(String, String)]) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for (name, value) in paragraph {
+        let Some((prefix, name)) = name.split_once('-') else { continue };
+        let Some(outputs) = prefix.strip_prefix(['X', 'x']) else { continue };
+        if name.is_empty() || !outputs.chars().all(|c| "SBCsbc".contains(c)) || !outputs.contains(['B', 'b']) {
+            continue;
+        }
+        fields.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        fields.push((name.to_owned(), value.clone()));
+    }
+    fields
+}
+
+/// `source [source…] dest-dir` lines. A lone source is installed into the same directory as in the source tree.
+fn parse_install(text: &str) -> Vec<(Vec<String>, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut words: Vec<String> = line.split_whitespace().map(String::from).collect();
+            if words.len() == 1 {
+                let dest = Path::new(&words[0]).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+                return Some((words, dest));
+            }
+            let dest = words.pop()?;
+            Some((words, dest))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROL: &str = "Source: foo
+Maintainer: Jane Doe <jane@example.com>
+Section: utils
+Priority: optional
+# a comment
+Build-Depends: debhelper-compat (= 13)
+XSBC-Original-Maintainer: Debian Foo Team <foo@example.org>
+XS-Testsuite: autopkgtest
+
+Package: foo
+Architecture: any
+Depends: ${shlibs:Depends}, ${misc:Depends},
+ libfoo1
+XB-Foo-Support: yes
+Description: does foo
+ The foo tool does foo.
+ .
+ And more.
+
+Package: foo-doc
+Section: doc
+Architecture: all
+Description: docs for foo
+";
+
+    #[test]
+    fn control_fields() {
+        let paragraph = package_paragraph(CONTROL, "foo").unwrap();
+        let fields = lowercase_fields(&paragraph);
+        assert_eq!("Jane Doe <jane@example.com>", fields["maintainer"]);
+        assert_eq!("utils", fields["section"]);
+        assert_eq!("${shlibs:Depends}, ${misc:Depends},\n libfoo1", fields["depends"]);
+        assert_eq!("doc", lowercase_fields(&package_paragraph(CONTROL, "foo-doc").unwrap())["section"]);
+        assert!(package_paragraph(CONTROL, "bar").is_none());
+        assert_eq!(vec![
+            ("Original-Maintainer".to_owned(), "Debian Foo Team <foo@example.org>".to_owned()),
+            ("Foo-Support".to_owned(), "yes".to_owned()),
+        ], binary_user_defined_fields(&paragraph));
+
+        let debian = DebianDir { fields, ..DebianDir::default() };
+        let (short, long) = debian.description();
+        assert_eq!(Some("does foo"), short.as_deref());
+        assert_eq!(Some("The foo tool does foo.\n\nAnd more."), long.as_deref());
+    }
+
+    #[test]
+    fn install_lines() {
+        let install = parse_install("target/release/foo usr/bin\n# comment\n\nconf/a.conf conf/b.conf etc/foo/\nusr/share/foo/data\n");
+        assert_eq!(vec![
+            (vec!["target/release/foo".to_owned()], "usr/bin".to_owned()),
+            (vec!["conf/a.conf".to_owned(), "conf/b.conf".to_owned()], "etc/foo/".to_owned()),
+            (vec!["usr/share/foo/data".to_owned()], "usr/share/foo".to_owned()),
+        ], install);
+    }
+
+    #[test]
+    fn debconf_config_is_a_script() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("control"), CONTROL).unwrap();
+        assert!(!DebianDir::read(dir.path(), "foo").unwrap().has_scripts);
+        fs::write(dir.path().join("foo.config"), "#!/bin/sh\n").unwrap();
+        assert!(DebianDir::read(dir.path(), "foo").unwrap().has_scripts);
+    }
+}
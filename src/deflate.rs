@@ -0,0 +1,215 @@
+//! Fast DEFLATE encoder, for when zopfli would take too long.
+//!
+//! It uses greedy matching and the fixed Huffman codes, so it compresses worse than `gzip -1`, but it's quick.
+//! See [RFC 1951](https://www.rfc-editor.org/rfc/rfc1951) and [RFC 1952](https://www.rfc-editor.org/rfc/rfc1952).
+use crate::inflate::{DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried
+const MAX_CHAIN: usize = 8;
+
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    /// Writes the `count` low bits of `value`, least significant first
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buf |= u64::from(value) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored most significant bit first
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.out.push(self.bit_buf as u8);
+        }
+        self.out
+    }
+}
+
+/// The fixed literal/length code from section 3.2.6 of RFC 1951
+fn literal_length(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.code(0x30 + symbol, 8),
+        144..=255 => w.code(0x190 + symbol - 144, 9),
+        256..=279 => w.code(symbol - 256, 7),
+        _ => w.code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn length(w: &mut BitWriter, len: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= len).unwrap_or(0);
+    literal_length(w, 257 + index as u32);
+    w.bits((len - usize::from(LENGTH_BASE[index])) as u32, u32::from(LENGTH_EXTRA[index]));
+}
+
+fn distance(w: &mut BitWriter, dist: usize) {
+    let index = DIST_BASE.iter().rposition(|&base| usize::from(base) <= dist).unwrap_or(0);
+    w.code(index as u32, 5);
+    w.bits((dist - usize::from(DIST_BASE[index])) as u32, u32::from(DIST_EXTRA[index]));
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = u32::from(data[0]) << 16 | u32::from(data[1]) << 8 | u32::from(data[2]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Largest amount of data in one stored block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Data that doesn't compress, such as already-compressed package members, is stored as-is,
+/// so that it grows by only 5 bytes per block instead of up to 1/8th with the fixed codes.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = deflate_fixed(data);
+    let stored_len = data.len() + 5 * data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    if compressed.len() <= stored_len {
+        return compressed;
+    }
+    deflate_stored(data)
+}
+
+/// Stored blocks from section 3.2.4 of RFC 1951
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5 * data.len().div_ceil(MAX_STORED_BLOCK).max(1));
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        // BFINAL and BTYPE 00, padded to the byte boundary
+        out.push(u8::from(chunks.peek().is_none()));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter { out: Vec::with_capacity(data.len() / 2), bit_buf: 0, bit_count: 0 };
+    // A single final block with fixed codes
+    w.bits(1, 1);
+    w.bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |pos: usize, head: &mut [usize], prev: &mut [usize]| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            prev[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(&data[pos..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW_SIZE || candidate >= pos {
+                    break;
+                }
+                let len = data[candidate..candidate + max_len].iter().zip(&data[pos..pos + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = pos - candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+                // `prev` links to the older position with the same hash
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            length(&mut w, best_len);
+            distance(&mut w, best_dist);
+            for p in pos..pos + best_len {
+                insert(p, &mut head, &mut prev);
+            }
+            pos += best_len;
+        } else {
+            literal_length(&mut w, u32::from(data[pos]));
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    literal_length(&mut w, 256);
+    w.finish()
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    !data.iter().fold(!0u32, |crc, &b| table[((crc ^ u32::from(b)) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Gzip-compresses the data quickly
+pub(crate) fn gzip_fast(data: &[u8]) -> Vec<u8> {
+    // no timestamp, "fastest" flag, unknown OS
+    let mut out = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 4, 255];
+    out.extend(deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+#[test]
+fn gzip_fast_roundtrip() {
+    let mut data = b"hello hello hello, this is a test of the encoder. ".repeat(2000);
+    data.extend((0..=255u8).cycle().take(100_000));
+    data.extend_from_slice(&[b'x'; 1000]);
+    for input in [&data[..], b"", b"ab", b"abcabcabc"] {
+        let compressed = gzip_fast(input);
+        assert_eq!(input, &crate::inflate::gunzip(&compressed).unwrap()[..]);
+    }
+    assert!(gzip_fast(&data).len() < data.len() / 4);
+    assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+}
+
+#[test]
+fn gzip_fast_of_random_data() {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let data: Vec<u8> = (0..200_000).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 32) as u8
+    }).collect();
+    for len in [0, 1, 0xFFFF, 0x10000, data.len()] {
+        let compressed = gzip_fast(&data[..len]);
+        assert_eq!(&data[..len], &crate::inflate::gunzip(&compressed).unwrap()[..]);
+        // gzip header and trailer, and 5 bytes per stored block
+        assert!(compressed.len() <= 18 + len + 5 * len.div_ceil(0xFFFF).max(1), "{len} -> {}", compressed.len());
+    }
+}
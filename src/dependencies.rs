@@ -1,10 +1,22 @@
 use crate::error::*;
+use std::collections::BTreeMap;
 use std::io::BufRead;
 use std::path::Path;
 use std::process::Command;
 
+/// Dependencies of a binary, as found by dpkg-shlibdeps
+pub(crate) struct ResolvedDependencies {
+    pub depends: Vec<String>,
+    /// Names of packages providing each shared library the binary links to
+    pub library_packages: BTreeMap<String, String>,
+}
+
 /// Resolves the dependencies based on the output of dpkg-shlibdeps on the binary.
 pub fn resolve(path: &Path) -> CDResult<Vec<String>> {
+    Ok(resolve_with_libraries(path)?.depends)
+}
+
+pub(crate) fn resolve_with_libraries(path: &Path) -> CDResult<ResolvedDependencies> {
     let temp_folder = tempfile::tempdir()?;
     let debian_folder = temp_folder.path().join("debian");
     let control_file_path = debian_folder.join("control");
@@ -19,6 +31,7 @@ pub fn resolve(path: &Path) -> CDResult<Vec<String>> {
     const DPKG_SHLIBDEPS_COMMAND: &str = "dpkg-shlibdeps";
     let output = Command::new(DPKG_SHLIBDEPS_COMMAND)
         .arg("-O") // Print result to stdout instead of a file.
+        .arg("-v") // Print which libraries were used
         .arg(path)
         .current_dir(temp_folder.path())
         .output()
@@ -31,8 +44,8 @@ pub fn resolve(path: &Path) -> CDResult<Vec<String>> {
         ));
     }
 
-    let deps = output.stdout.lines()
-        .filter_map(|line| line.ok())
+    let depends = output.stdout.lines()
+        .map_while(Result::ok)
         .find(|line| line.starts_with("shlibs:Depends="))
         .ok_or(CargoDebError::Str("Failed to find dependency specification."))?
         .trim_start_matches("shlibs:Depends=")
@@ -41,7 +54,48 @@ pub fn resolve(path: &Path) -> CDResult<Vec<String>> {
         .filter(|dep| !dep.starts_with("libgcc-")) // libgcc guaranteed by LSB to always be present
         .collect();
 
-    Ok(deps)
+    Ok(ResolvedDependencies {
+        depends,
+        // with -O, the debug output goes to stdout too
+        library_packages: library_packages(&String::from_utf8_lossy(&output.stdout)),
+    })
+}
+
+/// Parses debug output of `dpkg-shlibdeps -v`, which says which symbols or shlibs files were used for each library
+fn library_packages(debug_output: &str) -> BTreeMap<String, String> {
+    let mut packages = BTreeMap::new();
+    let mut unknown_libraries = Vec::new();
+    for line in debug_output.lines() {
+        let line = line.trim_start_matches("dpkg-shlibdeps: debug: ");
+        // e.g. "Using symbols file /var/lib/dpkg/info/libc6:amd64.symbols for libc.so.6"
+        if let Some((symbols_file, soname)) = line.strip_prefix("Using symbols file ").and_then(|l| l.rsplit_once(" for ")) {
+            let file_name = Path::new(symbols_file).file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+            let package = file_name.split(':').next().unwrap_or_default();
+            if !package.is_empty() && package != "symbols" {
+                packages.insert(soname.to_owned(), package.to_owned());
+            }
+        // e.g. "Using shlibs+objdump for libfoo.so.1 (file /usr/lib/libfoo.so.1)"
+        } else if let Some((soname, file)) = line.strip_prefix("Using shlibs+objdump for ").and_then(|l| l.split_once(" (file ")) {
+            unknown_libraries.push((soname.to_owned(), file.trim_end_matches(')').to_owned()));
+        }
+    }
+    unknown_libraries.retain(|(soname, _)| !packages.contains_key(soname));
+    if !unknown_libraries.is_empty() {
+        // Libraries without symbols files have to be looked up in the dpkg database
+        let output = Command::new("dpkg").arg("-S").args(unknown_libraries.iter().map(|(_, file)| file)).output();
+        if let Ok(output) = output {
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                // e.g. "libfoo1:amd64: /usr/lib/libfoo.so.1"
+                if let Some((package, file)) = line.rsplit_once(": ") {
+                    let package = package.split(':').next().unwrap_or_default();
+                    if let Some((soname, _)) = unknown_libraries.iter().find(|(_, f)| f == file) {
+                        packages.insert(soname.clone(), package.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    packages
 }
 
 #[test]
@@ -52,3 +106,17 @@ fn resolve_test() {
     assert!(deps.iter().any(|d| d.starts_with("libc")));
     assert!(!deps.iter().any(|d| d.starts_with("libgcc")));
 }
+
+#[test]
+fn library_packages_from_debug_output() {
+    let packages = library_packages("dpkg-shlibdeps: debug: >> Scanning target/release/foo (for Depends field)
+dpkg-shlibdeps: debug: Library libc.so.6 found in /lib/x86_64-linux-gnu/libc.so.6
+dpkg-shlibdeps: debug: Using symbols file /var/lib/dpkg/info/libgcc-s1:amd64.symbols for libgcc_s.so.1
+dpkg-shlibdeps: debug: Using symbols file /var/lib/dpkg/info/libc6:amd64.symbols for libc.so.6
+dpkg-shlibdeps: debug: Using symbols file /var/lib/dpkg/info/libc6:amd64.symbols for ld-linux-x86-64.so.2
+");
+    assert_eq!(3, packages.len());
+    assert_eq!("libc6", packages["libc.so.6"]);
+    assert_eq!("libc6", packages["ld-linux-x86-64.so.2"]);
+    assert_eq!("libgcc-s1", packages["libgcc_s.so.1"]);
+}
@@ -13,7 +13,7 @@
 /// <http://manpages.ubuntu.com/manpages/focal/en/man1/dh_installsystemd.1.html>
 use itertools::Itertools; // for .next_tuple()
 
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str;
@@ -21,8 +21,10 @@ use std::str;
 use crate::dh_lib::*;
 use crate::listener::Listener;
 use crate::manifest::Asset;
+use crate::vfs::FileSystem;
 use crate::util::*;
-use crate::CDResult;
+use crate::error::{CDResult, CargoDebError};
+use serde::{Deserialize, Serialize};
 
 /// From man 1 dh_installsystemd on Ubuntu 20.04 LTS. See:
 ///   <http://manpages.ubuntu.com/manpages/focal/en/man1/dh_installsystemd.1.html>
@@ -40,6 +42,8 @@ use crate::CDResult;
 ///            package build directory. Note that the "tmpfiles.d" mechanism is
 ///            currently only used by systemd.
 const LIB_SYSTEMD_SYSTEM_DIR: &str = "lib/systemd/system/";
+/// Where units end up with `usr-merge`
+const USR_LIB_SYSTEMD_SYSTEM_DIR: &str = "usr/lib/systemd/system/";
 const USR_LIB_TMPFILES_D_DIR: &str = "usr/lib/tmpfiles.d/";
 const SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS: [(&str, &str, &str); 12] = [
     ("",  "mount",   LIB_SYSTEMD_SYSTEM_DIR),
@@ -117,12 +121,76 @@ pub type PackageUnitFiles = HashMap<PathBuf, InstallRecipe>;
 /// >
 /// > Note: dh_installsystemd will still install unit files from debian/ but it will not
 /// > generate any maintscripts for them unless they are explicitly listed in unit file ...
+///
+/// # Cargo Deb specific options
+///
+/// `preset` ships a systemd preset file for the units, and applies the presets on the first
+/// installation instead of enabling the units. `mask` units are stopped and masked on installation,
+/// and unmasked on removal. `no_stop_on_remove` leaves the units running when the package is removed.
+///
+/// `units` overrides the options for individual units, by their file name.
 #[derive(Default, Debug)]
 pub struct Options {
     pub no_enable: bool,
     pub no_start: bool,
     pub restart_after_upgrade: bool,
     pub no_stop_on_upgrade: bool,
+    pub no_stop_on_remove: bool,
+    pub preset: Option<Preset>,
+    pub mask: Vec<String>,
+    pub units: BTreeMap<String, UnitOptions>,
+}
+
+/// The per-unit subset of `Options`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnitOptions {
+    pub no_enable: bool,
+    pub no_start: bool,
+    pub restart_after_upgrade: bool,
+    pub no_stop_on_upgrade: bool,
+    pub no_stop_on_remove: bool,
+}
+
+impl Options {
+    fn for_unit(&self, unit: &str) -> UnitOptions {
+        self.units.get(unit).copied().unwrap_or(UnitOptions {
+            no_enable: self.no_enable,
+            no_start: self.no_start,
+            restart_after_upgrade: self.restart_after_upgrade,
+            no_stop_on_upgrade: self.no_stop_on_upgrade,
+            no_stop_on_remove: self.no_stop_on_remove,
+        })
+    }
+}
+
+/// Default state of the units in the preset file.
+///
+/// See <https://www.freedesktop.org/software/systemd/man/systemd.preset.html>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Enable,
+    Disable,
+}
+
+const SYSTEMD_PRESET_DIR: &str = "usr/lib/systemd/system-preset/";
+
+/// A preset file with the given default for all non-template units in the assets
+pub fn preset_file(package: &str, assets: &[Asset], preset: Preset) -> Option<(PathBuf, Vec<u8>)> {
+    let action = match preset {
+        Preset::Enable => "enable",
+        Preset::Disable => "disable",
+    };
+    let units: BTreeSet<_> = assets.iter()
+        .filter(|v| is_unit_path(&v.target_path))
+        .map(|v| fname_from_path(v.target_path.as_path()))
+        .filter(|fname| !fname.contains('@'))
+        .collect();
+    if units.is_empty() {
+        return None;
+    }
+    let content = units.iter().map(|unit| format!("{} {}\n", action, unit)).collect::<String>();
+    Some((Path::new(SYSTEMD_PRESET_DIR).join(format!("90-{}.preset", package)), content.into_bytes()))
 }
 
 /// Find installable systemd unit files for the specified debian package (and
@@ -174,15 +242,18 @@ pub fn find_units(dir: &Path, main_package: &str, unit_name: Option<&str>) -> Pa
     installables
 }
 
+/// The unit is installed directly in the systemd system unit directory
+fn is_unit_path(path: &Path) -> bool {
+    [LIB_SYSTEMD_SYSTEM_DIR, USR_LIB_SYSTEMD_SYSTEM_DIR].iter()
+        .any(|dir| path.parent() == Some(Path::new(dir.trim_end_matches('/'))))
+}
+
 /// Determine if the given string is a systemd unit file comment line.
 ///
 /// See:
 ///   <https://www.freedesktop.org/software/systemd/man/systemd.syntax.html#Introduction>
 fn is_comment(s: &str) -> bool {
-    match s.chars().next() {
-        Some('#') | Some(';') => true,
-        _ => false,
-    }
+    matches!(s.chars().next(), Some('#') | Some(';'))
 }
 
 /// Strip off any first layer of outer quotes according to systemd quoting
@@ -210,7 +281,7 @@ fn unquote(s: &str) -> &str {
 ///
 /// Any `Asset`, whether identified by `find_units()` or added by the user
 /// manually in Cargo.toml, that will be installed into `LIB_SYSTEMD_SYSTEM_DIR`
-/// will be analysed.
+/// (or `USR_LIB_SYSTEMD_SYSTEM_DIR`) will be analysed.
 ///
 /// Unlike `dh_installsystemd` results are returned as a `ScriptFragments` value
 /// rather than being written to temporary files on disk.
@@ -221,7 +292,7 @@ fn unquote(s: &str) -> &str {
 ///
 /// See:
 ///   <https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n288>
-pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &mut dyn Listener) -> CDResult<ScriptFragments> {
+pub fn generate(package: &str, assets: &[Asset], options: &Options, fs: &dyn FileSystem, listener: &mut dyn Listener) -> CDResult<ScriptFragments> {
     let mut scripts = ScriptFragments::new();
 
     // add postinst code blocks to handle tmpfiles
@@ -249,7 +320,7 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
     installed_non_template_units.extend(
         assets
             .iter()
-            .filter(|v| v.target_path.starts_with(LIB_SYSTEMD_SYSTEM_DIR))
+            .filter(|v| is_unit_path(&v.target_path))
             .map(|v| fname_from_path(v.target_path.as_path()))
             .filter(|fname| !fname.contains('@')),
     );
@@ -281,8 +352,10 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
             start_units.insert(unit.clone());
 
             // get the unit file contents
-            let needle = Path::new(LIB_SYSTEMD_SYSTEM_DIR).join(unit);
-            let data = assets.iter().find(|&item| item.target_path == needle).unwrap().source.data()?;
+            let data = assets.iter()
+                .find(|&item| is_unit_path(&item.target_path) && item.target_path.file_name() == Some(unit.as_ref()))
+                .ok_or_else(|| CargoDebError::SystemdUnitNotInPackage(unit.clone()))?
+                .source.data(fs)?;
             let reader = data.into_owned();
 
             // for every line in the file look for specific keys that we are
@@ -309,19 +382,17 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
                     .next_tuple();
                 if let Some((key, value)) = possible_kv_pair {
                     let other_unit = unquote(value).to_string();
-                    match &key[..] {
-                        "Also" => {
-                            // The seen lookup prevents us from looping forever over
-                            // unit files that refer to each other. An actual
-                            // real-world example of such a loop is systemd's
-                            // systemd-readahead-drop.service, which contains
-                            // Also=systemd-readahead-collect.service, and that file
-                            // in turn contains Also=systemd-readahead-drop.service,
-                            // thus forming an endless loop.
-                            // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n340
-                            if seen.insert(other_unit.clone()) {
-                                also_units.insert(other_unit);
-                            }
+                    match key {
+                        // The seen lookup prevents us from looping forever over
+                        // unit files that refer to each other. An actual
+                        // real-world example of such a loop is systemd's
+                        // systemd-readahead-drop.service, which contains
+                        // Also=systemd-readahead-collect.service, and that file
+                        // in turn contains Also=systemd-readahead-drop.service,
+                        // thus forming an endless loop.
+                        // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n340
+                        "Also" if seen.insert(other_unit.clone()) => {
+                            also_units.insert(other_unit);
                         },
                         "Alias" => {
                             aliases.insert(other_unit);
@@ -340,14 +411,19 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
     // options passed to us.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n390
     if !enable_units.is_empty() {
-        let snippet = match options.no_enable {
-            true => "postinst-systemd-dont-enable",
-            false => "postinst-systemd-enable",
-        };
         for unit in &enable_units {
+            // the preset decides whether units are enabled
+            let snippet = match options.for_unit(unit).no_enable || options.preset.is_some() {
+                true => "postinst-systemd-dont-enable",
+                false => "postinst-systemd-enable",
+            };
             autoscript(&mut scripts, package, "postinst", snippet,
                 &map!{ "UNITFILE" => unit.clone() }, true, listener)?;
         }
+        if options.preset.is_some() {
+            autoscript(&mut scripts, package, "postinst", "postinst-systemd-preset",
+                &map!{ "UNITFILES" => enable_units.join(" ") }, true, listener)?;
+        }
         autoscript(&mut scripts, package, "postrm", "postrm-systemd",
             &map!{ "UNITFILES" => enable_units.join(" ") }, false, listener)?;
     }
@@ -355,8 +431,14 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
     // update the maintainer scripts to start units, where the exact action to
     // be taken is influenced by the options passed to us.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n398
-    if !start_units.is_empty() {
-        let mut replace = map! { "UNITFILES" => start_units.join(" ") };
+    //
+    // Units with the same options share the snippets.
+    let mut units_by_options = BTreeMap::<UnitOptions, Vec<&str>>::new();
+    for unit in &start_units {
+        units_by_options.entry(options.for_unit(unit)).or_default().push(unit);
+    }
+    for (options, units) in &units_by_options {
+        let mut replace = map! { "UNITFILES" => units.join(" ") };
 
         if options.restart_after_upgrade {
             let snippet;
@@ -376,19 +458,31 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &m
             autoscript(&mut scripts, package, "postinst", "postinst-systemd-start", &replace, true, listener)?;
         }
 
-        if options.no_stop_on_upgrade || options.restart_after_upgrade {
+        if options.no_stop_on_remove {
+            // the service keeps running
+        } else if options.no_stop_on_upgrade || options.restart_after_upgrade {
             // stop service only on remove
             autoscript(&mut scripts, package, "prerm", "prerm-systemd-restart", &replace, true, listener)?;
         } else if !options.no_start {
             // always stop service
             autoscript(&mut scripts, package, "prerm", "prerm-systemd", &replace, true, listener)?;
         }
+    }
 
+    if !start_units.is_empty() {
         // Run this with "default" order so it is always after other service
         // related autosnippets.
+        let replace = map! { "UNITFILES" => start_units.join(" ") };
 		autoscript(&mut scripts, package, "postrm", "postrm-systemd-reload-only", &replace, false, listener)?;
     }
 
+    // conflicting units of other packages
+    if !options.mask.is_empty() {
+        let replace = map! { "UNITFILES" => options.mask.join(" ") };
+        autoscript(&mut scripts, package, "postinst", "postinst-systemd-mask", &replace, false, listener)?;
+        autoscript(&mut scripts, package, "postrm", "postrm-systemd-unmask", &replace, false, listener)?;
+    }
+
     Ok(scripts)
 }
 
@@ -556,7 +650,7 @@ mod tests {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(0).return_const(());
 
-        let fragments = generate("", &vec![], &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("", &[], &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
 
         assert!(fragments.is_empty());
     }
@@ -573,7 +667,7 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert!(fragments.is_empty());
     }
 
@@ -590,7 +684,7 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert!(fragments.is_empty());
     }
 
@@ -607,7 +701,7 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert!(fragments.is_empty());
     }
 
@@ -626,7 +720,7 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert_eq!(1, fragments.len());
 
         let (fragment_name, fragment_bytes) = fragments.into_iter().next().unwrap();
@@ -647,7 +741,7 @@ mod tests {
 
         // Verify the content of the added comment lines
         let mut lines = created_text.lines();
-        assert!(lines.nth(0).unwrap().starts_with("# Automatically added by"));
+        assert!(lines.next().unwrap().starts_with("# Automatically added by"));
         assert_eq!(lines.nth_back(0).unwrap(), "# End automatically added section");
 
         // Check that the autoscript fragment lines were properly copied
@@ -676,10 +770,68 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert_eq!(0, fragments.len());
     }
 
+    #[test]
+    fn generate_preset_and_mask() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let assets = vec![
+            Asset::new(AssetSource::Data(b"[Service]\nExecStart=/usr/bin/mypkg\n[Install]\nWantedBy=multi-user.target\n".to_vec()),
+                PathBuf::from("usr/lib/systemd/system/mypkg.service"), 0o644, false),
+            Asset::new(AssetSource::Data(Vec::new()), PathBuf::from("lib/systemd/system/mypkg@.service"), 0o644, false),
+        ];
+        let options = Options {
+            preset: Some(Preset::Disable),
+            mask: vec!["other.service".into()],
+            ..Options::default()
+        };
+        let fragments = generate("mypkg", &assets, &options, &crate::vfs::RealFs, &mut mock_listener).unwrap();
+        let postinst = std::str::from_utf8(&fragments["mypkg.postinst.service"]).unwrap();
+        assert!(postinst.contains("systemctl --system preset mypkg.service"));
+        assert!(std::str::from_utf8(&fragments["mypkg.postinst.debhelper"]).unwrap().contains("deb-systemd-helper mask other.service"));
+        assert!(std::str::from_utf8(&fragments["mypkg.postrm.debhelper"]).unwrap().contains("deb-systemd-helper unmask other.service"));
+
+        let (path, content) = preset_file("mypkg", &assets, Preset::Disable).unwrap();
+        assert_eq!(Path::new("usr/lib/systemd/system-preset/90-mypkg.preset"), path);
+        assert_eq!(b"disable mypkg.service\n", &content[..]);
+    }
+
+    #[test]
+    fn generate_per_unit_options() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let unit = b"[Service]\nExecStart=/usr/bin/mypkg\n[Install]\nWantedBy=multi-user.target\n";
+        let assets = vec![
+            Asset::new(AssetSource::Data(unit.to_vec()), PathBuf::from("lib/systemd/system/mypkg.service"), 0o644, false),
+            Asset::new(AssetSource::Data(unit.to_vec()), PathBuf::from("lib/systemd/system/mypkg-worker.service"), 0o644, false),
+        ];
+        let mut options = Options { restart_after_upgrade: true, ..Options::default() };
+        options.units.insert("mypkg-worker.service".into(), UnitOptions { no_enable: true, no_start: true, restart_after_upgrade: true, no_stop_on_remove: true, ..UnitOptions::default() });
+        let fragments = generate("mypkg", &assets, &options, &crate::vfs::RealFs, &mut mock_listener).unwrap();
+        let postinst = std::str::from_utf8(&fragments["mypkg.postinst.service"]).unwrap();
+        assert!(postinst.contains("deb-systemd-helper update-state mypkg-worker.service"), "{}", postinst);
+        assert!(postinst.contains("$_dh_action mypkg.service "), "{}", postinst);
+        assert!(postinst.contains(" try-restart mypkg-worker.service"), "{}", postinst);
+        let prerm = std::str::from_utf8(&fragments["mypkg.prerm.service"]).unwrap();
+        assert!(prerm.contains("mypkg.service") && !prerm.contains("mypkg-worker.service"), "{}", prerm);
+    }
+
+    #[test]
+    fn generate_fails_for_also_units_that_are_not_installed() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let unit = b"[Unit]\nDescription=mypkg\n[Install]\nAlso=mypkg-helper.socket\n";
+        let assets = vec![Asset::new(AssetSource::Data(unit.to_vec()), PathBuf::from("lib/systemd/system/mypkg.service"), 0o644, false)];
+        let err = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap_err();
+        assert!(matches!(&err, CargoDebError::SystemdUnitNotInPackage(unit) if unit == "mypkg-helper.socket"), "{}", err);
+    }
+
     #[test]
     fn generate_acts_only_on_unit_files_with_the_expected_install_path() {
         // Note: find_units() will set the target path correctly.
@@ -693,7 +845,7 @@ mod tests {
             false,
         )];
 
-        let fragments = generate("mypkg", &assets, &Options::default(), &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &Options::default(), &crate::vfs::RealFs, &mut mock_listener).unwrap();
         assert_eq!(0, fragments.len());
     }
 
@@ -732,6 +884,9 @@ mod tests {
       case("lss", true,  true,  true,  false,  true),
       case("lss", true,  true,  true,  true,  false),
       case("lss", true,  true,  true,  true,  true),
+
+      case("ulss", false, false, false, false, false),
+      case("ulss", true,  false, true,  false, false),
     )]
     #[test]
     fn generate_creates_expected_autoscript_fragments(
@@ -747,6 +902,7 @@ mod tests {
         let install_base_path = match ip {
             "ult" => "usr/lib/tmpfiles.d",
             "lss" => "lib/systemd/system",
+            "ulss" => "usr/lib/systemd/system",
             x => panic!("Unsupported install path value '{}'", x),
         };
 
@@ -763,6 +919,7 @@ mod tests {
             no_start: ns,
             restart_after_upgrade: rau,
             no_stop_on_upgrade: nsou,
+            ..Options::default()
         };
 
         // setup mocks
@@ -802,7 +959,7 @@ WantedBy=multi-user.target");
         ]);
 
         // generate!
-        let fragments = generate("mypkg", &assets, &options, &mut mock_listener).unwrap();
+        let fragments = generate("mypkg", &assets, &options, &crate::vfs::RealFs, &mut mock_listener).unwrap();
 
         // verify, though don't verify creation of autoscript fragments as that
         // is verified in tests of the lower level functionality, instead verify
@@ -818,6 +975,7 @@ WantedBy=multi-user.target");
         // key:
         //   - ip    - install path
         //     - lss - lib/systemd/system/
+        //     - ulss - usr/lib/systemd/system/ (same as lss)
         //     - ult - usr/lib/tmpfiles.d/
         //   - [I]   - has an [Install] section in the unit file
         //   - ne    - the value of the boolean no_enable option
@@ -851,7 +1009,7 @@ WantedBy=multi-user.target");
                 assert_eq!(1, get_read_count("postinst-init-tmpfiles"));
                 autoscript_fragments_to_check_for.insert("postinst.debhelper");
             },
-            "lss" => {
+            "lss" | "ulss" => {
                 assert_eq!(1, get_read_count(unit_file_path));
                 if inst {
                     match options.no_enable {
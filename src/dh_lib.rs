@@ -18,6 +18,7 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use crate::error::*;
+use crate::manifest::GeneratedScripts;
 use crate::util::{is_path_file, read_file_to_string};
 use crate::{listener::Listener, CDResult};
 
@@ -26,16 +27,21 @@ use crate::{listener::Listener, CDResult};
 ///   https://git.launchpad.net/ubuntu/+source/debhelper/tree/autoscripts?h=applied/12.10ubuntu1
 /// To understand which scripts are invoked when, consult:
 ///   https://www.debian.org/doc/debian-policy/ap-flowcharts.htm
-
-static AUTOSCRIPTS: [(&str, &[u8]); 10] = [
+static AUTOSCRIPTS: [(&str, &[u8]); 16] = [
     ("postinst-init-tmpfiles", include_bytes!("../autoscripts/postinst-init-tmpfiles")),
+    ("postinst-statoverride", include_bytes!("../autoscripts/postinst-statoverride")),
     ("postinst-systemd-dont-enable", include_bytes!("../autoscripts/postinst-systemd-dont-enable")),
     ("postinst-systemd-enable", include_bytes!("../autoscripts/postinst-systemd-enable")),
+    ("postinst-systemd-mask", include_bytes!("../autoscripts/postinst-systemd-mask")),
+    ("postinst-systemd-preset", include_bytes!("../autoscripts/postinst-systemd-preset")),
     ("postinst-systemd-restart", include_bytes!("../autoscripts/postinst-systemd-restart")),
     ("postinst-systemd-restartnostart", include_bytes!("../autoscripts/postinst-systemd-restartnostart")),
     ("postinst-systemd-start", include_bytes!("../autoscripts/postinst-systemd-start")),
+    ("postrm-purge", include_bytes!("../autoscripts/postrm-purge")),
+    ("postrm-statoverride", include_bytes!("../autoscripts/postrm-statoverride")),
     ("postrm-systemd", include_bytes!("../autoscripts/postrm-systemd")),
     ("postrm-systemd-reload-only", include_bytes!("../autoscripts/postrm-systemd-reload-only")),
+    ("postrm-systemd-unmask", include_bytes!("../autoscripts/postrm-systemd-unmask")),
     ("prerm-systemd", include_bytes!("../autoscripts/prerm-systemd")),
     ("prerm-systemd-restart", include_bytes!("../autoscripts/prerm-systemd-restart")),
 ];
@@ -98,13 +104,7 @@ pub(crate) fn pkgfile(dir: &Path, main_package: &str, package: &str, filename: &
         paths_to_try.push(dir.join(filename));
     }
 
-    for path_to_try in paths_to_try {
-        if is_path_file(&path_to_try) {
-            return Some(path_to_try);
-        }
-    }
-
-    None
+    paths_to_try.into_iter().find(|path_to_try| is_path_file(path_to_try))
 }
 
 /// Get the bytes for the specified filename whose contents were embedded in our
@@ -125,10 +125,10 @@ pub(crate) fn get_embedded_autoscript(snippet_filename: &str) -> String {
     // else load from embedded strings
     let mut snippet = snippet.unwrap_or_else(|| {
         let (_, snippet_bytes) = AUTOSCRIPTS.iter().find(|(s, _)| *s == snippet_filename)
-            .expect(&format!("Unknown autoscript '{}'", snippet_filename));
+            .unwrap_or_else(|| panic!("Unknown autoscript '{}'", snippet_filename));
 
         // convert to string
-        String::from_utf8_lossy(&snippet_bytes).into_owned()
+        String::from_utf8_lossy(snippet_bytes).into_owned()
     });
 
     // normalize
@@ -248,10 +248,10 @@ fn autoscript_sed(snippet_filename: &str, replacements: &HashMap<&str, String>)
 /// # References
 ///
 /// <https://git.launchpad.net/ubuntu/+source/debhelper/tree/lib/Debian/Debhelper/Dh_Lib.pm?h=applied/12.10ubuntu1#n2161>
-fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
-    listener: &mut dyn Listener) -> CDResult<()>
+fn debhelper_script_subst(user_scripts_dir: Option<&Path>, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
+    options: &GeneratedScripts, listener: &mut dyn Listener) -> CDResult<()>
 {
-    let user_file = pkgfile(user_scripts_dir, package, package, script, unit_name);
+    let user_file = user_scripts_dir.and_then(|dir| pkgfile(dir, package, package, script, unit_name));
     let mut generated_scripts: Vec<String> = vec![
         format!("{}.{}.debhelper", package, script),
         format!("{}.{}.service", package, script),
@@ -268,6 +268,14 @@ fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments
             generated_text.push_str(std::str::from_utf8(contents)?);
         }
     }
+    if !generated_text.is_empty() {
+        if let Some(prologue) = options.prologue.get(script) {
+            generated_text.insert_str(0, &with_newline(prologue));
+        }
+        if let Some(epilogue) = options.epilogue.get(script) {
+            generated_text.push_str(&with_newline(epilogue));
+        }
+    }
 
     if let Some(user_file_path) = user_file {
         listener.info(format!("Augmenting maintainer script {}", user_file_path.display()));
@@ -286,8 +294,10 @@ fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments
 
         // give it a shebang header and rename it
         let mut new_text = String::new();
-        new_text.push_str("#!/bin/sh\n");
-        new_text.push_str("set -e\n");
+        new_text.push_str(&format!("#!{}\n", options.shell));
+        if options.set_e {
+            new_text.push_str("set -e\n");
+        }
         new_text.push_str(&generated_text);
 
         scripts.insert(script.into(), new_text.into());
@@ -296,18 +306,22 @@ fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments
     Ok(())
 }
 
+fn with_newline(code: &str) -> String {
+    if code.ends_with('\n') { code.to_owned() } else { format!("{}\n", code) }
+}
+
 /// Generate final maintainer scripts by merging the autoscripts that have been
 /// collected in the `ScriptFragments` map  with the maintainer scripts
-/// on disk supplied by the user.
+/// on disk supplied by the user, if there's a `maintainer-scripts` directory.
 ///
 /// See: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installdeb?h=applied/12.10ubuntu1#n300
-pub(crate) fn apply(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>,
-    listener: &mut dyn Listener) -> CDResult<()>
+pub(crate) fn apply(user_scripts_dir: Option<&Path>, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>,
+    options: &GeneratedScripts, listener: &mut dyn Listener) -> CDResult<()>
 {
     for script in &["postinst", "preinst", "prerm", "postrm"] {
         // note: we don't support custom defines thus we don't have the final
         // 'package_subst' argument to debhelper_script_subst().
-        debhelper_script_subst(user_scripts_dir, scripts, package, script, unit_name, listener)?;
+        debhelper_script_subst(user_scripts_dir, scripts, package, script, unit_name, options, listener)?;
     }
 
     Ok(())
@@ -422,10 +436,10 @@ mod tests {
     fn autoscript_test_wrapper(pkg: &str, script: &str, snippet: &str, unit: &str, scripts: Option<ScriptFragments>) -> ScriptFragments {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(1).return_const(());
-        let mut scripts = scripts.unwrap_or(ScriptFragments::new());
+        let mut scripts = scripts.unwrap_or_default();
         let replacements = map! { "UNITFILES" => unit.to_owned() };
         autoscript(&mut scripts, pkg, script, snippet, &replacements, false, &mut mock_listener).unwrap();
-        return scripts;
+        scripts
     }
 
     #[test]
@@ -454,13 +468,19 @@ mod tests {
 
         let expected_scripts = vec![
             "postinst-init-tmpfiles",
+            "postinst-statoverride",
             "postinst-systemd-dont-enable",
             "postinst-systemd-enable",
+            "postinst-systemd-mask",
+            "postinst-systemd-preset",
             "postinst-systemd-restart",
             "postinst-systemd-restartnostart",
             "postinst-systemd-start",
+            "postrm-purge",
+            "postrm-statoverride",
             "postrm-systemd",
             "postrm-systemd-reload-only",
+            "postrm-systemd-unmask",
             "prerm-systemd",
             "prerm-systemd-restart",
         ];
@@ -486,7 +506,7 @@ mod tests {
 
         // Populate an autoscript template and add the result to a
         // collection of scripts and return it to us.
-        let scripts = autoscript_test_wrapper("mypkg", maintainer_script, &autoscript_name, "dummyunit", None);
+        let scripts = autoscript_test_wrapper("mypkg", maintainer_script, autoscript_name, "dummyunit", None);
 
         // Expect autoscript() to have created one temporary script
         // fragment called <package>.<script>.debhelper.
@@ -511,7 +531,7 @@ mod tests {
 
         // Verify the content of the added comment lines
         let mut lines = created_text.lines();
-        assert!(lines.nth(0).unwrap().starts_with("# Automatically added by"));
+        assert!(lines.next().unwrap().starts_with("# Automatically added by"));
         assert_eq!(lines.nth_back(0).unwrap(), "# End automatically added section");
 
         // Check that the autoscript fragment lines were properly copied
@@ -529,7 +549,7 @@ mod tests {
         // populated but this time with the different value, and pass in
         // the existing set of created scripts to check how it gets
         // modified.
-        let scripts = autoscript_test_wrapper("mypkg", maintainer_script, &autoscript_name, "otherunit", Some(scripts));
+        let scripts = autoscript_test_wrapper("mypkg", maintainer_script, autoscript_name, "otherunit", Some(scripts));
 
         // The number and name of the output scripts should remain the same
         assert_eq!(1, scripts.len());
@@ -542,7 +562,7 @@ mod tests {
         assert_eq!((autoscript_line_count + 2) * 2, created_line_count);
 
         let mut lines = created_text.lines();
-        assert!(lines.nth(0).unwrap().starts_with("# Automatically added by"));
+        assert!(lines.next().unwrap().starts_with("# Automatically added by"));
         assert_eq!(lines.nth_back(0).unwrap(), "# End automatically added section");
 
         // The content should be different
@@ -601,7 +621,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
         assert_eq!(0, scripts.len());
     }
 
@@ -615,7 +635,7 @@ mod tests {
 
         let mut scripts = ScriptFragments::new();
 
-        match debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener) {
+        match debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener) {
             Ok(_) => (),
             Err(CargoDebError::DebHelperReplaceFailed(_)) => panic!("Test failed as expected"),
             Err(err) => panic!("Unexpected error {:?}", err),
@@ -633,7 +653,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
         assert_eq!(1, scripts.len());
         assert!(scripts.contains_key("myscript"));
     }
@@ -651,7 +671,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), "injected".as_bytes().to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -660,6 +680,26 @@ mod tests {
         assert_eq!(script_to_string(&scripts, "myscript"), "#!/bin/sh\nset -e\ninjected");
     }
 
+    #[test]
+    fn debhelper_script_subst_with_custom_shell_and_prologue() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let options = GeneratedScripts {
+            shell: "/bin/bash".into(),
+            set_e: false,
+            prologue: std::collections::BTreeMap::from([("postinst".to_owned(), "set -u".to_owned())]),
+            epilogue: std::collections::BTreeMap::from([("postinst".to_owned(), "exit 0\n".to_owned()), ("prerm".to_owned(), "exit 1".to_owned())]),
+        };
+        let mut scripts = ScriptFragments::new();
+        scripts.insert("mypkg.postinst.debhelper".to_owned(), b"injected\n".to_vec());
+        apply(None, &mut scripts, "mypkg", None, &options, &mut mock_listener).unwrap();
+
+        assert_eq!(script_to_string(&scripts, "postinst"), "#!/bin/bash\nset -u\ninjected\nexit 0\n");
+        // no fragments, so no script
+        assert!(!scripts.contains_key("prerm"));
+    }
+
     #[rstest]
     #[test]
     fn debhelper_script_subst_with_user_and_generated_file(valid_user_file: String) {
@@ -672,7 +712,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), "injected".as_bytes().to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -703,7 +743,7 @@ mod tests {
         scripts.insert(format!("mypkg.{}.service", maintainer_script), "second".as_bytes().to_vec());
 
         assert_eq!(2, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", maintainer_script, None, &mut mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", maintainer_script, None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
         assert_eq!(3, scripts.len());
         assert!(scripts.contains_key(&format!("mypkg.{}.debhelper", maintainer_script)));
         assert!(scripts.contains_key(&format!("mypkg.{}.service", maintainer_script)));
@@ -735,7 +775,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        let result = debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mut mock_listener);
+        let result = debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &GeneratedScripts::default(), &mut mock_listener);
 
         assert!(matches!(result, Err(CargoDebError::Io(_))));
         if let CargoDebError::Io(err) = result.unwrap_err() {
@@ -749,7 +789,7 @@ mod tests {
     fn apply_with_no_matching_files() {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(0).return_const(());
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mut mock_listener).unwrap();
+        apply(Some(Path::new("")), &mut ScriptFragments::new(), "mypkg", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
     }
 
     #[rstest]
@@ -764,6 +804,6 @@ mod tests {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(scripts.len()).return_const(());
 
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mut mock_listener).unwrap();
+        apply(Some(Path::new("")), &mut ScriptFragments::new(), "mypkg", None, &GeneratedScripts::default(), &mut mock_listener).unwrap();
     }
 }
@@ -0,0 +1,476 @@
+use crate::error::*;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::path::Path;
+
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOTE: u32 = 7;
+const SHT_DYNSYM: u32 = 11;
+const SHT_GNU_VERNEED: u32 = 0x6fff_fffe;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+const ET_DYN: u16 = 3;
+const DT_NEEDED: usize = 1;
+const DT_RPATH: usize = 15;
+const DT_RUNPATH: usize = 29;
+const DT_BIND_NOW: usize = 24;
+const DT_FLAGS: usize = 30;
+const DF_BIND_NOW: usize = 8;
+const DT_FLAGS_1: usize = 0x6fff_fffb;
+const DF_1_NOW: usize = 1;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// A `DT_RPATH` or `DT_RUNPATH` entry
+struct RunPath<'a> {
+    /// File offset of the entry in the dynamic section
+    entry: usize,
+    /// File offset of the string
+    string: usize,
+    value: &'a str,
+}
+
+/// Just enough of an ELF parser to read the symbol versions a binary needs
+struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+}
+
+struct Section {
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if !data.starts_with(b"\x7fELF") || data.len() < 0x40 {
+            return None;
+        }
+        let is_64 = match data[4] { 1 => false, 2 => true, _ => return None };
+        let little_endian = match data[5] { 1 => true, 2 => false, _ => return None };
+        Some(Self { data, is_64, little_endian })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes(offset)?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.bytes(offset)?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    /// A 32-bit or 64-bit word, depending on the ELF class
+    fn word(&self, offset: usize) -> Option<usize> {
+        if self.is_64 {
+            let b = self.bytes(offset)?;
+            usize::try_from(if self.little_endian { u64::from_le_bytes(b) } else { u64::from_be_bytes(b) }).ok()
+        } else {
+            usize::try_from(self.u32(offset)?).ok()
+        }
+    }
+
+    fn sections(&self) -> Option<Vec<Section>> {
+        let (table, entry_size, count) = if self.is_64 {
+            (self.word(0x28)?, self.u16(0x3A)?, self.u16(0x3C)?)
+        } else {
+            (self.word(0x20)?, self.u16(0x2E)?, self.u16(0x30)?)
+        };
+        (0..usize::from(count)).map(|i| {
+            let header = table.checked_add(i * usize::from(entry_size))?;
+            Some(if self.is_64 {
+                Section { kind: self.u32(header + 4)?, offset: self.word(header + 24)?, size: self.word(header + 32)?, link: self.u32(header + 40)? as usize }
+            } else {
+                Section { kind: self.u32(header + 4)?, offset: self.word(header + 16)?, size: self.word(header + 20)?, link: self.u32(header + 24)? as usize }
+            })
+        }).collect()
+    }
+
+    /// Whether the dynamic section lists any shared libraries
+    fn needs_libraries(&self) -> Option<bool> {
+        let (table, entry_size, count) = if self.is_64 {
+            (self.word(0x20)?, self.u16(0x36)?, self.u16(0x38)?)
+        } else {
+            (self.word(0x1C)?, self.u16(0x2A)?, self.u16(0x2C)?)
+        };
+        for i in 0..usize::from(count) {
+            let header = table.checked_add(i * usize::from(entry_size))?;
+            if self.u32(header)? != PT_DYNAMIC {
+                continue;
+            }
+            let (offset, size) = if self.is_64 {
+                (self.word(header + 8)?, self.word(header + 32)?)
+            } else {
+                (self.word(header + 4)?, self.word(header + 16)?)
+            };
+            let word_size = if self.is_64 { 8 } else { 4 };
+            for entry in (offset..offset.checked_add(size)?).step_by(2 * word_size) {
+                match self.word(entry)? {
+                    0 => break, // DT_NULL
+                    DT_NEEDED => return Some(true),
+                    _ => {},
+                }
+            }
+        }
+        Some(false)
+    }
+
+    /// `(type, offset, size)` of the program headers
+    fn segments(&self) -> Option<Vec<(u32, usize, usize)>> {
+        let (table, entry_size, count) = if self.is_64 {
+            (self.word(0x20)?, self.u16(0x36)?, self.u16(0x38)?)
+        } else {
+            (self.word(0x1C)?, self.u16(0x2A)?, self.u16(0x2C)?)
+        };
+        (0..usize::from(count)).map(|i| {
+            let header = table.checked_add(i * usize::from(entry_size))?;
+            Some(if self.is_64 {
+                (self.u32(header)?, self.word(header + 8)?, self.word(header + 32)?)
+            } else {
+                (self.u32(header)?, self.word(header + 4)?, self.word(header + 16)?)
+            })
+        }).collect()
+    }
+
+    /// The dynamic loader from `PT_INTERP`, e.g. `/lib64/ld-linux-x86-64.so.2`
+    fn interpreter(&self) -> Option<&'a str> {
+        let (_, offset, size) = self.segments()?.into_iter().find(|&(kind, ..)| kind == PT_INTERP)?;
+        let bytes = self.data.get(offset..offset.checked_add(size)?)?;
+        std::str::from_utf8(bytes.split(|&b| b == 0).next()?).ok()
+    }
+
+    /// `(tag, value)` pairs of the dynamic section
+    fn dynamic_entries(&self) -> Option<Vec<(usize, usize)>> {
+        let sections = self.sections()?;
+        let dynamic = match sections.iter().find(|s| s.kind == SHT_DYNAMIC) {
+            Some(dynamic) => dynamic,
+            None => return Some(Vec::new()),
+        };
+        let word_size = if self.is_64 { 8 } else { 4 };
+        (dynamic.offset..dynamic.offset.checked_add(dynamic.size)?).step_by(2 * word_size)
+            .map(|entry| Some((self.word(entry)?, self.word(entry + word_size)?)))
+            .take_while(|entry| entry.is_none_or(|(tag, _)| tag != 0))
+            .collect()
+    }
+
+    /// Names of the dynamic symbols, defined and undefined
+    fn dynamic_symbols(&self) -> Option<Vec<&'a str>> {
+        let sections = self.sections()?;
+        let mut names = Vec::new();
+        for symbols in sections.iter().filter(|s| s.kind == SHT_DYNSYM) {
+            let strings = sections.get(symbols.link)?;
+            let entry_size = if self.is_64 { 24 } else { 16 };
+            for entry in (symbols.offset..symbols.offset.checked_add(symbols.size)?).step_by(entry_size) {
+                names.push(self.string(strings, self.u32(entry)? as usize)?);
+            }
+        }
+        Some(names)
+    }
+
+    /// RPATH and RUNPATH entries, and the end of the dynamic section
+    fn runpaths(&self) -> Option<(Vec<RunPath<'a>>, usize)> {
+        let sections = self.sections()?;
+        let dynamic = match sections.iter().find(|s| s.kind == SHT_DYNAMIC) {
+            Some(dynamic) => dynamic,
+            None => return Some((Vec::new(), 0)),
+        };
+        let strings = sections.get(dynamic.link)?;
+        let word_size = if self.is_64 { 8 } else { 4 };
+        let end = dynamic.offset.checked_add(dynamic.size)?;
+        let mut runpaths = Vec::new();
+        for entry in (dynamic.offset..end).step_by(2 * word_size) {
+            match self.word(entry)? {
+                0 => break,
+                DT_RPATH | DT_RUNPATH => {
+                    let index = self.word(entry + word_size)?;
+                    runpaths.push(RunPath { entry, string: strings.offset.checked_add(index)?, value: self.string(strings, index)? });
+                },
+                _ => {},
+            }
+        }
+        Some((runpaths, end))
+    }
+
+    fn string(&self, table: &Section, index: usize) -> Option<&'a str> {
+        let start = table.offset.checked_add(index)?;
+        let rest = self.data.get(start..table.offset.checked_add(table.size)?)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..len]).ok()
+    }
+
+    /// `(library, version)` pairs from `.gnu.version_r`
+    fn needed_versions(&self) -> Option<Vec<(&'a str, &'a str)>> {
+        let sections = self.sections()?;
+        let mut versions = Vec::new();
+        for verneed in sections.iter().filter(|s| s.kind == SHT_GNU_VERNEED) {
+            let strings = sections.get(verneed.link)?;
+            let mut entry = verneed.offset;
+            loop {
+                let aux_count = self.u16(entry + 2)?;
+                let file = self.string(strings, self.u32(entry + 4)? as usize)?;
+                let mut aux = entry.checked_add(self.u32(entry + 8)? as usize)?;
+                for _ in 0..aux_count {
+                    versions.push((file, self.string(strings, self.u32(aux + 8)? as usize)?));
+                    match self.u32(aux + 12)? {
+                        0 => break,
+                        next => aux = aux.checked_add(next as usize)?,
+                    }
+                }
+                match self.u32(entry + 12)? {
+                    0 => break,
+                    next => entry = entry.checked_add(next as usize)?,
+                }
+            }
+        }
+        Some(versions)
+    }
+}
+
+/// `e_machine` values, with the class and byte order, and the dynamic loaders for Debian architectures
+const ARCHITECTURES: [(&str, u16, bool, bool, &[&str]); 14] = [
+    ("amd64", 62, true, true, &["/lib64/ld-linux-x86-64.so.2", "/lib/ld-musl-x86_64.so.1"]),
+    ("x32", 62, false, true, &["/libx32/ld-linux-x32.so.2"]),
+    ("i386", 3, false, true, &["/lib/ld-linux.so.2", "/lib/ld-musl-i386.so.1"]),
+    ("arm64", 183, true, true, &["/lib/ld-linux-aarch64.so.1", "/lib/ld-musl-aarch64.so.1"]),
+    ("armhf", 40, false, true, &["/lib/ld-linux-armhf.so.3", "/lib/ld-musl-armhf.so.1"]),
+    ("armel", 40, false, true, &["/lib/ld-linux.so.3", "/lib/ld-musl-arm.so.1"]),
+    ("ppc64el", 21, true, true, &["/lib64/ld64.so.2", "/lib/ld-musl-powerpc64le.so.1"]),
+    ("ppc64", 21, true, false, &["/lib64/ld64.so.1", "/lib/ld-musl-powerpc64.so.1"]),
+    ("powerpc", 20, false, false, &["/lib/ld.so.1", "/lib/ld-musl-powerpc.so.1"]),
+    ("s390x", 22, true, false, &["/lib/ld64.so.1", "/lib/ld-musl-s390x.so.1"]),
+    ("riscv64", 243, true, true, &["/lib/ld-linux-riscv64-lp64d.so.1", "/lib/ld-musl-riscv64.so.1"]),
+    ("loong64", 258, true, true, &["/lib64/ld-linux-loongarch-lp64d.so.1", "/lib/ld-musl-loongarch64.so.1"]),
+    ("mipsel", 8, false, true, &["/lib/ld.so.1", "/lib/ld-musl-mipsel.so.1"]),
+    ("mips64el", 8, true, true, &["/lib64/ld.so.1", "/lib/ld-musl-mips64el.so.1"]),
+];
+
+/// Checks that the binary is for `arch` (a Debian architecture), and that its dynamic loader
+/// is the one of that architecture. Loaders of unknown architectures are looked up on the host,
+/// if it's the same architecture.
+pub(crate) fn check_architecture(path: &Path, arch: &str, host_arch: &str) -> CDResult<()> {
+    let data = fs::read(path).map_err(|e| CargoDebError::IoFile("unable to read binary", e, path.to_owned()))?;
+    let elf = match Elf::parse(&data) {
+        Some(elf) => elf,
+        None => return Ok(()),
+    };
+    let (_, machine, is_64, little_endian, loaders) = match ARCHITECTURES.iter().find(|a| a.0 == arch) {
+        Some(expected) => *expected,
+        None => return Ok(()),
+    };
+    let found = elf.u16(0x12).ok_or_else(|| CargoDebError::BinaryNotExecutable(path.to_owned()))?;
+    if (found, elf.is_64, elf.little_endian) != (machine, is_64, little_endian) {
+        let found_arch = ARCHITECTURES.iter()
+            .find(|a| (a.1, a.2, a.3) == (found, elf.is_64, elf.little_endian))
+            .map_or_else(|| format!("ELF machine {}", found), |a| a.0.to_owned());
+        return Err(CargoDebError::BinaryArchitectureMismatch(path.to_owned(), found_arch, arch.to_owned()));
+    }
+    if let Some(interpreter) = elf.interpreter() {
+        let is_other_arch = ARCHITECTURES.iter().any(|a| a.0 != arch && a.4.contains(&interpreter) && !loaders.contains(&interpreter));
+        let is_missing = !loaders.contains(&interpreter) && arch == host_arch && !Path::new(interpreter).exists();
+        if is_other_arch || is_missing {
+            return Err(CargoDebError::BinaryInterpreterMismatch(path.to_owned(), interpreter.to_owned(), arch.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Security features of a binary, as reported by `hardening-check`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hardening {
+    /// Position-independent executable. `None` for shared libraries.
+    pub pie: Option<bool>,
+    /// Read-only relocations
+    pub relro: bool,
+    /// Immediate binding, which makes RELRO cover the GOT too
+    pub bind_now: bool,
+    /// Uses `__stack_chk_fail`. Rust doesn't use stack canaries, so this is only for C code.
+    pub stack_protector: bool,
+}
+
+/// GNU build-id from `.note.gnu.build-id`, in hex, as in `/usr/lib/debug/.build-id/` paths
+pub(crate) fn build_id(data: &[u8]) -> Option<String> {
+    let elf = Elf::parse(data)?;
+    let align4 = |n: usize| n.checked_add(3).map(|n| n & !3);
+    for note in elf.sections()?.into_iter().filter(|s| s.kind == SHT_NOTE) {
+        let end = note.offset.checked_add(note.size)?;
+        let mut entry = note.offset;
+        while entry.checked_add(12)? <= end {
+            let name_size = elf.u32(entry)? as usize;
+            let desc_size = elf.u32(entry + 4)? as usize;
+            let kind = elf.u32(entry + 8)?;
+            let name = entry + 12;
+            let desc = name.checked_add(align4(name_size)?)?;
+            if kind == NT_GNU_BUILD_ID && data.get(name..name.checked_add(name_size)?)? == b"GNU\0" {
+                let id = data.get(desc..desc.checked_add(desc_size)?)?;
+                return Some(id.iter().map(|b| format!("{:02x}", b)).collect());
+            }
+            entry = desc.checked_add(align4(desc_size)?)?;
+        }
+    }
+    None
+}
+
+/// `None` if it's not an ELF file
+pub(crate) fn hardening(data: &[u8]) -> Option<Hardening> {
+    let elf = Elf::parse(data)?;
+    let segments = elf.segments()?;
+    let has_interpreter = segments.iter().any(|&(kind, ..)| kind == PT_INTERP);
+    let is_dyn = elf.u16(0x10)? == ET_DYN;
+    let dynamic = elf.dynamic_entries()?;
+    Some(Hardening {
+        pie: if has_interpreter || !is_dyn { Some(is_dyn) } else { None },
+        relro: segments.iter().any(|&(kind, ..)| kind == PT_GNU_RELRO),
+        bind_now: dynamic.iter().any(|&(tag, value)| tag == DT_BIND_NOW
+            || (tag == DT_FLAGS && value & DF_BIND_NOW != 0)
+            || (tag == DT_FLAGS_1 && value & DF_1_NOW != 0)),
+        stack_protector: elf.dynamic_symbols()?.contains(&"__stack_chk_fail"),
+    })
+}
+
+/// Library search paths set in the binary with `-rpath`
+pub(crate) fn runpaths(data: &[u8]) -> Vec<String> {
+    Elf::parse(data).and_then(|elf| elf.runpaths())
+        .map(|(runpaths, _)| runpaths.into_iter().map(|r| r.value.to_owned()).collect())
+        .unwrap_or_default()
+}
+
+/// Removes the directories `keep` rejects from RPATH and RUNPATH, like `chrpath`.
+/// When none are left, the entry is removed from the dynamic section.
+///
+/// Returns whether anything has changed.
+pub(crate) fn scrub_runpaths(data: &mut [u8], keep: &dyn Fn(&str) -> bool) -> bool {
+    let (edits, word_size, dynamic_end) = {
+        let elf = match Elf::parse(data) {
+            Some(elf) => elf,
+            None => return false,
+        };
+        let (runpaths, end) = match elf.runpaths() {
+            Some(r) => r,
+            None => return false,
+        };
+        let edits: Vec<_> = runpaths.iter().filter_map(|r| {
+            let kept = r.value.split(':').filter(|dir| keep(dir)).collect::<Vec<_>>().join(":");
+            Some((r.entry, r.string, r.value.len(), kept)).filter(|(.., kept)| kept != r.value)
+        }).collect();
+        (edits, if elf.is_64 { 8 } else { 4 }, end)
+    };
+    // Later entries first, because removing one moves the ones after it
+    for (entry, string, len, kept) in edits.iter().rev() {
+        if kept.is_empty() {
+            let entry_size = 2 * word_size;
+            data.copy_within(entry + entry_size..dynamic_end, *entry);
+            data[dynamic_end - entry_size..dynamic_end].fill(0); // DT_NULL
+        } else {
+            // The kept string is never longer
+            data[*string..string + len].fill(0);
+            data[*string..string + kept.len()].copy_from_slice(kept.as_bytes());
+        }
+    }
+    !edits.is_empty()
+}
+
+/// The newest `GLIBC_x.y` symbol version the binary needs, e.g. `2.34`
+fn min_glibc_version(data: &[u8]) -> Option<String> {
+    let versions = Elf::parse(data)?.needed_versions()?;
+    versions.into_iter()
+        .filter_map(|(_, version)| version.strip_prefix("GLIBC_"))
+        .filter_map(|version| {
+            let parts: Option<Vec<u32>> = version.split('.').map(|p| p.parse().ok()).collect();
+            Some((parts?, version))
+        })
+        .max()
+        .map(|(_, version)| version.to_owned())
+}
+
+/// `libc6 (>= x.y)` based on the versioned glibc symbols the binary uses.
+///
+/// It's a fallback for when `dpkg-shlibdeps` isn't available. `None` if the binary doesn't link to glibc.
+pub(crate) fn glibc_dependency(path: &Path) -> CDResult<Option<String>> {
+    let data = fs::read(path).map_err(|e| CargoDebError::IoFile("unable to read binary", e, path.to_owned()))?;
+    Ok(min_glibc_version(&data).map(|version| format!("libc6 (>= {})", version)))
+}
+
+/// The binary doesn't link to any shared libraries, e.g. when built for musl
+pub(crate) fn is_static(path: &Path) -> CDResult<bool> {
+    let data = fs::read(path).map_err(|e| CargoDebError::IoFile("unable to read binary", e, path.to_owned()))?;
+    Ok(Elf::parse(&data).and_then(|elf| elf.needs_libraries()) == Some(false))
+}
+
+#[test]
+fn not_elf() {
+    assert_eq!(None, min_glibc_version(b"#!/bin/sh\n"));
+}
+
+#[test]
+fn scrub_rpath() {
+    // 64-bit ELF with only a dynamic section and its string table
+    let mut elf = vec![0u8; 256 + 3 * 64];
+    elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+    elf[0x29] = 1; // section headers at 256
+    elf[0x3A] = 64;
+    elf[0x3C] = 3;
+    let strings = b"\0/home/me/proj/target/release:$ORIGIN/../lib\0libfoo.so\0";
+    elf[64..64 + strings.len()].copy_from_slice(strings);
+    let dynamic = [(DT_RUNPATH, 1), (DT_NEEDED, 46), (0, 0)];
+    for (i, (tag, value)) in dynamic.iter().enumerate() {
+        elf[128 + i * 16..][..8].copy_from_slice(&(*tag as u64).to_le_bytes());
+        elf[136 + i * 16..][..8].copy_from_slice(&(*value as u64).to_le_bytes());
+    }
+    for (i, (kind, offset, size, link)) in [(SHT_DYNAMIC, 128u64, 48u64, 2u32), (3, 64, strings.len() as u64, 0)].iter().enumerate() {
+        let header = 256 + (i + 1) * 64;
+        elf[header + 4..][..4].copy_from_slice(&kind.to_le_bytes());
+        elf[header + 24..][..8].copy_from_slice(&offset.to_le_bytes());
+        elf[header + 32..][..8].copy_from_slice(&size.to_le_bytes());
+        elf[header + 40..][..4].copy_from_slice(&link.to_le_bytes());
+    }
+
+    assert_eq!(vec!["/home/me/proj/target/release:$ORIGIN/../lib"], runpaths(&elf));
+    assert!(scrub_runpaths(&mut elf, &|dir| dir.starts_with("$ORIGIN")));
+    assert_eq!(vec!["$ORIGIN/../lib"], runpaths(&elf));
+    assert!(!scrub_runpaths(&mut elf, &|dir| dir.starts_with("$ORIGIN")));
+    assert!(scrub_runpaths(&mut elf, &|_| false));
+    assert!(runpaths(&elf).is_empty());
+    assert_eq!(DT_NEEDED as u8, elf[128]);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+fn architecture_of_test_binary() {
+    let exe = std::env::current_exe().unwrap();
+    check_architecture(&exe, "amd64", "amd64").unwrap();
+    assert!(matches!(check_architecture(&exe, "arm64", "amd64"), Err(CargoDebError::BinaryArchitectureMismatch(_, found, _)) if found == "amd64"));
+    check_architecture(&exe, "unknown", "amd64").unwrap();
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+fn hardening_of_test_binary() {
+    let h = hardening(&fs::read(std::env::current_exe().unwrap()).unwrap()).unwrap();
+    assert_eq!(Some(true), h.pie);
+    assert!(h.relro);
+    assert_eq!(None, hardening(b"#!/bin/sh\n"));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn build_id_of_test_binary() {
+    let id = build_id(&fs::read(std::env::current_exe().unwrap()).unwrap()).unwrap();
+    assert!(id.len() >= 16 && id.bytes().all(|b| b.is_ascii_hexdigit()), "{}", id);
+    assert_eq!(None, build_id(b"#!/bin/sh\n"));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn glibc_version_of_test_binary() {
+    let dep = glibc_dependency(&std::env::current_exe().unwrap()).unwrap().unwrap();
+    assert!(dep.starts_with("libc6 (>= 2."), "{}", dep);
+    assert!(!is_static(&std::env::current_exe().unwrap()).unwrap());
+}
@@ -1,7 +1,8 @@
 #![allow(renamed_and_removed_lints)]
+use std::fmt::Write as _;
 use std::io;
 use std::num;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time;
 
 quick_error! {
@@ -27,6 +28,12 @@ quick_error! {
             display("{}", msg)
             from()
         }
+        Multiple(errors: Vec<CargoDebError>) {
+            display("stopped because of {} errors", errors.len())
+        }
+        InvalidArguments(msg: String) {
+            display("{}. See cargo deb --help", msg)
+        }
         NumParse(msg: &'static str, err: num::ParseIntError) {
             display("{}", msg)
             source(err)
@@ -37,6 +44,12 @@ quick_error! {
         BuildFailed {
             display("build failed")
         }
+        CrossBuildFailed(target: String, debian_triple: String) {
+            display("linking for {} failed, because no linker for it is configured\nnote: set linker = \"{}-gcc\" in [package.metadata.deb] (from the gcc-{} package), or target.{}.linker in .cargo/config.toml", target, debian_triple, debian_triple, target)
+        }
+        TestsFailed {
+            display("tests failed")
+        }
         DebHelperReplaceFailed(name: PathBuf) {
             display("unable to replace #DEBHELPER# token in maintainer script '{}'", name.display())
         }
@@ -53,6 +66,9 @@ quick_error! {
             display("unable to parse Cargo.toml")
             source(err)
         }
+        ParseManifest(msg: String, path: PathBuf, line: usize, column: usize, context: String) {
+            display("{}:{}:{}: {}\n{}", path.display(), line, column, msg, context)
+        }
         ParseJSON(err: serde_json::Error) {
             from()
             display("unable to parse `cargo metadata` output")
@@ -68,6 +84,9 @@ quick_error! {
         PackageNotFoundInWorkspace(name: String, available: String) {
             display("The workspace doesn't have a package named {}. Available packages are: {}", name, available)
         }
+        PackageNotInDebianControl(name: String, path: PathBuf) {
+            display("{} has more than one package, and none of them is named {}", path.display(), name)
+        }
         NoRootFoundInWorkspace(available: String) {
             display("This is a workspace with multiple packages, and there is no single package at the root. Please specify package name with -p. Available packages are: {}", available)
         }
@@ -82,6 +101,183 @@ quick_error! {
         AssetFileNotFound(path: PathBuf) {
             display("Asset file path does not match any files: {}", path.display())
         }
+        BinaryNotFound(path: PathBuf, hint: String) {
+            display("Binary '{}' has not been built.\nhint: {}", path.display(), hint)
+        }
+        DependenciesNotFound(names: String) {
+            display("Dependencies not found in the apt package index: {}", names)
+        }
+        BinaryNotExecutable(path: PathBuf) {
+            display("Binary '{}' is neither an ELF executable nor a script. Is the target dir stale?", path.display())
+        }
+        BinaryArchitectureMismatch(path: PathBuf, found: String, expected: String) {
+            display("Binary '{}' is for {}, but the package is for {}. Is the target dir stale, or is --target missing?", path.display(), found, expected)
+        }
+        BinaryInterpreterMismatch(path: PathBuf, interpreter: String, arch: String) {
+            display("Binary '{}' needs the dynamic loader {}, which {} systems don't have. Was it linked for another system?", path.display(), interpreter, arch)
+        }
+        UnknownPackageFormat(format: String) {
+            display("Unknown package format '{}'. Supported formats are 'deb' and 'ipk'", format)
+        }
+        UnknownCompatProfile(profile: String) {
+            display("Unknown compatibility profile '{}'. The supported profile is 'oldstable'", profile)
+        }
+        UnrepresentablePath(path: PathBuf, problem: &'static str) {
+            display("Can't package {:?}: {}", path, problem)
+        }
+        TarPathTooLong(path: PathBuf) {
+            display("Path is too long for the ustar format: {}", path.display())
+        }
+        UnknownWarningCode(code: String, option: &'static str) {
+            display("Unknown warning code '{}' for --{}. Use 'warnings' for all warnings", code, option)
+        }
+        DeniedWarnings(count: usize) {
+            display("{} denied warning(s) were reported, so the package was not made", count)
+        }
+        UndefinedVariable(name: String) {
+            display("Environment variable {} used in Cargo.toml metadata as ${{env:{}}} is not set", name, name)
+        }
+        MissingMaintainer {
+            display("The package has no maintainer. Set maintainer in [package.metadata.deb] or authors in [package], e.g. maintainer = \"Name <email@example.com>\"")
+        }
+        InvalidSystemdUnit(name: String) {
+            display("'{}' in [package.metadata.deb.systemd.units] is not a systemd unit file name, like foo.service", name)
+        }
+        InvalidPolkitFile(path: PathBuf, problem: String) {
+            display("Polkit file {} {}", path.display(), problem)
+        }
+        InvalidPackageName(name: String) {
+            display("'{}' is not a valid Debian package name. It must have at least two lowercase letters, digits, '+', '-', or '.', and start with a letter or digit", name)
+        }
+        InvalidSection(field: &'static str, value: String, suggestion: String) {
+            display("'{}' is not a valid Debian {}{}", value, field, suggestion)
+        }
+        InvalidBugsUrl(bugs: String) {
+            display("bugs must be a URL, like https://example.com/issues or debbugs://bugs.example.com, not '{}'", bugs)
+        }
+        InvalidDescription(line: String, problem: &'static str) {
+            display("The line '{}' of the package description {}", line, problem)
+        }
+        UnknownCompressType(compress_type: String) {
+            display("Unknown compression type '{}'. Supported types are 'xz', 'gz', 'zstd' and 'none'", compress_type)
+        }
+        UnknownDistroProfile(profile: String) {
+            display("Unknown distro profile '{}'. Supported profiles are 'debian' and 'ubuntu'", profile)
+        }
+        XzNotSupported {
+            display("xz compression needs cargo-deb built with the lzma feature. Use compress-type = \"gz\" instead")
+        }
+        UnknownInstalledSizeMethod(method: String) {
+            display("Unknown Installed-Size method '{}'. Supported methods are 'dpkg' and 'blocks'", method)
+        }
+        UnknownTarFormat(format: String) {
+            display("Unknown tar format '{}'. Supported formats are 'gnu', 'ustar' and 'pax'", format)
+        }
+        InvalidScriptShell(shell: String) {
+            display("generated-scripts shell '{}' should be an absolute path of the interpreter, e.g. /bin/bash", shell)
+        }
+        UnknownMaintainerScript(name: String) {
+            display("'{}' in generated-scripts isn't a maintainer script with generated code. Use preinst, postinst, prerm or postrm", name)
+        }
+        InvalidAssetOwner(owner: String) {
+            display("Invalid asset owner '{}'. It should be 'user:group' with lowercase system user and group names", owner)
+        }
+        InvalidPurgePath(path: String, problem: &'static str) {
+            display("remove-on-purge path '{}' isn't safe to delete: {}", path, problem)
+        }
+        InvalidArchMapping(target: String, arch: String) {
+            display("arch-map maps {} to '{}', which isn't a valid Debian architecture name. It can have only lowercase letters, digits and '-'", target, arch)
+        }
+        UnknownArmhfBaseline(baseline: String) {
+            display("Unknown armhf-baseline '{}'. Use 'armv7' for Debian and Ubuntu, or 'armv6' for Raspbian", baseline)
+        }
+        InvalidChangelogUrgency(urgency: String) {
+            display("Invalid changelog urgency '{}'. Use low, medium, high, emergency or critical", urgency)
+        }
+        InvalidChangelogDistribution(distribution: String) {
+            display("Invalid changelog distribution '{}'", distribution)
+        }
+        InvalidVersionSuffix(suffix: String) {
+            display("Invalid --deb-suffix '{}'. It can have only letters, digits, and '.', '+' or '~'", suffix)
+        }
+        InvalidStripKeep(name: String) {
+            display("Invalid symbol or section name '{}' in strip-keep-symbols or strip-keep-sections", name)
+        }
+        InvalidBackportSuite(suite: String) {
+            display("Invalid --backport suite '{}'. It must be a codename, e.g. 'bookworm' or 'jammy'", suite)
+        }
+        InvalidSubstvar(var: String) {
+            display("Invalid substitution variable: {}", var)
+        }
+        MissingFromCargoLock(package: String) {
+            display("{} is missing from Cargo.lock", package)
+        }
+        UnknownBinary(name: String, available: String) {
+            display("'{}' is not a binary target of this package. Available binaries are: {}", name, available)
+        }
+        UnknownSbomFormat(format: String) {
+            display("Unknown SBOM format '{}'. Supported formats are 'cyclonedx' and 'spdx'", format)
+        }
+        UnknownGraphFormat(format: String) {
+            display("Unknown graph format '{}'. Supported formats are 'dot' and 'json'", format)
+        }
+        UnknownConfigFormat(format: String) {
+            display("Unknown configuration format '{}'. Supported formats are 'toml' and 'json'", format)
+        }
+        UnknownShell(shell: String) {
+            display("Unknown shell '{}'. Completions are available for 'bash', 'zsh' and 'fish'", shell)
+        }
+        AssetChanged(path: PathBuf, len: u64) {
+            display("the asset {} changed while the package was being made: it isn't {} bytes long anymore", path.display(), len)
+        }
+        SystemdUnitNotInPackage(unit: String) {
+            display("systemd unit {} isn't in the package. Units named in Also= have to be installed too", unit)
+        }
+        SerializeConfig(reason: String) {
+            display("unable to print the configuration: {}", reason)
+        }
+        NotAPackage(path: PathBuf) {
+            display("'{}' is not a .deb or .ipk package", path.display())
+        }
+        UnsupportedCompression(member: String) {
+            display("Compression of package member '{}' is not supported", member)
+        }
+        VerificationFailed(path: PathBuf, problems: String) {
+            display("Package '{}' failed verification:\n{}", path.display(), problems)
+        }
+        AlreadyInitialized(manifest_path: PathBuf) {
+            display("{} already has a [package.metadata.deb] section", manifest_path.display())
+        }
+        InstalledFilesChanged(package: PathBuf, count: usize) {
+            display("{} installed file(s) differ from the package '{}'", count, package.display())
+        }
+        BuiltPackageNotFound(name: String, dir: PathBuf) {
+            display("There is no {}_*.deb in {}. Build it first with cargo deb, or give a path to the .deb", name, dir.display())
+        }
+        UnsafeArchivePath(path: PathBuf) {
+            display("Refusing to extract '{}' outside of the destination directory", path.display())
+        }
+        InvalidCompressedData(msg: &'static str) {
+            display("{}", msg)
+        }
+        MissingPackageMember(name: &'static str) {
+            display("{} is missing from the package", name)
+        }
+        IncompleteAsset(entry: &'static str) {
+            display("missing {} for asset in Cargo.toml", entry)
+        }
+        MissingCopyright {
+            display("The package must have a copyright or authors property")
+        }
+        InvalidDocBase(msg: &'static str) {
+            display("doc-base {}", msg)
+        }
+        ConflictingSettings(a: &'static str, b: &'static str) {
+            display("Use either {} or {}, not both", a, b)
+        }
+        MissingArguments(msg: &'static str) {
+            display("{}", msg)
+        }
         AssetGlobError(err: glob::GlobError) {
             from()
             display("unable to iterate asset glob result")
@@ -95,3 +291,183 @@ quick_error! {
 }
 
 pub type CDResult<T> = Result<T, CargoDebError>;
+
+impl CargoDebError {
+    /// Printed as `error[CD0217]`. The hundreds are the category: 0 I/O and other programs, 1 building,
+    /// 2 `Cargo.toml` and the configuration, 3 making the package, 4 command-line options, 5 checking packages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Str(_) => "CD0000",
+            Self::Io(_) => "CD0001",
+            Self::IoFile(..) => "CD0002",
+            Self::CommandFailed(..) => "CD0003",
+            Self::CommandError(..) => "CD0004",
+            Self::SystemTime(_) => "CD0005",
+            Self::Multiple(_) => "CD0006",
+            Self::BuildFailed => "CD0101",
+            Self::CrossBuildFailed(..) => "CD0102",
+            Self::TestsFailed => "CD0103",
+            Self::StripFailed(..) => "CD0104",
+            Self::BinaryNotFound(..) => "CD0105",
+            Self::BinaryNotExecutable(_) => "CD0106",
+            Self::BinaryArchitectureMismatch(..) => "CD0107",
+            Self::BinaryInterpreterMismatch(..) => "CD0108",
+            Self::ParseTOML(_) => "CD0201",
+            Self::ParseManifest(..) => "CD0202",
+            Self::ParseJSON(_) => "CD0203",
+            Self::ParseUTF8(_) => "CD0204",
+            Self::PackageNotFound(..) => "CD0205",
+            Self::PackageNotFoundInWorkspace(..) => "CD0206",
+            Self::PackageNotInDebianControl(..) => "CD0207",
+            Self::NoRootFoundInWorkspace(_) => "CD0208",
+            Self::VariantNotFound(_) => "CD0209",
+            Self::GlobPatternError(_) => "CD0210",
+            Self::AssetGlobError(_) => "CD0211",
+            Self::AssetFileNotFound(_) => "CD0212",
+            Self::UndefinedVariable(_) => "CD0213",
+            Self::MissingMaintainer => "CD0214",
+            Self::InvalidSystemdUnit(_) => "CD0215",
+            Self::InvalidPolkitFile(..) => "CD0216",
+            Self::InvalidPackageName(_) => "CD0217",
+            Self::InvalidSection(..) => "CD0218",
+            Self::InvalidBugsUrl(_) => "CD0219",
+            Self::InvalidDescription(..) => "CD0220",
+            Self::InvalidScriptShell(_) => "CD0221",
+            Self::UnknownMaintainerScript(_) => "CD0222",
+            Self::InvalidAssetOwner(_) => "CD0223",
+            Self::InvalidPurgePath(..) => "CD0224",
+            Self::InvalidArchMapping(..) => "CD0225",
+            Self::UnknownArmhfBaseline(_) => "CD0226",
+            Self::InvalidChangelogUrgency(_) => "CD0227",
+            Self::InvalidChangelogDistribution(_) => "CD0228",
+            Self::InvalidStripKeep(_) => "CD0229",
+            Self::InvalidSubstvar(_) => "CD0230",
+            Self::MissingFromCargoLock(_) => "CD0231",
+            Self::UnknownBinary(..) => "CD0232",
+            Self::UnknownCompressType(_) => "CD0233",
+            Self::UnknownDistroProfile(_) => "CD0234",
+            Self::UnknownInstalledSizeMethod(_) => "CD0235",
+            Self::UnknownTarFormat(_) => "CD0236",
+            Self::AlreadyInitialized(_) => "CD0237",
+            Self::IncompleteAsset(_) => "CD0238",
+            Self::MissingCopyright => "CD0239",
+            Self::InvalidDocBase(_) => "CD0240",
+            Self::ConflictingSettings(..) => "CD0241",
+            Self::UnrepresentablePath(..) => "CD0301",
+            Self::TarPathTooLong(_) => "CD0302",
+            Self::XzNotSupported => "CD0303",
+            Self::DebHelperReplaceFailed(_) => "CD0304",
+            Self::DependenciesNotFound(_) => "CD0305",
+            Self::DeniedWarnings(_) => "CD0306",
+            Self::InstallFailed => "CD0307",
+            Self::SerializeConfig(_) => "CD0308",
+            #[cfg(feature = "lzma")]
+            Self::LzmaCompressionError(_) => "CD0309",
+            Self::SystemdUnitNotInPackage(_) => "CD0310",
+            Self::AssetChanged(..) => "CD0311",
+            Self::InvalidArguments(_) => "CD0401",
+            Self::NumParse(..) => "CD0402",
+            Self::UnknownPackageFormat(_) => "CD0403",
+            Self::UnknownCompatProfile(_) => "CD0404",
+            Self::UnknownWarningCode(..) => "CD0405",
+            Self::InvalidVersionSuffix(_) => "CD0406",
+            Self::InvalidBackportSuite(_) => "CD0407",
+            Self::UnknownSbomFormat(_) => "CD0408",
+            Self::UnknownGraphFormat(_) => "CD0409",
+            Self::UnknownConfigFormat(_) => "CD0410",
+            Self::BuiltPackageNotFound(..) => "CD0411",
+            Self::UnknownShell(_) => "CD0412",
+            Self::MissingArguments(_) => "CD0413",
+            Self::NotAPackage(_) => "CD0501",
+            Self::UnsupportedCompression(_) => "CD0502",
+            Self::VerificationFailed(..) => "CD0503",
+            Self::InstalledFilesChanged(..) => "CD0504",
+            Self::UnsafeArchivePath(_) => "CD0505",
+            Self::InvalidCompressedData(_) => "CD0506",
+            Self::MissingPackageMember(_) => "CD0507",
+        }
+    }
+
+    /// `Ok` if there are no errors, or all of them to report together
+    pub(crate) fn from_errors(errors: Vec<Self>) -> CDResult<()> {
+        let mut errors: Vec<_> = errors.into_iter()
+            .flat_map(|err| match err {
+                Self::Multiple(errors) => errors,
+                err => vec![err],
+            })
+            .collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Multiple(errors)),
+        }
+    }
+
+    /// A TOML error of the file, with the line it's about, if it has a location
+    pub(crate) fn toml_in_file(err: toml::de::Error, path: &Path, content: &[u8]) -> Self {
+        let Some((mut line, mut column)) = err.line_col() else { return Self::ParseTOML(err) };
+        let msg = err.to_string();
+        let msg = msg.rsplit_once(" at line ").map_or(&*msg, |(msg, _)| msg).to_owned();
+        let text = String::from_utf8_lossy(content);
+        // Unknown fields are reported at the start of their table
+        let unknown_key = msg.strip_prefix("unknown field `").and_then(|rest| rest.split_once('`')).map(|(key, _)| key);
+        if let Some(key) = unknown_key {
+            let key_line = text.lines().enumerate().skip(line + 1)
+                .take_while(|(_, l)| !l.trim_start().starts_with('['))
+                .find(|(_, l)| l.trim_start().strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('=')));
+            if let Some((key_line, l)) = key_line {
+                line = key_line;
+                column = l.len() - l.trim_start().len();
+            }
+        }
+        let source_line = text.lines().nth(line).unwrap_or_default();
+        // TOML reports byte offsets, but the caret and the reported column count characters
+        let column = source_line.get(..column).map_or(column, |before| before.chars().count());
+        let gutter = " ".repeat((line + 1).to_string().len());
+        let mut context = String::new();
+        let _ = writeln!(context, "{} |", gutter);
+        let _ = writeln!(context, "{} | {}", line + 1, source_line);
+        let caret_indent: String = source_line.chars().take(column).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let _ = write!(context, "{} | {}^", gutter, caret_indent);
+        Self::ParseManifest(msg, path.to_owned(), line + 1, column + 1, context)
+    }
+}
+
+#[test]
+fn toml_error_context() {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Deb {
+        #[allow(dead_code)]
+        section: Option<String>,
+    }
+    let content = "[deb]\nsection = 5\n";
+    let err = toml::from_str::<std::collections::HashMap<String, Deb>>(content).err().unwrap();
+    let err = CargoDebError::toml_in_file(err, Path::new("Cargo.toml"), content.as_bytes());
+    assert_eq!("CD0202", err.code());
+    assert_eq!("Cargo.toml:2:11: invalid type: integer `5`, expected a string for key `deb.section`\n  |\n2 | section = 5\n  |           ^", err.to_string());
+
+    let content = "[deb]\nsection = \"net\"\n  secton = \"utils\"\n";
+    let err = toml::from_str::<std::collections::HashMap<String, Deb>>(content).err().unwrap();
+    let err = CargoDebError::toml_in_file(err, Path::new("Cargo.toml"), content.as_bytes());
+    assert!(err.to_string().starts_with("Cargo.toml:3:3: unknown field `secton`"), "{}", err);
+    assert!(err.to_string().ends_with("\n3 |   secton = \"utils\"\n  |   ^"), "{}", err);
+
+    let content = "\"ёё\" = { section = 5 }\n";
+    let err = toml::from_str::<std::collections::HashMap<String, Deb>>(content).err().unwrap();
+    let err = CargoDebError::toml_in_file(err, Path::new("Cargo.toml"), content.as_bytes());
+    assert!(err.to_string().starts_with("Cargo.toml:1:20: "), "{}", err);
+    assert!(err.to_string().ends_with(&format!("\n  | {}^", " ".repeat(19))), "{}", err);
+}
+
+#[test]
+fn error_codes_are_unique() {
+    let source = include_str!("error.rs");
+    let mut codes: Vec<_> = source.split("=> \"CD").skip(1).map(|rest| &rest[..4]).collect();
+    let count = codes.len();
+    codes.sort_unstable();
+    codes.dedup();
+    assert_eq!(count, codes.len());
+    assert!(count > 50);
+}
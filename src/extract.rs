@@ -0,0 +1,169 @@
+use crate::reader::{decompress, read_package, tar_entries, TarEntry};
+use crate::error::*;
+use crate::listener::{Listener, Warning};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tar::EntryType;
+
+/// Unpacks the data archive of the package into `dest_dir`, and the control archive into `dest_dir/DEBIAN`,
+/// like `dpkg-deb --raw-extract`, but without needing dpkg.
+pub fn extract_package(path: &Path, dest_dir: &Path, listener: &dyn Listener) -> CDResult<()> {
+    let members = read_package(path)?;
+    let control = members.iter().find(|m| m.name.starts_with("control.tar")).ok_or(CargoDebError::MissingPackageMember("control.tar"))?;
+    let data = members.iter().find(|m| m.name.starts_with("data.tar")).ok_or(CargoDebError::MissingPackageMember("data.tar"))?;
+
+    let data_entries = tar_entries(&decompress(data)?)?;
+    unpack(&data_entries, dest_dir, listener)?;
+    let control_entries = tar_entries(&decompress(control)?)?;
+    unpack(&control_entries, &dest_dir.join("DEBIAN"), listener)?;
+    listener.info(format!("Extracted {} files to {}", data_entries.len(), dest_dir.display()));
+    Ok(())
+}
+
+fn unpack(entries: &[TarEntry], dest_dir: &Path, listener: &dyn Listener) -> CDResult<()> {
+    fs::create_dir_all(dest_dir)?;
+    let real_dest_dir = dest_dir.canonicalize()?;
+    for entry in entries {
+        let dest = safe_join(dest_dir, &entry.path)?;
+        match entry.entry_type {
+            EntryType::Directory => {
+                check_inside(&real_dest_dir, &dest, &entry.path)?;
+                fs::create_dir_all(&dest)?;
+                set_mode(&dest, entry.mode)?;
+            },
+            EntryType::Symlink => {
+                let link_name = entry.link_name.as_deref().ok_or("symlink without a target")?;
+                create_parent(&real_dest_dir, &dest, &entry.path)?;
+                let _ = fs::remove_file(&dest);
+                symlink(link_name, &dest, listener)?;
+            },
+            EntryType::Link => {
+                let link_name = entry.link_name.as_deref().ok_or("hardlink without a target")?.trim_start_matches("./");
+                let source = safe_join(dest_dir, link_name)?;
+                check_inside(&real_dest_dir, &source, link_name)?;
+                create_parent(&real_dest_dir, &dest, &entry.path)?;
+                let _ = fs::remove_file(&dest);
+                fs::copy(&source, &dest)?;
+            },
+            _ if entry.entry_type.is_file() => {
+                create_parent(&real_dest_dir, &dest, &entry.path)?;
+                // don't write to where an earlier symlink points
+                let _ = fs::remove_file(&dest);
+                fs::write(&dest, &entry.data)
+                    .map_err(|e| CargoDebError::IoFile("unable to write extracted file", e, dest.clone()))?;
+                set_mode(&dest, entry.mode)?;
+            },
+            other => listener.warning(Warning::SkippedFile, format!("skipped {} of unsupported type {:?}", entry.path, other)),
+        }
+    }
+    Ok(())
+}
+
+/// Refuses paths that would escape the destination directory
+fn safe_join(dest_dir: &Path, entry_path: &str) -> CDResult<PathBuf> {
+    let entry_path = Path::new(entry_path);
+    if entry_path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(CargoDebError::UnsafeArchivePath(entry_path.to_owned()));
+    }
+    Ok(dest_dir.join(entry_path))
+}
+
+/// Refuses paths that go through symlinks to outside of the destination directory,
+/// e.g. `usr/x/passwd` after a symlink `usr/x -> /etc`
+fn check_inside(real_dest_dir: &Path, path: &Path, entry_path: &str) -> CDResult<()> {
+    // the rest doesn't exist yet, so it can't be a symlink
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(path);
+    if !existing.canonicalize()?.starts_with(real_dest_dir) {
+        return Err(CargoDebError::UnsafeArchivePath(entry_path.into()));
+    }
+    Ok(())
+}
+
+fn create_parent(real_dest_dir: &Path, path: &Path, entry_path: &str) -> CDResult<()> {
+    if let Some(parent) = path.parent() {
+        check_inside(real_dest_dir, parent, entry_path)?;
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> CDResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> CDResult<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(link_name: &str, dest: &Path, _listener: &dyn Listener) -> CDResult<()> {
+    std::os::unix::fs::symlink(link_name, dest)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn symlink(link_name: &str, dest: &Path, listener: &dyn Listener) -> CDResult<()> {
+    listener.warning(Warning::SkippedFile, format!("skipped symlink {} -> {}", dest.display(), link_name));
+    Ok(())
+}
+
+#[test]
+fn rejects_unsafe_paths() {
+    let dest = Path::new("/tmp/out");
+    assert_eq!(dest.join("usr/bin/foo"), safe_join(dest, "usr/bin/foo").unwrap());
+    assert!(safe_join(dest, "../etc/passwd").is_err());
+    assert!(safe_join(dest, "usr/../../etc").is_err());
+    assert!(safe_join(dest, "/etc/passwd").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn doesnt_write_through_symlinks() {
+    use crate::manifest::TarFormat;
+    use crate::tararchive::Archive;
+
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("secret"), "secret").unwrap();
+    let unpacked = |entries: &dyn Fn(&mut Archive)| {
+        let mut archive = Archive::new(0, TarFormat::Gnu);
+        entries(&mut archive);
+        let dest = tempfile::tempdir().unwrap();
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().return_const(());
+        let result = unpack(&tar_entries(&archive.into_inner().unwrap()).unwrap(), dest.path(), &mock_listener);
+        (result, dest)
+    };
+
+    let (result, _dest) = unpacked(&|archive| {
+        archive.symlink(Path::new("usr/x"), outside.path()).unwrap();
+        archive.file("usr/x/passwd", b"evil", 0o644).unwrap();
+    });
+    assert!(matches!(result, Err(CargoDebError::UnsafeArchivePath(_))));
+    assert!(!outside.path().join("passwd").exists());
+
+    let (result, _dest) = unpacked(&|archive| {
+        archive.symlink(Path::new("usr/x"), outside.path()).unwrap();
+        archive.hardlink(Path::new("usr/copy"), Path::new("usr/x/secret"), 0o644).unwrap();
+    });
+    assert!(matches!(result, Err(CargoDebError::UnsafeArchivePath(_))));
+
+    let (result, _dest) = unpacked(&|archive| {
+        archive.symlink(Path::new("usr/file"), &outside.path().join("secret")).unwrap();
+        archive.file("usr/file", b"evil", 0o644).unwrap();
+    });
+    result.unwrap();
+    assert_eq!("secret", fs::read_to_string(outside.path().join("secret")).unwrap());
+
+    // symlinks within the package are fine
+    let (result, dest) = unpacked(&|archive| {
+        archive.file("usr/lib/foo/data", b"data", 0o644).unwrap();
+        archive.symlink(Path::new("usr/share/foo"), Path::new("../lib/foo")).unwrap();
+        archive.file("usr/share/foo/more", b"more", 0o644).unwrap();
+    });
+    result.unwrap();
+    assert_eq!("more", fs::read_to_string(dest.path().join("usr/lib/foo/more")).unwrap());
+}
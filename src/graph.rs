@@ -0,0 +1,170 @@
+//! `--graph` output of the package's relationships to other packages, for auditing where they come from
+use crate::error::*;
+use crate::listener::Listener;
+use crate::manifest::Config;
+use serde_json::json;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz
+    Dot,
+    Json,
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            other => Err(CargoDebError::UnknownGraphFormat(other.to_owned())),
+        }
+    }
+}
+
+/// An edge from the package to another one
+#[derive(Debug, PartialEq)]
+struct Relationship {
+    field: &'static str,
+    package: String,
+    /// e.g. `>= 1.0`, or empty
+    version: String,
+    /// Binaries and libraries that caused the dependency, or the metadata key that set it
+    via: Vec<String>,
+}
+
+/// Relationships from `Depends`, `Recommends`, `Provides`, `Conflicts`, etc. Alternatives are separate edges.
+fn relationships(options: &Config, listener: &dyn Listener) -> CDResult<Vec<Relationship>> {
+    let mut edges = Vec::new();
+    for explanation in options.explain_dependencies(listener)? {
+        let via = if explanation.required_by.is_empty() {
+            vec!["package.metadata.deb.depends".to_owned()]
+        } else {
+            explanation.required_by.iter().map(|(binary, libraries)| if libraries.is_empty() {
+                binary.display().to_string()
+            } else {
+                format!("{} ({})", binary.display(), libraries.join(", "))
+            }).collect()
+        };
+        add_entry(&mut edges, "Depends", &explanation.dependency, &via);
+    }
+
+    let fields = [
+        ("Pre-Depends", "pre-depends", &options.pre_depends),
+        ("Recommends", "recommends", &options.recommends),
+        ("Suggests", "suggests", &options.suggests),
+        ("Enhances", "enhances", &options.enhances),
+        ("Conflicts", "conflicts", &options.conflicts),
+        ("Breaks", "breaks", &options.breaks),
+        ("Replaces", "replaces", &options.replaces),
+        ("Provides", "provides", &options.provides),
+    ];
+    for (field, key, value) in fields {
+        if let Some(value) = options.relationship_field(value.as_deref(), listener)? {
+            let via = [format!("package.metadata.deb.{}", key)];
+            for entry in value.split(',') {
+                add_entry(&mut edges, field, entry, &via);
+            }
+        }
+    }
+    Ok(edges)
+}
+
+fn add_entry(edges: &mut Vec<Relationship>, field: &'static str, entry: &str, via: &[String]) {
+    for alternative in entry.split('|') {
+        let alternative = alternative.trim();
+        let (package, version) = alternative.split_once('(').unwrap_or((alternative, ""));
+        let package = package.trim();
+        if package.is_empty() {
+            continue;
+        }
+        edges.push(Relationship {
+            field,
+            package: package.to_owned(),
+            version: version.trim().trim_end_matches(')').trim().to_owned(),
+            via: via.to_vec(),
+        });
+    }
+}
+
+fn graph(options: &Config, edges: &[Relationship], format: GraphFormat) -> String {
+    match format {
+        GraphFormat::Json => {
+            let graph = json!({
+                "package": options.deb_name,
+                "version": options.deb_version,
+                "relationships": edges.iter().map(|edge| json!({
+                    "field": edge.field,
+                    "package": edge.package,
+                    "version": edge.version,
+                    "via": edge.via,
+                })).collect::<Vec<_>>(),
+            });
+            let mut json = serde_json::to_string_pretty(&graph).unwrap_or_default();
+            json.push('\n');
+            json
+        },
+        GraphFormat::Dot => {
+            let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+            let mut dot = format!("digraph {} {{\n    {} [shape=box, label={}];\n",
+                quote(&options.deb_name), quote(&options.deb_name), quote(&format!("{} {}", options.deb_name, options.deb_version)));
+            for edge in edges {
+                let mut label = edge.field.to_owned();
+                if !edge.version.is_empty() {
+                    let _ = write!(label, " ({})", edge.version);
+                }
+                for via in &edge.via {
+                    let _ = write!(label, "\nvia {}", via);
+                }
+                let style = match edge.field {
+                    "Conflicts" | "Breaks" => ", color=red",
+                    "Recommends" | "Suggests" | "Enhances" => ", style=dashed",
+                    "Provides" | "Replaces" => ", style=dotted",
+                    _ => "",
+                };
+                let _ = writeln!(dot, "    {} -> {} [label={}{}];", quote(&options.deb_name), quote(&edge.package), quote(&label).replace('\n', "\\n"), style);
+            }
+            dot.push_str("}\n");
+            dot
+        },
+    }
+}
+
+/// Graph of the relationships, and which binaries or metadata keys they come from
+pub fn relationship_graph(options: &Config, listener: &dyn Listener, format: GraphFormat) -> CDResult<String> {
+    Ok(graph(options, &relationships(options, listener)?, format))
+}
+
+/// Writes the graph next to the package, as `<package>.dot` or `<package>.graph.json`
+pub fn write_graph(graph: &str, package_path: &Path, format: GraphFormat) -> CDResult<PathBuf> {
+    let path = package_path.with_extension(match format {
+        GraphFormat::Dot => "dot",
+        GraphFormat::Json => "graph.json",
+    });
+    fs::write(&path, graph).map_err(|e| CargoDebError::IoFile("unable to write the relationship graph", e, path.clone()))?;
+    Ok(path)
+}
+
+#[test]
+fn relationship_graph_formats() {
+    let (config, _) = crate::util::tests::test_config();
+    let mut edges = Vec::new();
+    add_entry(&mut edges, "Depends", "libssl3 (>= 3.0) | libssl1.1", &["target/release/foo (libssl.so.3)".to_owned()]);
+    add_entry(&mut edges, "Conflicts", "foo-legacy", &["package.metadata.deb.conflicts".to_owned()]);
+    assert_eq!(3, edges.len());
+    assert_eq!(">= 3.0", edges[0].version);
+    assert_eq!("libssl1.1", edges[1].package);
+
+    let dot = graph(&config, &edges, GraphFormat::Dot);
+    assert!(dot.contains("\"cargo-deb\" -> \"libssl3\" [label=\"Depends (>= 3.0)\\nvia target/release/foo (libssl.so.3)\"];"), "{}", dot);
+    assert!(dot.contains("\"cargo-deb\" -> \"foo-legacy\" [label=\"Conflicts\\nvia package.metadata.deb.conflicts\", color=red];"), "{}", dot);
+
+    let json: serde_json::Value = serde_json::from_str(&graph(&config, &edges, GraphFormat::Json)).unwrap();
+    assert_eq!("cargo-deb", json["package"]);
+    assert_eq!("foo-legacy", json["relationships"][2]["package"]);
+    assert!("xml".parse::<GraphFormat>().is_err());
+}
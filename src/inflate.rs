@@ -0,0 +1,260 @@
+//! Minimal DEFLATE decoder for reading gzip-compressed package members.
+//!
+//! See [RFC 1951](https://www.rfc-editor.org/rfc/rfc1951) and [RFC 1952](https://www.rfc-editor.org/rfc/rfc1952).
+use crate::error::*;
+
+const MAX_BITS: usize = 15;
+
+/// Decompresses all members of a gzip file
+pub(crate) fn gunzip(mut data: &[u8]) -> CDResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3);
+    loop {
+        let rest = gzip_member(data, &mut out)?;
+        // Concatenated gzip members are valid, and padding zeros are tolerated
+        if rest.iter().all(|&b| b == 0) {
+            return Ok(out);
+        }
+        data = rest;
+    }
+}
+
+/// Returns data remaining after the member
+fn gzip_member<'a>(data: &'a [u8], out: &mut Vec<u8>) -> CDResult<&'a [u8]> {
+    if data.len() < 18 || data[0] != 0x1F || data[1] != 0x8B || data[2] != 8 {
+        return Err(CargoDebError::InvalidCompressedData("not a gzip file"));
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 4 != 0 {
+        let extra_len = usize::from(*data.get(pos).ok_or(TRUNCATED)?) | usize::from(*data.get(pos + 1).ok_or(TRUNCATED)?) << 8;
+        pos += 2 + extra_len;
+    }
+    // file name and comment are NUL-terminated
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            pos += data.get(pos..).ok_or(TRUNCATED)?.iter().position(|&b| b == 0).ok_or(TRUNCATED)? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        pos += 2;
+    }
+    let start = out.len();
+    let mut bits = BitReader { data: data.get(pos..).ok_or(TRUNCATED)?, pos: 0, bit_buf: 0, bit_count: 0 };
+    inflate(&mut bits, out)?;
+    let rest = &bits.data[bits.pos..];
+    if rest.len() < 8 {
+        return Err(TRUNCATED);
+    }
+    let crc = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+    let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]);
+    if size != (out.len() - start) as u32 {
+        return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: length mismatch"));
+    }
+    if crc != crate::deflate::crc32(&out[start..]) {
+        return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: CRC mismatch"));
+    }
+    Ok(&rest[8..])
+}
+
+const TRUNCATED: CargoDebError = CargoDebError::InvalidCompressedData("gzip data is truncated");
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> CDResult<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos).ok_or(TRUNCATED)?;
+            self.pos += 1;
+            self.bit_buf |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1 << count) - 1);
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Stored blocks start at a byte boundary
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Canonical Huffman code as counts of codes per length and symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> CDResult<Self> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader<'_>) -> CDResult<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: invalid Huffman code"))
+    }
+}
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub(crate) const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub(crate) const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+fn inflate(bits: &mut BitReader<'_>, out: &mut Vec<u8>) -> CDResult<()> {
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored_block(bits, out)?,
+            1 => {
+                let mut lengths = [0u8; 288 + 30];
+                lengths[..144].iter_mut().for_each(|l| *l = 8);
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                lengths[280..288].iter_mut().for_each(|l| *l = 8);
+                lengths[288..].iter_mut().for_each(|l| *l = 5);
+                huffman_block(bits, out, &Huffman::new(&lengths[..288])?, &Huffman::new(&lengths[288..])?)?;
+            },
+            2 => {
+                let (lit_len, dist) = dynamic_tables(bits)?;
+                huffman_block(bits, out, &lit_len, &dist)?;
+            },
+            _ => return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: invalid block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored_block(bits: &mut BitReader<'_>, out: &mut Vec<u8>) -> CDResult<()> {
+    bits.align_to_byte();
+    let header = bits.data.get(bits.pos..bits.pos + 4).ok_or(TRUNCATED)?;
+    let len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    if len != usize::from(!u16::from_le_bytes([header[2], header[3]])) {
+        return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: invalid stored block"));
+    }
+    bits.pos += 4;
+    out.extend_from_slice(bits.data.get(bits.pos..bits.pos + len).ok_or(TRUNCATED)?);
+    bits.pos += len;
+    Ok(())
+}
+
+fn dynamic_tables(bits: &mut BitReader<'_>) -> CDResult<(Huffman, Huffman)> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let num_lit_len = bits.bits(5)? as usize + 257;
+    let num_dist = bits.bits(5)? as usize + 1;
+    let num_code_len = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &ORDER[..num_code_len] {
+        code_lengths[index] = bits.bits(3)? as u8;
+    }
+    let code_len_huffman = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(num_lit_len + num_dist);
+    while lengths.len() < num_lit_len + num_dist {
+        let (value, repeat) = match code_len_huffman.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => (*lengths.last().ok_or(CargoDebError::InvalidCompressedData("gzip data is corrupted: no length to repeat"))?, 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| value));
+    }
+    if lengths.len() > num_lit_len + num_dist {
+        return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: too many code lengths"));
+    }
+    Ok((Huffman::new(&lengths[..num_lit_len])?, Huffman::new(&lengths[num_lit_len..])?))
+}
+
+fn huffman_block(bits: &mut BitReader<'_>, out: &mut Vec<u8>, lit_len: &Huffman, dist: &Huffman) -> CDResult<()> {
+    loop {
+        let symbol = usize::from(lit_len.decode(bits)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: invalid length"));
+        }
+        let len = usize::from(LENGTH_BASE[symbol]) + bits.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+        let dist_symbol = usize::from(dist.decode(bits)?);
+        if dist_symbol >= DIST_BASE.len() {
+            return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: invalid distance"));
+        }
+        let distance = usize::from(DIST_BASE[dist_symbol]) + bits.bits(u32::from(DIST_EXTRA[dist_symbol]))? as usize;
+        if distance > out.len() {
+            return Err(CargoDebError::InvalidCompressedData("gzip data is corrupted: distance too far back"));
+        }
+        let start = out.len() - distance;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+#[test]
+fn gunzip_roundtrip() {
+    let mut data = b"hello hello hello, this is a test of the decoder. ".repeat(20);
+    data.extend((0..=255u8).cycle().take(3000));
+    for input in [&data[..], b"short"] {
+        let mut compressed = Vec::new();
+        zopfli::compress(&zopfli::Options::default(), &zopfli::Format::Gzip, input, &mut compressed).unwrap();
+        assert_eq!(input, &gunzip(&compressed).unwrap()[..]);
+    }
+    assert_eq!(b"", &gunzip(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap()[..]);
+    assert!(gunzip(b"not gzipped at all").is_err());
+}
+
+#[test]
+fn gunzip_stored_block() {
+    let mut gz = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+    gz.extend_from_slice(&[1, 3, 0, !3, !0]);
+    gz.extend_from_slice(b"abc");
+    gz.extend_from_slice(&[0xc2, 0x41, 0x24, 0x35, 3, 0, 0, 0]);
+    assert_eq!(b"abc", &gunzip(&gz).unwrap()[..]);
+
+    let mut corrupted = gz.clone();
+    corrupted[15] = b'x';
+    assert!(gunzip(&corrupted).is_err());
+}
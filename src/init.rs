@@ -0,0 +1,212 @@
+//! `cargo deb init`, which writes a starting `[package.metadata.deb]` section based on what's in the crate
+use crate::error::*;
+use crate::manifest::{check_section, SYSTEMD_UNIT_TYPES};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// What was found in the crate's directory and `Cargo.toml`
+#[derive(Debug, Default)]
+pub(crate) struct CrateInfo {
+    pub name: String,
+    pub binaries: Vec<String>,
+    pub authors: Vec<String>,
+    /// `license` from `Cargo.toml`
+    pub license: Option<String>,
+    /// `LICENSE`, `COPYING`, etc.
+    pub license_file: Option<String>,
+    pub readme: Option<String>,
+    /// Unit files in `debian/`
+    pub systemd_units: Vec<String>,
+}
+
+pub(crate) struct Answers {
+    pub section: String,
+    /// Runs as a systemd service
+    pub daemon: bool,
+}
+
+impl CrateInfo {
+    pub fn inspect(manifest_path: &Path) -> CDResult<Self> {
+        let manifest_dir = manifest_path.parent().unwrap_or(Path::new("."));
+        let manifest = fs::read_to_string(manifest_path)
+            .map_err(|e| CargoDebError::IoFile("unable to read the manifest", e, manifest_path.to_owned()))?;
+        let manifest: toml::Value = toml::from_str(&manifest)?;
+        let package = manifest.get("package").ok_or("Cargo.toml has no [package]. In a workspace, use --manifest-path for one of its crates")?;
+        // fields inherited from the workspace aren't strings
+        let string = |key: &str| package.get(key).and_then(|v| v.as_str()).map(String::from);
+        let name = string("name").ok_or("Cargo.toml has no package name")?;
+
+        let mut binaries: Vec<String> = manifest.get("bin").and_then(|b| b.as_array()).into_iter().flatten()
+            .filter_map(|bin| bin.get("name")?.as_str().map(String::from))
+            .collect();
+        if binaries.is_empty() {
+            if manifest_dir.join("src/main.rs").exists() {
+                binaries.push(name.clone());
+            }
+            for entry in fs::read_dir(manifest_dir.join("src/bin")).into_iter().flatten().flatten() {
+                let path = entry.path();
+                let is_bin = path.extension().is_some_and(|e| e == "rs") || path.join("main.rs").exists();
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()).filter(|_| is_bin) {
+                    binaries.push(stem.to_owned());
+                }
+            }
+            binaries.sort();
+        }
+
+        let root_file = |names: &[&str]| names.iter().find(|n| manifest_dir.join(n).is_file()).map(|n| (*n).to_owned());
+        let mut systemd_units: Vec<String> = fs::read_dir(manifest_dir.join("debian")).into_iter().flatten().flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.rsplit_once('.').is_some_and(|(_, ext)| SYSTEMD_UNIT_TYPES.contains(&ext)))
+            .collect();
+        systemd_units.sort();
+
+        Ok(Self {
+            binaries,
+            authors: package.get("authors").and_then(|a| a.as_array()).into_iter().flatten()
+                .filter_map(|a| a.as_str().map(String::from))
+                .collect(),
+            license: string("license"),
+            license_file: string("license-file").or_else(|| root_file(&["LICENSE", "LICENSE.md", "LICENSE.txt", "COPYING", "LICENSE-MIT"])),
+            readme: string("readme").or_else(|| root_file(&["README.md", "README", "README.txt"])),
+            systemd_units,
+            name,
+        })
+    }
+}
+
+/// Asks about the section and whether it's a daemon. Empty answers keep the defaults.
+pub(crate) fn ask(info: &CrateInfo, input: &mut dyn BufRead, output: &mut dyn Write) -> CDResult<Answers> {
+    let section = loop {
+        let section = read_line(input, output, "Section of the Debian archive, e.g. utils, net, admin, devel [utils]: ")?;
+        if section.is_empty() {
+            break "utils".to_owned();
+        }
+        match check_section(&section) {
+            Ok(()) => break section,
+            Err(err) => writeln!(output, "{}", err)?,
+        }
+    };
+    let daemon_default = !info.systemd_units.is_empty();
+    let daemon = read_line(input, output, if daemon_default { "Is it a daemon that runs as a systemd service? [Y/n]: " } else { "Is it a daemon that runs as a systemd service? [y/N]: " })?;
+    let daemon = match daemon.to_ascii_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => daemon_default,
+    };
+    Ok(Answers { section, daemon })
+}
+
+fn read_line(input: &mut dyn BufRead, output: &mut dyn Write, prompt: &str) -> CDResult<String> {
+    write!(output, "{}", prompt)?;
+    output.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(line.trim().to_owned())
+}
+
+/// The TOML to append to `Cargo.toml`, with comments about the defaults
+pub(crate) fn metadata_section(info: &CrateInfo, answers: &Answers) -> String {
+    let mut toml = String::new();
+    toml.push_str("[package.metadata.deb]\n");
+    toml.push_str("# Written by `cargo deb init`. See https://github.com/kornelski/cargo-deb#configuration for all the settings\n");
+    match info.authors.first() {
+        Some(author) => { let _ = writeln!(toml, "# maintainer = {}  # defaults to the first author", quoted(author)); },
+        None => toml.push_str("# maintainer = \"Your Name <you@example.com>\"  # defaults to the git user.name and user.email\n"),
+    }
+    toml.push_str("# copyright = \"Your Name\"  # defaults to the authors\n");
+    match (&info.license, &info.license_file) {
+        (None, Some(file)) => { let _ = writeln!(toml, "license-file = [{}, \"0\"]", quoted(file)); },
+        (None, None) => toml.push_str("# license-file = [\"LICENSE\", \"0\"]  # Cargo.toml has no license\n"),
+        (Some(_), _) => {},
+    }
+    if let Some(readme) = &info.readme {
+        let _ = writeln!(toml, "# extended-description-file = {}  # defaults to the readme", quoted(readme));
+    }
+    let _ = writeln!(toml, "section = {}", quoted(&answers.section));
+    toml.push_str("priority = \"optional\"\n");
+    toml.push_str("depends = \"$auto\"\n");
+
+    toml.push_str("assets = [\n");
+    for bin in &info.binaries {
+        let _ = writeln!(toml, "    [{}, \"usr/bin/\", \"755\"],", quoted(&format!("target/release/{}", bin)));
+    }
+    if let Some(readme) = &info.readme {
+        let file_name = Path::new(readme).file_name().map_or_else(|| readme.clone(), |f| f.to_string_lossy().into_owned());
+        let _ = writeln!(toml, "    [{}, {}, \"644\"],", quoted(readme), quoted(&format!("usr/share/doc/{}/{}", info.name, file_name)));
+    }
+    toml.push_str("]\n");
+
+    if answers.daemon {
+        toml.push_str("maintainer-scripts = \"debian/\"\n");
+        toml.push_str("\n[package.metadata.deb.systemd]\n");
+        if info.systemd_units.is_empty() {
+            let _ = writeln!(toml, "# Save the unit as debian/{}.service", info.name);
+        } else {
+            let _ = writeln!(toml, "# Installs {} from debian/", info.systemd_units.join(", "));
+        }
+        toml.push_str("enable = true\n");
+        toml.push_str("start = true\n");
+    }
+    toml
+}
+
+fn quoted(s: &str) -> String {
+    toml::Value::String(s.to_owned()).to_string()
+}
+
+/// Asks questions when stdin is a terminal, and appends the section to `Cargo.toml`
+pub fn init(manifest_path: Option<&str>, interactive: bool) -> CDResult<PathBuf> {
+    let manifest_path = PathBuf::from(manifest_path.unwrap_or("Cargo.toml"));
+    let manifest = fs::read_to_string(&manifest_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read the manifest", e, manifest_path.clone()))?;
+    if manifest.lines().any(|l| l.trim() == "[package.metadata.deb]") {
+        return Err(CargoDebError::AlreadyInitialized(manifest_path));
+    }
+    let info = CrateInfo::inspect(&manifest_path)?;
+    let answers = if interactive {
+        ask(&info, &mut io::stdin().lock(), &mut io::stderr())?
+    } else {
+        Answers { section: "utils".into(), daemon: !info.systemd_units.is_empty() }
+    };
+
+    let mut manifest = manifest;
+    if !manifest.ends_with("\n\n") {
+        manifest.push_str(if manifest.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+    manifest.push_str(&metadata_section(&info, &answers));
+    fs::write(&manifest_path, manifest)
+        .map_err(|e| CargoDebError::IoFile("unable to write the manifest", e, manifest_path.clone()))?;
+    Ok(manifest_path)
+}
+
+#[test]
+fn init_section() {
+    let info = CrateInfo {
+        name: "foo".into(),
+        binaries: vec!["foo".into(), "foo-helper".into()],
+        license_file: Some("COPYING".into()),
+        readme: Some("README.md".into()),
+        systemd_units: vec!["foo.service".into()],
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    let answers = ask(&info, &mut &b"utilities\nnet\n\n"[..], &mut output).unwrap();
+    assert_eq!("net", answers.section);
+    assert!(answers.daemon);
+    assert!(String::from_utf8(output).unwrap().contains("Did you mean 'utils'?"));
+
+    let toml = metadata_section(&info, &answers);
+    assert!(toml.contains("license-file = [\"COPYING\", \"0\"]\n"), "{}", toml);
+    assert!(toml.contains("    [\"target/release/foo-helper\", \"usr/bin/\", \"755\"],\n"), "{}", toml);
+    assert!(toml.contains("    [\"README.md\", \"usr/share/doc/foo/README.md\", \"644\"],\n"), "{}", toml);
+    let parsed: toml::Value = toml::from_str(&toml).unwrap();
+    let deb = parsed["package"]["metadata"]["deb"].clone();
+    assert_eq!("net", deb["section"].as_str().unwrap());
+    assert!(deb["systemd"]["enable"].as_bool().unwrap());
+    crate::manifest::check_metadata_deb(deb).unwrap();
+
+    let cli = Answers { section: "utils".into(), daemon: false };
+    assert!(!metadata_section(&CrateInfo { name: "foo".into(), ..Default::default() }, &cli).contains("systemd"));
+}
@@ -1,4 +1,5 @@
-#![recursion_limit = "128"]
+// quick_error! expands CargoDebError a few macro levels per variant, and since the armhf-baseline errors that is more than 256
+#![recursion_limit = "512"]
 
 /*!
 
@@ -25,29 +26,63 @@ pub mod control;
 pub mod data;
 pub mod listener;
 pub mod manifest;
-pub use crate::debarchive::DebArchive;
+pub mod reader;
+pub mod vfs;
+pub use crate::build_options::DebBuildOptions;
+pub use crate::check_deps::check_dependencies;
+pub use crate::completions::{completions, CliOption, OptionKind, CLI_OPTIONS};
+pub use crate::extract::extract_package;
+pub use crate::graph::{relationship_graph, write_graph, GraphFormat};
+pub use crate::print_config::{print_config, ConfigFormat};
+pub use crate::provenance::write_provenance;
+pub use crate::sbom::write_sbom_sidecar;
+pub use crate::verify::{verify_installed, verify_package};
+pub use crate::init::init;
+pub use crate::debarchive::{split_package, DebArchive};
+pub use crate::elf::Hardening;
 pub use crate::error::*;
-pub use crate::manifest::Config;
+pub use crate::manifest::{Config, ManifestOptions};
+pub use crate::util::clamped_timestamp;
 
 #[macro_use]
 mod util;
+mod build_options;
+mod check_deps;
+mod completions;
 mod config;
 mod debarchive;
+mod debian_dir;
+mod deflate;
 mod dependencies;
 mod dh_installsystemd;
 mod dh_lib;
+mod elf;
 mod error;
+mod extract;
+mod graph;
+mod init;
+mod inflate;
+mod markdown;
 mod ok_or;
 mod pathbytes;
+mod polkit;
+mod print_config;
+mod provenance;
+mod sbom;
+mod sha256;
 mod tararchive;
+mod template;
+mod unzstd;
+mod verify;
 mod wordsplit;
 
-use crate::listener::Listener;
+use crate::listener::{Listener, LogLevel, Warning};
 use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, Write};
 use std::path::Path;
-use std::process::{Command, ExitStatus};
+use std::process::{Command, ExitStatus, Stdio};
 
 const TAR_REJECTS_CUR_DIR: bool = true;
 
@@ -73,10 +108,8 @@ pub fn reset_deb_temp_directory(options: &Config) -> io::Result<()> {
     // but this time only debs from other versions of the same package
     let g = deb_dir.join(DebArchive::filename_glob(options));
     if let Ok(old_files) = glob::glob(g.to_str().expect("utf8 path")) {
-        for old_file in old_files {
-            if let Ok(old_file) = old_file {
-                let _ = fs::remove_file(old_file);
-            }
+        for old_file in old_files.flatten() {
+            let _ = fs::remove_file(old_file);
         }
     }
     fs::create_dir_all(deb_temp_dir)
@@ -88,21 +121,118 @@ pub fn remove_deb_temp_directory(options: &Config) {
     let _ = fs::remove_dir(&deb_temp_dir);
 }
 
-/// Builds a release binary with `cargo build --release`
-pub fn cargo_build(options: &Config, target: Option<&str>, other_flags: &[String], verbose: bool) -> CDResult<()> {
+/// Builds a release binary with `cargo build --release` (or the configured `--profile`)
+pub fn cargo_build(options: &Config, target: Option<&str>, other_flags: &[String], log_level: LogLevel) -> CDResult<()> {
+    let mut cmd = cargo_build_command(options, target, other_flags, log_level);
+    // cargo's messages are passed through, and checked for linker errors
+    if crate::listener::use_color() && env::var_os("CARGO_TERM_COLOR").is_none() {
+        cmd.env("CARGO_TERM_COLOR", "always");
+    }
+    let mut child = cmd.stderr(Stdio::piped()).spawn()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    let mut linking_failed = false;
+    if let Some(stderr) = child.stderr.take() {
+        let mut out = io::stderr().lock();
+        for line in io::BufReader::new(stderr).split(b'\n') {
+            let line = line?;
+            linking_failed |= is_linker_error(&line);
+            out.write_all(&line)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    let status = child.wait().map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    if !status.success() {
+        // The host's `cc` can't link for other architectures
+        if let Some(target) = target.filter(|&target| linking_failed && target != DEFAULT_TARGET) {
+            if options.linker.is_none() && options.cargo_linker(target).is_none() {
+                return Err(CargoDebError::CrossBuildFailed(target.to_owned(), debian_triple(target)));
+            }
+        }
+        return Err(CargoDebError::BuildFailed);
+    }
+    Ok(())
+}
+
+/// rustc's `linking with `cc` failed` or `linker `cc` not found`, possibly with color codes around `error`
+fn is_linker_error(line: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(line);
+    line.contains(": linking with `") || (line.contains(": linker `") && line.contains("` not found"))
+}
+
+/// Environment variables and arguments of `cargo build`, like `VAR=value build --release`.
+/// Packages, e.g. variants, built with the same ones can share the build.
+#[must_use]
+pub fn cargo_build_args(options: &Config, target: Option<&str>, other_flags: &[String]) -> Vec<OsString> {
+    let cmd = cargo_build_command(options, target, other_flags, LogLevel::Normal);
+    let mut env: Vec<OsString> = cmd.get_envs().map(|(name, value)| {
+        let mut var = name.to_owned();
+        var.push("=");
+        var.push(value.unwrap_or_default());
+        var
+    }).collect();
+    env.sort();
+    env.extend(cmd.get_args().map(OsStr::to_owned));
+    env
+}
+
+fn cargo_build_command(options: &Config, target: Option<&str>, other_flags: &[String], log_level: LogLevel) -> Command {
+    let profile = profile_flag(options);
+    let mut args = vec!["build".to_owned(), profile];
+    if options.binaries.is_none() && options.examples.is_empty() {
+        args.push("--all".into());
+    } else {
+        // Selecting targets with --all would look for them in every workspace member
+        args.push(format!("--package={}", options.name));
+        args.extend(options.binaries.as_ref().unwrap_or(&options.binary_targets).iter().map(|name| format!("--bin={}", name)));
+        args.extend(options.examples.iter().map(|name| format!("--example={}", name)));
+    }
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    cargo_command(options, &args, target, other_flags, log_level)
+}
+
+/// Runs `cargo test --release` for the package, with the same features, target and profile as the build
+pub fn cargo_test(options: &Config, target: Option<&str>, other_flags: &[String], log_level: LogLevel) -> CDResult<()> {
+    let package = format!("--package={}", options.name);
+    let profile = profile_flag(options);
+    let status = cargo_command(options, &["test", &profile, &package], target, other_flags, log_level).status()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    if !status.success() {
+        return Err(CargoDebError::TestsFailed);
+    }
+    Ok(())
+}
+
+fn profile_flag(options: &Config) -> String {
+    if options.profile == "release" {
+        "--release".to_owned()
+    } else {
+        format!("--profile={}", options.profile)
+    }
+}
+
+fn cargo_command(options: &Config, args: &[&str], target: Option<&str>, other_flags: &[String], log_level: LogLevel) -> Command {
     let mut cmd = Command::new("cargo");
     cmd.current_dir(&options.manifest_dir);
-    cmd.arg("build").args(&["--release", "--all"]);
+    cmd.args(args);
 
-    for flag in other_flags {
+    for flag in options.cargo_build_flags.iter().chain(other_flags) {
         cmd.arg(flag);
     }
+    cmd.envs(options.build_env());
+    // Relative paths and configs would be looked up from a different directory than `cargo metadata` did
+    cmd.env("CARGO_TARGET_DIR", &options.cargo_target_dir);
 
-    if verbose {
-        cmd.arg("--verbose");
+    match log_level {
+        LogLevel::Quiet => { cmd.arg("--quiet"); },
+        LogLevel::Normal => {},
+        LogLevel::Verbose => { cmd.arg("--verbose"); },
+        LogLevel::Debug => { cmd.arg("-vv"); },
     }
     if let Some(target) = target {
         cmd.arg(format!("--target={}", target));
+        if let Some(linker) = options.cross_linker(target) {
+            cmd.env(config::target_env_var(target, "linker"), linker);
+        }
         // Set helpful defaults for cross-compiling
         if env::var_os("PKG_CONFIG_ALLOW_CROSS").is_none() && env::var_os("PKG_CONFIG_PATH").is_none() {
             let pkg_config_path = format!("/usr/lib/{}/pkgconfig", debian_triple(target));
@@ -119,20 +249,14 @@ pub fn cargo_build(options: &Config, target: Option<&str>, other_flags: &[String
     if !features.is_empty() {
         cmd.arg(format!("--features={}", features.join(",")));
     }
-
-    let status = cmd.status()
-        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
-    if !status.success() {
-        return Err(CargoDebError::BuildFailed);
-    }
-    Ok(())
+    cmd
 }
 
 // Maps Rust's blah-unknown-linux-blah to Debian's blah-linux-blah
 fn debian_triple(rust_target_triple: &str) -> String {
     let mut p = rust_target_triple.split('-');
     let arch = p.next().unwrap();
-    let abi = p.last().unwrap_or("");
+    let abi = p.next_back().unwrap_or("");
 
     let (darch, dabi) = match (arch, abi) {
         ("i586", _) |
@@ -153,7 +277,7 @@ fn ensure_success(status: ExitStatus) -> io::Result<()> {
     if status.success() {
         Ok(())
     } else {
-        Err(io::Error::new(io::ErrorKind::Other, format!("{}", status)))
+        Err(io::Error::other(format!("{}", status)))
     }
 }
 
@@ -212,6 +336,7 @@ pub fn strip_binaries(options: &mut Config, target: Option<&str>, listener: &mut
                 }
                 Command::new(strip_cmd)
                    .arg("--strip-unneeded")
+                   .args(options.strip_keep.strip_args(&asset.strip_keep))
                    .arg(path)
                    .status()
                    .and_then(ensure_success)
@@ -238,7 +363,7 @@ pub fn strip_binaries(options: &mut Config, target: Option<&str>, listener: &mut
             },
             None => {
                 // This is unexpected - emit a warning if we come across it
-                listener.warning(format!("Found built asset with non-path source '{:?}'", asset));
+                listener.warning(Warning::NonPathAsset, format!("Found built asset with non-path source '{:?}'", asset));
             }
         }
     }
@@ -250,3 +375,21 @@ pub fn strip_binaries(options: &mut Config, target: Option<&str>, listener: &mut
 
     Ok(())
 }
+
+#[test]
+fn variants_with_other_build_env_are_built_again() {
+    let (mut config, _) = crate::util::tests::test_config();
+    let first = cargo_build_args(&config, None, &[]);
+    assert!(first.contains(&OsString::from(format!("CARGO_DEB_PACKAGE={}", config.deb_name))), "{:?}", first);
+    assert_eq!(first, cargo_build_args(&config, None, &[]));
+    config.deb_name.push_str("-server");
+    assert_ne!(first, cargo_build_args(&config, None, &[]));
+}
+
+#[test]
+fn linker_errors() {
+    assert!(is_linker_error(b"error: linking with `cc` failed: exit status: 1"));
+    assert!(is_linker_error(b"\x1b[0m\x1b[1m\x1b[38;5;9merror\x1b[0m\x1b[0m\x1b[1m: linker `aarch64-linux-gnu-gcc` not found\x1b[0m"));
+    assert!(!is_linker_error(b"error[E0425]: cannot find value `linker` in this scope"));
+    assert!(!is_linker_error(b"error: could not compile `foo` (bin \"foo\") due to 1 previous error"));
+}
@@ -1,25 +1,219 @@
+use crate::error::*;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Kinds of warnings. Their codes are printed as `warning[code]`, and can be given to `--deny` to make them errors,
+/// or to `--allow` to hide them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    MissingDescription,
+    MissingLicense,
+    UnusedReadme,
+    AutoDepends,
+    UndefinedSubstvar,
+    NonPathAsset,
+    IncompleteProvenance,
+    NoMd5sums,
+    SkippedFile,
+    NotLinux,
+    UsrMerge,
+    Fhs,
+    Rpath,
+    Hardening,
+    PackageName,
+    Policy,
+    ArmhfBaseline,
+    DebugBinary,
+}
+
+impl Warning {
+    pub const ALL: [Warning; 18] = [
+        Warning::MissingDescription,
+        Warning::MissingLicense,
+        Warning::UnusedReadme,
+        Warning::AutoDepends,
+        Warning::UndefinedSubstvar,
+        Warning::NonPathAsset,
+        Warning::IncompleteProvenance,
+        Warning::NoMd5sums,
+        Warning::SkippedFile,
+        Warning::NotLinux,
+        Warning::UsrMerge,
+        Warning::Fhs,
+        Warning::Rpath,
+        Warning::Hardening,
+        Warning::PackageName,
+        Warning::Policy,
+        Warning::ArmhfBaseline,
+        Warning::DebugBinary,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Warning::MissingDescription => "missing-description",
+            Warning::MissingLicense => "missing-license",
+            Warning::UnusedReadme => "unused-readme",
+            Warning::AutoDepends => "auto-depends",
+            Warning::UndefinedSubstvar => "undefined-substvar",
+            Warning::NonPathAsset => "non-path-asset",
+            Warning::IncompleteProvenance => "incomplete-provenance",
+            Warning::NoMd5sums => "no-md5sums",
+            Warning::SkippedFile => "skipped-file",
+            Warning::NotLinux => "not-linux",
+            Warning::UsrMerge => "usr-merge",
+            Warning::Fhs => "fhs",
+            Warning::Rpath => "rpath",
+            Warning::Hardening => "hardening",
+            Warning::PackageName => "package-name",
+            Warning::Policy => "policy",
+            Warning::ArmhfBaseline => "armhf-baseline",
+            Warning::DebugBinary => "debug-binary",
+        }
+    }
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait Listener: Send + Sync {
-    fn warning(&self, s: String);
+    fn warning(&self, code: Warning, s: String);
     fn info(&self, s: String);
 }
 
 pub struct NoOpListener;
 impl Listener for NoOpListener {
     fn info(&self, _s: String) {}
-    fn warning(&self, _s: String) {}
+    fn warning(&self, _code: Warning, _s: String) {}
+}
+
+/// How much is printed, set by `-q`, `-v` and `-vv`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Only errors
+    Quiet,
+    /// Warnings
+    Normal,
+    /// Warnings and progress
+    Verbose,
+    /// Also verbose output of cargo
+    Debug,
+}
+
+impl LogLevel {
+    pub fn from_flags(quiet: bool, verbose_count: usize) -> Self {
+        match (quiet, verbose_count) {
+            (true, _) => LogLevel::Quiet,
+            (false, 0) => LogLevel::Normal,
+            (false, 1) => LogLevel::Verbose,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+/// Colors are used when stderr is a terminal, unless `NO_COLOR` is set
+#[must_use]
+pub fn use_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// `error[code]` in bold red, or `warning[code]` in bold yellow
+#[must_use]
+pub fn label(kind: &str, code: &str, color: bool) -> String {
+    let style = if kind == "error" { "1;31" } else { "1;33" };
+    if color {
+        format!("\x1b[{}m{}[{}]\x1b[0m", style, kind, code)
+    } else {
+        format!("{}[{}]", kind, code)
+    }
 }
 
 pub struct StdErrListener {
-    pub verbose: bool,
+    level: LogLevel,
+    deny: Vec<Warning>,
+    allow: Vec<Warning>,
+    denied: AtomicUsize,
+    color: bool,
+}
+
+impl StdErrListener {
+    /// Warnings in `deny` are printed as errors, even when quiet, and counted in `denied()`
+    pub fn new(level: LogLevel, deny: Vec<Warning>) -> Self {
+        Self { level, deny, allow: Vec::new(), denied: AtomicUsize::new(0), color: use_color() }
+    }
+
+    /// Warnings in `allow` aren't printed, even if they're denied too, e.g. by `--deny warnings`
+    #[must_use]
+    pub fn with_allowed(mut self, allow: Vec<Warning>) -> Self {
+        self.allow = allow;
+        self
+    }
+
+    /// Number of warnings that were denied
+    pub fn denied(&self) -> usize {
+        self.denied.load(Ordering::Relaxed)
+    }
 }
+
 impl Listener for StdErrListener {
-    fn warning(&self, s: String) {
-        eprintln!("warning: {}", s);
+    fn warning(&self, code: Warning, s: String) {
+        if self.allow.contains(&code) {
+            return;
+        }
+        if self.deny.contains(&code) {
+            self.denied.fetch_add(1, Ordering::Relaxed);
+            eprintln!("{}: {}", label("error", code.code(), self.color), s);
+        } else if self.level >= LogLevel::Normal {
+            eprintln!("{}: {}", label("warning", code.code(), self.color), s);
+        }
     }
     fn info(&self, s: String) {
-        if self.verbose {
+        if self.level >= LogLevel::Verbose {
             eprintln!("info: {}", s);
         }
     }
 }
+
+/// Warnings about the control file that Lintian would also report, which `--deny policy` denies
+const POLICY: [Warning; 4] = [Warning::Policy, Warning::MissingDescription, Warning::MissingLicense, Warning::PackageName];
+
+/// Codes for `--deny`, separated by commas. `warnings` denies all of them, and `policy` also the other warnings about fields.
+pub fn parse_deny(codes: &[String]) -> CDResult<Vec<Warning>> {
+    parse_codes(codes, "deny")
+}
+
+/// Codes for `--allow`, like for `--deny`
+pub fn parse_allow(codes: &[String]) -> CDResult<Vec<Warning>> {
+    parse_codes(codes, "allow")
+}
+
+fn parse_codes(codes: &[String], option: &'static str) -> CDResult<Vec<Warning>> {
+    let mut warnings = Vec::new();
+    for code in codes.iter().flat_map(|codes| codes.split(',')).map(str::trim).filter(|c| !c.is_empty()) {
+        if code == "warnings" {
+            warnings.extend_from_slice(&Warning::ALL);
+        } else if code == "policy" {
+            warnings.extend_from_slice(&POLICY);
+        } else {
+            warnings.push(*Warning::ALL.iter().find(|w| w.code() == code).ok_or_else(|| CargoDebError::UnknownWarningCode(code.to_owned(), option))?);
+        }
+    }
+    Ok(warnings)
+}
+
+#[test]
+fn deny_warnings() {
+    assert_eq!(vec![Warning::MissingLicense, Warning::NoMd5sums], parse_deny(&["missing-license,no-md5sums".into()]).unwrap());
+    assert_eq!(Warning::ALL.len(), parse_deny(&["warnings".into()]).unwrap().len());
+    assert!(parse_deny(&["typo".into()]).is_err());
+    assert!(parse_deny(&["policy".into()]).unwrap().contains(&Warning::MissingDescription));
+
+    let listener = StdErrListener::new(LogLevel::Quiet, vec![Warning::MissingLicense]);
+    listener.warning(Warning::NoMd5sums, "ignored".into());
+    listener.warning(Warning::MissingLicense, "denied".into());
+    assert_eq!(1, listener.denied());
+
+    let listener = StdErrListener::new(LogLevel::Quiet, Warning::ALL.to_vec()).with_allowed(parse_allow(&["rpath".into()]).unwrap());
+    listener.warning(Warning::Rpath, "allowed".into());
+    assert_eq!(0, listener.denied());
+    assert!(parse_allow(&["typo".into()]).unwrap_err().to_string().contains("--allow"));
+    assert_eq!("warning[fhs]", label("warning", Warning::Fhs.code(), false));
+    assert_eq!("\x1b[1;31merror[CD0101]\x1b[0m", label("error", CargoDebError::BuildFailed.code(), true));
+}
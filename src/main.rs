@@ -1,6 +1,8 @@
 use cargo_deb::*;
+use std::collections::HashSet;
 use std::env;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::time;
 
@@ -9,46 +11,73 @@ struct CliOptions {
     no_strip: bool,
     separate_debug_symbols: bool,
     fast: bool,
-    verbose: bool,
+    verbose: usize,
     quiet: bool,
+    deny: Vec<String>,
+    allow: Vec<String>,
     install: bool,
+    check_deps: bool,
+    explain_deps: bool,
+    hardening: bool,
+    graph: Option<String>,
+    print_config: Option<String>,
+    run_tests: bool,
+    provenance: bool,
+    timings: bool,
+    split_size: Option<String>,
+    xz_threads: Option<String>,
+    xz_memory_limit: Option<String>,
+    sbom: Option<String>,
+    sbom_sidecar: bool,
+    build_ids: bool,
+    packages_index: Option<String>,
     package_name: Option<String>,
     output_path: Option<String>,
     variant: Option<String>,
+    target_dir: Option<String>,
     target: Option<String>,
+    profile: Option<String>,
     manifest_path: Option<String>,
+    deb_config: Option<String>,
     cargo_build_flags: Vec<String>,
     deb_version: Option<String>,
+    append_git_version: bool,
+    deb_suffix: Option<String>,
+    backport: Option<String>,
+    changelog_distribution: Option<String>,
+    distro_profile: Option<String>,
+    changelog_urgency: Option<String>,
+    format: Option<String>,
+    compat: Option<String>,
+    substvars: Vec<String>,
+    binaries: Vec<String>,
+    examples: Vec<String>,
+    features: Vec<String>,
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut cli_opts = getopts::Options::new();
-    cli_opts.optflag("", "no-build", "Assume project is already built");
-    cli_opts.optflag("", "no-strip", "Do not strip debug symbols from the binary");
-    cli_opts.optflag("", "separate-debug-symbols", "Strip debug symbols into a separate .debug file");
-    cli_opts.optflag("", "fast", "Use faster compression, which yields larger archive");
-    cli_opts.optflag("", "install", "Immediately install created package");
-    cli_opts.optopt("", "target", "Rust target for cross-compilation", "triple");
-    cli_opts.optopt("", "variant", "Alternative configuration section to use", "name");
-    cli_opts.optopt("", "manifest-path", "Cargo project file location", "./Cargo.toml");
-    cli_opts.optopt("p", "package", "Select one of packages belonging to a workspace", "name");
-    cli_opts.optopt("o", "output", "Write .deb to this file or directory", "path");
-    cli_opts.optflag("q", "quiet", "Don't print warnings");
-    cli_opts.optflag("v", "verbose", "Print progress");
-    cli_opts.optflag("h", "help", "Print this help menu");
-    cli_opts.optflag("", "version", "Show the version of cargo-deb");
-    cli_opts.optopt("", "deb-version", "Alternate version string for package", "version");
+    for option in CLI_OPTIONS {
+        let CliOption { short, long, help, hint, kind } = *option;
+        match kind {
+            OptionKind::Flag => cli_opts.optflag(short, long, help),
+            OptionKind::FlagMulti => cli_opts.optflagmulti(short, long, help),
+            OptionKind::FlagOpt => cli_opts.optflagopt(short, long, help, hint),
+            OptionKind::Opt => cli_opts.optopt(short, long, help, hint),
+            OptionKind::OptMulti => cli_opts.optmulti(short, long, help, hint),
+        };
+    }
 
     let matches = match cli_opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(err) => {
-            err_exit(&err);
+            err_exit(&CargoDebError::InvalidArguments(err.to_string()));
         },
     };
     if matches.opt_present("h") {
-        print!("{}", cli_opts.usage("Usage: cargo deb [options] [-- <cargo build flags>]"));
+        print!("{}", cli_opts.usage("Usage: cargo deb [options] [-- <cargo build flags>]\n       cargo deb verify <file.deb>\n       cargo deb verify-installed <package or file.deb> [<root dir>]\n       cargo deb extract <file.deb> <dir>\n       cargo deb init\n       cargo deb completions <bash|zsh|fish>"));
         return;
     }
 
@@ -57,27 +86,135 @@ fn main() {
         return;
     }
 
+    let mut free_args = matches.free.clone();
+    // `cargo deb` invocation passes the `deb` arg through.
+    if free_args.first().is_some_and(|arg| arg == "deb") {
+        free_args.remove(0);
+    }
+
+    if free_args.first().is_some_and(|arg| arg == "verify") {
+        let quiet = matches.opt_present("quiet");
+        match verify(&free_args[1..], quiet, matches.opt_count("verbose")) {
+            Ok(()) => return,
+            Err(err) => err_exit(&err),
+        }
+    }
+
+    if free_args.first().is_some_and(|arg| arg == "verify-installed") {
+        let quiet = matches.opt_present("quiet");
+        match verify_installed_files(&free_args[1..], quiet, matches.opt_count("verbose")) {
+            Ok(()) => return,
+            Err(err) => err_exit(&err),
+        }
+    }
+
+    if free_args.first().is_some_and(|arg| arg == "init") {
+        match cargo_deb::init(matches.opt_str("manifest-path").as_deref(), std::io::stdin().is_terminal()) {
+            Ok(manifest_path) => {
+                if !matches.opt_present("quiet") {
+                    println!("Added [package.metadata.deb] to {}", manifest_path.display());
+                }
+                return;
+            },
+            Err(err) => err_exit(&err),
+        }
+    }
+
+    if free_args.first().is_some_and(|arg| arg == "completions") {
+        let Some(shell) = free_args.get(1) else {
+            err_exit(&CargoDebError::MissingArguments("Specify the shell, e.g. cargo deb completions bash"));
+        };
+        match cargo_deb::completions(shell) {
+            Ok(script) => {
+                print!("{}", script);
+                return;
+            },
+            Err(err) => err_exit(&err),
+        }
+    }
+
+    if free_args.first().is_some_and(|arg| arg == "extract") {
+        let quiet = matches.opt_present("quiet");
+        match extract(&free_args[1..], quiet, matches.opt_count("verbose")) {
+            Ok(()) => return,
+            Err(err) => err_exit(&err),
+        }
+    }
+
     let install = matches.opt_present("install");
-    match process(CliOptions {
-        no_build: matches.opt_present("no-build"),
-        no_strip: matches.opt_present("no-strip"),
-        separate_debug_symbols: matches.opt_present("separate-debug-symbols"),
-        quiet: matches.opt_present("quiet"),
-        verbose: matches.opt_present("verbose"),
-        install,
-        // when installing locally it won't be transferred anywhere, so allow faster compression
-        fast: install || matches.opt_present("fast"),
-        variant: matches.opt_str("variant"),
-        target: matches.opt_str("target"),
-        output_path: matches.opt_str("output"),
-        package_name: matches.opt_str("package"),
-        manifest_path: matches.opt_str("manifest-path"),
-        deb_version: matches.opt_str("deb-version"),
-        cargo_build_flags: matches.free,
-    }) {
-        Ok(()) => {},
-        Err(err) => {
-            err_exit(&err);
+    let variants = matches.opt_strs("variant");
+    let output_path = matches.opt_str("output");
+    if variants.len() > 1 && output_path.as_deref().is_some_and(|path| !path.ends_with('/') && !Path::new(path).is_dir()) {
+        err_exit(&CargoDebError::InvalidArguments("--output must be a directory when making several --variant packages".into()));
+    }
+    let mut cargo_build_flags: Vec<String> = matches.opt_strs("cargo-build-flags").iter()
+        .flat_map(|flags| flags.split_whitespace().map(String::from))
+        .chain(free_args.iter().cloned())
+        .collect();
+    for flag in CARGO_NETWORK_FLAGS {
+        if matches.opt_present(&flag[2..]) && !cargo_build_flags.iter().any(|f| f == flag) {
+            cargo_build_flags.push(flag.to_owned());
+        }
+    }
+    // Variants with the same cargo build flags reuse the build of the first one
+    let mut builds = HashSet::new();
+    let variants = if variants.is_empty() { vec![None] } else { variants.into_iter().map(Some).collect() };
+    for variant in variants {
+        match process(CliOptions {
+            no_build: matches.opt_present("no-build"),
+            no_strip: matches.opt_present("no-strip"),
+            separate_debug_symbols: matches.opt_present("separate-debug-symbols"),
+            quiet: matches.opt_present("quiet"),
+            verbose: matches.opt_count("verbose"),
+            deny: matches.opt_strs("deny"),
+            allow: matches.opt_strs("allow"),
+            install,
+            check_deps: matches.opt_present("check-deps"),
+            explain_deps: matches.opt_present("explain-deps"),
+            hardening: matches.opt_present("hardening"),
+            graph: matches.opt_str("graph"),
+            print_config: matches.opt_present("print-config").then(|| matches.opt_str("print-config").unwrap_or_else(|| "toml".into())),
+            run_tests: matches.opt_present("run-tests"),
+            provenance: matches.opt_present("provenance"),
+            timings: matches.opt_present("timings"),
+            split_size: matches.opt_str("split-size"),
+            xz_threads: matches.opt_str("xz-threads"),
+            xz_memory_limit: matches.opt_str("xz-memory-limit"),
+            sbom: matches.opt_str("sbom"),
+            sbom_sidecar: matches.opt_present("sbom-sidecar"),
+            build_ids: matches.opt_present("build-ids"),
+            packages_index: matches.opt_str("check-deps"),
+            // when installing locally it won't be transferred anywhere, so allow faster compression
+            fast: install || matches.opt_present("fast"),
+            variant,
+            target_dir: matches.opt_str("target-dir"),
+            target: matches.opt_str("target"),
+            profile: matches.opt_str("profile"),
+            output_path: output_path.clone(),
+            package_name: matches.opt_str("package"),
+            manifest_path: matches.opt_str("manifest-path"),
+            deb_config: matches.opt_str("config"),
+            deb_version: matches.opt_str("deb-version"),
+            append_git_version: matches.opt_present("deb-version-append-git"),
+            deb_suffix: matches.opt_str("deb-suffix"),
+            backport: matches.opt_str("backport"),
+            changelog_distribution: matches.opt_str("changelog-distribution"),
+            distro_profile: matches.opt_str("distro-profile"),
+            changelog_urgency: matches.opt_str("changelog-urgency"),
+            format: matches.opt_str("format"),
+            compat: matches.opt_str("compat"),
+            substvars: matches.opt_strs("substvar"),
+            binaries: matches.opt_strs("bin"),
+            examples: matches.opt_strs("example"),
+            features: matches.opt_strs("features").iter()
+                .flat_map(|f| f.split([',', ' ']).filter(|f| !f.is_empty()).map(String::from))
+                .collect(),
+            cargo_build_flags: cargo_build_flags.clone(),
+        }, &mut builds) {
+            Ok(()) => {},
+            Err(err) => {
+                err_exit(&err);
+            }
         }
     }
 }
@@ -92,101 +229,416 @@ fn err_cause(err: &dyn std::error::Error, max: usize) {
     }
 }
 
-fn err_exit(err: &dyn std::error::Error) -> ! {
-    eprintln!("cargo-deb: {}", err);
-    err_cause(err, 3);
+fn err_exit(err: &CargoDebError) -> ! {
+    let color = listener::use_color();
+    let print = |err: &CargoDebError| {
+        eprintln!("{}: {}", listener::label("error", err.code(), color), err);
+        err_cause(err, 3);
+    };
+    if let CargoDebError::Multiple(errors) = err {
+        errors.iter().for_each(print);
+        eprintln!("cargo-deb: {}", err);
+    } else {
+        print(err);
+    }
     process::exit(1);
 }
 
+fn verify(packages: &[String], quiet: bool, verbose: usize) -> CDResult<()> {
+    if packages.is_empty() {
+        return Err(CargoDebError::MissingArguments("Specify path to the package, e.g. cargo deb verify target/debian/*.deb"));
+    }
+    let listener = listener::StdErrListener::new(listener::LogLevel::from_flags(quiet, verbose), Vec::new());
+    for package in packages {
+        verify_package(Path::new(package), &listener)?;
+        if !quiet {
+            println!("{}: OK", package);
+        }
+    }
+    Ok(())
+}
+
+/// Checks files installed from the package against a `.deb`, which can be given by name of the package built in `target/debian/`
+fn verify_installed_files(args: &[String], quiet: bool, verbose: usize) -> CDResult<()> {
+    let (package, root) = match args {
+        [package] => (package, Path::new("/")),
+        [package, root] => (package, Path::new(root)),
+        _ => return Err(CargoDebError::MissingArguments("Specify the package, e.g. cargo deb verify-installed target/debian/foo_1.0.0-1_amd64.deb")),
+    };
+    let package_path = if package.ends_with(".deb") || Path::new(package).is_file() {
+        PathBuf::from(package)
+    } else {
+        latest_built_package(package)?
+    };
+    let stderr_listener = listener::StdErrListener::new(listener::LogLevel::from_flags(quiet, verbose), Vec::new());
+    let listener: &dyn listener::Listener = &stderr_listener;
+    listener.info(format!("comparing with {}", package_path.display()));
+    let drift = verify_installed(&reader::Package::open(&package_path)?, root, listener)?;
+    for line in &drift {
+        println!("{}", line);
+    }
+    if !drift.is_empty() {
+        return Err(CargoDebError::InstalledFilesChanged(package_path, drift.len()));
+    }
+    if !quiet {
+        println!("{}: OK", package_path.display());
+    }
+    Ok(())
+}
+
+/// The most recently built `<name>_*.deb`
+fn latest_built_package(name: &str) -> CDResult<PathBuf> {
+    let dir = Path::new(&std::env::var_os("CARGO_TARGET_DIR").unwrap_or_else(|| "target".into())).join("debian");
+    let pattern = dir.join(format!("{}_*.deb", name));
+    glob::glob(&pattern.to_string_lossy())?
+        .filter_map(Result::ok)
+        .max_by_key(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .ok_or_else(|| CargoDebError::BuiltPackageNotFound(name.to_owned(), dir))
+}
+
+fn extract(args: &[String], quiet: bool, verbose: usize) -> CDResult<()> {
+    let (package, dest_dir) = match args {
+        [package, dest_dir] => (package, dest_dir),
+        _ => return Err(CargoDebError::MissingArguments("Specify the package and the destination directory, e.g. cargo deb extract foo.deb out/")),
+    };
+    let listener = listener::StdErrListener::new(listener::LogLevel::from_flags(quiet, verbose), Vec::new());
+    extract_package(Path::new(package), Path::new(dest_dir), &listener)
+}
+
+/// Cargo flags that also apply to cargo-deb's own `cargo metadata`
+const CARGO_NETWORK_FLAGS: [&str; 3] = ["--offline", "--locked", "--frozen"];
+
+/// Removes `--target-dir` from the cargo build flags. Cargo would take it as relative to the crate's directory.
+fn take_target_dir_flag(cargo_build_flags: &mut Vec<String>) -> Option<String> {
+    let pos = cargo_build_flags.iter().position(|flag| flag == "--target-dir" || flag.starts_with("--target-dir="))?;
+    let flag = cargo_build_flags.remove(pos);
+    match flag.strip_prefix("--target-dir=") {
+        Some(dir) => Some(dir.to_owned()),
+        None if pos < cargo_build_flags.len() => Some(cargo_build_flags.remove(pos)),
+        None => None,
+    }
+}
+
 fn process(
     CliOptions {
         manifest_path,
+        deb_config,
         output_path,
         package_name,
         variant,
+        target_dir,
         target,
+        profile,
         install,
+        check_deps,
+        explain_deps,
+        hardening,
+        graph,
+        print_config,
+        run_tests,
+        provenance,
+        timings,
+        split_size,
+        xz_threads,
+        xz_memory_limit,
+        sbom,
+        sbom_sidecar,
+        build_ids,
+        packages_index,
         no_build,
         no_strip,
         separate_debug_symbols,
         quiet,
+        deny,
+        allow,
         fast,
         verbose,
         mut cargo_build_flags,
         deb_version,
+        append_git_version,
+        deb_suffix,
+        backport,
+        changelog_distribution,
+        distro_profile,
+        changelog_urgency,
+        format,
+        compat,
+        substvars,
+        binaries,
+        examples,
+        features,
     }: CliOptions,
+    builds: &mut HashSet<Vec<std::ffi::OsString>>,
 ) -> CDResult<()> {
+    let started = time::SystemTime::now().duration_since(time::UNIX_EPOCH)?.as_secs();
+    let mut timings = Timings::new(timings);
+    let split_size = split_size.map(|size| size.parse::<u64>())
+        .transpose().map_err(|e| CargoDebError::NumParse("invalid --split-size", e))?;
+    let xz_limits = compress::XzLimits {
+        threads: xz_threads.map(|threads| threads.parse())
+            .transpose().map_err(|e| CargoDebError::NumParse("invalid --xz-threads", e))?,
+        memory_limit: xz_memory_limit.map(|mib| mib.parse::<u64>().map(|mib| mib << 20))
+            .transpose().map_err(|e| CargoDebError::NumParse("invalid --xz-memory-limit", e))?,
+    };
     let target = target.as_deref();
-    let variant = variant.as_deref();
+    let variant = match (variant.as_deref(), backport.as_deref()) {
+        (Some(variant), _) => Some(manifest::Variant::Named(variant)),
+        (None, Some(suite)) => Some(manifest::Variant::Backport(suite)),
+        (None, None) => None,
+    };
+    let package_format = match format {
+        Some(format) => format.parse()?,
+        None => manifest::PackageFormat::Deb,
+    };
+    if install && package_format != manifest::PackageFormat::Deb {
+        return Err(CargoDebError::InvalidArguments("--install works only with the deb format".into()));
+    }
+    // Old dpkg versions, e.g. on embedded devices, need conservative archive formats
+    let legacy_dpkg = match compat.as_deref() {
+        None => false,
+        Some("oldstable") => true,
+        Some(other) => return Err(CargoDebError::UnknownCompatProfile(other.to_owned())),
+    };
+
+    // Listener conditionally prints warnings
+    let log_level = listener::LogLevel::from_flags(quiet, verbose);
+    let mut stderr_listener = listener::StdErrListener::new(log_level, listener::parse_deny(&deny)?)
+        .with_allowed(listener::parse_allow(&allow)?);
+    let listener: &mut dyn listener::Listener = &mut stderr_listener;
 
     if install || target.is_none() {
-        warn_if_not_linux(); // compiling natively for non-linux = nope
+        warn_if_not_linux(listener); // compiling natively for non-linux = nope
     }
 
-    // `cargo deb` invocation passes the `deb` arg through.
-    if cargo_build_flags.first().map_or(false, |arg| arg == "deb") {
-        cargo_build_flags.remove(0);
-    }
+    // The build uses the target dir reported by cargo metadata
+    let target_dir = take_target_dir_flag(&mut cargo_build_flags).or(target_dir);
 
-    // Listener conditionally prints warnings
-    let mut listener_tmp1;
-    let mut listener_tmp2;
-    let listener: &mut dyn listener::Listener = if quiet {
-        listener_tmp1 = listener::NoOpListener;
-        &mut listener_tmp1
-    } else {
-        listener_tmp2 = listener::StdErrListener { verbose };
-        &mut listener_tmp2
-    };
+    // cargo metadata would otherwise update the index and Cargo.lock, which fails without network
+    let cargo_network_flags: Vec<String> = cargo_build_flags.iter()
+        .filter(|flag| CARGO_NETWORK_FLAGS.contains(&flag.as_str()))
+        .cloned()
+        .collect();
 
     let manifest_path = manifest_path.as_ref().map_or("Cargo.toml", |s| s.as_str());
-    let mut options = Config::from_manifest(
-        Path::new(manifest_path),
-        package_name.as_deref(),
+    let mut options = Config::from_manifest(ManifestOptions {
+        manifest_path: Some(Path::new(manifest_path)),
+        package_name: package_name.as_deref(),
         output_path,
         target,
         variant,
         deb_version,
-        listener,
-    )?;
+        profile: profile.as_deref(),
+        deb_config: deb_config.as_deref().map(Path::new),
+        cargo_flags: &cargo_network_flags,
+        features: &features,
+        target_dir: target_dir.as_deref().map(Path::new),
+    }, listener)?;
+    options.package_format = package_format;
+    if append_git_version {
+        options.append_git_version()?;
+    }
+    if let Some(suffix) = deb_suffix {
+        options.append_version_suffix(&suffix)?;
+    }
+    if let Some(suite) = &backport {
+        options.backport(suite)?;
+    }
+    if let Some(sbom) = sbom {
+        options.sbom = Some(sbom.parse()?);
+    }
+    options.build_ids |= build_ids;
+    if sbom_sidecar && options.sbom.is_none() {
+        return Err(CargoDebError::InvalidArguments("--sbom-sidecar needs an SBOM format. Set sbom in Cargo.toml or use --sbom".into()));
+    }
+    if let Some(distribution) = changelog_distribution {
+        options.changelog_distribution = Some(distribution);
+    }
+    if let Some(profile) = distro_profile {
+        options.distro_profile = Some(profile.parse()?);
+    }
+    if let Some(urgency) = changelog_urgency {
+        options.changelog_urgency = urgency;
+    }
+    if !binaries.is_empty() || !examples.is_empty() {
+        let binaries = if binaries.is_empty() { options.binaries.take() } else { Some(binaries) };
+        let examples = if examples.is_empty() { std::mem::take(&mut options.examples) } else { examples };
+        options.select_targets(binaries, examples)?;
+    }
+    for var in &substvars {
+        let (name, value) = manifest::parse_substvar(var)?;
+        options.substvars.insert(name, value);
+    }
+    if legacy_dpkg {
+        options.tar_format = manifest::TarFormat::Ustar;
+    }
+    // opkg and old dpkg versions don't support xz or zstd, even if it's in the config
+    if options.package_format != manifest::PackageFormat::Deb || legacy_dpkg {
+        if options.compress_type.is_some_and(|compress_type| compress_type != manifest::CompressType::Gz) {
+            listener.info(format!("using gzip instead of the configured compress-type, because {} supports only gzip",
+                if legacy_dpkg { "dpkg in oldstable" } else { "opkg" }));
+        }
+        options.compress_type = Some(manifest::CompressType::Gz);
+    }
+    let default_compress_type = options.distro_profile.map_or(manifest::CompressType::Xz, manifest::DistroProfile::compress_type);
+    options.compress_type = Some(options.compress_type.unwrap_or(
+        if default_compress_type == manifest::CompressType::Xz && !cfg!(feature = "lzma") {
+            manifest::CompressType::Gz
+        } else {
+            default_compress_type
+        }));
+
+    let build_options = DebBuildOptions::from_env();
+    if let Some(jobs) = build_options.parallel {
+        if !options.cargo_build_flags.iter().chain(&cargo_build_flags).any(|flag| flag.starts_with("-j") || flag.starts_with("--jobs")) {
+            cargo_build_flags.push(format!("--jobs={}", jobs));
+        }
+    }
+    if no_strip || build_options.nostrip {
+        options.strip = false;
+    }
+    options.separate_debug_symbols |= separate_debug_symbols;
+
+    if let Some(format) = print_config {
+        print!("{}", cargo_deb::print_config(&options, &cargo_build_flags, format.parse()?)?);
+        return Ok(());
+    }
     reset_deb_temp_directory(&options)?;
 
-    if !no_build {
-        cargo_build(&options, target, &cargo_build_flags, verbose)?;
+    if !no_build && !options.meta_package {
+        if builds.insert(cargo_build_args(&options, target, &cargo_build_flags)) {
+            timings.time("cargo build", || cargo_build(&options, target, &cargo_build_flags, log_level))?;
+        } else {
+            listener.info("reusing the build of the previous variant".into());
+        }
+    }
+
+    if run_tests || options.run_tests {
+        if build_options.nocheck {
+            listener.info("Not running tests, because DEB_BUILD_OPTIONS has nocheck".to_owned());
+        } else {
+            timings.time("cargo test", || cargo_test(&options, target, &cargo_build_flags, log_level))?;
+        }
+    }
+
+    timings.time("collecting assets", || options.resolve_assets(&*listener))?;
+    timings.time("checking for debug builds", || crate::data::check_debug_binaries(&options, listener));
+
+    if check_deps {
+        check_dependencies(&options, packages_index.as_ref().map(Path::new), listener)?;
+    }
+
+    if explain_deps {
+        for explanation in options.explain_dependencies(listener)? {
+            eprintln!("Depends: {}", explanation.dependency);
+            if explanation.required_by.is_empty() {
+                eprintln!("    set in Cargo.toml");
+            }
+            for (binary, libraries) in &explanation.required_by {
+                if libraries.is_empty() {
+                    eprintln!("    required by {}", binary.display());
+                } else {
+                    eprintln!("    {} links to {}", binary.display(), libraries.join(", "));
+                }
+            }
+        }
     }
 
-    options.resolve_assets()?;
+    let graph = match graph {
+        Some(format) => {
+            let format: GraphFormat = format.parse()?;
+            Some((relationship_graph(&options, listener, format)?, format))
+        },
+        None => None,
+    };
 
-    crate::data::compress_assets(&mut options, listener)?;
+    timings.time("compressing assets", || crate::data::compress_assets(&mut options, listener))?;
 
-    if (options.strip || separate_debug_symbols) && !no_strip {
-        strip_binaries(&mut options, target, listener, separate_debug_symbols)?;
+    if (options.strip || separate_debug_symbols) && !no_strip && !build_options.nostrip {
+        timings.time("stripping", || strip_binaries(&mut options, target, listener, separate_debug_symbols))?;
     }
+    timings.time("checking RPATH", || crate::data::check_runpaths(&mut options, listener))?;
+    let hardening_report = timings.time("checking hardening", || crate::data::check_hardening(&options, listener))?;
+    if hardening {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        for (path, h) in hardening_report {
+            eprintln!("{}:", path.display());
+            eprintln!(" Position Independent Executable: {}", h.pie.map_or("no, regular shared library (ignored)", yes_no));
+            eprintln!(" Stack protected: {}", if h.stack_protector { "yes" } else { "no, not found (Rust code doesn't use it)" });
+            eprintln!(" Read-only relocations: {}", yes_no(h.relro));
+            eprintln!(" Immediate binding: {}", yes_no(h.bind_now));
+        }
+    }
+    options.add_build_ids_asset()?;
+    options.add_sha256_manifest_asset();
 
     // Obtain the current time which will be used to stamp the generated files in the archives.
-    let system_time = time::SystemTime::now().duration_since(time::UNIX_EPOCH)?.as_secs();
+    // With SOURCE_DATE_EPOCH the archives are reproducible, and compressed data can be reused.
+    let system_time = clamped_timestamp();
     let mut deb_contents = DebArchive::new(&options)?;
 
     deb_contents.add_data("debian-binary", system_time, b"2.0\n")?;
 
-    // Initailize the contents of the data archive (files that go into the filesystem).
-    let (data_archive, asset_hashes) = data::generate_archive(&options, system_time, listener)?;
-    let original = data_archive.len();
-
     let listener_tmp = &mut *listener; // reborrow for the closure
     let options = &options;
-    let (control_compressed, data_compressed) = rayon::join(move || {
+    let compressor = compress::compressor(options.compress_type.unwrap_or(manifest::CompressType::Gz), fast, xz_limits)?;
+    let compressor = &*compressor;
+
+    // If the assets are the same as in the previous run, the compressed data.tar can be reused as-is
+    let cache_dir = options.compression_cache_dir();
+    let contents = timings.time("reading assets", || data::read_assets(options))?;
+    let fingerprint = data::assets_fingerprint(options, system_time, &contents);
+    let reused = data::DataArchiveManifest::read(&cache_dir)
+        .filter(|manifest| manifest.fingerprint == fingerprint)
+        .and_then(|manifest| Some((compress::cache_lookup(&cache_dir, compressor, &manifest.tar_hash)?, manifest)));
+
+    // Initailize the contents of the data archive (files that go into the filesystem).
+    let (data_archive, asset_hashes, original) = match reused {
+        Some((compressed, manifest)) => {
+            listener_tmp.info("assets haven't changed, reusing data.tar from the previous run".into());
+            (Err(compressed), manifest.hashes, manifest.tar_len)
+        },
+        None => {
+            let (data_archive, asset_hashes) = timings.time("building data.tar", || data::generate_archive(options, system_time, &contents, listener_tmp))?;
+            let original = data_archive.len();
+            (Ok(data_archive), asset_hashes, original)
+        },
+    };
+    drop(contents);
+    let manifest_hashes = data_archive.is_ok().then(|| asset_hashes.clone());
+
+    let show_progress = timings.enabled && original > PROGRESS_MIN_SIZE && std::io::stderr().is_terminal();
+    let ((control_compressed, control_time), (data_compressed, data_time)) = rayon::join(move || {
+        let start = time::Instant::now();
         // The control archive is the metadata for the package manager
-        let control_archive = control::generate_archive(options, system_time, asset_hashes, listener_tmp)?;
-        compress::xz_or_gz(&control_archive, fast)
+        let control_compressed = control::generate_archive(options, system_time, asset_hashes, listener_tmp)
+            .and_then(|control_archive| compress::compress_with(compressor, &control_archive, &|_| {}));
+        (control_compressed, start.elapsed())
     }, move || {
-        compress::xz_or_gz(&data_archive, fast)
+        let start = time::Instant::now();
+        let data_archive = match data_archive {
+            Ok(data_archive) => data_archive,
+            Err(reused) => return (Ok(reused), start.elapsed()),
+        };
+        let progress = |done: usize| if show_progress {
+            eprint!("\rcompressing data.tar: {}%", done * 100 / original);
+        };
+        let data_compressed = compress::cached(&cache_dir, compressor, &data_archive, &progress);
+        if show_progress {
+            eprintln!();
+        }
+        // Written after compressing, because that clears the cache dir
+        if let (Ok(_), Some(hashes)) = (&data_compressed, manifest_hashes) {
+            data::DataArchiveManifest::new(fingerprint, &data_archive, hashes).write(&cache_dir);
+        }
+        (data_compressed, start.elapsed())
     });
+    timings.add("building control.tar", control_time);
+    timings.add("compressing data.tar", data_time);
     let control_compressed = control_compressed?;
     let data_compressed = data_compressed?;
 
     // Order is important for Debian
-    deb_contents.add_data(&format!("control.tar.{}", control_compressed.extension()), system_time, &control_compressed)?;
+    deb_contents.add_data(&control_compressed.file_name("control.tar"), system_time, &control_compressed)?;
     drop(control_compressed);
     let compressed = data_compressed.len();
     listener.info(format!(
@@ -195,15 +647,59 @@ fn process(
         original,
         compressed * 100 / original
     ));
-    deb_contents.add_data(&format!("data.tar.{}", data_compressed.extension()), system_time, &data_compressed)?;
+    deb_contents.add_data(&data_compressed.file_name("data.tar"), system_time, &data_compressed)?;
     drop(data_compressed);
 
     let generated = deb_contents.finish()?;
     if !quiet {
         println!("{}", generated.display());
     }
+    // All of them are removed if warnings are denied
+    let mut artifacts = vec![generated.clone()];
+    if sbom_sidecar {
+        let sbom_path = write_sbom_sidecar(options, &generated)?;
+        listener.info(format!("SBOM written to {}", sbom_path.display()));
+        artifacts.push(sbom_path);
+    }
+    if options.build_ids {
+        let build_ids_path = data::write_build_ids_sidecar(options, &generated)?;
+        listener.info(format!("build-ids written to {}", build_ids_path.display()));
+        artifacts.push(build_ids_path);
+    }
+    if let Some((graph, format)) = graph {
+        let graph_path = write_graph(&graph, &generated, format)?;
+        listener.info(format!("relationship graph written to {}", graph_path.display()));
+        artifacts.push(graph_path);
+    }
+    if let Some(split_size) = split_size {
+        let parts = split_package(options, &generated, split_size, system_time)?;
+        if parts.is_empty() {
+            listener.info(format!("{} is smaller than {}KiB, so it wasn't split", generated.display(), split_size));
+        }
+        if !quiet {
+            for part in &parts {
+                println!("{}", part.display());
+            }
+        }
+        artifacts.extend(parts);
+    }
+    if provenance {
+        let finished = time::SystemTime::now().duration_since(time::UNIX_EPOCH)?.as_secs();
+        let provenance_path = write_provenance(options, &generated, started, finished, listener)?;
+        listener.info(format!("provenance written to {}", provenance_path.display()));
+        artifacts.push(provenance_path);
+    }
 
-    remove_deb_temp_directory(&options);
+    remove_deb_temp_directory(options);
+    timings.print();
+
+    let denied = stderr_listener.denied();
+    if denied > 0 {
+        for path in &artifacts {
+            let _ = std::fs::remove_file(path);
+        }
+        return Err(CargoDebError::DeniedWarnings(denied));
+    }
 
     if install {
         install_deb(&generated)?;
@@ -211,10 +707,48 @@ fn process(
     Ok(())
 }
 
+/// Compression progress is shown only for data larger than this
+const PROGRESS_MIN_SIZE: usize = 16 << 20;
+
+/// Durations of the steps of making the package, for `--timings`
+struct Timings {
+    enabled: bool,
+    start: time::Instant,
+    steps: Vec<(&'static str, time::Duration)>,
+}
+
+impl Timings {
+    fn new(enabled: bool) -> Self {
+        Self { enabled, start: time::Instant::now(), steps: Vec::new() }
+    }
+
+    fn time<T>(&mut self, step: &'static str, run: impl FnOnce() -> T) -> T {
+        let start = time::Instant::now();
+        let res = run();
+        self.add(step, start.elapsed());
+        res
+    }
+
+    fn add(&mut self, step: &'static str, duration: time::Duration) {
+        self.steps.push((step, duration));
+    }
+
+    fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        eprintln!("timings:");
+        for (step, duration) in &self.steps {
+            eprintln!("  {:<22} {:>8.2}s", step, duration.as_secs_f64());
+        }
+        eprintln!("  {:<22} {:>8.2}s", "total", self.start.elapsed().as_secs_f64());
+    }
+}
+
 #[cfg(target_os = "linux")]
-fn warn_if_not_linux() {}
+fn warn_if_not_linux(_listener: &dyn listener::Listener) {}
 
 #[cfg(not(target_os = "linux"))]
-fn warn_if_not_linux() {
-    eprintln!("warning: This command is for Linux only, and will not make sense when run on other systems");
+fn warn_if_not_linux(listener: &dyn listener::Listener) {
+    listener.warning(listener::Warning::NotLinux, "This command is for Linux only, and will not make sense when run on other systems".into());
 }
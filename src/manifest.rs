@@ -1,22 +1,117 @@
 use crate::config::CargoConfig;
-use crate::dependencies::resolve;
+use crate::dependencies::{resolve, resolve_with_libraries};
 use crate::dh_installsystemd;
 use crate::error::*;
-use crate::listener::Listener;
+use crate::listener::{Listener, Warning};
+use crate::debian_dir::{is_executable, DebianDir};
+use crate::markdown::markdown_to_text;
+use crate::template::interpolate;
 use crate::ok_or::OkOrThen;
-use crate::util::read_file_to_bytes;
+use crate::vfs::{is_glob_pattern, FileSystem, RealFs};
+use crate::pathbytes::{path_from_unix_bytes, AsUnixPathBytes};
+use crate::util::is_path_file;
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::From;
 use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-fn is_glob_pattern(s: &str) -> bool {
-    s.contains('*') || s.contains('[') || s.contains(']') || s.contains('!')
+/// Shorthands for asset destinations, which are the vendor directories that the shells load completions from
+const DEST_SHORTHANDS: [(&str, &str); 3] = [
+    ("$bash-completions", "usr/share/bash-completion/completions"),
+    ("$zsh-completions", "usr/share/zsh/vendor-completions"),
+    ("$fish-completions", "usr/share/fish/vendor_completions.d"),
+];
+
+/// Replaces a shorthand like `$bash-completions/` at the start of the destination
+fn expand_dest_shorthand(dest: &str) -> Cow<'_, str> {
+    for (shorthand, dir) in DEST_SHORTHANDS {
+        if let Some(rest) = dest.strip_prefix(shorthand) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return format!("{}{}", dir, if rest.is_empty() { "/" } else { rest }).into();
+            }
+        }
+    }
+    dest.into()
+}
+
+/// Sections of the Debian archive, from [Debian policy](https://www.debian.org/doc/debian-policy/ch-archive.html#sections)
+const SECTIONS: [&str; 60] = [
+    "admin", "cli-mono", "comm", "database", "debian-installer", "debug", "devel", "doc", "editors", "education",
+    "electronics", "embedded", "fonts", "games", "gnome", "gnu-r", "gnustep", "golang", "graphics", "hamradio",
+    "haskell", "httpd", "interpreters", "introspection", "java", "javascript", "kde", "kernel", "libdevel", "libs",
+    "lisp", "localization", "mail", "math", "metapackages", "misc", "net", "news", "ocaml", "oldlibs",
+    "otherosfs", "perl", "php", "python", "raku", "ruby", "rust", "science", "shells", "sound",
+    "tasks", "tex", "text", "utils", "vcs", "video", "web", "x11", "xfce", "zope",
+];
+const ARCHIVE_AREAS: [&str; 4] = ["main", "contrib", "non-free", "non-free-firmware"];
+/// `extra` is deprecated in favor of `optional`
+const PRIORITIES: [&str; 4] = ["required", "important", "standard", "optional"];
+
+/// Checks that the `[package.metadata.deb]` table has only known keys, for tests of generated configs
+#[cfg(test)]
+pub(crate) fn check_metadata_deb(table: toml::Value) -> Result<(), toml::de::Error> {
+    table.try_into::<CargoDeb>().map(drop)
+}
+
+/// Sections outside of main are prefixed with the archive area, e.g. `contrib/utils`
+pub(crate) fn check_section(section: &str) -> CDResult<()> {
+    let (area, name) = section.rsplit_once('/').unwrap_or(("main", section));
+    if !ARCHIVE_AREAS.contains(&area) || !SECTIONS.contains(&name) {
+        let suggestion = closest_match(name, &SECTIONS).map(|s| if area == "main" { s.to_owned() } else { format!("{}/{}", area, s) });
+        return Err(CargoDebError::InvalidSection("section", section.to_owned(), suggestion.map(|s| format!(". Did you mean '{}'?", s)).unwrap_or_default()));
+    }
+    Ok(())
+}
+
+/// A value from the list that's a few typos away, or starts with the same word, e.g. `utils` for `utilities`
+fn closest_match<'a>(value: &str, values: &[&'a str]) -> Option<&'a str> {
+    let value = value.to_ascii_lowercase();
+    values.iter().copied()
+        .map(|candidate| (edit_distance(&value, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= 2 || value.get(..4).is_some_and(|prefix| candidate.starts_with(prefix)))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Debian policy: `[a-z0-9][a-z0-9+.-]+`
+fn is_valid_package_name(name: &str) -> bool {
+    name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '+' | '.' | '-'))
+}
+
+/// Lowercase, with `_` replaced by `-`, and without other characters that aren't allowed in package names
+fn normalized_package_name(crate_name: &str) -> String {
+    let name: String = crate_name.chars()
+        .filter_map(|c| match c {
+            '_' => Some('-'),
+            c if c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-') => Some(c.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect();
+    name.trim_start_matches(['+', '.', '-']).to_owned()
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +122,7 @@ pub enum AssetSource {
     Data(Vec<u8>),
 }
 
+#[allow(clippy::len_without_is_empty)]
 impl AssetSource {
     #[must_use]
     pub fn path(&self) -> Option<&Path> {
@@ -37,18 +133,19 @@ impl AssetSource {
     }
 
     #[must_use]
-    pub fn len(&self) -> Option<u64> {
+    pub fn len(&self, fs: &dyn FileSystem) -> Option<u64> {
         match *self {
             // FIXME: may not be accurate if the executable is not stripped yet?
-            AssetSource::Path(ref p) => fs::metadata(p).ok().map(|m| m.len()),
+            AssetSource::Path(ref p) => fs.file_len(p).ok(),
             AssetSource::Data(ref d) => Some(d.len() as u64),
         }
     }
 
-    pub fn data(&self) -> CDResult<Cow<'_, [u8]>> {
+    /// Contents of the file from `fs`, usually `Config::fs`
+    pub fn data(&self, fs: &dyn FileSystem) -> CDResult<Cow<'_, [u8]>> {
         Ok(match *self {
             AssetSource::Path(ref p) => {
-                let data = read_file_to_bytes(p)
+                let data = fs.read(p)
                     .map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, p.to_owned()))?;
                 Cow::Owned(data)
             },
@@ -69,7 +166,8 @@ impl AssetSource {
     }
 }
 
-/// Configuration settings for the systemd_units functionality.
+/// Configuration settings for the systemd_units functionality, from `[package.metadata.deb.systemd]`
+/// (or the older `systemd-units`).
 ///
 /// `unit_scripts`: (optional) relative path to a directory containing correctly
 /// named systemd unit files. See `dh_lib::pkgfile()` and `dh_installsystemd.rs`
@@ -79,8 +177,11 @@ impl AssetSource {
 /// `unit_name`: (optjonal) in cases where the `unit_scripts` directory contains
 /// multiple units, only process those matching this unit name.
 ///
+/// `units`: settings of individual units by their installed file name, e.g. `foo.service`,
+/// overriding the ones of the whole table. Units with a `source` are installed from that file.
+///
 /// For details on the other options please see `dh_installsystemd::Options`.
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub(crate) struct SystemdUnitsConfig {
     pub unit_scripts: Option<PathBuf>,
@@ -89,6 +190,37 @@ pub(crate) struct SystemdUnitsConfig {
     pub start: Option<bool>,
     pub restart_after_upgrade: Option<bool>,
     pub stop_on_upgrade: Option<bool>,
+    pub stop_on_remove: Option<bool>,
+    pub preset: Option<dh_installsystemd::Preset>,
+    pub mask: Option<Vec<String>>,
+    pub units: Option<BTreeMap<String, SystemdUnit>>,
+}
+
+/// `[package.metadata.deb.systemd.units."<name>"]`
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct SystemdUnit {
+    pub source: Option<PathBuf>,
+    pub enable: Option<bool>,
+    pub start: Option<bool>,
+    pub restart_after_upgrade: Option<bool>,
+    pub stop_on_upgrade: Option<bool>,
+    pub stop_on_remove: Option<bool>,
+}
+
+/// Suffixes of unit files that can be installed in `/usr/lib/systemd/system`
+pub(crate) const SYSTEMD_UNIT_TYPES: [&str; 9] = ["service", "socket", "timer", "path", "mount", "automount", "target", "swap", "slice"];
+
+impl SystemdUnitsConfig {
+    fn unit_options(&self, unit: &SystemdUnit) -> dh_installsystemd::UnitOptions {
+        dh_installsystemd::UnitOptions {
+            no_enable: !unit.enable.or(self.enable).unwrap_or(true),
+            no_start: !unit.start.or(self.start).unwrap_or(true),
+            restart_after_upgrade: unit.restart_after_upgrade.or(self.restart_after_upgrade).unwrap_or(true),
+            no_stop_on_upgrade: !unit.stop_on_upgrade.or(self.stop_on_upgrade).unwrap_or(true),
+            no_stop_on_remove: !unit.stop_on_remove.or(self.stop_on_remove).unwrap_or(true),
+        }
+    }
 }
 
 /// Match the official dh_installsystemd defaults and rename the confusing
@@ -96,11 +228,284 @@ pub(crate) struct SystemdUnitsConfig {
 /// mostly, but not always, negative.
 impl From<&SystemdUnitsConfig> for dh_installsystemd::Options {
     fn from(config: &SystemdUnitsConfig) -> Self {
+        let defaults = config.unit_options(&SystemdUnit::default());
         Self {
-            no_enable: !config.enable.unwrap_or(true),
-            no_start: !config.start.unwrap_or(true),
-            restart_after_upgrade: config.restart_after_upgrade.unwrap_or(true),
-            no_stop_on_upgrade: !config.stop_on_upgrade.unwrap_or(true),
+            no_enable: defaults.no_enable,
+            no_start: defaults.no_start,
+            restart_after_upgrade: defaults.restart_after_upgrade,
+            no_stop_on_upgrade: defaults.no_stop_on_upgrade,
+            no_stop_on_remove: defaults.no_stop_on_remove,
+            preset: config.preset,
+            mask: config.mask.clone().unwrap_or_default(),
+            units: config.units.iter().flatten()
+                .map(|(name, unit)| (name.clone(), config.unit_options(unit)))
+                .collect(),
+        }
+    }
+}
+
+/// `[package.metadata.deb.generated-scripts]`: how maintainer scripts with generated fragments are written
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct GeneratedScripts {
+    /// Interpreter of scripts made from scratch, when there's no user script with `#DEBHELPER#`
+    #[serde(default = "default_shell")]
+    pub shell: String,
+    /// Start scripts made from scratch with `set -e`
+    #[serde(default = "default_true")]
+    pub set_e: bool,
+    /// Code inserted before the generated fragments, by script name, e.g. `postinst`
+    #[serde(default)]
+    pub prologue: BTreeMap<String, String>,
+    /// Code inserted after the generated fragments
+    #[serde(default)]
+    pub epilogue: BTreeMap<String, String>,
+}
+
+impl Default for GeneratedScripts {
+    fn default() -> Self {
+        Self { shell: default_shell(), set_e: true, prologue: BTreeMap::new(), epilogue: BTreeMap::new() }
+    }
+}
+
+fn default_shell() -> String {
+    "/bin/sh".into()
+}
+
+impl GeneratedScripts {
+    fn check(&self) -> CDResult<()> {
+        if !self.shell.starts_with('/') || self.shell.contains(char::is_whitespace) {
+            return Err(CargoDebError::InvalidScriptShell(self.shell.clone()));
+        }
+        if let Some(name) = self.prologue.keys().chain(self.epilogue.keys()).find(|name| !["preinst", "postinst", "prerm", "postrm"].contains(&name.as_str())) {
+            return Err(CargoDebError::UnknownMaintainerScript(name.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Registration of documentation with doc-base.
+///
+/// Each of `formats` describes one format of the same document, e.g. HTML with an `index`
+/// and `files` glob, or a single PDF in `files`. Paths are where the files are installed.
+///
+/// See <https://www.debian.org/doc/packaging-manuals/doc-base/>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct DocBaseConfig {
+    /// Document id, defaults to the package name
+    pub document: Option<String>,
+    /// Defaults to the package description
+    pub title: Option<String>,
+    pub author: Option<String>,
+    #[serde(rename = "abstract")]
+    pub abstract_: Option<String>,
+    /// Defaults to `Programming`
+    pub section: Option<String>,
+    pub formats: Vec<DocBaseFormat>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct DocBaseFormat {
+    /// e.g. `HTML` or `PDF`
+    pub format: String,
+    /// Required for HTML
+    pub index: Option<String>,
+    pub files: String,
+}
+
+/// Where an entry of the `Depends` field came from
+#[derive(Debug, Clone)]
+pub struct DependencyExplanation {
+    pub dependency: String,
+    /// Binaries that need the dependency, and shared libraries from it that they link to.
+    /// Empty if the dependency has been set explicitly.
+    pub required_by: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Kind of the package archive to make
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageFormat {
+    /// Debian package
+    Deb,
+    /// opkg package for OpenWrt and Yocto-based systems
+    Ipk,
+}
+
+impl PackageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Deb => "deb",
+            Self::Ipk => "ipk",
+        }
+    }
+}
+
+impl std::str::FromStr for PackageFormat {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "deb" => Ok(Self::Deb),
+            "ipk" => Ok(Self::Ipk),
+            other => Err(CargoDebError::UnknownPackageFormat(other.to_owned())),
+        }
+    }
+}
+
+/// Header format of entries in the control and data tarballs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarFormat {
+    /// GNU tar, understood by all versions of dpkg
+    Gnu,
+    /// POSIX ustar, for the most conservative tar implementations. Limits paths to 255 bytes.
+    Ustar,
+    /// POSIX pax, ustar with extended headers for long paths
+    Pax,
+}
+
+impl std::str::FromStr for TarFormat {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "gnu" => Ok(Self::Gnu),
+            "ustar" => Ok(Self::Ustar),
+            "pax" => Ok(Self::Pax),
+            other => Err(CargoDebError::UnknownTarFormat(other.to_owned())),
+        }
+    }
+}
+
+/// Which `[package.metadata.deb.variants.<name>]` to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant<'a> {
+    /// `--variant`, which adds the name to the package name
+    Named(&'a str),
+    /// `--backport`'s suite, if there's a variant for it
+    Backport(&'a str),
+}
+
+/// Compression of the control and data tarballs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressType {
+    Xz,
+    Gz,
+    Zstd,
+    /// Uncompressed tarballs
+    None,
+}
+
+impl std::str::FromStr for CompressType {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "xz" => Ok(Self::Xz),
+            "gz" | "gzip" => Ok(Self::Gz),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            "none" => Ok(Self::None),
+            other => Err(CargoDebError::UnknownCompressType(other.to_owned())),
+        }
+    }
+}
+
+/// `distro-profile` or `--distro-profile`: defaults that differ between Debian and Ubuntu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistroProfile {
+    Debian,
+    Ubuntu,
+}
+
+impl std::str::FromStr for DistroProfile {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "debian" => Ok(Self::Debian),
+            "ubuntu" => Ok(Self::Ubuntu),
+            other => Err(CargoDebError::UnknownDistroProfile(other.to_owned())),
+        }
+    }
+}
+
+impl DistroProfile {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Debian => "debian",
+            Self::Ubuntu => "ubuntu",
+        }
+    }
+
+    /// Ubuntu's dpkg supports zstd since 21.10, and uses it for its own packages
+    #[must_use]
+    pub fn compress_type(self) -> CompressType {
+        match self {
+            Self::Debian => CompressType::Xz,
+            Self::Ubuntu => CompressType::Zstd,
+        }
+    }
+
+    /// Ubuntu has no `unstable`. `devel` is the alias of its development series.
+    #[must_use]
+    pub fn changelog_distribution(self) -> &'static str {
+        match self {
+            Self::Debian => "unstable",
+            Self::Ubuntu => "devel",
+        }
+    }
+}
+
+/// How to estimate disk usage for the `Installed-Size` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstalledSizeMethod {
+    /// Like `dpkg-gencontrol`: file sizes rounded up to KiB, plus 1KiB for each directory
+    Dpkg,
+    /// Files and directories take whole 4KiB filesystem blocks
+    Blocks,
+}
+
+impl std::str::FromStr for InstalledSizeMethod {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "dpkg" => Ok(Self::Dpkg),
+            "blocks" => Ok(Self::Blocks),
+            other => Err(CargoDebError::UnknownInstalledSizeMethod(other.to_owned())),
+        }
+    }
+}
+
+/// Format of the software bill of materials installed with the package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// [CycloneDX](https://cyclonedx.org) JSON
+    CycloneDx,
+    /// [SPDX](https://spdx.dev) JSON
+    Spdx,
+}
+
+impl SbomFormat {
+    #[must_use]
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::CycloneDx => "sbom.cdx.json",
+            Self::Spdx => "sbom.spdx.json",
+        }
+    }
+}
+
+impl std::str::FromStr for SbomFormat {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "cyclonedx" => Ok(Self::CycloneDx),
+            "spdx" => Ok(Self::Spdx),
+            other => Err(CargoDebError::UnknownSbomFormat(other.to_owned())),
         }
     }
 }
@@ -144,6 +549,103 @@ pub struct UnresolvedAsset {
     pub target_path: PathBuf,
     pub chmod: u32,
     pub is_built: bool,
+    pub condition: AssetCondition,
+    pub dependencies: AssetDependencies,
+    pub owner: Option<AssetOwner>,
+    pub strip_keep: StripKeep,
+}
+
+/// Non-root owner of an asset, set with `dpkg-statoverride` after installation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetOwner {
+    pub user: String,
+    pub group: String,
+}
+
+impl std::str::FromStr for AssetOwner {
+    type Err = CargoDebError;
+
+    /// `user:group`, or `user` for the group of the same name
+    fn from_str(s: &str) -> CDResult<Self> {
+        let (user, group) = s.split_once(':').unwrap_or((s, s));
+        // like adduser's NAME_REGEX
+        let is_valid = |name: &str| name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"_-".contains(&b));
+        if !is_valid(user) || !is_valid(group) {
+            return Err(CargoDebError::InvalidAssetOwner(s.to_owned()));
+        }
+        Ok(Self { user: user.to_owned(), group: group.to_owned() })
+    }
+}
+
+/// Symbols and sections that `strip` keeps, e.g. for symbolication of backtraces
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StripKeep {
+    /// `strip --keep-symbol`
+    pub symbols: Vec<String>,
+    /// `strip --keep-section`, e.g. `.comment` or `.note.gnu.build-id`
+    pub sections: Vec<String>,
+}
+
+impl StripKeep {
+    fn new(symbols: Option<Vec<String>>, sections: Option<Vec<String>>) -> CDResult<Self> {
+        let keep = Self { symbols: symbols.unwrap_or_default(), sections: sections.unwrap_or_default() };
+        if let Some(name) = keep.symbols.iter().chain(&keep.sections).find(|name| name.is_empty() || name.contains(char::is_whitespace)) {
+            return Err(CargoDebError::InvalidStripKeep(name.clone()));
+        }
+        Ok(keep)
+    }
+
+    /// Arguments for `strip`, with the asset's on top of the package's
+    pub(crate) fn strip_args(&self, asset: &StripKeep) -> Vec<String> {
+        self.symbols.iter().chain(&asset.symbols).map(|symbol| format!("--keep-symbol={}", symbol))
+            .chain(self.sections.iter().chain(&asset.sections).map(|section| format!("--keep-section={}", section)))
+            .collect()
+    }
+}
+
+/// Per-binary control over the `Depends` field
+#[derive(Debug, Clone)]
+pub struct AssetDependencies {
+    /// Run `dpkg-shlibdeps` on this binary when `$auto` is used
+    pub auto: bool,
+    /// Extra dependencies of this binary, merged into the package's `Depends`
+    pub depends: Option<String>,
+}
+
+impl Default for AssetDependencies {
+    fn default() -> Self {
+        Self { auto: true, depends: None }
+    }
+}
+
+/// Conditions under which an asset is included in the package.
+/// An asset without any conditions is always included.
+#[derive(Debug, Clone, Default)]
+pub struct AssetCondition {
+    /// Glob pattern matched against the Rust target triple, e.g. `aarch64-*`
+    pub target: Option<String>,
+    /// Cargo feature that has to be enabled for the build
+    pub feature: Option<String>,
+    /// Skip the asset silently if the source doesn't exist
+    pub exists: bool,
+}
+
+impl AssetCondition {
+    /// Checks the conditions that can be evaluated before looking at the files
+    fn is_met(&self, target: &str, enabled_features: &HashSet<String>) -> CDResult<bool> {
+        if let Some(ref pattern) = self.target {
+            if !glob::Pattern::new(pattern)?.matches(target) {
+                return Ok(false);
+            }
+        }
+        if let Some(ref feature) = self.feature {
+            if !enabled_features.contains(feature) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -152,6 +654,10 @@ pub struct Asset {
     pub target_path: PathBuf,
     pub chmod: u32,
     is_built: bool,
+    pub dependencies: AssetDependencies,
+    pub owner: Option<AssetOwner>,
+    /// Kept when stripping, in addition to the package's `strip_keep`
+    pub strip_keep: StripKeep,
 }
 
 impl Asset {
@@ -172,6 +678,9 @@ impl Asset {
             target_path,
             chmod,
             is_built,
+            dependencies: AssetDependencies::default(),
+            owner: None,
+            strip_keep: StripKeep::default(),
         }
     }
 
@@ -182,7 +691,7 @@ impl Asset {
     fn is_dynamic_library(&self) -> bool {
         self.target_path.file_name()
             .and_then(|f| f.to_str())
-            .map_or(false, |f| f.ends_with(DLL_SUFFIX))
+            .is_some_and(|f| f.ends_with(DLL_SUFFIX))
     }
 
     /// Returns the target path for the debug symbol file, which will be
@@ -207,6 +716,18 @@ impl Asset {
     }
 }
 
+/// Explains the likely reason why a binary that should have been built is missing
+fn binary_not_found(path: PathBuf, binary_targets: &[String]) -> CargoDebError {
+    let file_name = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+    let is_renamed_bin = !file_name.ends_with(DLL_SUFFIX) && !binary_targets.is_empty() && !binary_targets.contains(&file_name);
+    let hint = if is_renamed_bin {
+        format!("'{}' is not a binary target of this package. Available binaries are: {}. Has it been renamed?", file_name, binary_targets.join(", "))
+    } else {
+        "make sure the package has been built for the same target (don't use --no-build without building it first)".to_owned()
+    };
+    CargoDebError::BinaryNotFound(path, hint)
+}
+
 /// Adds `.debug` to the end of a path to a filename
 ///
 fn debug_filename(path: &Path) -> PathBuf {
@@ -240,6 +761,87 @@ fn get_architecture_specification(depend: &str) -> CDResult<(String, Option<Arch
     }
 }
 
+/// Appends `Provides` of the enabled features from `feature-provides`
+fn feature_provides(provides: Option<String>, feature_provides: &BTreeMap<String, String>, enabled_features: &HashSet<String>) -> Option<String> {
+    let extra = feature_provides.iter()
+        .filter(|(feature, _)| enabled_features.contains(feature.as_str()))
+        .map(|(_, provides)| provides.as_str());
+    let all: Vec<_> = provides.as_deref().into_iter().chain(extra)
+        .map(|p| p.trim().trim_matches(','))
+        .filter(|p| !p.is_empty())
+        .collect();
+    if all.is_empty() { None } else { Some(all.join(", ")) }
+}
+
+/// Prefixes without the leading and trailing `/`, sorted so that the longest (most specific) ones are tried first
+fn path_map(map: BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut map: Vec<_> = map.into_iter()
+        .map(|(from, to)| (from.trim_matches('/').to_owned(), to.trim_end_matches('/').to_owned()))
+        .collect();
+    map.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    map
+}
+
+/// Top-level directories that are symlinks to `/usr` on merged-/usr systems
+const LEGACY_ROOT_DIRS: [&str; 7] = ["bin", "sbin", "lib", "lib32", "lib64", "libx32", "libo32"];
+
+fn git_version(version: &str, hash: &str, timestamp: u64) -> String {
+    format!("{}+git{}.{}", version, crate::util::compact_date(timestamp), &hash[..hash.len().min(7)])
+}
+
+/// The suffix can't have `-` or `:`, because they would change which part of the version is the revision or epoch
+fn version_with_suffix(version: &str, suffix: &str) -> CDResult<String> {
+    if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_alphanumeric() || b"+.~".contains(&b)) {
+        return Err(CargoDebError::InvalidVersionSuffix(suffix.to_owned()));
+    }
+    Ok(format!("{}{}", version, suffix))
+}
+
+/// Debian suites and their release numbers, for `~bpo12+1` suffixes of backports
+const DEBIAN_RELEASES: [(&str, u32); 6] = [("stretch", 9), ("buster", 10), ("bullseye", 11), ("bookworm", 12), ("trixie", 13), ("forky", 14)];
+
+/// Version suffix and changelog distribution of a backport, e.g. `~bpo12+1` and `bookworm-backports` for Debian,
+/// or `~jammy1` and `jammy` for other suites, like Ubuntu's PPAs
+fn backport_suffix(suite: &str) -> CDResult<(String, String)> {
+    if !suite.starts_with(|c: char| c.is_ascii_lowercase()) || !suite.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()) {
+        return Err(CargoDebError::InvalidBackportSuite(suite.to_owned()));
+    }
+    Ok(match DEBIAN_RELEASES.iter().find(|&&(name, _)| name == suite) {
+        Some((_, release)) => (format!("~bpo{}+1", release), format!("{}-backports", suite)),
+        None => (format!("~{}1", suite), suite.to_owned()),
+    })
+}
+
+/// `user.name <user.email>` from git config, for packages without authors
+fn git_config_maintainer(dir: &Path) -> Option<String> {
+    let get = |key: &str| {
+        let output = Command::new("git").current_dir(dir).args(["config", key]).output().ok().filter(|o| o.status.success())?;
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_owned()).filter(|v| !v.is_empty())
+    };
+    Some(format!("{} <{}>", get("user.name")?, get("user.email")?))
+}
+
+/// Parses `name=value` lines of a `debian/substvars` file. Comments start with `#`.
+fn parse_substvars(content: &str) -> CDResult<BTreeMap<String, String>> {
+    let mut vars = BTreeMap::new();
+    for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (name, value) = parse_substvar(line)?;
+        vars.insert(name, value);
+    }
+    Ok(vars)
+}
+
+/// Parses a single `name=value` assignment. `name?=value` is accepted too.
+pub fn parse_substvar(assignment: &str) -> CDResult<(String, String)> {
+    let (name, value) = assignment.split_once('=')
+        .ok_or_else(|| CargoDebError::InvalidSubstvar(assignment.to_owned()))?;
+    let name = name.trim_end_matches('?').trim();
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-:".contains(&b)) {
+        return Err(CargoDebError::InvalidSubstvar(assignment.to_owned()));
+    }
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
 /// Architecture specification strings
 /// <https://www.debian.org/doc/debian-policy/ch-customized-programs.html#s-arch-spec>
 fn match_architecture(spec: ArchSpec, target_arch: &str) -> CDResult<bool> {
@@ -248,7 +850,7 @@ fn match_architecture(spec: ArchSpec, target_arch: &str) -> CDResult<bool> {
         ArchSpec::Require(pkg) => (false, pkg),
     };
     let output = Command::new("dpkg-architecture")
-        .args(&["-a", target_arch, "-i", &spec])
+        .args(["-a", target_arch, "-i", &spec])
         .output()
         .map_err(|e| CargoDebError::CommandFailed(e, "dpkg-architecture"))?;
     if neg {
@@ -263,32 +865,58 @@ fn match_architecture(spec: ArchSpec, target_arch: &str) -> CDResult<bool> {
 pub struct Config {
     /// Root directory where `Cargo.toml` is located. It's a subdirectory in workspaces.
     pub manifest_dir: PathBuf,
+    /// Root of the Cargo workspace, with `Cargo.lock`
+    pub workspace_root: PathBuf,
     /// User-configured output path for *.deb
     pub deb_output_path: Option<String>,
     /// Triple. `None` means current machine architecture.
     pub target: Option<String>,
-    /// `CARGO_TARGET_DIR`
+    /// `CARGO_TARGET_DIR`, with the target triple when cross-compiling
     pub target_dir: PathBuf,
+    /// `CARGO_TARGET_DIR` as reported by `cargo metadata`, so that the build puts files in the same place
+    pub(crate) cargo_target_dir: PathBuf,
+    /// Cargo profile to build with, `release` by default
+    pub profile: String,
     /// The name of the project to build
     pub name: String,
     /// The name to give the Debian package; usually the same as the Cargo project name
     pub deb_name: String,
     /// The version to give the Debian package; usually the same as the Cargo version
     pub deb_version: String,
+    /// Hash of the git commit the package is built from, recorded in the generated changelog
+    pub git_commit: Option<String>,
     /// The software license of the project (SPDX format).
     pub license: Option<String>,
     /// The location of the license file
     pub license_file: Option<PathBuf>,
     /// number of lines to skip when reading `license_file`
     pub license_file_skip_lines: usize,
+    /// `readme` from `Cargo.toml`
+    pub readme: Option<PathBuf>,
+    /// Install the readme and license files if the assets don't have any docs
+    pub default_docs: bool,
     /// The copyright of the project
     /// (Debian's `copyright` file contents).
     pub copyright: String,
     pub changelog: Option<String>,
+    /// Distribution of the generated changelog entry, e.g. `bookworm-backports`. See `changelog_distribution()` for the default.
+    pub changelog_distribution: Option<String>,
+    /// Urgency of the generated changelog entry: `low`, `medium`, `high`, `emergency` or `critical`
+    pub changelog_urgency: String,
+    /// Path to the `NEWS.Debian` file with upgrade notes for apt-listchanges
+    pub news: Option<String>,
     /// The homepage URL of the project.
     pub homepage: Option<String>,
     /// Documentation URL from `Cargo.toml`. Fallback if `homepage` is missing.
     pub documentation: Option<String>,
+    /// Vendor or distribution that made the package, for the `Origin` field
+    pub origin: Option<String>,
+    /// URL of the bug tracker, e.g. `debbugs://bugs.example.com`, for the `Bugs` field
+    pub bugs: Option<String>,
+    /// Co-maintainers, for the `Uploaders` field
+    pub uploaders: Option<String>,
+    /// Other fields for the control file, e.g. `Original-Maintainer` from `XSBC-Original-Maintainer` of `debian/control`
+    pub user_defined_fields: Vec<(String, String)>,
     /// The URL of the software repository.
     pub repository: Option<String>,
     /// A short description of the project.
@@ -329,11 +957,35 @@ pub struct Config {
     ///
     /// See [PackageTransition](https://wiki.debian.org/PackageTransition).
     pub provides: Option<String>,
+    /// User-defined `${name}` substitution variables for relationship fields
+    pub substvars: BTreeMap<String, String>,
 
     /// The Debian architecture of the target system.
     pub architecture: String,
+    /// The opkg architecture of the target system, used instead of `architecture` for `.ipk`
+    pub ipk_architecture: String,
+    /// Whether to make a `.deb` or an `.ipk`
+    pub package_format: PackageFormat,
+    /// Format of the tarballs inside the package
+    pub tar_format: TarFormat,
+    /// Compression of the tarballs. `None` picks xz, or gz for formats that don't support it.
+    pub compress_type: Option<CompressType>,
+    /// Debian or Ubuntu defaults of compression, changelog distribution and control fields
+    pub distro_profile: Option<DistroProfile>,
+    /// How `Installed-Size` is computed
+    pub installed_size_method: InstalledSizeMethod,
+    /// Install a software bill of materials made from `Cargo.lock`
+    pub sbom: Option<SbomFormat>,
+    /// Install `Cargo.lock` in the doc directory
+    pub include_cargo_lock: bool,
+    /// Install `build-ids.json` with GNU build-ids of the binaries in the doc directory
+    pub build_ids: bool,
+    /// Install `/usr/share/<package>/manifest.sha256` with checksums of all the files
+    pub sha256_manifest: bool,
     /// A list of configuration files installed by the package.
     pub conf_files: Option<String>,
+    /// Files and directories created by the program, e.g. in `/var/lib`, that are deleted when the package is purged
+    pub remove_on_purge: Vec<String>,
     /// All of the files that are to be packaged.
     pub(crate) assets: Assets,
     /// The location of the triggers file
@@ -343,23 +995,85 @@ pub struct Config {
     /// List of Cargo features to use during build
     pub features: Vec<String>,
     pub default_features: bool,
+    /// Cargo features that will be enabled in the build, including defaults and implied ones
+    pub(crate) enabled_features: HashSet<String>,
+    /// Exact versions of packages of other workspace members that the crate depends on, for `${workspace:Depends}`
+    pub(crate) workspace_depends: Vec<String>,
+    /// Names of `[[bin]]` targets of the package
+    pub(crate) binary_targets: Vec<String>,
+    /// Binary targets to build and package. `None` means all of them
+    pub binaries: Option<Vec<String>>,
+    /// Examples to build and package
+    pub examples: Vec<String>,
     /// Should the binary be stripped from debug symbols?
     pub strip: bool,
+    /// Symbols and sections that stripping keeps in all binaries
+    pub strip_keep: StripKeep,
     /// Should the debug symbols be moved to a separate file included in the package? (implies `strip:true`)
     pub separate_debug_symbols: bool,
     /// Should symlinks be preserved in the assets
     pub preserve_symlinks: bool,
+    /// Store files with identical content and permissions as hardlinks
+    pub hardlink_duplicates: bool,
+    /// Move assets from `/bin`, `/sbin` and `/lib*` to `/usr`
+    pub usr_merge: bool,
+    /// Prefixes of asset destinations to replace, longest first
+    pub path_map: Vec<(String, String)>,
+    /// Remove RPATH and RUNPATH pointing into the build tree from ELF files
+    pub scrub_rpath: bool,
+    /// Run `cargo test` before packaging
+    pub run_tests: bool,
+    /// Binaries are statically linked, and have no library dependencies. `None` means detect it.
+    pub static_binaries: Option<bool>,
+    /// The package has no files, only dependencies (`assets = []`), so there's nothing to build
+    pub meta_package: bool,
+    /// Extra flags for `cargo build` and `cargo test`, from `cargo-build-flags`
+    pub cargo_build_flags: Vec<String>,
+    /// Linker for cross-compiling, unless Cargo's config or environment sets one for the target
+    pub linker: Option<String>,
     /// Details of how to install any systemd units
     pub(crate) systemd_units: Option<SystemdUnitsConfig>,
+    /// Documentation to register with doc-base
+    pub(crate) doc_base: Option<DocBaseConfig>,
+    /// Interpreter, `set -e` and extra code of maintainer scripts with generated fragments
+    pub(crate) generated_scripts: GeneratedScripts,
+    /// Where assets are globbed and read from. Tests can replace it with a `vfs::MemoryFs`.
+    pub fs: Box<dyn FileSystem>,
     _use_constructor_to_make_this_struct_: (),
 }
 
+/// What `Config::from_manifest` loads, and the settings that don't come from `Cargo.toml`.
+/// The defaults are for `Cargo.toml` in the current working directory.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestOptions<'a> {
+    /// `None` is `Cargo.toml` in the current working directory
+    pub manifest_path: Option<&'a Path>,
+    /// Workspace member to package. `None` is the root package.
+    pub package_name: Option<&'a str>,
+    /// `--output` file or directory
+    pub output_path: Option<String>,
+    /// Rust target triple. `None` means the host machine's architecture.
+    pub target: Option<&'a str>,
+    pub variant: Option<Variant<'a>>,
+    /// Replaces the version from `Cargo.toml`
+    pub deb_version: Option<String>,
+    /// Cargo profile. `None` means `release`.
+    pub profile: Option<&'a str>,
+    /// A file with settings that take precedence over `[package.metadata.deb]`
+    pub deb_config: Option<&'a Path>,
+    /// Passed to `cargo metadata`, e.g. `--offline`, `--locked` or `--frozen`
+    pub cargo_flags: &'a [String],
+    /// Cargo features to enable in addition to the `features` setting, like `cargo build --features`
+    pub features: &'a [String],
+    /// Like `cargo build --target-dir`. `None` uses `CARGO_TARGET_DIR` or cargo's default.
+    pub target_dir: Option<&'a Path>,
+}
+
 impl Config {
-    /// Makes a new config from `Cargo.toml` in the current working directory.
-    ///
-    /// `None` target means the host machine's architecture.
-    pub fn from_manifest(manifest_path: &Path, package_name: Option<&str>, output_path: Option<String>, target: Option<&str>, variant: Option<&str>, deb_version: Option<String>, listener: &dyn Listener) -> CDResult<Config> {
-        let metadata = cargo_metadata(manifest_path)?;
+    /// Makes a new config from `Cargo.toml`
+    pub fn from_manifest(options: ManifestOptions<'_>, listener: &dyn Listener) -> CDResult<Config> {
+        let ManifestOptions { manifest_path, package_name, output_path, target, variant, deb_version, profile, deb_config, cargo_flags, features, target_dir } = options;
+        let metadata = cargo_metadata(manifest_path.unwrap_or(Path::new("Cargo.toml")), cargo_flags, target_dir)?;
         let available_package_names = || {
             metadata.packages.iter()
                 .filter(|p| metadata.workspace_members.iter().any(|w| w == &p.id))
@@ -381,30 +1095,23 @@ impl Config {
         let target_dir = Path::new(&metadata.target_directory);
         let manifest_path = Path::new(&root_package.manifest_path);
         let manifest_dir = manifest_path.parent().unwrap();
-        let content = fs::read(&manifest_path)
+        let content = fs::read(manifest_path)
             .map_err(|e| CargoDebError::IoFile("unable to read Cargo.toml", e, manifest_path.to_owned()))?;
-        toml::from_slice::<Cargo>(&content)?.into_config(root_package, manifest_dir, output_path, target_dir, target, variant, deb_version, listener)
+        let workspace_root = Path::new(&metadata.workspace_root);
+        let mut cargo = toml::from_slice::<Cargo>(&content).map_err(|e| CargoDebError::toml_in_file(e, manifest_path, &content))?;
+        if let Some(deb_config) = deb_config {
+            cargo.use_deb_config(deb_config)?;
+        }
+        let mut config = cargo.into_config(root_package, manifest_dir, workspace_root, output_path, target_dir, target, variant, deb_version, profile.unwrap_or("release"), features, listener)?;
+        config.workspace_depends = workspace_depends(&metadata, root_package, &config.enabled_features);
+        Ok(config)
     }
 
     pub(crate) fn get_dependencies(&self, listener: &dyn Listener) -> CDResult<String> {
         let mut deps = HashSet::new();
-        for word in self.depends.split(',') {
-            let word = word.trim();
+        for word in self.depends_words(listener)? {
             if word == "$auto" {
-                let bin = self.all_binaries();
-                let resolved = bin.par_iter()
-                    .filter_map(|p| p.path())
-                    .filter_map(|bname| match resolve(bname) {
-                        Ok(bindeps) => Some(bindeps),
-                        Err(err) => {
-                            listener.warning(format!("{} (no auto deps for {})", err, bname.display()));
-                            None
-                        },
-                    })
-                    .collect::<Vec<_>>();
-                for dep in resolved.into_iter().flat_map(|s| s.into_iter()) {
-                    deps.insert(dep);
-                }
+                deps.extend(self.auto_dependencies(listener));
             } else {
                 let (dep, arch_spec) = get_architecture_specification(&word)?;
                 if let Some(spec) = arch_spec {
@@ -416,33 +1123,169 @@ impl Config {
                 }
             }
         }
+        for (word, _) in self.asset_dependencies() {
+            let (dep, arch_spec) = get_architecture_specification(word)?;
+            if arch_spec.map_or(Ok(true), |spec| match_architecture(spec, &self.architecture))? {
+                deps.insert(dep);
+            }
+        }
+        deps.extend(self.misc_dependencies());
         Ok(deps.into_iter().collect::<Vec<_>>().join(", "))
     }
 
-    pub fn resolve_assets(&mut self) -> CDResult<()> {
-        for UnresolvedAsset { source_path, target_path, chmod, is_built } in self.assets.unresolved.drain(..) {
-            let source_prefix: PathBuf = source_path.iter()
-                .take_while(|part| !is_glob_pattern(part.to_str().unwrap()))
-                .collect();
-            let source_is_glob = is_glob_pattern(source_path.to_str().unwrap());
-            let file_matches = glob::glob(source_path.to_str().expect("utf8 path"))?
-                // Remove dirs from globs without throwing away errors
-                .map(|entry| {
-                    let source_file = entry?;
-                    Ok(if source_file.is_dir() { None } else { Some(source_file) })
-                })
-                .filter_map(|res| match res {
-                    Ok(None) => None,
-                    Ok(Some(x)) => Some(Ok(x)),
-                    Err(x) => Some(Err(x)),
-                })
-                .collect::<CDResult<Vec<_>>>()?;
+    /// Entries of `depends` after substitution. `${shlibs:Depends}` is the same as `$auto`, unless it's set in substvars.
+    fn depends_words(&self, listener: &dyn Listener) -> CDResult<Vec<String>> {
+        let depends = if self.substvars.contains_key("shlibs:Depends") {
+            self.expand_substvars(&self.depends, listener)?
+        } else {
+            self.expand_substvars(&self.depends.replace("${shlibs:Depends}", "$auto"), listener)?
+        };
+        Ok(depends.split(',').map(|word| word.trim()).filter(|word| !word.is_empty()).map(String::from).collect())
+    }
 
-            // If glob didn't match anything, it's likely an error
-            // as all files should exist when called to resolve
-            if file_matches.is_empty() {
-                return Err(CargoDebError::AssetFileNotFound(source_path));
+    fn auto_dependencies(&self, listener: &dyn Listener) -> Vec<String> {
+        let mut deps: Vec<_> = self.dynamically_linked_binaries(listener).into_par_iter()
+            .filter_map(|bname| match resolve(bname) {
+                Ok(bindeps) => Some(bindeps),
+                // Without dpkg-shlibdeps, at least the glibc version can be found
+                Err(err) => match crate::elf::glibc_dependency(bname) {
+                    Ok(Some(libc)) => {
+                        listener.warning(Warning::AutoDepends, format!("{} (only {} detected for {})", err, libc, bname.display()));
+                        Some(vec![libc])
+                    },
+                    _ => {
+                        listener.warning(Warning::AutoDepends, format!("{} (no auto deps for {})", err, bname.display()));
+                        None
+                    },
+                },
+            })
+            .flatten()
+            .collect();
+        deps.sort_unstable();
+        deps.dedup();
+        deps
+    }
+
+    /// Replaces `${name}` substitution variables the way `dpkg-gencontrol` does.
+    /// Undefined variables are replaced with nothing.
+    pub(crate) fn expand_substvars(&self, value: &str, listener: &dyn Listener) -> CDResult<String> {
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            expanded.push_str(&rest[..start]);
+            let len = rest[start..].find('}').ok_or_else(|| CargoDebError::InvalidSubstvar(value.to_owned()))?;
+            let name = &rest[start + 2..start + len];
+            match self.substvar(name, listener) {
+                Some(var) => expanded.push_str(&var),
+                None => listener.warning(Warning::UndefinedSubstvar, format!("substitution variable ${{{}}} used, but is not defined", name)),
             }
+            rest = &rest[start + len + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn substvar(&self, name: &str, listener: &dyn Listener) -> Option<String> {
+        if let Some(var) = self.substvars.get(name) {
+            return Some(var.clone());
+        }
+        Some(match name {
+            "shlibs:Depends" => self.auto_dependencies(listener).join(", "),
+            "misc:Depends" => self.misc_dependencies().join(", "),
+            // debhelper would fill it in, but there's nothing to add here
+            "misc:Pre-Depends" => String::new(),
+            "binary:Version" | "source:Version" => self.deb_version.clone(),
+            "workspace:Depends" => self.workspace_depends.join(", "),
+            "source:Upstream-Version" => self.upstream_version().to_owned(),
+            "Arch" => self.package_architecture().to_owned(),
+            _ => return None,
+        })
+    }
+
+    /// Relationship field (e.g. `Recommends`) after substitution, without empty entries
+    pub(crate) fn relationship_field(&self, value: Option<&str>, listener: &dyn Listener) -> CDResult<Option<String>> {
+        let value = match value {
+            Some(value) => self.expand_substvars(value, listener)?,
+            None => return Ok(None),
+        };
+        let entries: Vec<_> = value.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()).collect();
+        Ok(if entries.is_empty() { None } else { Some(entries.join(", ")) })
+    }
+
+    /// Lists binaries and shared libraries that caused each of the `Depends` entries
+    pub fn explain_dependencies(&self, listener: &dyn Listener) -> CDResult<Vec<DependencyExplanation>> {
+        let mut explanations: BTreeMap<String, Vec<(PathBuf, Vec<String>)>> = BTreeMap::new();
+        for word in self.depends_words(listener)? {
+            if word == "$auto" {
+                let resolved = self.dynamically_linked_binaries(listener).into_par_iter()
+                    .filter_map(|bname| match resolve_with_libraries(bname) {
+                        Ok(bindeps) => Some((bname.to_owned(), bindeps)),
+                        Err(err) => {
+                            listener.warning(Warning::AutoDepends, format!("{} (no auto deps for {})", err, bname.display()));
+                            None
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                for (binary, bindeps) in resolved {
+                    for dep in bindeps.depends {
+                        let package_names: Vec<_> = dep.split('|')
+                            .filter_map(|alternative| alternative.split(|c: char| c.is_whitespace() || c == '(').find(|s| !s.is_empty()))
+                            .collect();
+                        let libraries = bindeps.library_packages.iter()
+                            .filter(|(_, package)| package_names.contains(&package.as_str()))
+                            .map(|(soname, _)| soname.clone())
+                            .collect();
+                        explanations.entry(dep).or_default().push((binary.clone(), libraries));
+                    }
+                }
+            } else {
+                let (dep, arch_spec) = get_architecture_specification(&word)?;
+                if arch_spec.map_or(Ok(true), |spec| match_architecture(spec, &self.architecture))? {
+                    explanations.entry(dep).or_default();
+                }
+            }
+        }
+        for (word, asset) in self.asset_dependencies() {
+            let (dep, arch_spec) = get_architecture_specification(word)?;
+            if arch_spec.map_or(Ok(true), |spec| match_architecture(spec, &self.architecture))? {
+                let source = asset.source.path().map_or_else(|| asset.target_path.clone(), Path::to_owned);
+                explanations.entry(dep).or_default().push((source, Vec::new()));
+            }
+        }
+        Ok(explanations.into_iter().map(|(dependency, required_by)| DependencyExplanation { dependency, required_by }).collect())
+    }
+
+    /// Errors about the assets that `resolve_assets` would find, for the ones that exist before the build
+    fn check_source_assets(&self) -> Vec<CargoDebError> {
+        let target = self.target.as_deref().unwrap_or(crate::DEFAULT_TARGET);
+        self.assets.unresolved.iter()
+            .filter(|asset| !asset.is_built)
+            .filter_map(|asset| match asset.condition.is_met(target, &self.enabled_features) {
+                Ok(true) => asset_matches(&*self.fs, &asset.source_path, false, asset.condition.exists, &self.binary_targets).err(),
+                Ok(false) => None,
+                Err(err) => Some(err),
+            })
+            .collect()
+    }
+
+    pub fn resolve_assets(&mut self, listener: &dyn Listener) -> CDResult<()> {
+        let target = self.target.as_deref().unwrap_or(crate::DEFAULT_TARGET);
+        // Every missing asset is reported, not only the first one
+        let mut errors = Vec::new();
+        for UnresolvedAsset { source_path, target_path, chmod, is_built, condition, dependencies, owner, strip_keep } in self.assets.unresolved.drain(..) {
+            match condition.is_met(target, &self.enabled_features) {
+                Ok(true) => {},
+                Ok(false) => continue,
+                Err(err) => { errors.push(err); continue; },
+            }
+            let file_matches = match asset_matches(&*self.fs, &source_path, is_built, condition.exists, &self.binary_targets) {
+                Ok(file_matches) => file_matches,
+                Err(err) => { errors.push(err); continue; },
+            };
+            let source_prefix: PathBuf = source_path.iter()
+                .take_while(|part| !part.to_str().is_some_and(is_glob_pattern))
+                .collect();
+            let source_is_glob = source_path.to_str().is_some_and(is_glob_pattern);
 
             for source_file in file_matches {
                 // XXX: how do we handle duplicated assets?
@@ -451,17 +1294,121 @@ impl Config {
                 } else {
                     target_path.clone()
                 };
-                self.assets.resolved.push(Asset::new(
+                let mut asset = Asset::new(
                     AssetSource::Path(source_file),
                     target_file,
                     chmod,
                     is_built,
-                ));
+                );
+                asset.dependencies = dependencies.clone();
+                asset.owner = owner.clone();
+                asset.strip_keep = strip_keep.clone();
+                self.assets.resolved.push(asset);
+            }
+        }
+        errors.extend(self.add_default_doc_assets().err());
+        // The version may have been changed after loading the manifest, so the changelog is made again
+        errors.extend(self.add_changelog_asset().err());
+        // Cargo.lock may not exist until the build
+        errors.extend(self.add_sbom_asset().err());
+        errors.extend(self.add_cargo_lock_asset().err());
+        errors.extend(self.apply_path_map().err());
+        self.apply_usr_merge(listener);
+        self.add_systemd_preset_asset();
+        self.check_fhs(listener);
+        errors.extend(self.check_binaries().err());
+        CargoDebError::from_errors(errors)
+    }
+
+    /// Warns about asset destinations that don't follow the [FHS](https://refspecs.linuxfoundation.org/FHS_3.0/fhs/index.html)
+    /// as required by [Debian policy](https://www.debian.org/doc/debian-policy/ch-opersys.html#file-system-structure)
+    fn check_fhs(&self, listener: &dyn Listener) {
+        for asset in &self.assets.resolved {
+            let path = &asset.target_path;
+            let problem = if path.starts_with("opt") && path.components().count() == 2 {
+                "is directly in /opt, but it should be in a /opt/<package>/ subdirectory (FHS 3.13)"
+            } else if path.starts_with("usr/etc") {
+                "is in /usr/etc, but configuration belongs in /etc (FHS 3.7, Debian policy 10.7.2)"
+            } else if path.starts_with("usr/local") {
+                "is in /usr/local, which is for the local administrator, not packages (Debian policy 9.1.2)"
+            } else if path.starts_with("usr") && asset.chmod & 0o022 != 0 {
+                "is writable by group or others, but /usr is read-only data (FHS 4.1)"
+            } else {
+                continue;
+            };
+            listener.warning(Warning::Fhs, format!("/{} {}", path.display(), problem));
+        }
+    }
+
+    /// Replaces the first matching prefix of `path_map` in asset destinations.
+    /// Prefixes match whole path components, so `opt/foo` doesn't match `opt/foobar`.
+    fn apply_path_map(&mut self) -> CDResult<()> {
+        if self.path_map.is_empty() {
+            return Ok(());
+        }
+        for asset in &mut self.assets.resolved {
+            // as bytes, because file names don't have to be UTF-8
+            let target = asset.target_path.as_unix_path();
+            let mapped = self.path_map.iter().find_map(|(from, to)| {
+                let rest = target.strip_prefix(from.as_bytes())?;
+                if !rest.is_empty() && !rest.starts_with(b"/") {
+                    return None;
+                }
+                Some([to.as_bytes(), rest].concat())
+            });
+            if let Some(mapped) = mapped {
+                let start = mapped.iter().take_while(|&&b| b == b'/').count();
+                asset.target_path = path_from_unix_bytes(&mapped[start..])?.into_owned();
             }
         }
         Ok(())
     }
 
+    /// Debian requires files to be in `/usr`, and `/bin`, `/sbin` and `/lib*` to be only symlinks to it.
+    /// With `usr_merge` the assets are moved there, otherwise they're reported.
+    fn apply_usr_merge(&mut self, listener: &dyn Listener) {
+        let mut legacy = Vec::new();
+        for asset in &mut self.assets.resolved {
+            let dir = asset.target_path.components().next().and_then(|c| c.as_os_str().to_str());
+            if !dir.is_some_and(|dir| LEGACY_ROOT_DIRS.contains(&dir)) {
+                continue;
+            }
+            legacy.push(Path::new("/").join(&asset.target_path));
+            if self.usr_merge {
+                asset.target_path = Path::new("usr").join(&asset.target_path);
+            }
+        }
+        let Some(first) = legacy.first() else { return };
+        let files = if legacy.len() > 1 { format!("{} and {} more", first.display(), legacy.len() - 1) } else { first.display().to_string() };
+        if self.usr_merge {
+            listener.warning(Warning::UsrMerge, format!("{} moved to /usr, because Debian's /bin, /sbin and /lib are symlinks to /usr. Set usr-merge = false to keep the paths", files));
+        } else {
+            listener.warning(Warning::UsrMerge, format!("{} installed outside /usr, which Debian doesn't allow since the /usr merge", files));
+        }
+    }
+
+    /// Checks that binaries built by Cargo exist, look executable, and are for the package's architecture,
+    /// so that a renamed or stale binary fails before packaging starts.
+    fn check_binaries(&self) -> CDResult<()> {
+        let mut errors = Vec::new();
+        for path in self.built_binaries().iter().filter_map(|asset| asset.source.path()) {
+            let mut magic = [0; 4];
+            let read = fs::File::open(path).and_then(|mut f| io::Read::read_exact(&mut f, &mut magic));
+            match read {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    errors.push(binary_not_found(path.to_owned(), &self.binary_targets));
+                },
+                Err(_) => errors.push(CargoDebError::BinaryNotExecutable(path.to_owned())),
+                Ok(()) if magic == *b"\x7fELF" => {
+                    errors.extend(crate::elf::check_architecture(path, &self.architecture, get_arch(crate::DEFAULT_TARGET)).err());
+                },
+                Ok(()) if magic.starts_with(b"#!") => {},
+                Ok(()) => errors.push(CargoDebError::BinaryNotExecutable(path.to_owned())),
+            }
+        }
+        CargoDebError::from_errors(errors)
+    }
+
     pub(crate) fn add_copyright_asset(&mut self) -> CDResult<()> {
         let copyright_file = crate::data::generate_copyright_asset(self)?;
         self.assets.resolved.push(Asset::new(
@@ -475,6 +1422,46 @@ impl Config {
         Ok(())
     }
 
+    /// With `default-docs`, installs the readme as it is, and license texts that aren't in the copyright file already,
+    /// unless files from the assets are in the doc directory. Examples from `doc-examples` don't count.
+    /// The license files are looked for next to `Cargo.toml`, or else in the workspace root.
+    fn add_default_doc_assets(&mut self) -> CDResult<()> {
+        let doc_dir = Path::new("usr/share/doc").join(&self.deb_name);
+        let has_docs = self.assets.resolved.iter().any(|asset| {
+            asset.source.path().is_some() && asset.target_path.starts_with("usr/share/doc") && !asset.target_path.starts_with(doc_dir.join("examples"))
+        });
+        if !self.default_docs || self.meta_package || has_docs {
+            return Ok(());
+        }
+        if let Some(readme) = &self.readme {
+            let path = self.path_in_workspace(readme);
+            self.fs.file_len(&path).map_err(|e| CargoDebError::IoFile("unable to read readme", e, path.clone()))?;
+            let target_path = doc_dir.join(readme.file_name().unwrap_or_default());
+            self.assets.resolved.push(Asset::new(AssetSource::Path(path), target_path, 0o644, false));
+        }
+        if self.license_file.is_none() {
+            let mut license_files = self.license_files_in(&self.manifest_dir)?;
+            if license_files.is_empty() && self.workspace_root != self.manifest_dir {
+                license_files = self.license_files_in(&self.workspace_root)?;
+            }
+            for path in license_files {
+                let target_path = doc_dir.join(path.file_name().unwrap_or_default());
+                self.assets.resolved.push(Asset::new(AssetSource::Path(path), target_path, 0o644, false));
+            }
+        }
+        Ok(())
+    }
+
+    /// `LICENSE*`, `LICENCE*` and `COPYING*` files in the directory, sorted
+    fn license_files_in(&self, dir: &Path) -> CDResult<Vec<PathBuf>> {
+        let dir = glob::Pattern::escape(&dir.to_string_lossy());
+        let mut files = Vec::new();
+        for prefix in ["COPYING", "LICENCE", "LICENSE"] {
+            files.extend(self.fs.glob(&format!("{}/{}*", dir, prefix))?);
+        }
+        Ok(files)
+    }
+
     pub fn add_debug_assets(&mut self) {
         let mut assets_to_add: Vec<Asset> = Vec::new();
         for asset in self.built_binaries().into_iter().filter(|a| a.source.path().is_some()) {
@@ -492,39 +1479,315 @@ impl Config {
         self.assets.resolved.append(&mut assets_to_add);
     }
 
+    /// Builds and packages only the given binaries (all of them if `None`) and examples.
+    ///
+    /// Explicitly listed assets are kept as they are, so they need to refer to `target/release/examples/` themselves.
+    pub fn select_targets(&mut self, binaries: Option<Vec<String>>, examples: Vec<String>) -> CDResult<()> {
+        if let Some(unknown) = binaries.iter().flatten().find(|&name| !self.binary_targets.contains(name)) {
+            return Err(CargoDebError::UnknownBinary(unknown.clone(), self.binary_targets.join(", ")));
+        }
+        self.binaries = binaries;
+        self.examples = examples;
+        if !self.assets.unresolved.is_empty() {
+            return Ok(());
+        }
+
+        let selected: Vec<_> = self.binaries.as_ref().unwrap_or(&self.binary_targets).iter()
+            .map(|name| (self.path_in_build(name), Path::new("usr/bin").join(name)))
+            .chain(self.examples.iter().map(|name| (self.path_in_build(Path::new("examples").join(name)), Path::new("usr/bin").join(name))))
+            .collect();
+        let examples_dir = self.path_in_build("examples");
+        let binary_paths: Vec<_> = self.binary_targets.iter().map(|name| self.path_in_build(name)).collect();
+        self.assets.resolved.retain(|asset| {
+            let path = asset.source.path();
+            !asset.is_built || path.is_none_or(|path| !path.starts_with(&examples_dir) && !binary_paths.iter().any(|p| p == path))
+        });
+        for (source_path, target_path) in selected {
+            self.assets.resolved.push(Asset::new(AssetSource::Path(source_path), target_path, 0o755, true));
+        }
+        Ok(())
+    }
+
+    /// Appends `+git<date>.<short hash>` of the latest commit to `deb_version`, for snapshot builds
+    pub fn append_git_version(&mut self) -> CDResult<()> {
+        let (hash, timestamp) = self.git_head()?;
+        self.deb_version = git_version(&self.deb_version, &hash, timestamp);
+        self.git_commit = Some(hash);
+        Ok(())
+    }
+
+    /// Appends e.g. `~bookworm1` to `deb_version`, so that builds of the same version for different distros have different versions and file names
+    pub fn append_version_suffix(&mut self, suffix: &str) -> CDResult<()> {
+        self.deb_version = version_with_suffix(&self.deb_version, suffix)?;
+        Ok(())
+    }
+
+    /// `--backport`: appends the suite's version suffix, and sets the changelog distribution
+    pub fn backport(&mut self, suite: &str) -> CDResult<()> {
+        let (suffix, distribution) = backport_suffix(suite)?;
+        self.append_version_suffix(&suffix)?;
+        self.changelog_distribution = Some(distribution);
+        Ok(())
+    }
+
+    /// Hash and commit time of the latest git commit of the project
+    pub(crate) fn git_head(&self) -> CDResult<(String, u64)> {
+        const GIT_COMMAND: &str = "git";
+        let output = Command::new(GIT_COMMAND)
+            .current_dir(&self.manifest_dir)
+            .args(["log", "-1", "--format=%H %ct"])
+            .output()
+            .map_err(|e| CargoDebError::CommandFailed(e, GIT_COMMAND))?;
+        if !output.status.success() {
+            return Err(CargoDebError::CommandError(GIT_COMMAND, "log".to_owned(), output.stderr));
+        }
+        let output = String::from_utf8(output.stdout)?;
+        let (hash, timestamp) = output.trim().split_once(' ')
+            .and_then(|(hash, time)| Some((hash, time.parse().ok()?)))
+            .ok_or("unexpected output of git log")?;
+        Ok((hash.to_owned(), timestamp))
+    }
+
+    /// Replaces the changelog asset if it has already been added
     fn add_changelog_asset(&mut self) -> CDResult<()> {
-        // The file is autogenerated later
-        if self.changelog.is_some() {
-            if let Some(changelog_file) = crate::data::generate_changelog_asset(self)? {
+        let changelog_file = crate::data::generate_changelog_asset(self)?;
+        let target_path = Path::new("usr/share/doc")
+            .join(&self.deb_name)
+            .join("changelog.Debian.gz");
+        self.assets.resolved.retain(|asset| asset.target_path != target_path);
+        self.assets.resolved.push(Asset::new(
+            AssetSource::Data(changelog_file),
+            target_path,
+            0o644,
+            false,
+        ));
+        Ok(())
+    }
+
+    fn add_sbom_asset(&mut self) -> CDResult<()> {
+        if let Some(format) = self.sbom {
+            let sbom = crate::sbom::generate_sbom(self, format)?;
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Data(sbom),
+                Path::new("usr/share/doc")
+                    .join(&self.deb_name)
+                    .join(format.file_name()),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_cargo_lock_asset(&mut self) -> CDResult<()> {
+        if self.include_cargo_lock {
+            let cargo_lock = crate::data::generate_cargo_lock_asset(self)?;
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Data(cargo_lock),
+                Path::new("usr/share/doc")
+                    .join(&self.deb_name)
+                    .join("Cargo.lock.gz"),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Needs to be after stripping, so that the separate debug files are known
+    pub fn add_build_ids_asset(&mut self) -> CDResult<()> {
+        if self.build_ids {
+            let build_ids = crate::data::generate_build_ids(self)?;
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Data(build_ids),
+                Path::new("usr/share/doc")
+                    .join(&self.deb_name)
+                    .join("build-ids.json"),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Where the `sha256-manifest` is installed
+    pub(crate) fn sha256_manifest_path(&self) -> Option<PathBuf> {
+        self.sha256_manifest.then(|| Path::new("usr/share").join(&self.deb_name).join("manifest.sha256"))
+    }
+
+    /// Its contents are filled in by `read_assets`, from the checksums it computes along with the MD5 ones,
+    /// so it covers all the assets, even those added after it. `Installed-Size` uses `sha256_manifest_len`.
+    pub fn add_sha256_manifest_asset(&mut self) {
+        if let Some(path) = self.sha256_manifest_path() {
+            self.assets.resolved.push(Asset::new(AssetSource::Data(Vec::new()), path, 0o644, false));
+        }
+    }
+
+    fn add_news_asset(&mut self) -> CDResult<()> {
+        if let Some(news_file) = crate::data::generate_news_asset(self)? {
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Data(news_file),
+                Path::new("usr/share/doc")
+                    .join(&self.deb_name)
+                    .join("NEWS.Debian.gz"),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_doc_base_asset(&mut self) -> CDResult<()> {
+        if let Some(ref doc_base) = self.doc_base {
+            let control = crate::data::generate_doc_base_asset(self, doc_base)?;
+            let document = doc_base.document.as_deref().unwrap_or(&self.deb_name);
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Data(control),
+                Path::new("usr/share/doc-base").join(document),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    /// shared-mime-info XML definitions, installed where `update-mime-database` picks them up
+    fn add_mime_assets(&mut self, mime_types: Option<Vec<String>>) -> CDResult<()> {
+        for source in mime_types.unwrap_or_default() {
+            let source_path = self.path_in_workspace(&source);
+            if !is_path_file(&source_path) {
+                return Err(CargoDebError::AssetFileNotFound(source_path));
+            }
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Path(source_path),
+                PathBuf::from("usr/share/mime/packages/"),
+                0o644,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    /// GNU info documents, installed in `/usr/share/info`, where `install-info` adds them to the directory
+    fn add_info_assets(&mut self, info_pages: Option<Vec<String>>) -> CDResult<()> {
+        for source in info_pages.unwrap_or_default() {
+            let pattern = self.path_in_workspace(&source);
+            let mut found = false;
+            for source_path in glob::glob(pattern.to_str().expect("utf8 path"))? {
+                let source_path = source_path?;
+                // The `dir` index is made by `install-info`, and must not be in packages
+                if !is_path_file(&source_path) || source_path.file_name().is_some_and(|name| name == "dir") {
+                    continue;
+                }
+                found = true;
                 self.assets.resolved.push(Asset::new(
-                    AssetSource::Data(changelog_file),
-                    Path::new("usr/share/doc")
-                        .join(&self.deb_name)
-                        .join("changelog.Debian.gz"),
+                    AssetSource::Path(source_path),
+                    PathBuf::from("usr/share/info/"),
                     0o644,
                     false,
                 ));
             }
+            if !found {
+                return Err(CargoDebError::AssetFileNotFound(pattern));
+            }
+        }
+        Ok(())
+    }
+
+    /// Example sources and other auxiliary docs, installed in `/usr/share/doc/<package>/examples`.
+    /// Globs keep the directory structure below them, like in `assets`.
+    fn add_doc_example_assets(&mut self, doc_examples: Option<DocExamples>) {
+        let (sources, exists) = match doc_examples {
+            None | Some(DocExamples::Enabled(false)) => return,
+            // crates without examples don't need to turn it off
+            Some(DocExamples::Enabled(true)) => (vec!["examples/**/*".to_owned()], true),
+            Some(DocExamples::Files(sources)) => (sources, false),
+        };
+        let target_path = Path::new("usr/share/doc").join(&self.deb_name).join("examples/");
+        for source in sources {
+            self.assets.unresolved.push(UnresolvedAsset {
+                source_path: self.path_in_workspace(&source),
+                target_path: target_path.clone(),
+                chmod: 0o644,
+                is_built: false,
+                condition: AssetCondition { exists, ..AssetCondition::default() },
+                dependencies: AssetDependencies::default(),
+                owner: None,
+                strip_keep: StripKeep::default(),
+            });
+        }
+    }
+
+    /// polkit `.policy` action definitions, which are checked for XML errors, and JavaScript `.rules`
+    fn add_polkit_assets(&mut self, actions: Option<Vec<String>>, rules: Option<Vec<String>>) -> CDResult<()> {
+        for source in actions.unwrap_or_default() {
+            let source_path = self.path_in_workspace(&source);
+            if !source.ends_with(".policy") {
+                return Err(CargoDebError::InvalidPolkitFile(source_path, "must have the .policy extension".into()));
+            }
+            let xml = crate::util::read_file_to_string(source_path.clone())
+                .map_err(|e| CargoDebError::IoFile("unable to read polkit action", e, source_path.clone()))?;
+            crate::polkit::check_policy(&xml).map_err(|problem| CargoDebError::InvalidPolkitFile(source_path.clone(), problem))?;
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Path(source_path),
+                PathBuf::from("usr/share/polkit-1/actions/"),
+                0o644,
+                false,
+            ));
+        }
+        for source in rules.unwrap_or_default() {
+            let source_path = self.path_in_workspace(&source);
+            if !source.ends_with(".rules") {
+                return Err(CargoDebError::InvalidPolkitFile(source_path, "must have the .rules extension".into()));
+            }
+            if !is_path_file(&source_path) {
+                return Err(CargoDebError::AssetFileNotFound(source_path));
+            }
+            self.assets.resolved.push(Asset::new(
+                AssetSource::Path(source_path),
+                PathBuf::from("usr/share/polkit-1/rules.d/"),
+                0o644,
+                false,
+            ));
         }
         Ok(())
     }
 
     fn add_systemd_assets(&mut self) -> CDResult<()> {
         if let Some(ref config) = self.systemd_units {
+            for (name, unit) in config.units.iter().flatten() {
+                let is_unit = name.rsplit_once('.').is_some_and(|(stem, ext)| !stem.is_empty() && SYSTEMD_UNIT_TYPES.contains(&ext));
+                if !is_unit || name.contains('/') {
+                    return Err(CargoDebError::InvalidSystemdUnit(name.clone()));
+                }
+                if let Some(source) = &unit.source {
+                    let source_path = self.path_in_workspace(source);
+                    if !is_path_file(&source_path) {
+                        return Err(CargoDebError::AssetFileNotFound(source_path));
+                    }
+                    self.assets.resolved.push(Asset::new(
+                        AssetSource::Path(source_path),
+                        Path::new("lib/systemd/system").join(name),
+                        0o644,
+                        false,
+                    ));
+                }
+            }
+
             let units_dir_option = config.unit_scripts.as_ref()
                 .or(self.maintainer_scripts.as_ref());
             if let Some(unit_dir) = units_dir_option {
                 let search_path = self.path_in_workspace(unit_dir);
                 let package = &self.name;
-                let unit_name = if let Some(ref unit_name) = config.unit_name {
-                    Some(unit_name.as_str())
-                } else {
-                    None
-                };
+                let unit_name = config.unit_name.as_deref();
 
                 let units = dh_installsystemd::find_units(&search_path, package, unit_name);
 
                 for (source, target) in &units {
+                    // explicitly configured sources take precedence
+                    if self.assets.resolved.iter().any(|asset| asset.target_path == target.path) {
+                        continue;
+                    }
                     self.assets.resolved.push(Asset::new(
                         AssetSource::Path(source.clone()),
                         target.path.clone(),
@@ -537,9 +1800,45 @@ impl Config {
         Ok(())
     }
 
-    /// Executables AND dynamic libraries
-    fn all_binaries(&self) -> Vec<&AssetSource> {
-        self.binaries(false).iter().map(|asset| &asset.source).collect()
+    /// The preset file needs to know all units, including ones from `assets`
+    fn add_systemd_preset_asset(&mut self) {
+        let preset = self.systemd_units.as_ref().and_then(|config| config.preset);
+        if let Some(preset) = preset {
+            if let Some((path, preset_file)) = dh_installsystemd::preset_file(&self.deb_name, &self.assets.resolved, preset) {
+                self.assets.resolved.push(Asset::new(AssetSource::Data(preset_file), path, 0o644, false));
+            }
+        }
+    }
+
+    /// Executables AND dynamic libraries, except ones with `auto-depends = false`
+    fn auto_depends_binaries(&self) -> Vec<&AssetSource> {
+        self.binaries(false).iter()
+            .filter(|asset| asset.dependencies.auto)
+            .map(|asset| &asset.source)
+            .collect()
+    }
+
+    /// Binaries to look up library dependencies of. Statically linked binaries don't have any.
+    fn dynamically_linked_binaries(&self, listener: &dyn Listener) -> Vec<&Path> {
+        let is_musl = self.target.as_deref().unwrap_or(crate::DEFAULT_TARGET).contains("-musl");
+        self.auto_depends_binaries().into_iter()
+            .filter_map(|source| source.path())
+            .filter(|path| {
+                let is_static = self.static_binaries
+                    .unwrap_or_else(|| is_musl || crate::elf::is_static(path).unwrap_or(false));
+                if is_static {
+                    listener.info(format!("{} is statically linked, so it has no library dependencies", path.display()));
+                }
+                !is_static
+            })
+            .collect()
+    }
+
+    /// Extra `depends` of individual assets, with the asset they came from
+    fn asset_dependencies(&self) -> impl Iterator<Item = (&str, &Asset)> {
+        self.assets.resolved.iter()
+            .flat_map(|asset| asset.dependencies.depends.iter().flat_map(|d| d.split(',')).map(move |word| (word.trim(), asset)))
+            .filter(|(word, _)| !word.is_empty())
     }
 
     /// Executables AND dynamic libraries, but only in `target/release`
@@ -586,8 +1885,68 @@ impl Config {
         None
     }
 
+    /// `deb_version` without the epoch and the revision
+    #[must_use]
+    pub fn upstream_version(&self) -> &str {
+        let version = self.deb_version.split_once(':').map_or(self.deb_version.as_str(), |(_, v)| v);
+        version.rsplit_once('-').map_or(version, |(v, _)| v)
+    }
+
+    /// The part of `deb_version` after the last `-`, e.g. `1` or `1~bpo12+1`
+    #[must_use]
+    pub fn debian_revision(&self) -> Option<&str> {
+        self.deb_version.rsplit_once('-').map(|(_, revision)| revision)
+    }
+
+    /// Variables set for `cargo build`, so that binaries can report the version they were packaged as,
+    /// e.g. with `option_env!("CARGO_DEB_VERSION")`
+    pub(crate) fn build_env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("CARGO_DEB_PACKAGE", self.deb_name.clone()),
+            ("CARGO_DEB_VERSION", self.deb_version.clone()),
+            ("CARGO_DEB_UPSTREAM_VERSION", self.upstream_version().to_owned()),
+            ("CARGO_DEB_REVISION", self.debian_revision().unwrap_or_default().to_owned()),
+            ("CARGO_DEB_ARCH", self.package_architecture().to_owned()),
+        ]
+    }
+
+    /// `unstable`, unless set, or implied by the distro profile
+    #[must_use]
+    pub fn changelog_distribution(&self) -> &str {
+        self.changelog_distribution.as_deref()
+            .unwrap_or_else(|| self.distro_profile.map_or("unstable", DistroProfile::changelog_distribution))
+    }
+
+    /// What debhelper would add to `${misc:Depends}`. The systemd scripts use `deb-systemd-helper`,
+    /// which Debian doesn't have without init-system-helpers, e.g. on systems with another init.
+    /// Every Ubuntu system boots with systemd and has it.
+    fn misc_dependencies(&self) -> Vec<String> {
+        if self.distro_profile == Some(DistroProfile::Debian) && self.systemd_units.is_some() {
+            vec!["init-system-helpers (>= 1.54~)".to_owned()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Architecture name for the control file and the package file name
+    pub fn package_architecture(&self) -> &str {
+        match self.package_format {
+            PackageFormat::Deb => &self.architecture,
+            PackageFormat::Ipk => &self.ipk_architecture,
+        }
+    }
+
     pub(crate) fn path_in_build<P: AsRef<Path>>(&self, rel_path: P) -> PathBuf {
-        self.target_dir.join("release").join(rel_path)
+        self.target_dir.join(self.profile_dir_name()).join(rel_path)
+    }
+
+    /// Cargo puts artifacts of the `dev` and `test` profiles in `debug`
+    pub(crate) fn profile_dir_name(&self) -> &str {
+        match self.profile.as_str() {
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            other => other,
+        }
     }
 
     pub(crate) fn path_in_workspace<P: AsRef<Path>>(&self, rel_path: P) -> PathBuf {
@@ -599,6 +1958,12 @@ impl Config {
         self.target_dir.join("debian").join(&self.name)
     }
 
+    /// Compressed `data.tar` from the previous run, see `compress::cached`
+    #[must_use]
+    pub fn compression_cache_dir(&self) -> PathBuf {
+        self.target_dir.join("debian").join(".cache").join(&self.deb_name)
+    }
+
     /// Save final .deb here
     pub(crate) fn deb_output_path(&self, filename: &str) -> PathBuf {
         if let Some(ref path_str) = self.deb_output_path {
@@ -617,34 +1982,155 @@ impl Config {
         self.target_dir.join("debian")
     }
 
+    /// Cargo runs in the crate's directory, and looks for its config from there
     pub(crate) fn cargo_config(&self) -> CDResult<Option<CargoConfig>> {
-        CargoConfig::new(&self.target_dir)
+        CargoConfig::new(&self.manifest_dir)
+    }
+
+    /// Linker for the target in `CARGO_TARGET_<TRIPLE>_LINKER` or `.cargo/config.toml`, or `-C linker` in the rustflags
+    pub(crate) fn cargo_linker(&self, target: &str) -> Option<String> {
+        if let Some(linker) = crate::config::env_linker(target) {
+            return Some(linker);
+        }
+        let cargo_config = self.cargo_config().ok().flatten();
+        if let Some(linker) = cargo_config.as_ref().and_then(|conf| conf.linker_command(target)) {
+            return Some(linker.into_owned());
+        }
+        let rustflags = crate::config::env_rustflags(target).or_else(|| cargo_config?.rustflags(target))?;
+        crate::config::linker_in_rustflags(&rustflags).map(String::from)
+    }
+
+    /// The `linker` setting, when Cargo doesn't have a linker for the target already
+    pub(crate) fn cross_linker(&self, target: &str) -> Option<&str> {
+        self.linker.as_deref().filter(|_| self.cargo_linker(target).is_none())
     }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 struct Cargo {
     pub package: cargo_toml::Package<CargoPackageMetadata>,
-    pub profile: Option<cargo_toml::Profiles>,
+    pub profile: Option<BTreeMap<String, CargoProfile>>,
+    #[serde(default)]
+    pub features: HashMap<String, Vec<String>>,
+}
+
+/// The parts of `[profile.*]` that matter for packaging
+#[derive(Clone, Debug, Deserialize, Default)]
+struct CargoProfile {
+    pub debug: Option<toml::Value>,
+    pub inherits: Option<String>,
 }
 
 impl Cargo {
+    /// `debug` setting of the profile, or of the profiles it inherits from
+    fn profile_debug(&self, name: &str) -> Option<&toml::Value> {
+        let mut name = name;
+        // inheritance cycles are an error in Cargo
+        for _ in 0..8 {
+            let profile = self.profile.as_ref().and_then(|p| p.get(name));
+            if let Some(debug) = profile.and_then(|p| p.debug.as_ref()) {
+                return Some(debug);
+            }
+            name = match (profile.and_then(|p| p.inherits.as_deref()), name) {
+                (Some(parent), _) => parent,
+                (None, "test") => "dev",
+                (None, "bench") => "release",
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    /// Settings from a separate file with the same keys as `[package.metadata.deb]` take precedence over the ones in `Cargo.toml`
+    fn use_deb_config(&mut self, path: &Path) -> CDResult<()> {
+        let content = fs::read(path)
+            .map_err(|e| CargoDebError::IoFile("unable to read packaging config", e, path.to_owned()))?;
+        let deb_config = toml::from_slice::<CargoDeb>(&content).map_err(|e| CargoDebError::toml_in_file(e, path, &content))?;
+        let metadata = self.package.metadata.get_or_insert_with(CargoPackageMetadata::default);
+        metadata.deb = Some(match metadata.deb.take() {
+            Some(deb) => deb_config.inherit_from(deb),
+            None => deb_config,
+        });
+        Ok(())
+    }
+
+    /// Expands `$CARGO_PKG_*`, `${env:NAME}` and `${target}` placeholders in the metadata
+    fn interpolate_metadata(&mut self, deb: &mut CargoDeb, target: Option<&str>) -> CDResult<()> {
+        let package = &self.package;
+        let version = package.version.as_str();
+        let (release, pre) = version.split('+').next().unwrap_or(version).split_once('-').unwrap_or((version, ""));
+        let mut release = release.split('.');
+        let vars: HashMap<&str, String> = vec![
+            ("CARGO_PKG_NAME", package.name.clone()),
+            ("CARGO_PKG_VERSION", version.to_owned()),
+            ("CARGO_PKG_VERSION_MAJOR", release.next().unwrap_or_default().to_owned()),
+            ("CARGO_PKG_VERSION_MINOR", release.next().unwrap_or_default().to_owned()),
+            ("CARGO_PKG_VERSION_PATCH", release.next().unwrap_or_default().to_owned()),
+            ("CARGO_PKG_VERSION_PRE", pre.to_owned()),
+            ("CARGO_PKG_AUTHORS", package.authors.join(":")),
+            ("CARGO_PKG_DESCRIPTION", package.description.clone().unwrap_or_default()),
+            ("CARGO_PKG_HOMEPAGE", package.homepage.clone().unwrap_or_default()),
+            ("CARGO_PKG_REPOSITORY", package.repository.clone().unwrap_or_default()),
+            ("CARGO_PKG_LICENSE", package.license.clone().unwrap_or_default()),
+            ("target", target.unwrap_or(crate::DEFAULT_TARGET).to_owned()),
+        ].into_iter().collect();
+        let lookup = |name: &str| -> CDResult<Option<String>> {
+            if let Some(var) = name.strip_prefix("env:") {
+                return std::env::var(var).map(Some).map_err(|_| CargoDebError::UndefinedVariable(var.to_owned()));
+            }
+            Ok(vars.get(name).cloned())
+        };
+        let expand = |value: &mut String| -> CDResult<()> {
+            *value = interpolate(value, &lookup)?;
+            Ok(())
+        };
+
+        let fields = vec![
+            &mut self.package.description, &mut deb.extended_description, &mut deb.maintainer, &mut deb.copyright,
+            &mut deb.uploaders, &mut deb.origin, &mut deb.bugs,
+            &mut deb.section, &mut deb.depends, &mut deb.pre_depends, &mut deb.recommends, &mut deb.suggests,
+            &mut deb.enhances, &mut deb.conflicts, &mut deb.breaks, &mut deb.replaces, &mut deb.provides,
+        ];
+        for value in fields.into_iter().flatten() {
+            expand(value)?;
+        }
+        for asset in deb.assets.iter_mut().flatten() {
+            match asset {
+                CargoDebAsset::Array(values) => values.iter_mut().try_for_each(expand)?,
+                CargoDebAsset::Table(table) => {
+                    expand(&mut table.source)?;
+                    expand(&mut table.dest)?;
+                    if let Some(depends) = &mut table.depends {
+                        expand(depends)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
     /// Convert Cargo.toml/metadata information into internal configu structure
     ///
-    /// **IMPORTANT**: This function must not create or expect to see any files on disk!
+    /// **IMPORTANT**: This function must not create or expect to see any built files on disk!
     /// It's run before destination directory is cleaned up, and before the build start!
+    /// It only reads the package's own sources, like `debian-dir` and `extended-description-file`.
     ///
+    #[allow(clippy::too_many_arguments)]
     fn into_config(
         mut self,
         root_package: &CargoMetadataPackage,
         manifest_dir: &Path,
+        workspace_root: &Path,
         deb_output_path: Option<String>,
         target_dir: &Path,
         target: Option<&str>,
-        variant: Option<&str>,
+        variant: Option<Variant<'_>>,
         deb_version: Option<String>,
+        profile: &str,
+        extra_features: &[String],
         listener: &dyn Listener,
     ) -> CDResult<Config> {
+        let cargo_target_dir = target_dir.to_owned();
         // Cargo cross-compiles to a dir
         let target_dir = if let Some(target) = target {
             target_dir.join(target)
@@ -652,59 +2138,150 @@ impl Cargo {
             target_dir.to_owned()
         };
 
-        // If we build against a variant use that config and change the package name
-        let mut deb = if let Some(variant) = variant {
-            // Use dash as underscore is not allowed in package names
-            self.package.name = format!("{}-{}", self.package.name, variant);
-            let mut deb = self.package
-                .metadata
-                .take()
-                .and_then(|m| m.deb)
-                .unwrap_or_else(CargoDeb::default);
-            let variant = deb.variants
-                .as_mut()
-                .and_then(|v| v.remove(variant))
-                .ok_or_else(|| CargoDebError::VariantNotFound(variant.to_string()))?;
-            variant.inherit_from(deb)
-        } else {
-            self.package
-                .metadata
-                .take()
-                .and_then(|m| m.deb)
-                .unwrap_or_else(CargoDeb::default)
+        let mut deb = self.package
+            .metadata
+            .take()
+            .and_then(|m| m.deb)
+            .unwrap_or_default();
+        match variant {
+            // If we build against a variant use that config and change the package name
+            Some(Variant::Named(variant)) => {
+                // Use dash as underscore is not allowed in package names
+                self.package.name = format!("{}-{}", self.package.name, variant);
+                let variant = deb.variants
+                    .as_mut()
+                    .and_then(|v| v.remove(variant))
+                    .ok_or_else(|| CargoDebError::VariantNotFound(variant.to_string()))?;
+                deb = variant.inherit_from(deb);
+            },
+            // A backport is the same package, and doesn't need a variant
+            Some(Variant::Backport(suite)) => {
+                if let Some(variant) = deb.variants.as_mut().and_then(|v| v.remove(suite)) {
+                    deb = variant.inherit_from(deb);
+                }
+            },
+            None => {},
+        }
+
+        if let Some(debian_dir) = deb.debian_dir.clone() {
+            let package_name = deb.name.clone().unwrap_or_else(|| self.package.name.clone());
+            let debian = DebianDir::read(&manifest_dir.join(&debian_dir), &package_name)?;
+            let (description, extended_description) = debian.description();
+            if self.package.description.is_none() {
+                self.package.description = description;
+            }
+            deb = deb.inherit_from(CargoDeb::from_debian_dir(&debian, &debian_dir, extended_description, manifest_dir));
+        }
+        deb.apply_env_overrides(|name| std::env::var(name).ok());
+        // All the problems are reported together, so that they can be fixed at once
+        let mut errors = Vec::new();
+        collect_error(&mut errors, self.interpolate_metadata(&mut deb, target));
+        if let Some(bugs) = deb.bugs.as_deref().filter(|bugs| !bugs.contains("://")) {
+            errors.push(CargoDebError::InvalidBugsUrl(bugs.to_owned()));
+        }
+
+        let mut features = deb.features.take().unwrap_or_default();
+        for feature in extra_features {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        let default_features = deb.default_features.unwrap_or(true);
+        let enabled_features = self.enabled_features(&features, default_features);
+        let (license_file, license_file_skip_lines) = collect_error(&mut errors, self.license_file(deb.license_file.as_ref())).unwrap_or_default();
+        let readme = self.package.readme.as_ref();
+        self.check_config(manifest_dir, readme, &deb, listener);
+        let deb_name = match deb.name.take() {
+            Some(name) => name,
+            None => {
+                let name = normalized_package_name(&self.package.name);
+                if name != self.package.name {
+                    listener.warning(Warning::PackageName, format!("The crate name '{}' isn't a valid Debian package name, so the package is named '{}'. Set `name` in [package.metadata.deb] to choose a different one", self.package.name, name));
+                }
+                name
+            },
+        };
+        if !is_valid_package_name(&deb_name) {
+            errors.push(CargoDebError::InvalidPackageName(deb_name.clone()));
+        }
+        if let Some(section) = deb.section.as_deref() {
+            errors.extend(check_section(section).err());
+        }
+        let arch_map = deb.arch_map.take().unwrap_or_default();
+        let architecture = collect_error(&mut errors, debian_arch(target.unwrap_or(crate::DEFAULT_TARGET), &arch_map))
+            .unwrap_or_else(|| get_arch(target.unwrap_or(crate::DEFAULT_TARGET))).to_owned();
+        if let Some(scripts) = &deb.generated_scripts {
+            errors.extend(scripts.check().err());
+        }
+        for path in deb.remove_on_purge.iter().flatten() {
+            errors.extend(check_purge_path(path, &[&deb_name, &root_package.name]).err());
+        }
+        errors.extend(check_armhf_baseline(target.unwrap_or(crate::DEFAULT_TARGET), &architecture, deb.armhf_baseline.as_deref(), listener).err());
+        if let Some(priority) = deb.priority.as_deref().filter(|p| !PRIORITIES.contains(p)) {
+            let suggestion = if priority == "extra" { Some("optional") } else { closest_match(priority, &PRIORITIES) };
+            errors.push(CargoDebError::InvalidSection("priority", priority.to_owned(), suggestion.map(|s| format!(". Did you mean '{}'?", s)).unwrap_or_default()));
+        }
+        let tar_format = collect_error(&mut errors, deb.tar_format.as_deref().map(str::parse).transpose()).flatten();
+        let compress_type = collect_error(&mut errors, deb.compress_type.as_deref().map(str::parse).transpose()).flatten();
+        let distro_profile = collect_error(&mut errors, deb.distro_profile.as_deref().map(str::parse).transpose()).flatten();
+        let installed_size_method = collect_error(&mut errors, deb.installed_size_method.as_deref().map(str::parse).transpose()).flatten();
+        let sbom = collect_error(&mut errors, deb.sbom.as_deref().map(str::parse).transpose()).flatten();
+        let strip_keep = collect_error(&mut errors, StripKeep::new(deb.strip_keep_symbols.take(), deb.strip_keep_sections.take())).unwrap_or_default();
+        let copyright = deb.copyright.take().ok_or_then(|| {
+            if self.package.authors.is_empty() {
+                return Err(CargoDebError::MissingCopyright);
+            }
+            Ok(self.package.authors.join(", "))
+        });
+        let copyright = collect_error(&mut errors, copyright).unwrap_or_default();
+        let maintainer = deb.maintainer.take().ok_or_then(|| {
+            if let Some(author) = self.package.authors.first() {
+                return Ok(author.to_owned());
+            }
+            let maintainer = git_config_maintainer(manifest_dir).ok_or(CargoDebError::MissingMaintainer)?;
+            listener.info(format!("maintainer and authors not set. Using {} from git config", maintainer));
+            Ok(maintainer)
+        });
+        let maintainer = collect_error(&mut errors, maintainer).unwrap_or_default();
+        let systemd_units = match (deb.systemd.take(), deb.systemd_units.take()) {
+            (Some(systemd), Some(_)) => {
+                errors.push(CargoDebError::ConflictingSettings("[package.metadata.deb.systemd]", "systemd-units"));
+                Some(systemd)
+            },
+            (systemd, systemd_units) => systemd.or(systemd_units),
         };
-
-        let (license_file, license_file_skip_lines) = self.license_file(deb.license_file.as_ref())?;
-        let readme = self.package.readme.as_ref();
-        self.check_config(manifest_dir, readme, &deb, listener);
         let mut config = Config {
             manifest_dir: manifest_dir.to_owned(),
+            workspace_root: workspace_root.to_owned(),
             deb_output_path,
             target: target.map(|t| t.to_string()),
             target_dir,
+            cargo_target_dir,
+            profile: profile.to_owned(),
             name: self.package.name.clone(),
-            deb_name: deb.name.take().unwrap_or_else(|| self.package.name.clone()),
+            deb_name,
             deb_version: deb_version.unwrap_or(self.version_string(deb.revision)),
+            git_commit: None,
             license: self.package.license.take(),
             license_file,
             license_file_skip_lines,
-            copyright: deb.copyright.take().ok_or_then(|| {
-                if self.package.authors.is_empty() {
-                    return Err("The package must have a copyright or authors property".into());
-                }
-                Ok(self.package.authors.join(", "))
-            })?,
+            copyright,
             homepage: self.package.homepage.clone(),
             documentation: self.package.documentation.clone(),
+            origin: deb.origin.take(),
+            bugs: deb.bugs.take(),
+            uploaders: deb.uploaders.take(),
+            user_defined_fields: std::mem::take(&mut deb.user_defined_fields),
             repository: self.package.repository.take(),
             description: self.package.description.take().unwrap_or_else(||format!("[generated from Rust crate {}]", self.package.name)),
-            extended_description: self.extended_description(
+            readme: readme.map(PathBuf::from),
+            default_docs: deb.default_docs.unwrap_or(false),
+            extended_description: collect_error(&mut errors, self.extended_description(
                 deb.extended_description.take(),
-                deb.extended_description_file.as_ref().or(readme))?,
-            maintainer: deb.maintainer.take().ok_or_then(|| {
-                Ok(self.package.authors.get(0)
-                    .ok_or("The package must have a maintainer or authors property")?.to_owned())
-            })?,
+                deb.extended_description_file.as_ref().or(readme),
+                deb.extended_description_end.as_deref(),
+                manifest_dir)).flatten(),
+            maintainer,
             depends: deb.depends.take().unwrap_or_else(|| "$auto".to_owned()),
             pre_depends: deb.pre_depends.take(),
             recommends: deb.recommends.take(),
@@ -713,74 +2290,164 @@ impl Cargo {
             conflicts: deb.conflicts.take(),
             breaks: deb.breaks.take(),
             replaces: deb.replaces.take(),
-            provides: deb.provides.take(),
+            provides: feature_provides(deb.provides.take(), &deb.feature_provides.take().unwrap_or_default(), &enabled_features),
+            substvars: BTreeMap::new(),
             section: deb.section.take(),
             priority: deb.priority.take().unwrap_or_else(|| "optional".to_owned()),
-            architecture: get_arch(target.unwrap_or(crate::DEFAULT_TARGET)).to_owned(),
+            architecture,
+            ipk_architecture: deb.ipk_architecture.take()
+                .unwrap_or_else(|| get_ipk_arch(target.unwrap_or(crate::DEFAULT_TARGET)).to_owned()),
+            package_format: PackageFormat::Deb,
+            tar_format: tar_format.unwrap_or(TarFormat::Gnu),
+            compress_type,
+            distro_profile,
+            installed_size_method: installed_size_method.unwrap_or(InstalledSizeMethod::Dpkg),
+            sbom,
+            include_cargo_lock: deb.include_cargo_lock.unwrap_or(false),
+            build_ids: deb.build_ids.unwrap_or(false),
+            sha256_manifest: deb.sha256_manifest.unwrap_or(false),
             conf_files: deb.conf_files.map(|x| format_conffiles(&x)),
+            remove_on_purge: deb.remove_on_purge.take().unwrap_or_default(),
             assets: Assets::new(),
             triggers_file: deb.triggers_file.map(PathBuf::from),
             changelog: deb.changelog.take(),
+            changelog_distribution: deb.changelog_distribution.take(),
+            changelog_urgency: deb.changelog_urgency.take().unwrap_or_else(|| "medium".to_owned()),
+            news: deb.news.take(),
             maintainer_scripts: deb.maintainer_scripts.map(PathBuf::from),
-            features: deb.features.take().unwrap_or_default(),
-            default_features: deb.default_features.unwrap_or(true),
+            features,
+            default_features,
+            enabled_features,
+            workspace_depends: Vec::new(),
+            binary_targets: root_package.targets.iter()
+                .filter(|t| t.kind.iter().any(|k| k == "bin"))
+                .map(|t| t.name.clone())
+                .collect(),
+            binaries: None,
+            examples: Vec::new(),
             separate_debug_symbols: deb.separate_debug_symbols.unwrap_or(false),
-            strip: self.profile.as_ref().and_then(|p|p.release.as_ref())
-                .and_then(|r| r.debug.as_ref())
-                .map_or(true, |debug| match *debug {
+            strip_keep,
+            strip: self.profile_debug(profile)
+                .is_none_or(|debug| match *debug {
                     toml::Value::Integer(0) => false,
                     toml::Value::Boolean(value) => value,
                     _ => true
                 }),
             preserve_symlinks: deb.preserve_symlinks.unwrap_or(false),
-            systemd_units: deb.systemd_units.take(),
+            hardlink_duplicates: deb.hardlink_duplicates.unwrap_or(false),
+            usr_merge: deb.usr_merge.unwrap_or(true),
+            path_map: path_map(deb.path_map.take().unwrap_or_default()),
+            scrub_rpath: deb.scrub_rpath.unwrap_or(false),
+            run_tests: deb.run_tests.unwrap_or(false),
+            static_binaries: deb.r#static,
+            meta_package: deb.assets.as_ref().is_some_and(|assets| assets.is_empty()),
+            cargo_build_flags: deb.cargo_build_flags.take().unwrap_or_default(),
+            linker: deb.linker.take(),
+            systemd_units,
+            doc_base: deb.doc_base.take(),
+            generated_scripts: deb.generated_scripts.take().unwrap_or_default(),
+            fs: Box::new(RealFs),
             _use_constructor_to_make_this_struct_: (),
         };
-        let assets = self.take_assets(&config, deb.assets.take(), &root_package.targets, readme)?;
-        if assets.is_empty() {
-            return Err("No binaries or cdylibs found. The package is empty. Please specify some assets to package in Cargo.toml".into());
+        let errors_before_assets = errors.len();
+        let assets = self.take_assets(&config, deb.assets.take(), &root_package.targets, &mut errors);
+        let assets_failed = errors.len() > errors_before_assets;
+        if config.meta_package {
+            // Nothing in it is architecture-specific
+            config.architecture = "all".to_owned();
+            config.ipk_architecture = "all".to_owned();
+        } else if assets.is_empty() && !assets_failed {
+            errors.push("No binaries or cdylibs found. The package is empty. Please specify some assets to package in Cargo.toml, or set `assets = []` to make a meta-package".into());
         }
         config.assets = assets;
-        config.add_copyright_asset()?;
-        config.add_changelog_asset()?;
-        config.add_systemd_assets()?;
+        if deb.binaries.is_some() || deb.examples.is_some() {
+            errors.extend(config.select_targets(deb.binaries.take(), deb.examples.take().unwrap_or_default()).err());
+        }
+        if let Some(path) = deb.substvars_file.take() {
+            let path = config.path_in_workspace(path);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| CargoDebError::IoFile("unable to read substvars file", e, path.clone()))
+                .and_then(|content| parse_substvars(&content));
+            config.substvars = collect_error(&mut errors, content).unwrap_or_default();
+        }
+        errors.extend(config.add_copyright_asset().err());
+        errors.extend(config.add_changelog_asset().err());
+        errors.extend(config.add_news_asset().err());
+        errors.extend(config.add_doc_base_asset().err());
+        errors.extend(config.add_mime_assets(deb.mime_types.take()).err());
+        errors.extend(config.add_info_assets(deb.info_pages.take()).err());
+        config.add_doc_example_assets(deb.doc_examples.take());
+        errors.extend(config.add_polkit_assets(deb.polkit_actions.take(), deb.polkit_rules.take()).err());
+        errors.extend(config.add_systemd_assets().err());
+        if !errors.is_empty() {
+            // The build would be wasted, so missing files that aren't built are reported now too
+            errors.extend(config.check_source_assets());
+        }
 
+        CargoDebError::from_errors(errors)?;
         Ok(config)
     }
 
     fn check_config(&self, manifest_dir: &Path, readme: Option<&String>, deb: &CargoDeb, listener: &dyn Listener) {
         if self.package.description.is_none() {
-            listener.warning("description field is missing in Cargo.toml".to_owned());
+            listener.warning(Warning::MissingDescription, "description field is missing in Cargo.toml".to_owned());
         }
         if self.package.license.is_none() && self.package.license_file.is_none() {
-            listener.warning("license field is missing in Cargo.toml".to_owned());
+            listener.warning(Warning::MissingLicense, "license field is missing in Cargo.toml".to_owned());
         }
         if let Some(readme) = readme {
             if deb.extended_description.is_none() && deb.extended_description_file.is_none() && (readme.ends_with(".md") || readme.ends_with(".markdown")) {
-                listener.warning(format!("extended-description field missing. Using {}, but markdown may not render well.",readme));
+                listener.info(format!("extended-description field missing. Using {} converted to plain text", readme));
             }
         } else {
             for p in &["README.md", "README.markdown", "README.txt", "README"] {
                 if manifest_dir.join(p).exists() {
-                    listener.warning(format!("{} file exists, but is not specified in `readme` Cargo.toml field", p));
+                    listener.warning(Warning::UnusedReadme, format!("{} file exists, but is not specified in `readme` Cargo.toml field", p));
                     break;
                 }
             }
         }
     }
 
-    fn extended_description(&self, desc: Option<String>, desc_file: Option<&String>) -> CDResult<Option<String>> {
+    /// Markdown files are converted to plain text. `end` is a line where the description from the file stops.
+    fn extended_description(&self, desc: Option<String>, desc_file: Option<&String>, end: Option<&str>, manifest_dir: &Path) -> CDResult<Option<String>> {
         Ok(if desc.is_some() {
             desc
         } else if let Some(desc_file) = desc_file {
-            Some(fs::read_to_string(desc_file)
-                .map_err(|err| CargoDebError::IoFile(
-                        "unable to read extended description from file", err, PathBuf::from(desc_file)))?)
+            let desc_path = manifest_dir.join(desc_file);
+            let text = fs::read_to_string(&desc_path)
+                .map_err(|err| CargoDebError::IoFile("unable to read extended description from file", err, desc_path))?;
+            Some(if desc_file.ends_with(".md") || desc_file.ends_with(".markdown") {
+                markdown_to_text(&text, end)
+            } else if let Some(end) = end {
+                text.lines().take_while(|line| line.trim() != end.trim()).collect::<Vec<_>>().join("\n")
+            } else {
+                text
+            })
         } else {
             None
         })
     }
 
+    /// Features requested for the build, expanded with the features they enable
+    fn enabled_features(&self, features: &[String], default_features: bool) -> HashSet<String> {
+        let mut enabled = HashSet::new();
+        let mut todo: Vec<&str> = features.iter().map(|f| f.as_str()).collect();
+        if default_features {
+            todo.push("default");
+        }
+        while let Some(feature) = todo.pop() {
+            if !enabled.insert(feature.to_owned()) {
+                continue;
+            }
+            if let Some(implied) = self.features.get(feature) {
+                // "dep/feature" and "dep:name" refer to dependencies, not features of this crate
+                todo.extend(implied.iter().map(|f| f.as_str()).filter(|f| !f.contains('/') && !f.contains(':')));
+            }
+        }
+        enabled
+    }
+
     fn license_file(&mut self, license_file: Option<&LicenseFile>) -> CDResult<(Option<PathBuf>, usize)> {
         Ok(match license_file {
             Some(LicenseFile::Vec(args)) => {
@@ -800,33 +2467,17 @@ impl Cargo {
         })
     }
 
-    fn take_assets(&self, options: &Config, assets: Option<Vec<Vec<String>>>, targets: &[CargoMetadataTarget], readme: Option<&String>) -> CDResult<Assets> {
-        Ok(if let Some(assets) = assets {
+    /// Invalid assets are added to `errors` and left out
+    fn take_assets(&self, options: &Config, assets: Option<Vec<CargoDebAsset>>, targets: &[CargoMetadataTarget], errors: &mut Vec<CargoDebError>) -> Assets {
+        if let Some(assets) = assets {
             // Treat all explicit assets as unresolved until after the build step
             let mut unresolved_assets = vec![];
-            for mut asset_line in assets {
-                let mut asset_parts = asset_line.drain(..);
-                let source_path = PathBuf::from(asset_parts.next()
-                    .ok_or("missing path (first array entry) for asset in Cargo.toml")?);
-                let (is_built, source_path) = if let Ok(rel_path) = source_path.strip_prefix("target/release") {
-                    (true, options.path_in_build(rel_path))
-                } else {
-                    (false, options.path_in_workspace(&source_path))
-                };
-                let target_path = PathBuf::from(asset_parts.next().ok_or("missing target (second array entry) for asset in Cargo.toml")?);
-                let chmod = u32::from_str_radix(&asset_parts.next().ok_or("missing chmod (third array entry) for asset in Cargo.toml")?, 8)
-                    .map_err(|e| CargoDebError::NumParse("unable to parse chmod argument", e))?;
-
-                unresolved_assets.push(UnresolvedAsset {
-                    source_path,
-                    target_path,
-                    chmod,
-                    is_built,
-                })
+            for asset in assets {
+                unresolved_assets.extend(collect_error(errors, unresolved_asset(options, asset)));
             }
             Assets::with_unresolved_assets(unresolved_assets)
         } else {
-            let mut implied_assets: Vec<_> = targets
+            let implied_assets: Vec<_> = targets
                 .iter()
                 .filter_map(|t| {
                     if t.crate_types.iter().any(|ty| ty == "bin") && t.kind.iter().any(|k| k == "bin") {
@@ -850,46 +2501,79 @@ impl Cargo {
                     }
                 })
                 .collect();
-            if let Some(readme) = readme {
-                let target_path = Path::new("usr/share/doc").join(&self.package.name).join(readme);
-                implied_assets.push(Asset::new(
-                    AssetSource::Path(PathBuf::from(readme)),
-                    target_path,
-                    0o644,
-                    false,
-                ));
-            }
             Assets::with_resolved_assets(implied_assets)
-        })
+        }
     }
 
     /// Debian-compatible version of the semver version
     fn version_string(&self, revision: Option<String>) -> String {
-        let debianized_version;
-        let mut version = &self.package.version;
-
-        // Make debian's version ordering (newer versions) more compatible with semver's.
-        // Keep "semver-1" and "semver-xxx" as-is (assuming these are irrelevant, or debian revision already),
-        // but change "semver-beta.1" to "semver~beta.1"
-        let mut parts = version.splitn(2, '-');
-        let semver_main = parts.next().unwrap();
-        if let Some(semver_pre) = parts.next() {
-            let pre_ascii = semver_pre.as_bytes();
-            if pre_ascii.iter().any(|c| !c.is_ascii_digit()) && pre_ascii.iter().any(|c| c.is_ascii_digit()) {
-                debianized_version = format!("{}~{}", semver_main, semver_pre);
-                version = &debianized_version;
-            }
+        debian_version(&self.package.version, revision)
+    }
+}
+
+/// Debian-compatible version of the semver version
+fn debian_version(version: &str, revision: Option<String>) -> String {
+    let debianized_version;
+    let mut version = version;
+
+    // Make debian's version ordering (newer versions) more compatible with semver's.
+    // Keep "semver-1" and "semver-xxx" as-is (assuming these are irrelevant, or debian revision already),
+    // but change "semver-beta.1" to "semver~beta.1"
+    let mut parts = version.splitn(2, '-');
+    let semver_main = parts.next().unwrap();
+    if let Some(semver_pre) = parts.next() {
+        let pre_ascii = semver_pre.as_bytes();
+        if pre_ascii.iter().any(|c| !c.is_ascii_digit()) && pre_ascii.iter().any(|c| c.is_ascii_digit()) {
+            debianized_version = format!("{}~{}", semver_main, semver_pre);
+            version = &debianized_version;
         }
+    }
 
-        if let Some(revision) = revision {
-            format!("{}-{}", version, revision)
-        } else {
-            version.to_owned()
+    if let Some(revision) = revision {
+        format!("{}-{}", version, revision)
+    } else {
+        version.to_owned()
+    }
+}
+
+/// Workspace members with `[package.metadata.deb]` that are runtime dependencies of the package,
+/// as `name (= version)` of the packages made from them, so that they can only be upgraded together
+fn workspace_depends(metadata: &CargoMetadata, root_package: &CargoMetadataPackage, enabled_features: &HashSet<String>) -> Vec<String> {
+    let enabled_deps = enabled_optional_dependencies(&root_package.features, enabled_features);
+    root_package.dependencies.iter()
+        .filter(|dep| dep.kind.is_none() && (!dep.optional || enabled_deps.contains(dep.rename.as_deref().unwrap_or(&dep.name))))
+        .filter_map(|dep| {
+            let member = metadata.packages.iter()
+                .find(|p| p.name == dep.name && metadata.workspace_members.iter().any(|w| w == &p.id))?;
+            let deb = member.metadata.as_ref()?.get("deb")?;
+            let name = deb.get("name").and_then(|n| n.as_str()).unwrap_or(&member.name);
+            let revision = deb.get("revision").and_then(|r| r.as_str()).map(String::from);
+            Some(format!("{} (= {})", name, debian_version(&member.version, revision)))
+        })
+        .collect()
+}
+
+/// Optional dependencies, by their name in `[dependencies]`, that the features turn on:
+/// with `dep:name`, with `name/feature` (but not `name?/feature`), or with the implicit feature of the same name
+fn enabled_optional_dependencies<'a>(feature_table: &'a HashMap<String, Vec<String>>, enabled_features: &'a HashSet<String>) -> HashSet<&'a str> {
+    let mut enabled = HashSet::new();
+    for feature in enabled_features {
+        let Some(implied) = feature_table.get(feature) else {
+            enabled.insert(feature.as_str());
+            continue;
+        };
+        for value in implied {
+            if let Some(dep) = value.strip_prefix("dep:") {
+                enabled.insert(dep);
+            } else if let Some((dep, _)) = value.split_once('/').filter(|(dep, _)| !dep.ends_with('?')) {
+                enabled.insert(dep);
+            }
         }
     }
+    enabled
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Default)]
 struct CargoPackageMetadata {
     pub deb: Option<CargoDeb>,
 }
@@ -901,14 +2585,59 @@ enum LicenseFile {
     Vec(Vec<String>),
 }
 
+/// `doc-examples` is either `true` for the crate's `examples/` directory, or a list of files and globs
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum DocExamples {
+    Enabled(bool),
+    Files(Vec<String>),
+}
+
+/// An asset is either `[source, dest, mode]` or a table with the same fields and optional conditions
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum CargoDebAsset {
+    Array(Vec<String>),
+    Table(CargoDebAssetTable),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CargoDebAssetTable {
+    pub source: String,
+    pub dest: String,
+    pub mode: String,
+    pub target: Option<String>,
+    pub feature: Option<String>,
+    #[serde(default)]
+    pub exists: bool,
+    #[serde(default = "default_true")]
+    pub auto_depends: bool,
+    pub depends: Option<String>,
+    /// `user:group`
+    pub owner: Option<String>,
+    pub strip_keep_symbols: Option<Vec<String>>,
+    pub strip_keep_sections: Option<Vec<String>>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Clone, Debug, Deserialize, Default)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct CargoDeb {
     pub name: Option<String>,
     pub maintainer: Option<String>,
+    pub uploaders: Option<String>,
+    pub origin: Option<String>,
+    pub bugs: Option<String>,
     pub copyright: Option<String>,
     pub license_file: Option<LicenseFile>,
     pub changelog: Option<String>,
+    pub changelog_distribution: Option<String>,
+    pub changelog_urgency: Option<String>,
+    pub news: Option<String>,
     pub depends: Option<String>,
     pub pre_depends: Option<String>,
     pub recommends: Option<String>,
@@ -918,31 +2647,136 @@ struct CargoDeb {
     pub breaks: Option<String>,
     pub replaces: Option<String>,
     pub provides: Option<String>,
+    pub feature_provides: Option<BTreeMap<String, String>>,
     pub extended_description: Option<String>,
     pub extended_description_file: Option<String>,
+    pub extended_description_end: Option<String>,
     pub section: Option<String>,
     pub priority: Option<String>,
     pub revision: Option<String>,
     pub conf_files: Option<Vec<String>>,
-    pub assets: Option<Vec<Vec<String>>>,
+    pub remove_on_purge: Option<Vec<String>>,
+    pub assets: Option<Vec<CargoDebAsset>>,
     pub triggers_file: Option<String>,
+    pub substvars_file: Option<String>,
     pub maintainer_scripts: Option<String>,
     pub features: Option<Vec<String>>,
     pub default_features: Option<bool>,
     pub separate_debug_symbols: Option<bool>,
+    pub strip_keep_symbols: Option<Vec<String>>,
+    pub strip_keep_sections: Option<Vec<String>>,
+    pub run_tests: Option<bool>,
+    pub r#static: Option<bool>,
+    pub cargo_build_flags: Option<Vec<String>>,
+    pub binaries: Option<Vec<String>>,
+    pub examples: Option<Vec<String>>,
     pub preserve_symlinks: Option<bool>,
+    pub hardlink_duplicates: Option<bool>,
+    pub usr_merge: Option<bool>,
+    pub path_map: Option<BTreeMap<String, String>>,
+    pub scrub_rpath: Option<bool>,
+    pub linker: Option<String>,
+    pub systemd: Option<SystemdUnitsConfig>,
     pub systemd_units: Option<SystemdUnitsConfig>,
+    pub doc_base: Option<DocBaseConfig>,
+    pub generated_scripts: Option<GeneratedScripts>,
+    pub mime_types: Option<Vec<String>>,
+    pub info_pages: Option<Vec<String>>,
+    pub doc_examples: Option<DocExamples>,
+    pub default_docs: Option<bool>,
+    pub polkit_actions: Option<Vec<String>>,
+    pub polkit_rules: Option<Vec<String>>,
+    pub ipk_architecture: Option<String>,
+    pub tar_format: Option<String>,
+    pub armhf_baseline: Option<String>,
+    pub arch_map: Option<BTreeMap<String, String>>,
+    pub debian_dir: Option<String>,
+    pub compress_type: Option<String>,
+    pub distro_profile: Option<String>,
+    pub installed_size_method: Option<String>,
+    pub sbom: Option<String>,
+    pub include_cargo_lock: Option<bool>,
+    pub build_ids: Option<bool>,
+    pub sha256_manifest: Option<bool>,
     pub variants: Option<HashMap<String, CargoDeb>>,
+    /// Only from `debian/control`
+    #[serde(skip)]
+    pub user_defined_fields: Vec<(String, String)>,
 }
 
 impl CargoDeb {
+    /// Defaults from `debian/control`, `debian/install`, and `debian/*.service` of a dpkg-buildpackage setup
+    fn from_debian_dir(debian: &DebianDir, debian_dir: &str, extended_description: Option<String>, manifest_dir: &Path) -> Self {
+        // debian/install paths are relative to the directory that has the debian/ dir
+        let root = Path::new(debian_dir).parent().unwrap_or(Path::new(""));
+        let assets = debian.install.iter().flat_map(|(sources, dest)| {
+            sources.iter().map(move |source| {
+                let source = root.join(source).to_string_lossy().into_owned();
+                let executable = source.starts_with("target/") || is_executable(&manifest_dir.join(&source));
+                let dest = format!("{}/", dest.trim_end_matches('/'));
+                CargoDebAsset::Array(vec![source, dest, if executable { "755" } else { "644" }.to_owned()])
+            })
+        }).collect::<Vec<_>>();
+        let scripts = debian.has_scripts || debian.has_units;
+        CargoDeb {
+            maintainer: debian.field("maintainer"),
+            section: debian.field("section"),
+            priority: debian.field("priority"),
+            depends: debian.field("depends"),
+            pre_depends: debian.field("pre-depends"),
+            recommends: debian.field("recommends"),
+            suggests: debian.field("suggests"),
+            enhances: debian.field("enhances"),
+            conflicts: debian.field("conflicts"),
+            breaks: debian.field("breaks"),
+            replaces: debian.field("replaces"),
+            provides: debian.field("provides"),
+            user_defined_fields: debian.user_defined_fields.clone(),
+            extended_description,
+            assets: Some(assets).filter(|assets| !assets.is_empty()),
+            maintainer_scripts: Some(debian_dir.to_owned()).filter(|_| scripts),
+            systemd: Some(SystemdUnitsConfig::default()).filter(|_| debian.has_units),
+            ..CargoDeb::default()
+        }
+    }
+
+    /// `CARGO_DEB_*` variables override settings from `Cargo.toml`, e.g. `CARGO_DEB_REVISION` sets `revision`
+    fn apply_env_overrides(&mut self, var: impl Fn(&str) -> Option<String>) {
+        let overrides = [
+            ("CARGO_DEB_REVISION", &mut self.revision),
+            ("CARGO_DEB_MAINTAINER", &mut self.maintainer),
+            ("CARGO_DEB_SECTION", &mut self.section),
+            ("CARGO_DEB_PRIORITY", &mut self.priority),
+            ("CARGO_DEB_DEPENDS", &mut self.depends),
+            ("CARGO_DEB_COMPRESS_TYPE", &mut self.compress_type),
+            ("CARGO_DEB_CHANGELOG_DISTRIBUTION", &mut self.changelog_distribution),
+        ];
+        for (name, setting) in overrides {
+            if let Some(value) = var(name) {
+                *setting = Some(value);
+            }
+        }
+    }
+
     fn inherit_from(self, parent: CargoDeb) -> CargoDeb {
+        // The two spellings of the systemd settings replace each other
+        let (systemd, systemd_units) = if self.systemd.is_some() || self.systemd_units.is_some() {
+            (self.systemd, self.systemd_units)
+        } else {
+            (parent.systemd, parent.systemd_units)
+        };
         CargoDeb {
             name: self.name.or(parent.name),
             maintainer: self.maintainer.or(parent.maintainer),
+            uploaders: self.uploaders.or(parent.uploaders),
+            origin: self.origin.or(parent.origin),
+            bugs: self.bugs.or(parent.bugs),
             copyright: self.copyright.or(parent.copyright),
             license_file: self.license_file.or(parent.license_file),
             changelog: self.changelog.or(parent.changelog),
+            changelog_distribution: self.changelog_distribution.or(parent.changelog_distribution),
+            changelog_urgency: self.changelog_urgency.or(parent.changelog_urgency),
+            news: self.news.or(parent.news),
             depends: self.depends.or(parent.depends),
             pre_depends: self.pre_depends.or(parent.pre_depends),
             recommends: self.recommends.or(parent.recommends),
@@ -952,20 +2786,66 @@ impl CargoDeb {
             breaks: self.breaks.or(parent.breaks),
             replaces: self.replaces.or(parent.replaces),
             provides: self.provides.or(parent.provides),
+            feature_provides: self.feature_provides.or(parent.feature_provides),
             extended_description: self.extended_description.or(parent.extended_description),
             extended_description_file: self.extended_description_file.or(parent.extended_description_file),
+            extended_description_end: self.extended_description_end.or(parent.extended_description_end),
             section: self.section.or(parent.section),
             priority: self.priority.or(parent.priority),
             revision: self.revision.or(parent.revision),
             conf_files: self.conf_files.or(parent.conf_files),
+            remove_on_purge: self.remove_on_purge.or(parent.remove_on_purge),
             assets: self.assets.or(parent.assets),
             triggers_file: self.triggers_file.or(parent.triggers_file),
+            substvars_file: self.substvars_file.or(parent.substvars_file),
             maintainer_scripts: self.maintainer_scripts.or(parent.maintainer_scripts),
             features: self.features.or(parent.features),
             default_features: self.default_features.or(parent.default_features),
             separate_debug_symbols: self.separate_debug_symbols.or(parent.separate_debug_symbols),
+            strip_keep_symbols: self.strip_keep_symbols.or(parent.strip_keep_symbols),
+            strip_keep_sections: self.strip_keep_sections.or(parent.strip_keep_sections),
+            run_tests: self.run_tests.or(parent.run_tests),
+            r#static: self.r#static.or(parent.r#static),
+            cargo_build_flags: self.cargo_build_flags.or(parent.cargo_build_flags),
+            binaries: self.binaries.or(parent.binaries),
+            examples: self.examples.or(parent.examples),
             preserve_symlinks: self.preserve_symlinks.or(parent.preserve_symlinks),
-            systemd_units: self.systemd_units.or(parent.systemd_units),
+            hardlink_duplicates: self.hardlink_duplicates.or(parent.hardlink_duplicates),
+            usr_merge: self.usr_merge.or(parent.usr_merge),
+            path_map: self.path_map.or(parent.path_map),
+            scrub_rpath: self.scrub_rpath.or(parent.scrub_rpath),
+            linker: self.linker.or(parent.linker),
+            systemd,
+            systemd_units,
+            doc_base: self.doc_base.or(parent.doc_base),
+            generated_scripts: self.generated_scripts.or(parent.generated_scripts),
+            mime_types: self.mime_types.or(parent.mime_types),
+            info_pages: self.info_pages.or(parent.info_pages),
+            doc_examples: self.doc_examples.or(parent.doc_examples),
+            default_docs: self.default_docs.or(parent.default_docs),
+            polkit_actions: self.polkit_actions.or(parent.polkit_actions),
+            polkit_rules: self.polkit_rules.or(parent.polkit_rules),
+            ipk_architecture: self.ipk_architecture.or(parent.ipk_architecture),
+            tar_format: self.tar_format.or(parent.tar_format),
+            armhf_baseline: self.armhf_baseline.or(parent.armhf_baseline),
+            arch_map: match (self.arch_map, parent.arch_map) {
+                (Some(mut map), Some(parent)) => {
+                    for (target, arch) in parent {
+                        map.entry(target).or_insert(arch);
+                    }
+                    Some(map)
+                },
+                (map, parent) => map.or(parent),
+            },
+            debian_dir: self.debian_dir.or(parent.debian_dir),
+            compress_type: self.compress_type.or(parent.compress_type),
+            distro_profile: self.distro_profile.or(parent.distro_profile),
+            user_defined_fields: if self.user_defined_fields.is_empty() { parent.user_defined_fields } else { self.user_defined_fields },
+            installed_size_method: self.installed_size_method.or(parent.installed_size_method),
+            sbom: self.sbom.or(parent.sbom),
+            include_cargo_lock: self.include_cargo_lock.or(parent.include_cargo_lock),
+            build_ids: self.build_ids.or(parent.build_ids),
+            sha256_manifest: self.sha256_manifest.or(parent.sha256_manifest),
             variants: self.variants.or(parent.variants),
         }
     }
@@ -978,6 +2858,7 @@ struct CargoMetadata {
     #[serde(default)]
     workspace_members: Vec<String>,
     target_directory: String,
+    workspace_root: String,
 }
 
 #[derive(Deserialize)]
@@ -989,8 +2870,30 @@ struct CargoMetadataResolve {
 struct CargoMetadataPackage {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub version: String,
     pub targets: Vec<CargoMetadataTarget>,
     pub manifest_path: String,
+    #[serde(default)]
+    pub dependencies: Vec<CargoMetadataDependency>,
+    /// `[features]`, for finding which optional dependencies are enabled
+    #[serde(default)]
+    pub features: HashMap<String, Vec<String>>,
+    /// `[package.metadata]`. The root package's settings are read from its `Cargo.toml` instead,
+    /// but the `deb` names and revisions of other workspace members come from here.
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadataDependency {
+    pub name: String,
+    /// `None` for normal dependencies, otherwise `dev` or `build`
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    /// The name in `[dependencies]`, if it's not the package's name (`package = …`)
+    pub rename: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -1000,12 +2903,88 @@ struct CargoMetadataTarget {
     pub crate_types: Vec<String>,
 }
 
+/// An asset from `assets` in `Cargo.toml`, which is resolved after the build
+fn unresolved_asset(options: &Config, asset: CargoDebAsset) -> CDResult<UnresolvedAsset> {
+    let (source_path, target_path, chmod, condition, dependencies, owner, strip_keep) = match asset {
+        CargoDebAsset::Array(mut asset_line) => {
+            let mut asset_parts = asset_line.drain(..);
+            let source_path = asset_parts.next()
+                .ok_or(CargoDebError::IncompleteAsset("path (first array entry)"))?;
+            let target_path = asset_parts.next().ok_or(CargoDebError::IncompleteAsset("target (second array entry)"))?;
+            let chmod = asset_parts.next().ok_or(CargoDebError::IncompleteAsset("chmod (third array entry)"))?;
+            (source_path, target_path, chmod, AssetCondition::default(), AssetDependencies::default(), None, StripKeep::default())
+        },
+        CargoDebAsset::Table(CargoDebAssetTable { source, dest, mode, target, feature, exists, auto_depends, depends, owner, strip_keep_symbols, strip_keep_sections }) => {
+            let owner = owner.as_deref().map(str::parse).transpose()?;
+            let strip_keep = StripKeep::new(strip_keep_symbols, strip_keep_sections)?;
+            (source, dest, mode, AssetCondition { target, feature, exists }, AssetDependencies { auto: auto_depends, depends }, owner, strip_keep)
+        },
+    };
+    let source_path = PathBuf::from(source_path);
+    // `target/release` works with any profile
+    let built_path = source_path.strip_prefix("target/release")
+        .or_else(|_| source_path.strip_prefix(Path::new("target").join(options.profile_dir_name())));
+    let (is_built, source_path) = if let Ok(rel_path) = built_path {
+        (true, options.path_in_build(rel_path))
+    } else {
+        (false, options.path_in_workspace(&source_path))
+    };
+    let target_path = PathBuf::from(&*expand_dest_shorthand(&target_path));
+    let chmod = u32::from_str_radix(&chmod, 8)
+        .map_err(|e| CargoDebError::NumParse("unable to parse chmod argument", e))?;
+
+    Ok(UnresolvedAsset {
+        source_path,
+        target_path,
+        chmod,
+        is_built,
+        condition,
+        dependencies,
+        owner,
+        strip_keep,
+    })
+}
+
+/// Files matching the asset's source. It's an error if there are none, unless the asset is optional.
+fn asset_matches(fs: &dyn FileSystem, source_path: &Path, is_built: bool, optional: bool, binary_targets: &[String]) -> CDResult<Vec<PathBuf>> {
+    let pattern = source_path.to_str()
+        .ok_or_else(|| CargoDebError::UnrepresentablePath(source_path.to_owned(), "asset source paths are glob patterns, which must be UTF-8"))?;
+    let file_matches = fs.glob(pattern)?;
+
+    // If glob didn't match anything, it's likely an error
+    // as all files should exist when called to resolve
+    if file_matches.is_empty() && !optional {
+        if is_built && !is_glob_pattern(pattern) {
+            return Err(binary_not_found(source_path.to_owned(), binary_targets));
+        }
+        return Err(CargoDebError::AssetFileNotFound(source_path.to_owned()));
+    }
+    Ok(file_matches)
+}
+
+/// The value, or `None` after adding the error to the others
+fn collect_error<T>(errors: &mut Vec<CargoDebError>, result: CDResult<T>) -> Option<T> {
+    result.map_err(|e| errors.push(e)).ok()
+}
+
 /// Returns the path of the `Cargo.toml` that we want to build.
-fn cargo_metadata(manifest_path: &Path) -> CDResult<CargoMetadata> {
+///
+/// It runs in the directory of the manifest like the build, so that `build.target-dir` is from the same `.cargo/config.toml`.
+fn cargo_metadata(manifest_path: &Path, cargo_flags: &[String], target_dir: Option<&Path>) -> CDResult<CargoMetadata> {
+    let manifest_path = std::env::current_dir()?.join(manifest_path);
     let mut cmd = Command::new("cargo");
     cmd.arg("metadata");
     cmd.arg("--format-version=1");
     cmd.arg(format!("--manifest-path={}", manifest_path.display()));
+    cmd.args(cargo_flags);
+    if let Some(dir) = manifest_path.parent() {
+        cmd.current_dir(dir);
+    }
+    // cargo metadata doesn't take --target-dir. A relative dir is relative to where cargo-deb was started, not to the manifest.
+    let target_dir = target_dir.map(PathBuf::from).or_else(|| std::env::var_os("CARGO_TARGET_DIR").map(PathBuf::from));
+    if let Some(target_dir) = target_dir {
+        cmd.env("CARGO_TARGET_DIR", std::env::current_dir()?.join(target_dir));
+    }
 
     let output = cmd.output()
         .map_err(|e| CargoDebError::CommandFailed(e, "cargo (is it in your PATH?)"))?;
@@ -1018,11 +2997,23 @@ fn cargo_metadata(manifest_path: &Path) -> CDResult<CargoMetadata> {
     Ok(metadata)
 }
 
+/// Generic opkg architecture name. Distros like OpenWrt use CPU-specific names (e.g. `aarch64_cortex-a53`),
+/// which have to be set in `ipk-architecture`.
+pub(crate) fn get_ipk_arch(target: &str) -> &str {
+    let arch = target.split('-').next().unwrap();
+    match arch {
+        "i586" | "i686" | "x86" => "i386",
+        "riscv64gc" => "riscv64",
+        arm if arm.starts_with("arm") || arm.starts_with("thumb") => "arm",
+        other_arch => other_arch,
+    }
+}
+
 /// Debianizes the architecture name
 pub(crate) fn get_arch(target: &str) -> &str {
     let mut parts = target.split('-');
     let arch = parts.next().unwrap();
-    let abi = parts.last().unwrap_or("");
+    let abi = parts.next_back().unwrap_or("");
     match (arch, abi) {
         // https://wiki.debian.org/Multiarch/Tuples
         // rustc --print target-list
@@ -1049,6 +3040,59 @@ pub(crate) fn get_arch(target: &str) -> &str {
     }
 }
 
+/// Directories shared with other packages, which must not be deleted on purge
+/// Directories for files that programs create at run time, and which `remove-on-purge` may delete from
+const PURGE_ROOTS: [&str; 11] = ["/etc", "/opt", "/run", "/srv", "/var/backups", "/var/cache", "/var/lib", "/var/log", "/var/opt", "/var/spool", "/var/tmp"];
+
+/// Paths of `remove-on-purge` are deleted with `rm -rf`, so they have to be specific to the package:
+/// in one of the `PURGE_ROOTS`, and with a directory or file named after the package below it.
+/// `names` are the package's names, e.g. the Debian and the crate name.
+fn check_purge_path(path: &str, names: &[&str]) -> CDResult<()> {
+    let under_root = PURGE_ROOTS.iter().find_map(|root| path.strip_prefix(root)?.strip_prefix('/'));
+    let problem = if !path.starts_with('/') {
+        "it has to be an absolute path"
+    } else if path.contains(['\'', '\n', '*', '?', '[']) {
+        "it can't have quotes, newlines or glob patterns"
+    } else if Path::new(path).components().any(|c| matches!(c, std::path::Component::ParentDir | std::path::Component::CurDir)) {
+        "it can't have . or .. in it"
+    } else if under_root.is_none() {
+        "it has to be in /etc, /var/lib, /var/cache, /var/log, /srv, /opt or a similar directory for files made at run time"
+    } else if !under_root.unwrap_or_default().split('/').any(|c| names.iter().any(|name| c == *name || c.strip_prefix(name).is_some_and(|ext| ext.starts_with('.')))) {
+        "it has to be in a directory named after the package, or be a file named after it, e.g. /var/lib/<package>"
+    } else {
+        return Ok(());
+    };
+    Err(CargoDebError::InvalidPurgePath(path.to_owned(), problem))
+}
+
+/// Architecture from `arch-map`, which takes precedence over `get_arch`
+fn debian_arch<'a>(target: &'a str, arch_map: &'a BTreeMap<String, String>) -> CDResult<&'a str> {
+    match arch_map.get(target) {
+        Some(arch) if arch.is_empty() || !arch.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') => {
+            Err(CargoDebError::InvalidArchMapping(target.to_owned(), arch.clone()))
+        },
+        Some(arch) => Ok(arch),
+        None => Ok(get_arch(target)),
+    }
+}
+
+/// Debian's armhf needs ARMv7, but Raspbian (32-bit Raspberry Pi OS) uses the same name for ARMv6,
+/// so ARMv7 binaries packaged for it crash with illegal instructions on the Pi 1 and Zero.
+/// `baseline` is `armv7` (Debian, the default) or `armv6` (Raspbian).
+fn check_armhf_baseline(target: &str, architecture: &str, baseline: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
+    match baseline {
+        None | Some("armv7") => {},
+        Some("armv6") => {
+            let cpu = target.split('-').next().unwrap_or_default();
+            if architecture == "armhf" && !cpu.starts_with("armv6") && cpu != "arm" {
+                listener.warning(Warning::ArmhfBaseline, format!("{} isn't ARMv6, so the armhf package won't run on all Raspbian devices (Pi 1 and Zero). Build for arm-unknown-linux-gnueabihf instead", target));
+            }
+        },
+        Some(other) => return Err(CargoDebError::UnknownArmhfBaseline(other.to_owned())),
+    }
+    Ok(())
+}
+
 /// Format conffiles section, ensuring each path has a leading slash
 ///
 /// Starting with [dpkg 1.20.1](https://github.com/guillemj/dpkg/blob/68ab722604217d3ab836276acfc0ae1260b28f5f/debian/changelog#L393),
@@ -1076,6 +3120,80 @@ mod tests {
         assert_eq!("armhf", get_arch("arm-unknown-linux-gnueabihf"));
     }
 
+    #[test]
+    fn purge_paths() {
+        let names = ["foo", "foo-bin"];
+        check_purge_path("/var/lib/foo", &names).unwrap();
+        check_purge_path("/etc/foo/generated.conf", &names).unwrap();
+        check_purge_path("/var/log/foo/", &names).unwrap();
+        check_purge_path("/var/log/foo.log", &names).unwrap();
+        check_purge_path("/etc/default/foo-bin", &names).unwrap();
+        check_purge_path("/srv/foo", &names).unwrap();
+        assert!(check_purge_path("var/lib/foo", &names).is_err());
+        assert!(check_purge_path("/var/lib", &names).is_err());
+        assert!(check_purge_path("/var/lib/", &names).is_err());
+        assert!(check_purge_path("/etc", &names).is_err());
+        assert!(check_purge_path("/etc/ssh", &names).is_err());
+        assert!(check_purge_path("/etc/ssh/foo-keys", &names).is_err());
+        assert!(check_purge_path("/etc/foobar", &names).is_err());
+        assert!(check_purge_path("/var/www", &names).is_err());
+        assert!(check_purge_path("/var/www/foo", &names).is_err());
+        assert!(check_purge_path("/var/libfoo/foo", &names).is_err());
+        assert!(check_purge_path("/var/lib/foo/../..", &names).is_err());
+        assert!(check_purge_path("/var/lib/foo'; rm -rf /'", &names).is_err());
+        assert!(check_purge_path("/var/log/foo/*.log", &names).is_err());
+        assert!(check_purge_path("/usr/share/foo", &names).is_err());
+    }
+
+    #[test]
+    fn distro_profile_defaults() {
+        let (mut config, mock_listener) = crate::util::tests::test_config();
+        config.systemd_units.get_or_insert(SystemdUnitsConfig::default());
+        assert_eq!("unstable", config.changelog_distribution());
+        assert!(config.misc_dependencies().is_empty());
+
+        config.distro_profile = Some("ubuntu".parse().unwrap());
+        assert_eq!("devel", config.changelog_distribution());
+        assert!(config.misc_dependencies().is_empty());
+
+        config.distro_profile = Some(DistroProfile::Debian);
+        config.changelog_distribution = Some("bookworm-backports".into());
+        assert_eq!("bookworm-backports", config.changelog_distribution());
+        assert_eq!(Some("init-system-helpers (>= 1.54~)".to_owned()), config.relationship_field(Some("${misc:Depends}"), &mock_listener).unwrap());
+        assert!("mint".parse::<DistroProfile>().is_err());
+    }
+
+    #[test]
+    fn user_arch_map() {
+        let map = BTreeMap::from([
+            ("x86_64-unknown-none".to_owned(), "amd64".to_owned()),
+            ("aarch64-unknown-linux-gnu".to_owned(), "arm64ilp32".to_owned()),
+            ("riscv64gc-unknown-linux-gnu".to_owned(), "RISC-V".to_owned()),
+        ]);
+        assert_eq!("amd64", debian_arch("x86_64-unknown-none", &map).unwrap());
+        assert_eq!("arm64ilp32", debian_arch("aarch64-unknown-linux-gnu", &map).unwrap());
+        assert_eq!("armhf", debian_arch("armv7-unknown-linux-gnueabihf", &map).unwrap());
+        assert!(debian_arch("riscv64gc-unknown-linux-gnu", &map).is_err());
+    }
+
+    #[test]
+    fn raspbian_armhf_baseline() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().withf(|code, msg| *code == Warning::ArmhfBaseline && msg.starts_with("armv7-unknown-linux-gnueabihf")).times(1).return_const(());
+        check_armhf_baseline("armv7-unknown-linux-gnueabihf", "armhf", Some("armv6"), &mock_listener).unwrap();
+        check_armhf_baseline("arm-unknown-linux-gnueabihf", "armhf", Some("armv6"), &mock_listener).unwrap();
+        check_armhf_baseline("armv7-unknown-linux-gnueabihf", "armhf", None, &mock_listener).unwrap();
+        check_armhf_baseline("aarch64-unknown-linux-gnu", "arm64", Some("armv6"), &mock_listener).unwrap();
+        assert!(check_armhf_baseline("arm-unknown-linux-gnueabihf", "armhf", Some("raspbian"), &mock_listener).is_err());
+    }
+
+    #[test]
+    fn match_ipk_arch() {
+        assert_eq!("arm", get_ipk_arch("armv7-unknown-linux-musleabihf"));
+        assert_eq!("mipsel", get_ipk_arch("mipsel-unknown-linux-musl"));
+        assert_eq!("x86_64", get_ipk_arch("x86_64-unknown-linux-gnu"));
+    }
+
     #[test]
     fn arch_spec() {
         use ArchSpec::*;
@@ -1188,13 +3306,10 @@ mod tests {
 
     #[test]
     fn add_systemd_assets_with_no_config_does_nothing() {
-        let mut mock_listener = crate::listener::MockListener::new();
-        mock_listener.expect_info().return_const(());
-
         // supply a systemd unit file as if it were available on disk
         add_test_fs_paths(&vec![to_canon_static_str("cargo-deb.service")]);
 
-        let config = Config::from_manifest(Path::new("Cargo.toml"), None, None, None, None, None, &mut mock_listener).unwrap();
+        let (config, _) = crate::util::tests::test_config();
 
         let num_unit_assets = config.assets.resolved
             .iter()
@@ -1206,13 +3321,10 @@ mod tests {
 
     #[test]
     fn add_systemd_assets_with_config_adds_unit_assets() {
-        let mut mock_listener = crate::listener::MockListener::new();
-        mock_listener.expect_info().return_const(());
-
         // supply a systemd unit file as if it were available on disk
         add_test_fs_paths(&vec![to_canon_static_str("cargo-deb.service")]);
 
-        let mut config = Config::from_manifest(Path::new("Cargo.toml"), None, None, None, None, None, &mut mock_listener).unwrap();
+        let (mut config, _) = crate::util::tests::test_config();
 
         config.systemd_units.get_or_insert(SystemdUnitsConfig::default());
         config.maintainer_scripts.get_or_insert(PathBuf::new());
@@ -1227,6 +3339,509 @@ mod tests {
         assert_eq!(1, num_unit_assets);
     }
 
+    #[test]
+    fn systemd_units_with_sources() {
+        add_test_fs_paths(&vec![to_canon_static_str("other.timer")]);
+        let (mut config, _) = crate::util::tests::test_config();
+
+        let mut units = BTreeMap::new();
+        units.insert("cleanup.timer".to_owned(), SystemdUnit { source: Some("other.timer".into()), start: Some(false), ..SystemdUnit::default() });
+        config.systemd_units = Some(SystemdUnitsConfig { units: Some(units), stop_on_remove: Some(false), ..SystemdUnitsConfig::default() });
+        config.add_systemd_assets().unwrap();
+        assert!(config.assets.resolved.iter().any(|a| a.target_path == Path::new("lib/systemd/system/cleanup.timer")));
+
+        let options = dh_installsystemd::Options::from(config.systemd_units.as_ref().unwrap());
+        assert!(options.no_stop_on_remove);
+        assert!(options.units["cleanup.timer"].no_start);
+        assert!(!options.units["cleanup.timer"].no_enable);
+
+        let mut units = BTreeMap::new();
+        units.insert("cleanup".to_owned(), SystemdUnit::default());
+        config.systemd_units = Some(SystemdUnitsConfig { units: Some(units), ..SystemdUnitsConfig::default() });
+        assert!(matches!(config.add_systemd_assets(), Err(CargoDebError::InvalidSystemdUnit(_))));
+    }
+
+    #[test]
+    fn path_map_rewrites_destinations() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.path_map = path_map(vec![
+            ("/usr/local/".to_owned(), "usr/".to_owned()),
+            ("usr/local/share/foo/".to_owned(), "/usr/share/bar/".to_owned()),
+            ("opt/foo/".to_owned(), "usr/lib/foo/".to_owned()),
+            ("/opt/baz".to_owned(), "usr/lib/baz".to_owned()),
+        ].into_iter().collect());
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("/usr/local/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("usr/local/share/foo/data"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/foo/plugin.so"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/foobar"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/baz"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/baz/lib.so"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/bazooka"), 0o644, false),
+        ];
+        config.apply_path_map().unwrap();
+        let targets: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/bin/foo", "usr/share/bar/data", "usr/lib/foo/plugin.so", "opt/foobar", "usr/lib/baz", "usr/lib/baz/lib.so", "opt/bazooka"], targets);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path_map() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.path_map = path_map(vec![("opt/foo/".to_owned(), "usr/share/foo/".to_owned())].into_iter().collect());
+        let target = path_from_unix_bytes(b"opt/foo/caf\xe9.txt").unwrap().into_owned();
+        config.assets.resolved = vec![Asset::new(AssetSource::Data(vec![]), target, 0o644, false)];
+        config.apply_path_map().unwrap();
+        assert_eq!(&b"usr/share/foo/caf\xe9.txt"[..], &config.assets.resolved[0].target_path.as_unix_path()[..]);
+    }
+
+    #[test]
+    fn package_names() {
+        assert_eq!("my-crate", normalized_package_name("My_Crate"));
+        assert_eq!("foo2", normalized_package_name("_foo2"));
+        assert!(is_valid_package_name("libfoo-1.0+dfsg"));
+        assert!(!is_valid_package_name("x"));
+        assert!(!is_valid_package_name("-foo"));
+        assert!(!is_valid_package_name("Foo"));
+        assert!(!is_valid_package_name("foo_bar"));
+    }
+
+    #[test]
+    fn section_suggestions() {
+        assert_eq!(Some("utils"), closest_match("utilities", &SECTIONS));
+        assert_eq!(Some("net"), closest_match("Net", &SECTIONS));
+        assert_eq!(Some("devel"), closest_match("develop", &SECTIONS));
+        assert_eq!(None, closest_match("whatever", &SECTIONS));
+        assert!(check_section("golang").is_ok() && check_section("non-free/raku").is_ok());
+        assert_eq!(Some("optional"), closest_match("optinal", &PRIORITIES));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+
+    #[test]
+    fn completion_dest_shorthands() {
+        assert_eq!("usr/share/bash-completion/completions/", expand_dest_shorthand("$bash-completions/"));
+        assert_eq!("usr/share/zsh/vendor-completions/_foo", expand_dest_shorthand("$zsh-completions/_foo"));
+        assert_eq!("usr/share/fish/vendor_completions.d/", expand_dest_shorthand("$fish-completions"));
+        assert_eq!("$bash-completionsx/", expand_dest_shorthand("$bash-completionsx/"));
+        assert_eq!("usr/share/$bash-completions/", expand_dest_shorthand("usr/share/$bash-completions/"));
+    }
+
+    #[test]
+    fn fhs_warnings() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        mock_listener.expect_warning().withf(|code, _| *code == Warning::Fhs).times(4).return_const(());
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("opt/foo/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("usr/etc/foo.conf"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("usr/local/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("usr/share/foo/cache"), 0o666, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("var/lib/foo/cache"), 0o666, false),
+        ];
+        config.check_fhs(&mock_listener);
+    }
+
+    #[test]
+    fn usr_merge_moves_legacy_paths() {
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        mock_listener.expect_warning().times(2).return_const(());
+        config.assets.resolved = vec![
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("/lib/systemd/system/foo.service"), 0o644, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("sbin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("usr/bin/foo"), 0o755, false),
+            Asset::new(AssetSource::Data(vec![]), PathBuf::from("library/foo"), 0o644, false),
+        ];
+        config.apply_usr_merge(&mock_listener);
+        let targets: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/lib/systemd/system/foo.service", "usr/sbin/foo", "usr/bin/foo", "library/foo"], targets);
+
+        config.usr_merge = false;
+        config.assets.resolved[0].target_path = PathBuf::from("bin/foo");
+        config.apply_usr_merge(&mock_listener);
+        assert_eq!(Path::new("bin/foo"), config.assets.resolved[0].target_path);
+    }
+
+    #[test]
+    fn asset_condition() {
+        let features: HashSet<String> = vec!["gui".to_owned()].into_iter().collect();
+        assert!(AssetCondition::default().is_met("x86_64-unknown-linux-gnu", &features).unwrap());
+
+        let arm = AssetCondition { target: Some("aarch64-*".into()), ..Default::default() };
+        assert!(arm.is_met("aarch64-unknown-linux-gnu", &features).unwrap());
+        assert!(!arm.is_met("x86_64-unknown-linux-gnu", &features).unwrap());
+
+        let gui = AssetCondition { feature: Some("gui".into()), ..Default::default() };
+        assert!(gui.is_met("x86_64-unknown-linux-gnu", &features).unwrap());
+        let tui = AssetCondition { feature: Some("tui".into()), ..Default::default() };
+        assert!(!tui.is_met("x86_64-unknown-linux-gnu", &features).unwrap());
+    }
+
+    #[test]
+    fn parse_conditional_assets() {
+        let deb: CargoDeb = toml::from_str(r#"
+assets = [
+    ["target/release/foo", "usr/bin/", "755"],
+    { source = "target/release/foo-gui", dest = "usr/bin/", mode = "755", feature = "gui", target = "x86_64-*" },
+    { source = "extra/*", dest = "usr/share/foo/", mode = "644", exists = true },
+    { source = "vendor/helper", dest = "usr/lib/foo/", mode = "755", auto-depends = false, depends = "libssl3" },
+]
+"#).unwrap();
+        let assets = deb.assets.unwrap();
+        assert!(matches!(assets[0], CargoDebAsset::Array(_)));
+        match &assets[1] {
+            CargoDebAsset::Table(t) => {
+                assert_eq!(Some("gui"), t.feature.as_deref());
+                assert_eq!(Some("x86_64-*"), t.target.as_deref());
+                assert!(!t.exists);
+                assert!(t.auto_depends);
+            },
+            _ => panic!("expected a table"),
+        }
+        assert!(matches!(&assets[2], CargoDebAsset::Table(t) if t.exists));
+        assert!(matches!(&assets[3], CargoDebAsset::Table(t) if !t.auto_depends && t.depends.as_deref() == Some("libssl3")));
+    }
+
+    #[test]
+    fn custom_profiles() {
+        let cargo: Cargo = toml::from_str(r#"
+[package]
+name = "foo"
+version = "1.0.0"
+
+[profile.dev]
+debug = 0
+
+[profile.dist]
+inherits = "release"
+
+[profile.release]
+debug = false
+
+[profile.symbols]
+inherits = "dist"
+debug = true
+"#).unwrap();
+        assert_eq!(Some(&toml::Value::Boolean(false)), cargo.profile_debug("dist"));
+        assert_eq!(Some(&toml::Value::Boolean(true)), cargo.profile_debug("symbols"));
+        assert_eq!(Some(&toml::Value::Integer(0)), cargo.profile_debug("test"));
+        assert_eq!(None, cargo.profile_debug("unknown"));
+
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let mut config = Config::from_manifest(ManifestOptions { profile: Some("dist"), ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!(config.target_dir.join("dist/foo"), config.path_in_build("foo"));
+        config.profile = "test".into();
+        assert_eq!("debug", config.profile_dir_name());
+    }
+
+    #[test]
+    fn external_deb_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deb.toml");
+        fs::write(&path, "section = \"games\"\nrevision = \"9\"\n").unwrap();
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let config = Config::from_manifest(ManifestOptions { deb_config: Some(&path), ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!(Some("games"), config.section.as_deref());
+        assert!(config.deb_version.ends_with("-9"), "{}", config.deb_version);
+        // settings that aren't in the file still come from Cargo.toml
+        assert_eq!("optional", config.priority);
+    }
+
+    #[test]
+    fn default_docs() {
+        let (mut config, _) = crate::util::tests::test_config();
+        let binary = Asset::new(AssetSource::Path(config.path_in_build("cargo-deb")), "usr/bin/cargo-deb".into(), 0o755, true);
+        config.assets = Assets::with_resolved_assets(vec![binary.clone()]);
+        config.license_file = None;
+        config.add_default_doc_assets().unwrap();
+        assert_eq!(1, config.assets.resolved.len(), "default-docs is off by default");
+
+        config.default_docs = true;
+        config.add_default_doc_assets().unwrap();
+        let targets: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/bin/cargo-deb", "usr/share/doc/cargo-deb/README.md", "usr/share/doc/cargo-deb/LICENSE"], targets);
+
+        config.assets = Assets::with_resolved_assets(vec![binary.clone(), Asset::new(AssetSource::Path("README.md".into()), "usr/share/doc/cargo-deb/README".into(), 0o644, false)]);
+        config.add_default_doc_assets().unwrap();
+        assert_eq!(2, config.assets.resolved.len());
+
+        config.assets = Assets::with_resolved_assets(vec![binary.clone()]);
+        config.default_docs = false;
+        config.add_default_doc_assets().unwrap();
+        assert_eq!(1, config.assets.resolved.len());
+
+        // A workspace member without its own license files gets the workspace's
+        let mut fs = crate::vfs::MemoryFs::new();
+        fs.add_file("/ws/member/README", "hello")
+            .add_file("/ws/COPYING", "GPL")
+            .add_file("/ws/LICENSE-MIT", "MIT")
+            .add_file("/ws/src/LICENSE", "not this");
+        config.fs = Box::new(fs);
+        config.manifest_dir = "/ws/member".into();
+        config.workspace_root = "/ws".into();
+        config.readme = Some("README".into());
+        config.default_docs = true;
+        config.add_default_doc_assets().unwrap();
+        let sources: Vec<_> = config.assets.resolved.iter().skip(1).map(|a| a.source.path().unwrap().to_str().unwrap()).collect();
+        assert_eq!(vec!["/ws/member/README", "/ws/COPYING", "/ws/LICENSE-MIT"], sources);
+    }
+
+    #[test]
+    fn doc_examples() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.assets = Assets::new();
+        config.add_doc_example_assets(Some(DocExamples::Enabled(false)));
+        assert!(config.assets.is_empty());
+
+        config.add_doc_example_assets(Some(DocExamples::Enabled(true)));
+        config.add_doc_example_assets(Some(DocExamples::Files(vec!["docs/demo.toml".into()])));
+        let unresolved = &config.assets.unresolved;
+        assert_eq!(config.path_in_workspace("examples/**/*"), unresolved[0].source_path);
+        assert!(unresolved[0].condition.exists);
+        assert!(!unresolved[1].condition.exists);
+        assert_eq!(Path::new("usr/share/doc/cargo-deb/examples/"), unresolved[1].target_path);
+        assert_eq!(0o644, unresolved[1].chmod);
+    }
+
+    #[test]
+    fn select_binaries_and_examples() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.binary_targets = vec!["foo".into(), "bar".into()];
+        config.assets = Assets::with_resolved_assets(vec![
+            Asset::new(AssetSource::Path(config.path_in_build("foo")), "usr/bin/foo".into(), 0o755, true),
+            Asset::new(AssetSource::Path(config.path_in_build("bar")), "usr/bin/bar".into(), 0o755, true),
+            Asset::new(AssetSource::Path("README.md".into()), "usr/share/doc/foo/README.md".into(), 0o644, false),
+        ]);
+
+        assert!(config.select_targets(Some(vec!["baz".into()]), vec![]).is_err());
+        config.select_targets(Some(vec!["bar".into()]), vec!["demo".into()]).unwrap();
+        let targets: Vec<_> = config.assets.resolved.iter().map(|a| a.target_path.to_str().unwrap()).collect();
+        assert_eq!(vec!["usr/share/doc/foo/README.md", "usr/bin/bar", "usr/bin/demo"], targets);
+        assert_eq!(Some(config.path_in_build("examples/demo").as_path()), config.assets.resolved[2].source.path());
+
+        config.select_targets(None, vec![]).unwrap();
+        assert_eq!(3, config.assets.resolved.len());
+    }
+
+    #[test]
+    fn per_binary_dependencies() {
+        let (mut config, mock_listener) = crate::util::tests::test_config();
+        config.depends = "libc6".into();
+        config.architecture = "amd64".into();
+
+        let mut helper = Asset::new(AssetSource::Path("vendor/helper".into()), "usr/lib/foo/helper".into(), 0o755, false);
+        helper.dependencies = AssetDependencies { auto: false, depends: Some("libssl3, libarm [arm64]".into()) };
+        let mut plugin = Asset::new(AssetSource::Path("vendor/plugin.so".into()), "usr/lib/foo/plugin.so".into(), 0o644, false);
+        plugin.dependencies.depends = Some("zlib1g, libc6".into());
+        config.assets.resolved = vec![helper, plugin];
+
+        let auto: Vec<_> = config.auto_depends_binaries().iter().filter_map(|s| s.path()).collect();
+        assert_eq!(vec![Path::new("vendor/plugin.so")], auto);
+
+        let deps = config.get_dependencies(&mock_listener).unwrap();
+        let mut deps: Vec<_> = deps.split(", ").collect();
+        deps.sort_unstable();
+        assert_eq!(vec!["libc6", "libssl3", "zlib1g"], deps);
+    }
+
+    #[test]
+    fn substvars() {
+        let vars = parse_substvars("# comment\nfoo:Depends=libfoo1 (>= 1.2), bar\n\nopt?=x\n").unwrap();
+        assert_eq!(Some("libfoo1 (>= 1.2), bar"), vars.get("foo:Depends").map(|s| s.as_str()));
+        assert_eq!(Some("x"), vars.get("opt").map(|s| s.as_str()));
+        assert!(parse_substvars("no equals sign").is_err());
+        assert!(parse_substvar("bad name=1").is_err());
+
+        let (mut config, mut mock_listener) = crate::util::tests::test_config();
+        mock_listener.expect_warning().times(1).return_const(());
+        config.deb_version = "1:2.0.1-3".into();
+        config.substvars = vars;
+        config.depends = "${misc:Depends}, ${foo:Depends}, ${undefined}".into();
+        let mut deps = config.get_dependencies(&mock_listener).unwrap().split(", ").map(String::from).collect::<Vec<_>>();
+        deps.sort_unstable();
+        assert_eq!(vec!["bar", "libfoo1 (>= 1.2)"], deps);
+
+        config.substvars.insert("shlibs:Depends".into(), "libbar2 (>= 2.1)".into());
+        config.depends = "${shlibs:Depends}, baz".into();
+        assert_eq!(vec!["libbar2 (>= 2.1)", "baz"], config.depends_words(&mock_listener).unwrap());
+
+        assert_eq!(Some("foo-data (= 1:2.0.1-3), foo-doc (>= 2.0.1)".to_owned()), config.relationship_field(
+            Some("foo-data (= ${binary:Version}), foo-doc (>= ${source:Upstream-Version}),"), &mock_listener).unwrap());
+        assert_eq!(None, config.relationship_field(Some("${misc:Depends}"), &mock_listener).unwrap());
+        assert!(config.expand_substvars("${unterminated", &mock_listener).is_err());
+    }
+
+    #[test]
+    fn provides_from_features() {
+        let mut map = BTreeMap::new();
+        map.insert("mysql".to_owned(), "foo-mysql".to_owned());
+        map.insert("pg".to_owned(), "foo-pg, foo-sql".to_owned());
+        let enabled: HashSet<String> = vec!["default".to_owned(), "pg".to_owned()].into_iter().collect();
+        assert_eq!(Some("foo-any, foo-pg, foo-sql"), feature_provides(Some("foo-any".into()), &map, &enabled).as_deref());
+        assert_eq!(None, feature_provides(None, &map, &HashSet::new()));
+        assert_eq!(Some("foo-mysql"), feature_provides(None, &map, &vec!["mysql".to_owned()].into_iter().collect()).as_deref());
+    }
+
+    #[test]
+    fn features_from_command_line() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let features = ["lzma".to_owned(), "extra".to_owned()];
+        let config = Config::from_manifest(ManifestOptions { features: &features, ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!(&features[..], &config.features[..]);
+        assert!(config.enabled_features.contains("extra"));
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut deb = CargoDeb { revision: Some("1".into()), section: Some("utils".into()), ..CargoDeb::default() };
+        deb.apply_env_overrides(|name| match name {
+            "CARGO_DEB_REVISION" => Some("2".into()),
+            "CARGO_DEB_COMPRESS_TYPE" => Some("gz".into()),
+            _ => None,
+        });
+        assert_eq!(Some("2"), deb.revision.as_deref());
+        assert_eq!(Some("utils"), deb.section.as_deref());
+        assert_eq!(Some(CompressType::Gz), deb.compress_type.as_deref().map(str::parse).transpose().unwrap());
+    }
+
+    #[test]
+    fn git_snapshot_version() {
+        assert_eq!("1.2.0~beta1-1+git20261014.0123abc", git_version("1.2.0~beta1-1", "0123abcdef0123abcdef", 1_791_981_296));
+    }
+
+    #[test]
+    fn changelog_is_made_again_for_a_new_version() {
+        let (mut config, _) = crate::util::tests::test_config();
+        let is_changelog = |asset: &&Asset| asset.target_path.ends_with("changelog.Debian.gz");
+        assert_eq!(1, config.assets.resolved.iter().filter(is_changelog).count());
+
+        config.deb_version = "9.9.9-1".into();
+        config.add_changelog_asset().unwrap();
+        let changelogs: Vec<_> = config.assets.resolved.iter().filter(is_changelog).collect();
+        assert_eq!(1, changelogs.len());
+        let changelog = crate::inflate::gunzip(&changelogs[0].source.data(&crate::vfs::RealFs).unwrap()).unwrap();
+        assert!(String::from_utf8(changelog).unwrap().contains("(9.9.9-1)"));
+    }
+
+    #[test]
+    fn version_suffix() {
+        assert_eq!("1.2.0-1~bookworm1", version_with_suffix("1.2.0-1", "~bookworm1").unwrap());
+        assert_eq!("1.2.0-1+deb12u1", version_with_suffix("1.2.0-1", "+deb12u1").unwrap());
+        assert!(version_with_suffix("1.2.0-1", "-bookworm").is_err());
+        assert!(version_with_suffix("1.2.0-1", "~book worm").is_err());
+        assert!(version_with_suffix("1.2.0-1", "").is_err());
+    }
+
+    #[test]
+    fn backport_suffixes() {
+        assert_eq!(("~bpo12+1".to_owned(), "bookworm-backports".to_owned()), backport_suffix("bookworm").unwrap());
+        assert_eq!(("~jammy1".to_owned(), "jammy".to_owned()), backport_suffix("jammy").unwrap());
+        assert!(backport_suffix("Bookworm").is_err());
+        assert!(backport_suffix("bookworm-backports").is_err());
+        assert!(backport_suffix("").is_err());
+    }
+
+    #[test]
+    fn strip_keep_args() {
+        let package = StripKeep::new(Some(vec!["rust_eh_personality".into()]), Some(vec![".comment".into()])).unwrap();
+        let asset = StripKeep::new(None, Some(vec![".note.gnu.build-id".into()])).unwrap();
+        assert_eq!(vec!["--keep-symbol=rust_eh_personality", "--keep-section=.comment", "--keep-section=.note.gnu.build-id"], package.strip_args(&asset));
+        assert!(StripKeep::default().strip_args(&StripKeep::default()).is_empty());
+        assert!(StripKeep::new(Some(vec!["".into()]), None).is_err());
+        assert!(StripKeep::new(None, Some(vec![".comment .text".into()])).is_err());
+    }
+
+    #[test]
+    fn cross_linker_defers_to_cargo() {
+        let (mut config, _) = crate::util::tests::test_config();
+        // Nothing in the environment or the user's config has a linker for a made-up target
+        let target = "riscv64gc-cargodebtest-linux-gnu";
+        let dir = tempfile::tempdir().unwrap();
+        config.manifest_dir = dir.path().to_owned();
+        assert_eq!(None, config.cross_linker(target));
+        config.linker = Some("riscv64-linux-gnu-gcc".into());
+        assert_eq!(Some("riscv64-linux-gnu-gcc"), config.cross_linker(target));
+        fs::create_dir(dir.path().join(".cargo")).unwrap();
+        fs::write(dir.path().join(".cargo/config.toml"), "[target.riscv64gc-cargodebtest-linux-gnu]\nlinker = \"clang\"\n").unwrap();
+        assert_eq!(None, config.cross_linker(target));
+    }
+
+    #[test]
+    fn build_env_versions() {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.deb_version = "1:2.0.1-3~bpo12+1".into();
+        let env: HashMap<_, _> = config.build_env().into_iter().collect();
+        assert_eq!("1:2.0.1-3~bpo12+1", env["CARGO_DEB_VERSION"]);
+        assert_eq!("2.0.1", env["CARGO_DEB_UPSTREAM_VERSION"]);
+        assert_eq!("3~bpo12+1", env["CARGO_DEB_REVISION"]);
+        assert_eq!("cargo-deb", env["CARGO_DEB_PACKAGE"]);
+
+        config.deb_version = "2.0.1".into();
+        assert_eq!(None, config.debian_revision());
+        assert_eq!("2.0.1", config.upstream_version());
+    }
+
+    #[test]
+    fn backport_variant_keeps_name() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        mock_listener.expect_warning().return_const(());
+        let manifest = Path::new("example/Cargo.toml");
+        let named = Config::from_manifest(ManifestOptions { manifest_path: Some(manifest), variant: Some(Variant::Named("meta")), ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!("example-meta", named.deb_name);
+        let mut backport = Config::from_manifest(ManifestOptions { manifest_path: Some(manifest), variant: Some(Variant::Backport("meta")), ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!("example", backport.deb_name);
+        assert_eq!(named.depends, backport.depends);
+        backport.backport("bookworm").unwrap();
+        assert!(backport.deb_version.ends_with("~bpo12+1"), "{}", backport.deb_version);
+        assert_eq!("bookworm-backports", backport.changelog_distribution());
+
+        let plain = Config::from_manifest(ManifestOptions { manifest_path: Some(manifest), variant: Some(Variant::Backport("bookworm")), ..ManifestOptions::default() }, &mock_listener).unwrap();
+        assert_eq!("example", plain.deb_name);
+    }
+
+    #[test]
+    fn binary_not_found_hint() {
+        let targets = vec!["foo-cli".to_owned()];
+        match binary_not_found(PathBuf::from("target/release/foo"), &targets) {
+            CargoDebError::BinaryNotFound(_, hint) => assert!(hint.contains("foo-cli"), "{}", hint),
+            other => panic!("unexpected {:?}", other),
+        }
+        match binary_not_found(PathBuf::from("target/release/foo-cli"), &targets) {
+            CargoDebError::BinaryNotFound(_, hint) => assert!(hint.contains("--no-build"), "{}", hint),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_assets_are_reported_together() {
+        let (mut config, mock_listener) = crate::util::tests::test_config();
+        let mut fs = crate::vfs::MemoryFs::new();
+        fs.add_file("/src/present.txt", "")
+            .add_file(config.path_in_workspace(config.readme.as_ref().unwrap()), "# cargo-deb");
+        config.fs = Box::new(fs);
+        config.assets.unresolved = ["/src/present.txt", "/src/missing.txt", "/src/missing/*"].iter().map(|path| UnresolvedAsset {
+            source_path: path.into(),
+            target_path: "usr/share/foo/".into(),
+            chmod: 0o644,
+            is_built: false,
+            condition: Default::default(),
+            dependencies: Default::default(),
+            owner: None,
+            strip_keep: Default::default(),
+        }).collect();
+        match config.resolve_assets(&mock_listener) {
+            Err(CargoDebError::Multiple(errors)) => {
+                assert_eq!(2, errors.len());
+                assert!(errors.iter().all(|e| matches!(e, CargoDebError::AssetFileNotFound(_))), "{:?}", errors);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(config.assets.resolved.iter().any(|asset| asset.source.path() == Some(Path::new("/src/present.txt"))));
+    }
+
     #[test]
     fn format_conffiles_empty() {
         let actual = format_conffiles::<String>(&[]);
@@ -1280,6 +3895,7 @@ fn deb_ver() {
             resolver: None,
         },
         profile: None,
+        features: HashMap::new(),
     };
     assert_eq!("1.2.3-1", c.version_string(None));
     assert_eq!("1.2.3-1-2", c.version_string(Some("2".into())));
@@ -1290,3 +3906,38 @@ fn deb_ver() {
     assert_eq!("1.2.0-new", c.version_string(None));
     assert_eq!("1.2.0-new-11", c.version_string(Some("11".into())));
 }
+
+#[test]
+fn workspace_depends_pins_versions() {
+    let metadata: CargoMetadata = serde_json::from_str(r#"{
+        "packages": [
+            {"id": "app", "name": "app", "version": "1.0.0", "targets": [], "manifest_path": "app/Cargo.toml", "dependencies": [
+                {"name": "app-core", "kind": null},
+                {"name": "app-plugins", "kind": null, "optional": true},
+                {"name": "app-gui", "kind": null, "optional": true, "rename": "gui"},
+                {"name": "app-tls", "kind": null, "optional": true},
+                {"name": "app-test-utils", "kind": "dev"},
+                {"name": "serde", "kind": null}
+            ], "features": {"desktop": ["dep:gui", "app-tls?/ring"], "secure": ["app-tls/ring"]}},
+            {"id": "core", "name": "app-core", "version": "1.1.0-beta.1", "targets": [], "manifest_path": "core/Cargo.toml",
+                "metadata": {"deb": {"name": "libapp-core", "revision": "2"}}},
+            {"id": "plugins", "name": "app-plugins", "version": "1.0.0", "targets": [], "manifest_path": "plugins/Cargo.toml", "metadata": {"deb": {}}},
+            {"id": "gui", "name": "app-gui", "version": "1.0.0", "targets": [], "manifest_path": "gui/Cargo.toml", "metadata": {"deb": {}}},
+            {"id": "tls", "name": "app-tls", "version": "1.0.0", "targets": [], "manifest_path": "tls/Cargo.toml", "metadata": {"deb": {}}},
+            {"id": "test-utils", "name": "app-test-utils", "version": "1.0.0", "targets": [], "manifest_path": "test-utils/Cargo.toml", "metadata": {"deb": {}}},
+            {"id": "serde", "name": "serde", "version": "1.0.0", "targets": [], "manifest_path": "serde/Cargo.toml", "metadata": {"deb": {}}}
+        ],
+        "resolve": {"root": "app"},
+        "workspace_members": ["app", "core", "plugins", "gui", "tls", "test-utils"],
+        "target_directory": "target",
+        "workspace_root": "."
+    }"#).unwrap();
+    let root = &metadata.packages[0];
+    assert_eq!(vec!["libapp-core (= 1.1.0~beta.1-2)"], workspace_depends(&metadata, root, &HashSet::new()));
+    let features = HashSet::from(["app-plugins".to_owned()]);
+    assert_eq!(vec!["libapp-core (= 1.1.0~beta.1-2)", "app-plugins (= 1.0.0)"], workspace_depends(&metadata, root, &features));
+    let features = HashSet::from(["desktop".to_owned()]);
+    assert_eq!(vec!["libapp-core (= 1.1.0~beta.1-2)", "app-gui (= 1.0.0)"], workspace_depends(&metadata, root, &features));
+    let features = HashSet::from(["secure".to_owned()]);
+    assert_eq!(vec!["libapp-core (= 1.1.0~beta.1-2)", "app-tls (= 1.0.0)"], workspace_depends(&metadata, root, &features));
+}
@@ -0,0 +1,294 @@
+//! Markdown to plain text for the extended description, which doesn't support any markup.
+//!
+//! Paragraphs are separated by blank lines, and list items and code blocks are indented,
+//! so that they're displayed verbatim. Badges, images, and HTML are removed.
+
+/// `end` is a line (e.g. `<!-- end of description -->` or `## Installation`) after which the text is cut off
+pub(crate) fn markdown_to_text(markdown: &str, end: Option<&str>) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut in_code = false;
+    let mut in_comment = false;
+    for line in markdown.lines() {
+        if end.is_some_and(|end| line.trim() == end.trim()) {
+            break;
+        }
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            out.push(format!(" {}", line));
+            continue;
+        }
+        let line = strip_comments(line, &mut in_comment);
+        let trimmed = line.trim();
+
+        // Setext heading underlines and horizontal rules
+        if trimmed.len() >= 3 && trimmed.chars().all(|c| c == '=' || c == '-' || c == '*' || c == '_') {
+            continue;
+        }
+        // Link reference definitions
+        if trimmed.starts_with('[') && trimmed.contains("]: ") {
+            continue;
+        }
+        let text = inline_text(trimmed);
+        let text = text.trim();
+        if text.is_empty() {
+            if !trimmed.is_empty() {
+                // a line of only badges or HTML isn't a paragraph break
+                continue;
+            }
+            out.push(String::new());
+        } else if let Some(heading) = heading(text) {
+            out.push(String::new());
+            out.push(heading.to_owned());
+            out.push(String::new());
+        } else if let Some(item) = list_item(text) {
+            let indent = line.len() - line.trim_start().len();
+            out.push(format!("{} * {}", " ".repeat(indent / 2), item));
+        } else {
+            out.push(text.to_owned());
+        }
+    }
+
+    // Collapse blank lines
+    let mut text = String::with_capacity(markdown.len());
+    let mut blank = true;
+    for line in out {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && !text.is_empty() {
+            text.push('\n');
+        }
+        blank = false;
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text.truncate(text.trim_end().len());
+    text
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    if text.len() < line.len() && (text.is_empty() || text.starts_with(' ')) {
+        Some(text.trim().trim_end_matches('#').trim())
+    } else {
+        None
+    }
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).or_else(|| line.strip_prefix("+ ")) {
+        return Some(item.trim());
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ").or_else(|| line[digits..].strip_prefix(") ")) {
+            return Some(item.trim());
+        }
+    }
+    None
+}
+
+/// Removes `<!-- -->` comments, which can span lines
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    loop {
+        if *in_comment {
+            match rest.find("-->") {
+                Some(end) => {
+                    rest = &rest[end + 3..];
+                    *in_comment = false;
+                },
+                None => return out,
+            }
+        }
+        match rest.find("<!--") {
+            Some(start) => {
+                out.push_str(&rest[..start]);
+                rest = &rest[start + 4..];
+                *in_comment = true;
+            },
+            None => {
+                out.push_str(rest);
+                return out;
+            },
+        }
+    }
+}
+
+/// Drops images and HTML tags, keeps the text of links, and removes emphasis
+fn inline_text(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(pos) = rest.find(['!', '[', '<']) {
+        out.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        if let Some(after) = tail.strip_prefix("![") {
+            // image, possibly inside a link (badge)
+            if let Some((_, len)) = link_len(after) {
+                rest = &after[len..];
+                continue;
+            }
+        } else if let Some(after) = tail.strip_prefix('[') {
+            if let Some((text_len, len)) = link_len(after) {
+                // the text can be an image
+                let text = inline_text(&after[..text_len]);
+                if text.trim().is_empty() {
+                    rest = &after[len..];
+                    continue;
+                }
+                out.push_str(&text);
+                rest = &after[len..];
+                continue;
+            }
+        } else if tail.starts_with('<') {
+            if let Some(end) = tail.find('>') {
+                let tag = &tail[1..end];
+                if tag.starts_with("http") && !tag.contains(' ') {
+                    out.push_str(tag); // autolink
+                }
+                if tag.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') {
+                    rest = &tail[end + 1..];
+                    continue;
+                }
+            }
+        }
+        out.push_str(&tail[..1]);
+        rest = &tail[1..];
+    }
+    out.push_str(rest);
+    strip_code_and_emphasis(&out)
+}
+
+/// Keeps the text of `code` spans as-is, and removes `*` and `_` emphasis around words elsewhere
+fn strip_code_and_emphasis(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let after = &rest[start + ticks..];
+        let Some(end) = after.find(&rest[start..start + ticks]) else { break };
+        out.push_str(&strip_emphasis(&rest[..start]));
+        out.push_str(&after[..end]);
+        rest = after[end..].trim_start_matches('`');
+    }
+    out.push_str(&strip_emphasis(rest));
+    out
+}
+
+/// Delimiters are removed only in pairs, when they open at the start of a word and close at its end,
+/// so `foo__bar` and `2 * 3 * 4` stay the same
+fn strip_emphasis(text: &str) -> String {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation());
+    // byte range, delimiter char, whether it can open and close
+    let mut runs = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut prev = None;
+    while let Some((start, c)) = chars.next() {
+        if c == '*' || c == '_' {
+            let mut end = start + 1;
+            while chars.next_if(|&(_, next)| next == c).is_some() {
+                end += 1;
+            }
+            let next = text[end..].chars().next();
+            let can_open = is_boundary(prev) && next.is_some_and(|n| !n.is_whitespace());
+            let can_close = prev.is_some_and(|p: char| !p.is_whitespace()) && is_boundary(next);
+            runs.push((start..end, c, can_open, can_close));
+        }
+        prev = Some(c);
+    }
+
+    let mut removed = Vec::new();
+    let mut openers: Vec<usize> = Vec::new();
+    for (i, (range, c, can_open, can_close)) in runs.iter().enumerate() {
+        let opener = openers.iter().rposition(|&o| runs[o].1 == *c && runs[o].0.len() == range.len());
+        match opener {
+            Some(pos) if *can_close => {
+                removed.push(runs[openers[pos]].0.clone());
+                removed.push(range.clone());
+                openers.truncate(pos);
+            },
+            _ if *can_open => openers.push(i),
+            _ => {},
+        }
+    }
+    removed.sort_by_key(|range| range.start);
+
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    for range in removed {
+        out.push_str(&text[pos..range.start]);
+        pos = range.end;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+/// Lengths of the text and of the whole `text](url)` or `text][ref]` after the opening `[`.
+/// The text can have nested brackets.
+fn link_len(after_bracket: &str) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    for (i, c) in after_bracket.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            ']' => {
+                let rest = &after_bracket[i + 1..];
+                let close = match rest.chars().next() {
+                    Some('(') => ')',
+                    Some('[') => ']',
+                    _ => return None,
+                };
+                return rest.find(close).map(|end| (i, i + 1 + end + 1));
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+#[test]
+fn readme_to_description() {
+    let readme = r#"# cargo-deb [![Build](https://ci.example.com/badge.svg)](https://ci.example.com)
+
+<p align="center"><img src="logo.png"></p>
+
+A **Cargo** subcommand that makes [Debian](https://www.debian.org) packages
+from `Cargo.toml`.
+
+Features
+--------
+
+- Fast
+- Easy to *use*
+  - nested
+1. one
+
+```sh
+cargo deb
+```
+<!-- a
+comment -->
+## Installation
+
+Not included.
+"#;
+    assert_eq!("cargo-deb\n\nA Cargo subcommand that makes Debian packages\nfrom Cargo.toml.\n\nFeatures\n\n * Fast\n * Easy to use\n  * nested\n * one\n\n cargo deb\n\nInstallation\n\nNot included.",
+        markdown_to_text(readme, None));
+    assert!(markdown_to_text(readme, Some("## Installation")).ends_with(" cargo deb"));
+}
+
+#[test]
+fn emphasis_at_word_boundaries() {
+    assert_eq!("bold, italic and both.", inline_text("**bold**, *italic* and ___both___."));
+    assert_eq!("foo__bar and snake_case_name", inline_text("foo__bar and snake_case_name"));
+    assert_eq!("2 * 3 * 4 and a*b", inline_text("2 * 3 * 4 and a*b"));
+    assert_eq!("Implements __init__ and *args", inline_text("Implements `__init__` and `*args`"));
+    assert_eq!("unclosed *emphasis", inline_text("unclosed *emphasis"));
+    assert_eq!("(nested bold inside)", inline_text("(*nested **bold** inside*)"));
+}
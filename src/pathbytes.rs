@@ -1,3 +1,4 @@
+use crate::error::{CDResult, CargoDebError};
 use std::borrow::Cow;
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
@@ -29,7 +30,39 @@ impl AsUnixPathBytes for Path {
     }
 }
 
+/// Inverse of `as_unix_path`. File names don't have to be UTF-8 on Unix.
+pub fn path_from_unix_bytes(bytes: &[u8]) -> CDResult<Cow<'_, Path>> {
+    #[cfg(unix)]
+    return Ok(Path::new(std::ffi::OsStr::from_bytes(bytes)).into());
+    #[cfg(not(unix))]
+    return Ok(Path::new(std::str::from_utf8(bytes)?).into());
+}
+
+/// Errors about names that can be in the filesystem, but not in a package
+pub fn check_representable(path: &Path) -> CDResult<()> {
+    #[cfg(not(unix))]
+    if path.to_str().is_none() {
+        return Err(CargoDebError::UnrepresentablePath(path.to_owned(), "paths in packages are bytes, and non-Unix paths must be UTF-8 to be converted"));
+    }
+    if path.as_unix_path().contains(&b'\n') {
+        return Err(CargoDebError::UnrepresentablePath(path.to_owned(), "dpkg's md5sums and conffiles are line-based, so file names can't have newlines"));
+    }
+    Ok(())
+}
+
 #[test]
 fn unix_path() {
     assert_eq!(b"foo/bar/baz"[..], Path::new("foo/bar/baz").as_unix_path()[..]);
+    assert!(check_representable(Path::new("usr/share/foo/bar baz")).is_ok());
+    assert!(check_representable(Path::new("usr/share/foo/bar\nbaz")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn latin1_path() {
+    let latin1 = b"usr/share/foo/caf\xe9.txt";
+    let path = path_from_unix_bytes(latin1).unwrap();
+    assert!(path.to_str().is_none());
+    assert_eq!(&latin1[..], &path.as_unix_path()[..]);
+    assert!(check_representable(&path).is_ok());
 }
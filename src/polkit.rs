@@ -0,0 +1,96 @@
+//! Checks of [polkit](https://www.freedesktop.org/software/polkit/docs/latest/polkit.8.html) action definitions.
+//!
+//! This isn't a full XML parser. It only checks that tags are balanced, and that the document
+//! is a `<policyconfig>` with `<action id="…">` elements, which is what polkitd rejects otherwise.
+
+/// `Err` has a description of the problem
+pub(crate) fn check_policy(xml: &str) -> Result<(), String> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut root = None;
+    let mut actions = 0;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let tag = &rest[start..];
+        let end = if tag.starts_with("<!--") {
+            tag.find("-->").map(|end| end + 3)
+        } else if tag.starts_with("<![CDATA[") {
+            tag.find("]]>").map(|end| end + 3)
+        } else if tag.starts_with("<?") {
+            tag.find("?>").map(|end| end + 2)
+        } else {
+            tag.find('>').map(|end| end + 1)
+        };
+        let end = end.ok_or_else(|| format!("unterminated tag near '{}'", tag.chars().take(20).collect::<String>()))?;
+        let (markup, after) = tag.split_at(end);
+        rest = after;
+        if markup.starts_with("<!") || markup.starts_with("<?") {
+            continue;
+        }
+
+        let inner = &markup[1..markup.len() - 1];
+        if let Some(name) = inner.strip_prefix('/') {
+            let name = name.trim();
+            match stack.pop() {
+                Some(open) if open == name => {},
+                Some(open) => return Err(format!("</{}> closes <{}>", name, open)),
+                None => return Err(format!("</{}> has no opening tag", name)),
+            }
+            continue;
+        }
+        let self_closing = inner.ends_with('/');
+        let inner = inner.trim_end_matches('/');
+        let name = inner.split_whitespace().next().ok_or("has an empty tag")?;
+        if stack.is_empty() {
+            if root.is_some() {
+                return Err(format!("<{}> is outside of the root element", name));
+            }
+            root = Some(name);
+        }
+        if name == "action" {
+            if !has_attribute(inner, "id") {
+                return Err("has an <action> without an id".into());
+            }
+            actions += 1;
+        }
+        if !self_closing {
+            stack.push(name);
+        }
+    }
+    if let Some(open) = stack.pop() {
+        return Err(format!("<{}> is not closed", open));
+    }
+    match root {
+        Some("policyconfig") if actions > 0 => Ok(()),
+        Some("policyconfig") => Err("doesn't define any actions".into()),
+        Some(other) => Err(format!("has <{}> instead of <policyconfig> as the root element", other)),
+        None => Err("is empty".into()),
+    }
+}
+
+fn has_attribute(tag: &str, name: &str) -> bool {
+    tag.split_whitespace().skip(1).any(|attr| attr.split_once('=').is_some_and(|(n, v)| n == name && v.len() > 2))
+}
+
+#[test]
+fn policy_files() {
+    let policy = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE policyconfig PUBLIC "-//freedesktop//DTD PolicyKit Policy Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/PolicyKit/1/policyconfig.dtd">
+<policyconfig>
+  <vendor>Example</vendor>
+  <!-- <unclosed> in a comment -->
+  <action id="com.example.helper.run">
+    <description>Run the helper</description>
+    <defaults><allow_any>no</allow_any><allow_active>auth_admin_keep</allow_active></defaults>
+    <annotate key="org.freedesktop.policykit.exec.path">/usr/libexec/helper</annotate>
+    <annotate key="org.freedesktop.policykit.exec.allow_gui"/>
+  </action>
+</policyconfig>
+"#;
+    assert_eq!(Ok(()), check_policy(policy));
+    assert!(check_policy(&policy.replace("</defaults>", "")).unwrap_err().contains("</action> closes <defaults>"));
+    assert!(check_policy(&policy.replace(r#" id="com.example.helper.run""#, "")).is_err());
+    assert!(check_policy("<policyconfig></policyconfig>").is_err());
+    assert!(check_policy("<policy><action id=\"a\"/></policy>").is_err());
+    assert!(check_policy("<policyconfig><action id=\"a\">").is_err());
+}
@@ -0,0 +1,195 @@
+//! `--print-config` output of the configuration after merging `Cargo.toml`, the workspace, the variant,
+//! `--config` file, command-line options and `CARGO_DEB_*` environment variables
+use crate::error::*;
+use crate::manifest::{AssetSource, CompressType, Config, DistroProfile, InstalledSizeMethod, PackageFormat, SbomFormat, TarFormat};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl std::str::FromStr for ConfigFormat {
+    type Err = CargoDebError;
+
+    fn from_str(s: &str) -> CDResult<Self> {
+        match s {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            other => Err(CargoDebError::UnknownConfigFormat(other.to_owned())),
+        }
+    }
+}
+
+/// Settings by their `[package.metadata.deb]` keys, and package fields from `Cargo.toml` by their Cargo names.
+/// Unset values are `null`.
+fn effective_config(options: &Config, cargo_build_flags: &[String]) -> CDResult<Value> {
+    // Globs that haven't been resolved yet, and files added by cargo-deb
+    let assets: Vec<Value> = options.assets.unresolved.iter().map(|asset| json!({
+        "source": asset.source_path.display().to_string(),
+        "dest": asset.target_path.display().to_string(),
+        "mode": format!("{:o}", asset.chmod),
+        "target": asset.condition.target,
+        "feature": asset.condition.feature,
+        "exists": asset.condition.exists,
+        "auto-depends": asset.dependencies.auto,
+        "depends": asset.dependencies.depends,
+        "owner": asset.owner.as_ref().map(|owner| format!("{}:{}", owner.user, owner.group)),
+        "strip-keep-symbols": asset.strip_keep.symbols,
+        "strip-keep-sections": asset.strip_keep.sections,
+    }))
+    .chain(options.assets.resolved.iter().map(|asset| json!({
+        "source": match &asset.source {
+            AssetSource::Path(path) => path.display().to_string(),
+            AssetSource::Data(_) => "(generated)".to_owned(),
+        },
+        "dest": asset.target_path.display().to_string(),
+        "mode": format!("{:o}", asset.chmod),
+        "auto-depends": asset.dependencies.auto,
+        "depends": asset.dependencies.depends,
+        "owner": asset.owner.as_ref().map(|owner| format!("{}:{}", owner.user, owner.group)),
+        "strip-keep-symbols": asset.strip_keep.symbols,
+        "strip-keep-sections": asset.strip_keep.sections,
+    })))
+    .collect();
+    Ok(json!({
+        "name": options.deb_name,
+        "version": options.deb_version,
+        "architecture": match options.package_format {
+            PackageFormat::Deb => &options.architecture,
+            PackageFormat::Ipk => &options.ipk_architecture,
+        },
+        "target": options.target,
+        "profile": options.profile,
+        "crate": options.name,
+        "description": options.description,
+        "extended-description": options.extended_description,
+        "license": options.license,
+        "readme": options.readme.as_ref().map(|path| path.display().to_string()),
+        "license-file": options.license_file.as_ref().map(|file| json!([file.display().to_string(), options.license_file_skip_lines.to_string()])),
+        "homepage": options.homepage,
+        "documentation": options.documentation,
+        "repository": options.repository,
+        "maintainer": options.maintainer,
+        "uploaders": options.uploaders,
+        "origin": options.origin,
+        "bugs": options.bugs,
+        "copyright": options.copyright,
+        "changelog": options.changelog,
+        "changelog-distribution": options.changelog_distribution(),
+        "changelog-urgency": options.changelog_urgency,
+        "news": options.news,
+        "section": options.section,
+        "priority": options.priority,
+        "depends": options.depends,
+        "pre-depends": options.pre_depends,
+        "recommends": options.recommends,
+        "suggests": options.suggests,
+        "enhances": options.enhances,
+        "conflicts": options.conflicts,
+        "breaks": options.breaks,
+        "replaces": options.replaces,
+        "provides": options.provides,
+        "substvars": options.substvars,
+        "conf-files": options.conf_files.as_ref().map(|files| files.lines().collect::<Vec<_>>()),
+        "remove-on-purge": options.remove_on_purge,
+        "triggers-file": options.triggers_file.as_ref().map(|path| path.display().to_string()),
+        "maintainer-scripts": options.maintainer_scripts.as_ref().map(|path| path.display().to_string()),
+        "generated-scripts": to_value(&options.generated_scripts)?,
+        "features": options.features,
+        "default-features": options.default_features,
+        "binaries": options.binaries,
+        "examples": options.examples,
+        "strip": options.strip,
+        "separate-debug-symbols": options.separate_debug_symbols,
+        "strip-keep-symbols": options.strip_keep.symbols,
+        "strip-keep-sections": options.strip_keep.sections,
+        "preserve-symlinks": options.preserve_symlinks,
+        "hardlink-duplicates": options.hardlink_duplicates,
+        "usr-merge": options.usr_merge,
+        "path-map": options.path_map.iter().map(|(from, to)| (from.clone(), Value::from(to.as_str()))).collect::<serde_json::Map<_, _>>(),
+        "scrub-rpath": options.scrub_rpath,
+        "run-tests": options.run_tests,
+        "static": options.static_binaries,
+        "cargo-build-flags": options.cargo_build_flags.iter().chain(cargo_build_flags).collect::<Vec<_>>(),
+        "linker": options.linker,
+        "format": options.package_format.extension(),
+        "tar-format": match options.tar_format {
+            TarFormat::Gnu => "gnu",
+            TarFormat::Ustar => "ustar",
+            TarFormat::Pax => "pax",
+        },
+        "compress-type": options.compress_type.map(|compress_type| match compress_type {
+            CompressType::Xz => "xz",
+            CompressType::Gz => "gz",
+            CompressType::Zstd => "zstd",
+            CompressType::None => "none",
+        }),
+        "distro-profile": options.distro_profile.map(DistroProfile::name),
+        "installed-size-method": match options.installed_size_method {
+            InstalledSizeMethod::Dpkg => "dpkg",
+            InstalledSizeMethod::Blocks => "blocks",
+        },
+        "sbom": options.sbom.map(|sbom| match sbom {
+            SbomFormat::CycloneDx => "cyclonedx",
+            SbomFormat::Spdx => "spdx",
+        }),
+        "default-docs": options.default_docs,
+        "include-cargo-lock": options.include_cargo_lock,
+        "build-ids": options.build_ids,
+        "sha256-manifest": options.sha256_manifest,
+        "assets": assets,
+        "systemd-units": to_value(&options.systemd_units)?,
+        "doc-base": to_value(&options.doc_base)?,
+    }))
+}
+
+fn to_value(value: &impl serde::Serialize) -> CDResult<Value> {
+    serde_json::to_value(value).map_err(|e| CargoDebError::SerializeConfig(e.to_string()))
+}
+
+/// TOML has no `null`, so unset values are left out
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, without_nulls(v)))
+            .collect()),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        other => other,
+    }
+}
+
+/// The configuration as it will be used to make the package. The `cargo_build_flags` are the extra ones from the command line.
+pub fn print_config(options: &Config, cargo_build_flags: &[String], format: ConfigFormat) -> CDResult<String> {
+    let config = effective_config(options, cargo_build_flags)?;
+    match format {
+        ConfigFormat::Json => {
+            let mut json = serde_json::to_string_pretty(&config).map_err(|e| CargoDebError::SerializeConfig(e.to_string()))?;
+            json.push('\n');
+            Ok(json)
+        },
+        ConfigFormat::Toml => {
+            let config = toml::Value::try_from(without_nulls(config)).map_err(|e| CargoDebError::SerializeConfig(e.to_string()))?;
+            toml::to_string(&config).map_err(|e| CargoDebError::SerializeConfig(e.to_string()))
+        },
+    }
+}
+
+#[test]
+fn print_config_formats() {
+    let (config, _) = crate::util::tests::test_config();
+
+    let json: Value = serde_json::from_str(&print_config(&config, &["--locked".into()], ConfigFormat::Json).unwrap()).unwrap();
+    assert_eq!("cargo-deb", json["name"]);
+    assert_eq!(Value::Null, json["pre-depends"]);
+    assert_eq!("--locked", json["cargo-build-flags"].as_array().unwrap().last().unwrap());
+    assert_eq!("755", json["assets"][0]["mode"]);
+
+    let toml: toml::Value = toml::from_str(&print_config(&config, &[], ConfigFormat::Toml).unwrap()).unwrap();
+    assert_eq!(json["maintainer"], toml["maintainer"].as_str().unwrap());
+    assert!(toml.get("pre-depends").is_none());
+    assert_eq!(json["assets"].as_array().unwrap().len(), toml["assets"].as_array().unwrap().len());
+    assert!("yaml".parse::<ConfigFormat>().is_err());
+}
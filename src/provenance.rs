@@ -0,0 +1,107 @@
+use crate::error::*;
+use crate::listener::{Listener, Warning};
+use crate::manifest::Config;
+use crate::sha256::sha256_hex;
+use crate::util::rfc3339_date;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Writes an [in-toto statement](https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md)
+/// with [SLSA provenance](https://slsa.dev/spec/v1.0/provenance) of the package to `<package>.intoto.jsonl`.
+///
+/// `started` and `finished` are UNIX timestamps of the build.
+pub fn write_provenance(options: &Config, package_path: &Path, started: u64, finished: u64, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let package = fs::read(package_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read package", e, package_path.to_owned()))?;
+    let git_commit = match options.git_commit.clone() {
+        Some(commit) => Some(commit),
+        None => match options.git_head() {
+            Ok((commit, _)) => Some(commit),
+            Err(err) => {
+                listener.warning(Warning::IncompleteProvenance, format!("source commit is not recorded in the provenance: {}", err));
+                None
+            },
+        },
+    };
+    let cargo_lock_path = options.workspace_root.join("Cargo.lock");
+    let cargo_lock = match fs::read(&cargo_lock_path) {
+        Ok(lock) => Some(lock),
+        Err(_) => {
+            listener.warning(Warning::IncompleteProvenance, format!("{} is not recorded in the provenance", cargo_lock_path.display()));
+            None
+        },
+    };
+
+    let file_name = package_path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+    let statement = provenance_statement(options, &file_name, &package, git_commit.as_deref(), cargo_lock.as_deref(), (started, finished));
+
+    let mut path = package_path.as_os_str().to_owned();
+    path.push(".intoto.jsonl");
+    let path = PathBuf::from(path);
+    let mut line = serde_json::to_vec(&statement)?;
+    line.push(b'\n');
+    fs::write(&path, line).map_err(|e| CargoDebError::IoFile("unable to write provenance", e, path.clone()))?;
+    Ok(path)
+}
+
+fn provenance_statement(options: &Config, file_name: &str, package: &[u8], git_commit: Option<&str>, cargo_lock: Option<&[u8]>, (started, finished): (u64, u64)) -> serde_json::Value {
+    let mut resolved_dependencies = Vec::new();
+    if let Some(commit) = git_commit {
+        let uri = match options.repository {
+            Some(ref repo) => format!("git+{}@{}", repo.trim_start_matches("git+"), commit),
+            None => format!("git+file://{}@{}", options.manifest_dir.display(), commit),
+        };
+        resolved_dependencies.push(json!({"uri": uri, "digest": {"gitCommit": commit}}));
+    }
+    if let Some(lock) = cargo_lock {
+        resolved_dependencies.push(json!({"name": "Cargo.lock", "digest": {"sha256": sha256_hex(lock)}}));
+    }
+
+    json!({
+        "_type": "https://in-toto.io/Statement/v1",
+        "subject": [{
+            "name": file_name,
+            "digest": {"sha256": sha256_hex(package)},
+        }],
+        "predicateType": "https://slsa.dev/provenance/v1",
+        "predicate": {
+            "buildDefinition": {
+                "buildType": "https://github.com/mmstick/cargo-deb/provenance@v1",
+                "externalParameters": {
+                    "package": options.name,
+                    "version": options.deb_version,
+                    "target": options.target.as_deref().unwrap_or(crate::DEFAULT_TARGET),
+                    "features": options.features,
+                    "defaultFeatures": options.default_features,
+                },
+                "resolvedDependencies": resolved_dependencies,
+            },
+            "runDetails": {
+                "builder": {
+                    "id": "https://crates.io/crates/cargo-deb",
+                    "version": {"cargo-deb": env!("CARGO_PKG_VERSION")},
+                },
+                "metadata": {
+                    "startedOn": rfc3339_date(started),
+                    "finishedOn": rfc3339_date(finished),
+                },
+            },
+        },
+    })
+}
+
+#[test]
+fn provenance_statement_fields() {
+    let (mut config, _) = crate::util::tests::test_config();
+    config.repository = Some("https://github.com/mmstick/cargo-deb".into());
+
+    let statement = provenance_statement(&config, "cargo-deb_1.0_amd64.deb", b"abc", Some("0123abc"), Some(b""), (0, 60));
+    assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", statement["subject"][0]["digest"]["sha256"]);
+    assert_eq!("cargo-deb_1.0_amd64.deb", statement["subject"][0]["name"]);
+    let dependencies = &statement["predicate"]["buildDefinition"]["resolvedDependencies"];
+    assert_eq!("git+https://github.com/mmstick/cargo-deb@0123abc", dependencies[0]["uri"]);
+    assert_eq!("0123abc", dependencies[0]["digest"]["gitCommit"]);
+    assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", dependencies[1]["digest"]["sha256"]);
+    assert_eq!("1970-01-01T00:01:00Z", statement["predicate"]["runDetails"]["metadata"]["finishedOn"]);
+}
@@ -0,0 +1,172 @@
+//! Reading of `.deb` and `.ipk` packages, without needing `dpkg-deb`.
+//!
+//! ```no_run
+//! let package = cargo_deb::reader::Package::open("target/debian/foo_1.0.0-1_amd64.deb".as_ref())?;
+//! for (name, value) in package.control_fields()? {
+//!     println!("{}: {}", name, value);
+//! }
+//! for entry in package.data_entries()? {
+//!     println!("/{} {:o}", entry.path, entry.mode);
+//! }
+//! # Ok::<(), cargo_deb::CargoDebError>(())
+//! ```
+use crate::error::*;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+pub use tar::EntryType;
+
+/// A member of the outer `ar` archive (or the outer tarball of an `.ipk`)
+pub struct Member {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// A file from the control or data tarball
+pub struct TarEntry {
+    /// Without the leading `./`
+    pub path: String,
+    pub mode: u32,
+    pub entry_type: EntryType,
+    /// Target of symlinks and hardlinks
+    pub link_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// A package read into memory
+pub struct Package {
+    /// In their archive order, e.g. `debian-binary`, `control.tar.xz`, `data.tar.xz`
+    pub members: Vec<Member>,
+}
+
+impl Package {
+    pub fn open(path: &Path) -> CDResult<Self> {
+        Ok(Self { members: read_package(path)? })
+    }
+
+    pub fn from_bytes(package: &[u8]) -> CDResult<Self> {
+        let members = members_from_bytes(package).ok_or("not a .deb or .ipk package")??;
+        Ok(Self { members })
+    }
+
+    /// The first member with the name prefix, e.g. `data.tar`
+    #[must_use]
+    pub fn member(&self, prefix: &str) -> Option<&Member> {
+        self.members.iter().find(|m| m.name.starts_with(prefix))
+    }
+
+    /// Files of the decompressed `control.tar`
+    pub fn control_entries(&self) -> CDResult<Vec<TarEntry>> {
+        tar_entries(&decompress(self.member("control.tar").ok_or(CargoDebError::MissingPackageMember("control.tar"))?)?)
+    }
+
+    /// Files, directories and links of the decompressed `data.tar`
+    pub fn data_entries(&self) -> CDResult<Vec<TarEntry>> {
+        tar_entries(&decompress(self.member("data.tar").ok_or(CargoDebError::MissingPackageMember("data.tar"))?)?)
+    }
+
+    /// Fields of the `control` file, in their order
+    pub fn control_fields(&self) -> CDResult<Vec<(String, String)>> {
+        let entries = self.control_entries()?;
+        let control = entries.iter().find(|e| e.path == "control").ok_or(CargoDebError::MissingPackageMember("control file"))?;
+        Ok(parse_control(&String::from_utf8_lossy(&control.data)))
+    }
+}
+
+/// Members of a `.deb` or `.ipk` package in their archive order
+pub fn read_package(path: &Path) -> CDResult<Vec<Member>> {
+    let package = fs::read(path)
+        .map_err(|e| CargoDebError::IoFile("unable to read package", e, path.to_owned()))?;
+    members_from_bytes(&package).ok_or_else(|| CargoDebError::NotAPackage(path.to_owned()))?
+}
+
+/// `None` if it's neither an `ar` archive nor a gzipped tarball
+fn members_from_bytes(package: &[u8]) -> Option<CDResult<Vec<Member>>> {
+    if package.starts_with(b"!<arch>\n") {
+        Some(ar_members(package))
+    } else if package.starts_with(&[0x1F, 0x8B]) {
+        // opkg's .ipk is a gzipped tarball
+        Some(crate::inflate::gunzip(package).and_then(|tarball| tar_entries(&tarball)).map(|entries| {
+            entries.into_iter()
+                .filter(|e| e.entry_type.is_file())
+                .map(|e| Member { name: e.path, data: e.data })
+                .collect()
+        }))
+    } else {
+        None
+    }
+}
+
+fn ar_members(package: &[u8]) -> CDResult<Vec<Member>> {
+    let mut archive = ar::Archive::new(package);
+    let mut members = Vec::new();
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(entry.header().identifier())
+            .trim_end_matches('/').to_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        members.push(Member { name, data });
+    }
+    Ok(members)
+}
+
+/// Decompresses `control.tar.*` or `data.tar.*` member based on the file extension
+pub fn decompress(member: &Member) -> CDResult<Vec<u8>> {
+    match member.name.rsplit_once(".tar").map(|(_, ext)| ext) {
+        Some("") => Ok(member.data.clone()),
+        Some(".gz") => crate::inflate::gunzip(&member.data),
+        #[cfg(feature = "lzma")]
+        Some(".xz") => {
+            let mut out = Vec::new();
+            xz2::read::XzDecoder::new(&member.data[..]).read_to_end(&mut out)?;
+            Ok(out)
+        },
+        Some(".zst") => crate::unzstd::unzstd(&member.data),
+        _ => Err(CargoDebError::UnsupportedCompression(member.name.clone())),
+    }
+}
+
+pub fn tar_entries(tarball: &[u8]) -> CDResult<Vec<TarEntry>> {
+    let mut archive = tar::Archive::new(tarball);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_owned();
+        let link_name = entry.link_name()?.map(|l| l.to_string_lossy().into_owned());
+        let header = entry.header();
+        let mode = header.mode()?;
+        let entry_type = header.entry_type();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push(TarEntry { path, mode, entry_type, link_name, data });
+    }
+    Ok(entries)
+}
+
+/// Fields of a control file paragraph. Continuation lines are joined with `\n`, without their leading space.
+#[must_use]
+pub fn parse_control(control: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in control.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(&line[1..]);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_owned(), value.trim().to_owned()));
+        } else if line.trim().is_empty() {
+            break;
+        }
+    }
+    fields
+}
+
+#[test]
+fn control_fields() {
+    let fields = parse_control("Package: foo\nVersion: 1.0\nDescription: does foo\n It really does.\n .\n More.\n");
+    assert_eq!(("Package".to_owned(), "foo".to_owned()), fields[0]);
+    assert_eq!("does foo\nIt really does.\n.\nMore.", fields[2].1);
+    assert!(Package::from_bytes(b"not a package").is_err());
+}
@@ -0,0 +1,253 @@
+use crate::error::*;
+use crate::manifest::{Config, SbomFormat};
+use crate::sha256::sha256_hex;
+use crate::util::{clamped_timestamp, rfc3339_date};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+#[derive(Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<LockPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+impl LockPackage {
+    fn purl(&self) -> String {
+        format!("pkg:cargo/{}@{}", self.name, self.version)
+    }
+}
+
+/// Software bill of materials of the crates from `Cargo.lock` that the package depends on
+pub(crate) fn generate_sbom(options: &Config, format: SbomFormat) -> CDResult<Vec<u8>> {
+    let path = options.workspace_root.join("Cargo.lock");
+    let lock = fs::read_to_string(&path)
+        .map_err(|e| CargoDebError::IoFile("unable to read Cargo.lock for the SBOM", e, path.clone()))?;
+    sbom_from_lock(options, format, &lock, clamped_timestamp())
+}
+
+/// Writes a copy of the SBOM installed by the package next to it, e.g. as `<package>.cdx.json`
+pub fn write_sbom_sidecar(options: &Config, package_path: &Path) -> CDResult<PathBuf> {
+    let format = options.sbom.ok_or("the package has no SBOM. Set sbom in Cargo.toml or use --sbom")?;
+    let target_path = Path::new("usr/share/doc").join(&options.deb_name).join(format.file_name());
+    let sbom = options.assets.resolved.iter()
+        .find(|asset| asset.target_path == target_path)
+        .ok_or("the package has no SBOM")?
+        .source.data(&*options.fs)?;
+
+    let mut path = package_path.as_os_str().to_owned();
+    path.push(format.file_name().trim_start_matches("sbom"));
+    let path = PathBuf::from(path);
+    fs::write(&path, sbom).map_err(|e| CargoDebError::IoFile("unable to write SBOM", e, path.clone()))?;
+    Ok(path)
+}
+
+fn sbom_from_lock(options: &Config, format: SbomFormat, lock: &str, timestamp: u64) -> CDResult<Vec<u8>> {
+    let packages = toml::from_str::<CargoLock>(lock)?.package;
+    let root = packages.iter().position(|p| p.name == options.name && p.source.is_none())
+        .ok_or_else(|| CargoDebError::MissingFromCargoLock(options.name.clone()))?;
+
+    // Dependencies of the package, but not of other workspace members
+    let mut dependencies = vec![Vec::new(); packages.len()];
+    let mut reachable = BTreeSet::new();
+    let mut queue = vec![root];
+    while let Some(index) = queue.pop() {
+        if !reachable.insert(index) {
+            continue;
+        }
+        for dep in &packages[index].dependencies {
+            let dep_index = find_dependency(&packages, dep)
+                .ok_or_else(|| CargoDebError::MissingFromCargoLock(dep.clone()))?;
+            dependencies[index].push(dep_index);
+            queue.push(dep_index);
+        }
+    }
+
+    let sbom = match format {
+        SbomFormat::CycloneDx => cyclonedx(options, &packages, root, &reachable, &dependencies, timestamp),
+        SbomFormat::Spdx => spdx(options, &packages, root, &reachable, &dependencies, timestamp, &sha256_hex(lock.as_bytes())),
+    };
+    Ok(serde_json::to_vec_pretty(&sbom)?)
+}
+
+/// Entries are `name`, `name version` or `name version (source)` when the name is ambiguous
+fn find_dependency(packages: &[LockPackage], dep: &str) -> Option<usize> {
+    let mut parts = dep.splitn(3, ' ');
+    let name = parts.next()?;
+    let version = parts.next();
+    let source = parts.next().map(|s| s.trim_start_matches('(').trim_end_matches(')'));
+    packages.iter().position(|p| {
+        p.name == name
+            && version.is_none_or(|v| v == p.version)
+            && source.is_none_or(|s| p.source.as_deref() == Some(s))
+    })
+}
+
+fn cyclonedx(options: &Config, packages: &[LockPackage], root: usize, reachable: &BTreeSet<usize>, dependencies: &[Vec<usize>], timestamp: u64) -> serde_json::Value {
+    let component = |index: usize| {
+        let package = &packages[index];
+        let mut component = json!({
+            "type": if index == root { "application" } else { "library" },
+            "bom-ref": package.purl(),
+            "name": package.name,
+            "version": package.version,
+            "purl": package.purl(),
+        });
+        if let Some(ref checksum) = package.checksum {
+            component["hashes"] = json!([{"alg": "SHA-256", "content": checksum}]);
+        }
+        if index == root {
+            if let Some(ref license) = options.license {
+                component["licenses"] = json!([{"expression": license}]);
+            }
+        }
+        component
+    };
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": rfc3339_date(timestamp),
+            "tools": {"components": [{"type": "application", "name": "cargo-deb", "version": env!("CARGO_PKG_VERSION")}]},
+            "component": component(root),
+        },
+        "components": reachable.iter().filter(|&&i| i != root).map(|&i| component(i)).collect::<Vec<_>>(),
+        "dependencies": reachable.iter().map(|&i| json!({
+            "ref": packages[i].purl(),
+            "dependsOn": dependencies[i].iter().map(|&d| packages[d].purl()).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn spdx(options: &Config, packages: &[LockPackage], root: usize, reachable: &BTreeSet<usize>, dependencies: &[Vec<usize>], timestamp: u64, lock_hash: &str) -> serde_json::Value {
+    let spdx_id = |index: usize| format!("SPDXRef-Package-{}", index);
+    let spdx_packages: Vec<_> = reachable.iter().map(|&index| {
+        let package = &packages[index];
+        let download_location = if package.source.as_deref() == Some(CRATES_IO) {
+            format!("https://crates.io/api/v1/crates/{}/{}/download", package.name, package.version)
+        } else {
+            "NOASSERTION".to_owned()
+        };
+        let license = if index == root { options.license.as_deref() } else { None };
+        let mut spdx_package = json!({
+            "name": package.name,
+            "SPDXID": spdx_id(index),
+            "versionInfo": package.version,
+            "downloadLocation": download_location,
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license.unwrap_or("NOASSERTION"),
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [{"referenceCategory": "PACKAGE-MANAGER", "referenceType": "purl", "referenceLocator": package.purl()}],
+        });
+        if let Some(ref checksum) = package.checksum {
+            spdx_package["checksums"] = json!([{"algorithm": "SHA256", "checksumValue": checksum}]);
+        }
+        spdx_package
+    }).collect();
+
+    let mut relationships = vec![json!({"spdxElementId": "SPDXRef-DOCUMENT", "relationshipType": "DESCRIBES", "relatedSpdxElement": spdx_id(root)})];
+    for &index in reachable {
+        for &dep in &dependencies[index] {
+            relationships.push(json!({"spdxElementId": spdx_id(index), "relationshipType": "DEPENDS_ON", "relatedSpdxElement": spdx_id(dep)}));
+        }
+    }
+
+    let document_name = format!("{}-{}", options.deb_name, options.deb_version);
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": document_name,
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", document_name, &lock_hash[..16]),
+        "creationInfo": {
+            "created": rfc3339_date(timestamp),
+            "creators": [format!("Tool: cargo-deb-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "cargo-deb"
+version = "1.0.0"
+dependencies = ["foo", "bar 2.0.0"]
+
+[[package]]
+name = "bar"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "bar"
+version = "2.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "abcd"
+
+[[package]]
+name = "foo"
+version = "0.1.0"
+source = "git+https://example.com/foo#0123"
+dependencies = ["bar 2.0.0"]
+
+[[package]]
+name = "other-member"
+version = "1.0.0"
+dependencies = ["bar 1.0.0"]
+"#;
+
+    fn config() -> Config {
+        let (mut config, _) = crate::util::tests::test_config();
+        config.license = Some("MIT".into());
+        config
+    }
+
+    #[test]
+    fn cyclonedx_from_lock() {
+        let sbom: serde_json::Value = serde_json::from_slice(&sbom_from_lock(&config(), SbomFormat::CycloneDx, LOCK, 0).unwrap()).unwrap();
+        assert_eq!("CycloneDX", sbom["bomFormat"]);
+        assert_eq!("pkg:cargo/cargo-deb@1.0.0", sbom["metadata"]["component"]["purl"]);
+        assert_eq!("MIT", sbom["metadata"]["component"]["licenses"][0]["expression"]);
+        let components: Vec<_> = sbom["components"].as_array().unwrap().iter().map(|c| c["bom-ref"].as_str().unwrap()).collect();
+        assert_eq!(vec!["pkg:cargo/bar@2.0.0", "pkg:cargo/foo@0.1.0"], components);
+        assert_eq!("abcd", sbom["components"][0]["hashes"][0]["content"]);
+        assert_eq!(json!(["pkg:cargo/foo@0.1.0", "pkg:cargo/bar@2.0.0"]), sbom["dependencies"][0]["dependsOn"]);
+    }
+
+    #[test]
+    fn spdx_from_lock() {
+        let sbom: serde_json::Value = serde_json::from_slice(&sbom_from_lock(&config(), SbomFormat::Spdx, LOCK, 0).unwrap()).unwrap();
+        assert_eq!("SPDX-2.3", sbom["spdxVersion"]);
+        assert_eq!("1970-01-01T00:00:00Z", sbom["creationInfo"]["created"]);
+        let packages = sbom["packages"].as_array().unwrap();
+        assert_eq!(3, packages.len());
+        assert_eq!("MIT", packages[0]["licenseDeclared"]);
+        assert_eq!("https://crates.io/api/v1/crates/bar/2.0.0/download", packages[1]["downloadLocation"]);
+        assert_eq!("NOASSERTION", packages[2]["downloadLocation"]);
+        assert_eq!(4, sbom["relationships"].as_array().unwrap().len());
+    }
+}
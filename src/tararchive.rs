@@ -1,39 +1,52 @@
 use crate::error::*;
+use crate::manifest::TarFormat;
+use crate::pathbytes::*;
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::io;
 use std::path::{Component, Path, PathBuf};
 use tar::EntryType;
 use tar::Header as TarHeader;
 
+/// Size of the name and link name fields in the tar header
+const NAME_FIELD_LEN: usize = 100;
+
 pub struct Archive {
     added_directories: HashSet<PathBuf>,
     time: u64,
+    format: TarFormat,
     tar: tar::Builder<Vec<u8>>,
 }
 
 impl Archive {
-    pub fn new(time: u64) -> Self {
+    pub fn new(time: u64, format: TarFormat) -> Self {
         Self {
             added_directories: HashSet::new(),
             time,
+            format,
             tar: tar::Builder::new(Vec::new()),
         }
     }
 
-    fn directory(&mut self, path: &Path) -> io::Result<()> {
-        let mut header = TarHeader::new_gnu();
+    fn header(&self) -> TarHeader {
+        match self.format {
+            TarFormat::Gnu => TarHeader::new_gnu(),
+            TarFormat::Ustar | TarFormat::Pax => TarHeader::new_ustar(),
+        }
+    }
+
+    fn directory(&mut self, path: &Path) -> CDResult<()> {
+        let mut header = self.header();
         header.set_mtime(self.time);
         header.set_size(0);
         header.set_mode(0o755);
+        header.set_entry_type(EntryType::Directory);
         // Lintian insists on dir paths ending with /, which Rust doesn't
-        let mut path_str = path.to_string_lossy().to_string();
-        if !path_str.ends_with('/') {
-            path_str += "/";
+        let mut path_bytes = normalized(path);
+        if !path_bytes.ends_with(b"/") {
+            path_bytes.push(b'/');
         }
-        header.set_path(&path_str)?;
-        header.set_entry_type(EntryType::Directory);
-        header.set_cksum();
-        self.tar.append(&header, &mut io::empty())
+        self.append(header, &path_bytes, None, io::empty())
     }
 
     fn add_parent_directories(&mut self, path: &Path) -> CDResult<()> {
@@ -60,30 +73,112 @@ impl Archive {
     }
 
     fn file_(&mut self, path: &Path, out_data: &[u8], chmod: u32) -> CDResult<()> {
+        self.file_from_reader(path, out_data, out_data.len() as u64, chmod)
+    }
+
+    /// Copies `len` bytes from the reader, without keeping the whole file in memory.
+    /// It's an error if the reader has more or fewer bytes, e.g. because the file changed after its size was checked.
+    pub fn file_from_reader(&mut self, path: &Path, mut reader: impl io::Read, len: u64, chmod: u32) -> CDResult<()> {
         self.add_parent_directories(path)?;
 
-        let mut header = TarHeader::new_gnu();
+        let mut header = self.header();
         header.set_mtime(self.time);
-        header.set_path(path)?;
         header.set_mode(chmod);
-        header.set_size(out_data.len() as u64);
-        header.set_cksum();
-        self.tar.append(&header, out_data)?;
+        header.set_size(len);
+        let mut limited = io::Read::take(&mut reader, len);
+        self.append(header, &normalized(path), None, &mut limited)?;
+        if limited.limit() != 0 || reader.read(&mut [0; 1])? != 0 {
+            return Err(CargoDebError::AssetChanged(path.to_owned(), len));
+        }
         Ok(())
     }
 
+    /// Hardlink to a file added earlier
+    pub fn hardlink(&mut self, path: &Path, target: &Path, chmod: u32) -> CDResult<()> {
+        self.add_parent_directories(path)?;
+
+        let mut header = self.header();
+        header.set_mtime(self.time);
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        header.set_mode(chmod);
+        self.append(header, &normalized(path), Some(&normalized(target)), io::empty())
+    }
+
     pub fn symlink(&mut self, path: &Path, link_name: &Path) -> CDResult<()> {
         self.add_parent_directories(path.as_ref())?;
 
-        let mut header = TarHeader::new_gnu();
+        let mut header = self.header();
         header.set_mtime(self.time);
         header.set_entry_type(EntryType::Symlink);
-        header.set_path(&path)?;
-        header.set_link_name(&link_name)?;
         header.set_size(0);
         header.set_mode(0o777);
+        self.append(header, &normalized(path), Some(&link_name.as_unix_path()), io::empty())
+    }
+
+    /// Sets the path and link name, adding extra entries for names that don't fit in the header
+    fn append(&mut self, mut header: TarHeader, path: &[u8], link_name: Option<&[u8]>, data: impl io::Read) -> CDResult<()> {
+        let link_name = link_name.unwrap_or_default();
+        let path_is_long = path.len() > NAME_FIELD_LEN;
+        let link_is_long = link_name.len() > NAME_FIELD_LEN;
+        match self.format {
+            TarFormat::Gnu => {
+                if path_is_long {
+                    self.gnu_long_name(EntryType::GNULongName, path)?;
+                }
+                if link_is_long {
+                    self.gnu_long_name(EntryType::GNULongLink, link_name)?;
+                }
+                set_truncated(&mut header.as_old_mut().name, path);
+                set_truncated(&mut header.as_old_mut().linkname, link_name);
+            },
+            TarFormat::Ustar => {
+                // splits the path into the prefix and name fields, up to 255 bytes
+                let path = bytes_to_path(path)?;
+                header.set_path(&path).map_err(|_| CargoDebError::TarPathTooLong(path.into_owned()))?;
+                if link_is_long {
+                    return Err(CargoDebError::TarPathTooLong(bytes_to_path(link_name)?.into_owned()));
+                }
+                set_truncated(&mut header.as_old_mut().linkname, link_name);
+            },
+            TarFormat::Pax => {
+                if path_is_long || link_is_long {
+                    let mut records = Vec::new();
+                    if path_is_long {
+                        pax_record(&mut records, "path", path);
+                    }
+                    if link_is_long {
+                        pax_record(&mut records, "linkpath", link_name);
+                    }
+                    let mut pax_header = TarHeader::new_ustar();
+                    pax_header.set_entry_type(EntryType::XHeader);
+                    pax_header.set_mtime(self.time);
+                    pax_header.set_mode(0o644);
+                    pax_header.set_size(records.len() as u64);
+                    set_truncated(&mut pax_header.as_old_mut().name, b"././@PaxHeader");
+                    pax_header.set_cksum();
+                    self.tar.append(&pax_header, &records[..])?;
+                }
+                set_truncated(&mut header.as_old_mut().name, path);
+                set_truncated(&mut header.as_old_mut().linkname, link_name);
+            },
+        }
+        header.set_cksum();
+        self.tar.append(&header, data)?;
+        Ok(())
+    }
+
+    /// GNU tar extension: the name is stored as the content of a preceding pseudo-entry
+    fn gnu_long_name(&mut self, entry_type: EntryType, name: &[u8]) -> CDResult<()> {
+        let mut header = TarHeader::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_size(name.len() as u64 + 1);
+        set_truncated(&mut header.as_old_mut().name, b"././@LongLink");
         header.set_cksum();
-        self.tar.append(&header, &mut io::empty())?;
+        let mut data = name.to_vec();
+        data.push(0);
+        self.tar.append(&header, &data[..])?;
         Ok(())
     }
 
@@ -91,3 +186,123 @@ impl Archive {
         self.tar.into_inner()
     }
 }
+
+/// Path without `./` components, like `tar` stores it
+fn normalized(path: &Path) -> Vec<u8> {
+    let parts: Vec<_> = path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| Path::new(c.as_os_str()).as_unix_path().into_owned())
+        .collect();
+    parts.join(&b'/')
+}
+
+/// Copies as much of the name as fits into a fixed-size header field
+fn set_truncated(field: &mut [u8], name: &[u8]) {
+    let len = name.len().min(field.len());
+    field[..len].copy_from_slice(&name[..len]);
+    field[len..].iter_mut().for_each(|b| *b = 0);
+}
+
+/// A pax record is `"<len> <key>=<value>\n"`, where the length includes itself
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let rest_len = key.len() + value.len() + 3; // space, '=', '\n'
+    let mut len = rest_len + 1;
+    while len != rest_len + len.to_string().len() {
+        len = rest_len + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{} {}=", len, key).as_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+fn bytes_to_path(bytes: &[u8]) -> CDResult<Cow<'_, Path>> {
+    path_from_unix_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn long_path() -> PathBuf {
+        let dir = "d".repeat(99);
+        Path::new("usr/share").join(&dir).join(&dir).join(format!("{}.txt", "f".repeat(80)))
+    }
+
+    fn read_back(archive: Archive) -> Vec<(PathBuf, Option<PathBuf>, String)> {
+        let bytes = archive.into_inner().unwrap();
+        let mut tar = tar::Archive::new(&bytes[..]);
+        tar.entries().unwrap().map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().into_owned();
+            let link = entry.link_name().unwrap().map(|l| l.into_owned());
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            (path, link, content)
+        })
+        .filter(|(path, ..)| !path.to_string_lossy().ends_with('/'))
+        .collect()
+    }
+
+    #[test]
+    fn long_paths_gnu_and_pax() {
+        let path = long_path();
+        assert!(path.as_os_str().len() > 255);
+        let link = Path::new("../..").join(long_path());
+        for &format in &[TarFormat::Gnu, TarFormat::Pax] {
+            let mut archive = Archive::new(0, format);
+            archive.file(&path, b"hello", 0o644).unwrap();
+            archive.symlink(Path::new("usr/bin/link"), &link).unwrap();
+            let entries = read_back(archive);
+            assert_eq!(vec![
+                (path.clone(), None, "hello".to_owned()),
+                (PathBuf::from("usr/bin/link"), Some(link.clone()), String::new()),
+            ], entries, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn ustar_path_limit() {
+        let mut archive = Archive::new(0, TarFormat::Ustar);
+        let medium_path = Path::new("usr/share").join("d".repeat(99)).join("f".repeat(99));
+        archive.file(&medium_path, b"ok", 0o644).unwrap();
+        assert_eq!(medium_path, read_back(archive)[0].0);
+
+        let mut archive = Archive::new(0, TarFormat::Ustar);
+        assert!(archive.file(long_path(), b"", 0o644).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latin1_names() {
+        let path = path_from_unix_bytes(b"usr/share/foo/caf\xe9.txt").unwrap().into_owned();
+        let link = path_from_unix_bytes(b"caf\xe9.txt").unwrap().into_owned();
+        for &format in &[TarFormat::Gnu, TarFormat::Ustar, TarFormat::Pax] {
+            let mut archive = Archive::new(0, format);
+            archive.file(&path, b"hello", 0o644).unwrap();
+            archive.symlink(Path::new("usr/share/foo/link"), &link).unwrap();
+            let entries = read_back(archive);
+            assert_eq!(path, entries[0].0, "{:?}", format);
+            assert_eq!(Some(link.clone()), entries[1].1, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn pax_record_length() {
+        let mut out = Vec::new();
+        pax_record(&mut out, "path", b"ab");
+        assert_eq!(&b"11 path=ab\n"[..], &out[..]);
+        out.clear();
+        pax_record(&mut out, "path", "x".repeat(92).as_bytes());
+        assert_eq!(102, out.len());
+        assert!(out.starts_with(b"102 path="));
+    }
+
+    #[test]
+    fn reader_of_wrong_length() {
+        let mut archive = Archive::new(0, TarFormat::Gnu);
+        archive.file_from_reader(Path::new("usr/share/foo/exact"), &b"12345"[..], 5, 0o644).unwrap();
+        assert!(matches!(archive.file_from_reader(Path::new("usr/share/foo/short"), &b"1234"[..], 5, 0o644), Err(CargoDebError::AssetChanged(_, 5))));
+        assert!(matches!(archive.file_from_reader(Path::new("usr/share/foo/long"), &b"123456"[..], 5, 0o644), Err(CargoDebError::AssetChanged(_, 5))));
+    }
+}
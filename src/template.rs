@@ -0,0 +1,42 @@
+use crate::error::*;
+
+/// Expands `$NAME` and `${name}` placeholders.
+///
+/// `lookup` returns `None` for names it doesn't know, and these are kept as-is, e.g. `$auto` or `${shlibs:Depends}`.
+pub(crate) fn interpolate(value: &str, lookup: &dyn Fn(&str) -> CDResult<Option<String>>) -> CDResult<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        match if name.is_empty() { None } else { lookup(name)? } {
+            Some(var) => expanded.push_str(&var),
+            None => expanded.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + 1 + len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[test]
+fn placeholders() {
+    let lookup = |name: &str| Ok(match name {
+        "CARGO_PKG_VERSION" => Some("1.2.3".to_owned()),
+        "target" => Some("x86_64-unknown-linux-gnu".to_owned()),
+        _ => None,
+    });
+    assert_eq!("foo 1.2.3-x86_64-unknown-linux-gnu", interpolate("foo $CARGO_PKG_VERSION-${target}", &lookup).unwrap());
+    assert_eq!("foo (= 1.2.3), $auto, ${shlibs:Depends}", interpolate("foo (= ${CARGO_PKG_VERSION}), $auto, ${shlibs:Depends}", &lookup).unwrap());
+    assert_eq!("$ $UNKNOWN ${unterminated", interpolate("$ $UNKNOWN ${unterminated", &lookup).unwrap());
+    assert_eq!("$", interpolate("$", &lookup).unwrap());
+}
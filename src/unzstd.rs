@@ -0,0 +1,637 @@
+//! Minimal Zstandard decoder for reading zstd-compressed package members.
+//!
+//! See [RFC 8878](https://www.rfc-editor.org/rfc/rfc8878). Dictionaries aren't supported, because packages don't use them.
+use crate::error::*;
+use std::convert::TryInto;
+
+const MAGIC: u32 = 0xFD2F_B528;
+const TRUNCATED: CargoDebError = CargoDebError::InvalidCompressedData("zstd data is truncated");
+const CORRUPTED: CargoDebError = CargoDebError::InvalidCompressedData("zstd data is corrupted");
+
+/// Decompresses all frames of a zstd file
+pub(crate) fn unzstd(mut data: &[u8]) -> CDResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 4);
+    while !data.is_empty() {
+        let magic = u32::from_le_bytes(data.get(..4).ok_or(TRUNCATED)?.try_into().unwrap());
+        data = if magic & 0xFFFF_FFF0 == 0x184D_2A50 {
+            // skippable frame
+            let len = u32::from_le_bytes(data.get(4..8).ok_or(TRUNCATED)?.try_into().unwrap()) as usize;
+            data.get(8 + len..).ok_or(TRUNCATED)?
+        } else if magic == MAGIC {
+            frame(&data[4..], &mut out)?
+        } else {
+            return Err(CargoDebError::InvalidCompressedData("not a zstd file"));
+        };
+    }
+    Ok(out)
+}
+
+/// Returns data remaining after the frame
+fn frame<'a>(data: &'a [u8], out: &mut Vec<u8>) -> CDResult<&'a [u8]> {
+    let descriptor = *data.first().ok_or(TRUNCATED)?;
+    let content_size_flag = descriptor >> 6;
+    let single_segment = descriptor & 0x20 != 0;
+    let has_checksum = descriptor & 4 != 0;
+    if descriptor & 8 != 0 {
+        return Err(CORRUPTED);
+    }
+    let mut pos = 1 + usize::from(!single_segment);
+    let dictionary_id_len = [0, 1, 2, 4][usize::from(descriptor & 3)];
+    if data.get(pos..pos + dictionary_id_len).ok_or(TRUNCATED)?.iter().any(|&b| b != 0) {
+        return Err(CargoDebError::InvalidCompressedData("zstd data needs a dictionary, which isn't supported"));
+    }
+    pos += dictionary_id_len;
+    let content_size_len = match content_size_flag {
+        0 => usize::from(single_segment),
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let content_size = data.get(pos..pos + content_size_len).ok_or(TRUNCATED)?.iter().rev()
+        .fold(0u64, |size, &b| size << 8 | u64::from(b)) + if content_size_len == 2 { 256 } else { 0 };
+    pos += content_size_len;
+
+    let start = out.len();
+    let mut state = FrameState {
+        huffman: None,
+        literal_lengths: None,
+        offsets: None,
+        match_lengths: None,
+        repeat_offsets: [1, 4, 8],
+    };
+    loop {
+        let header = data.get(pos..pos + 3).ok_or(TRUNCATED)?;
+        let header = u32::from(header[0]) | u32::from(header[1]) << 8 | u32::from(header[2]) << 16;
+        let size = (header >> 3) as usize;
+        pos += 3;
+        match (header >> 1) & 3 {
+            0 => {
+                out.extend_from_slice(data.get(pos..pos + size).ok_or(TRUNCATED)?);
+                pos += size;
+            },
+            1 => {
+                let byte = *data.get(pos).ok_or(TRUNCATED)?;
+                out.resize(out.len() + size, byte);
+                pos += 1;
+            },
+            2 => {
+                compressed_block(data.get(pos..pos + size).ok_or(TRUNCATED)?, &mut state, out, start)?;
+                pos += size;
+            },
+            _ => return Err(CORRUPTED),
+        }
+        if header & 1 != 0 {
+            break;
+        }
+    }
+    if content_size_len > 0 && content_size != (out.len() - start) as u64 {
+        return Err(CargoDebError::InvalidCompressedData("zstd data is corrupted: length mismatch"));
+    }
+    if has_checksum {
+        let checksum = data.get(pos..pos + 4).ok_or(TRUNCATED)?;
+        if checksum != (xxh64(&out[start..]) as u32).to_le_bytes() {
+            return Err(CargoDebError::InvalidCompressedData("zstd data is corrupted: checksum mismatch"));
+        }
+        pos += 4;
+    }
+    Ok(&data[pos..])
+}
+
+/// Tables that later blocks of the frame can reuse
+struct FrameState {
+    huffman: Option<Huffman>,
+    literal_lengths: Option<Fse>,
+    offsets: Option<Fse>,
+    match_lengths: Option<Fse>,
+    repeat_offsets: [usize; 3],
+}
+
+fn compressed_block(block: &[u8], state: &mut FrameState, out: &mut Vec<u8>, frame_start: usize) -> CDResult<()> {
+    let (literals, rest) = literals_section(block, state)?;
+    let num_sequences = match *rest.first().ok_or(TRUNCATED)? {
+        0 => {
+            out.extend_from_slice(&literals);
+            return Ok(());
+        },
+        n @ 1..=127 => (usize::from(n), 1),
+        n @ 128..=254 => ((usize::from(n) - 128) << 8 | usize::from(*rest.get(1).ok_or(TRUNCATED)?), 2),
+        _ => (usize::from(*rest.get(1).ok_or(TRUNCATED)?) | usize::from(*rest.get(2).ok_or(TRUNCATED)?) << 8 | 0x7F00, 3),
+    };
+    let (num_sequences, mut pos) = num_sequences;
+    let modes = *rest.get(pos).ok_or(TRUNCATED)?;
+    pos += 1;
+    if modes & 3 != 0 {
+        return Err(CORRUPTED);
+    }
+    let literal_lengths = sequence_table(rest, &mut pos, modes >> 6, &mut state.literal_lengths, &LITERAL_LENGTHS_DEFAULT, 6, 35, 9)?;
+    let offsets = sequence_table(rest, &mut pos, (modes >> 4) & 3, &mut state.offsets, &OFFSETS_DEFAULT, 5, 31, 8)?;
+    let match_lengths = sequence_table(rest, &mut pos, (modes >> 2) & 3, &mut state.match_lengths, &MATCH_LENGTHS_DEFAULT, 6, 52, 9)?;
+
+    let mut bits = BackwardBits::new(&rest[pos..])?;
+    let mut ll_state = bits.read(literal_lengths.accuracy_log) as usize;
+    let mut of_state = bits.read(offsets.accuracy_log) as usize;
+    let mut ml_state = bits.read(match_lengths.accuracy_log) as usize;
+    let mut literals = &literals[..];
+    for i in 0..num_sequences {
+        let of_code = u32::from(offsets.entries[of_state].symbol);
+        let ml_code = usize::from(match_lengths.entries[ml_state].symbol);
+        let ll_code = usize::from(literal_lengths.entries[ll_state].symbol);
+        if of_code > 31 {
+            return Err(CORRUPTED);
+        }
+        let offset_value = (1usize << of_code) + bits.read(of_code) as usize;
+        let (ml_base, ml_bits) = MATCH_LENGTH_CODES[ml_code];
+        let match_length = ml_base as usize + bits.read(ml_bits) as usize;
+        let (ll_base, ll_bits) = LITERAL_LENGTH_CODES[ll_code];
+        let literal_length = ll_base as usize + bits.read(ll_bits) as usize;
+
+        let reps = &mut state.repeat_offsets;
+        let offset = if offset_value > 3 {
+            let offset = offset_value - 3;
+            *reps = [offset, reps[0], reps[1]];
+            offset
+        } else {
+            // a repeated offset, shifted by one when there are no literals
+            let index = offset_value - 1 + usize::from(literal_length == 0);
+            if index == 0 {
+                reps[0]
+            } else {
+                let offset = if index == 3 { reps[0].saturating_sub(1).max(1) } else { reps[index] };
+                if index > 1 {
+                    reps[2] = reps[1];
+                }
+                reps[1] = reps[0];
+                reps[0] = offset;
+                offset
+            }
+        };
+
+        if i + 1 < num_sequences {
+            ll_state = literal_lengths.entries[ll_state].next(&mut bits);
+            ml_state = match_lengths.entries[ml_state].next(&mut bits);
+            of_state = offsets.entries[of_state].next(&mut bits);
+        }
+
+        let (copied, rest) = literals.split_at_checked(literal_length).ok_or(CORRUPTED)?;
+        out.extend_from_slice(copied);
+        literals = rest;
+        if offset > out.len() - frame_start {
+            return Err(CORRUPTED);
+        }
+        let from = out.len() - offset;
+        if offset >= match_length {
+            out.extend_from_within(from..from + match_length);
+        } else {
+            // the match overlaps the bytes it produces
+            for i in 0..match_length {
+                out.push(out[from + i]);
+            }
+        }
+    }
+    if !bits.is_empty() {
+        return Err(CORRUPTED);
+    }
+    out.extend_from_slice(literals);
+    Ok(())
+}
+
+/// Returns the decoded literals and the rest of the block
+fn literals_section<'a>(block: &'a [u8], state: &mut FrameState) -> CDResult<(Vec<u8>, &'a [u8])> {
+    let header = block.get(..5.min(block.len())).ok_or(TRUNCATED)?;
+    let byte0 = usize::from(*header.first().ok_or(TRUNCATED)?);
+    let header_value = |len: usize| -> CDResult<usize> {
+        Ok(header.get(..len).ok_or(TRUNCATED)?.iter().rev().fold(0, |v, &b| v << 8 | usize::from(b)))
+    };
+    let block_type = byte0 & 3;
+    let size_format = (byte0 >> 2) & 3;
+    if block_type < 2 {
+        let (header_len, size) = match size_format {
+            0 | 2 => (1, byte0 >> 3),
+            1 => (2, header_value(2)? >> 4),
+            _ => (3, header_value(3)? >> 4),
+        };
+        let rest = &block[header_len..];
+        return Ok(if block_type == 0 {
+            let (literals, rest) = rest.split_at_checked(size).ok_or(TRUNCATED)?;
+            (literals.to_vec(), rest)
+        } else {
+            (vec![*rest.first().ok_or(TRUNCATED)?; size], &rest[1..])
+        });
+    }
+
+    let (header_len, size_bits, streams) = match size_format {
+        0 => (3, 10, 1),
+        1 => (3, 10, 4),
+        2 => (4, 14, 4),
+        _ => (5, 18, 4),
+    };
+    let value = header_value(header_len)?;
+    let mask = (1 << size_bits) - 1;
+    let regenerated_size = (value >> 4) & mask;
+    let compressed_size = (value >> (4 + size_bits)) & mask;
+    let (mut compressed, rest) = block[header_len..].split_at_checked(compressed_size).ok_or(TRUNCATED)?;
+    if block_type == 2 {
+        let (huffman, len) = Huffman::read(compressed)?;
+        state.huffman = Some(huffman);
+        compressed = &compressed[len..];
+    }
+    let huffman = state.huffman.as_ref().ok_or(CORRUPTED)?;
+    let mut literals = Vec::with_capacity(regenerated_size);
+    if streams == 1 {
+        huffman.decode(compressed, regenerated_size, &mut literals)?;
+    } else {
+        let jump = compressed.get(..6).ok_or(TRUNCATED)?;
+        let mut sizes = [0; 4];
+        for (size, chunk) in sizes.iter_mut().zip(jump.chunks(2)) {
+            *size = usize::from(u16::from_le_bytes([chunk[0], chunk[1]]));
+        }
+        sizes[3] = (compressed.len() - 6).checked_sub(sizes[..3].iter().sum()).ok_or(CORRUPTED)?;
+        let stream_size = regenerated_size.div_ceil(4);
+        let mut streams = &compressed[6..];
+        for (i, &size) in sizes.iter().enumerate() {
+            let (stream, rest) = streams.split_at(size);
+            let count = if i < 3 { stream_size } else { regenerated_size.checked_sub(3 * stream_size).ok_or(CORRUPTED)? };
+            huffman.decode(stream, count, &mut literals)?;
+            streams = rest;
+        }
+    }
+    Ok((literals, rest))
+}
+
+/// Reads the table for the compression mode of literal lengths, offsets or match lengths
+#[allow(clippy::too_many_arguments)]
+fn sequence_table(data: &[u8], pos: &mut usize, mode: u8, previous: &mut Option<Fse>, default: &[i16], default_log: u32, max_symbol: usize, max_log: u32) -> CDResult<Fse> {
+    let table = match mode {
+        0 => Fse::new(default, default_log)?,
+        1 => {
+            let symbol = *data.get(*pos).ok_or(TRUNCATED)?;
+            *pos += 1;
+            if usize::from(symbol) > max_symbol {
+                return Err(CORRUPTED);
+            }
+            Fse { accuracy_log: 0, entries: vec![FseEntry { symbol, bits: 0, base: 0 }] }
+        },
+        2 => {
+            let (table, len) = Fse::read(data.get(*pos..).ok_or(TRUNCATED)?, max_symbol, max_log)?;
+            *pos += len;
+            table
+        },
+        _ => return previous.clone().ok_or(CORRUPTED),
+    };
+    *previous = Some(table.clone());
+    Ok(table)
+}
+
+/// Finite State Entropy decoding table
+#[derive(Clone)]
+struct Fse {
+    accuracy_log: u32,
+    entries: Vec<FseEntry>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u32,
+    base: u16,
+}
+
+impl FseEntry {
+    fn next(&self, bits: &mut BackwardBits<'_>) -> usize {
+        usize::from(self.base) + bits.read(self.bits) as usize
+    }
+}
+
+impl Fse {
+    /// Reads a table description. Returns the table and length of the description in bytes.
+    fn read(data: &[u8], max_symbol: usize, max_log: u32) -> CDResult<(Self, usize)> {
+        let mut bits = ForwardBits { data, pos: 0 };
+        let accuracy_log = bits.read(4)? + 5;
+        if accuracy_log > max_log {
+            return Err(CORRUPTED);
+        }
+        let mut remaining = 1i32 << accuracy_log;
+        let mut probabilities = Vec::new();
+        while remaining > 0 {
+            if probabilities.len() > max_symbol {
+                return Err(CORRUPTED);
+            }
+            let count = 32 - (remaining + 1).leading_zeros();
+            let mut value = bits.read(count)? as i32;
+            let lower_mask = (1 << (count - 1)) - 1;
+            let threshold = (1 << count) - 1 - (remaining + 1);
+            if value & lower_mask < threshold {
+                bits.pos -= 1;
+                value &= lower_mask;
+            } else if value > lower_mask {
+                value -= threshold;
+            }
+            let probability = value - 1;
+            remaining -= probability.abs();
+            probabilities.push(probability as i16);
+            if probability == 0 {
+                loop {
+                    let repeat = bits.read(2)?;
+                    probabilities.extend((0..repeat).map(|_| 0));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+        }
+        if remaining != 0 || probabilities.len() > max_symbol + 1 {
+            return Err(CORRUPTED);
+        }
+        Ok((Self::new(&probabilities, accuracy_log)?, bits.pos.div_ceil(8)))
+    }
+
+    /// Spreads the symbols over the states. `-1` is a probability lower than 1.
+    fn new(probabilities: &[i16], accuracy_log: u32) -> CDResult<Self> {
+        let size = 1usize << accuracy_log;
+        let mut entries = vec![FseEntry::default(); size];
+        let mut next_state = vec![0u16; probabilities.len()];
+        let mut high = size;
+        for (symbol, &p) in probabilities.iter().enumerate() {
+            if p == -1 {
+                high -= 1;
+                entries[high].symbol = symbol as u8;
+                next_state[symbol] = 1;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut pos = 0;
+        for (symbol, &p) in probabilities.iter().enumerate() {
+            if p <= 0 {
+                continue;
+            }
+            next_state[symbol] = p as u16;
+            for _ in 0..p {
+                entries[pos].symbol = symbol as u8;
+                pos = (pos + step) & (size - 1);
+                while pos >= high {
+                    pos = (pos + step) & (size - 1);
+                }
+            }
+        }
+        if pos != 0 {
+            return Err(CORRUPTED);
+        }
+        for entry in &mut entries {
+            let state = next_state[usize::from(entry.symbol)];
+            next_state[usize::from(entry.symbol)] += 1;
+            entry.bits = accuracy_log - (15 - state.leading_zeros());
+            entry.base = ((u32::from(state) << entry.bits) - size as u32) as u16;
+        }
+        Ok(Self { accuracy_log, entries })
+    }
+}
+
+/// Huffman decoding table for literals, indexed by the next `max_bits` bits
+struct Huffman {
+    max_bits: u32,
+    entries: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    /// Reads a tree description. Returns the table and length of the description in bytes.
+    fn read(data: &[u8]) -> CDResult<(Self, usize)> {
+        let header = usize::from(*data.first().ok_or(TRUNCATED)?);
+        let (mut weights, len) = if header < 128 {
+            let data = data.get(1..1 + header).ok_or(TRUNCATED)?;
+            let (fse, table_len) = Fse::read(data, 255, 6)?;
+            let mut bits = BackwardBits::new(&data[table_len..])?;
+            let mut states = [bits.read(fse.accuracy_log) as usize, bits.read(fse.accuracy_log) as usize];
+            let mut weights = Vec::new();
+            // two interleaved states, until the bits run out
+            'decode: loop {
+                for i in 0..2 {
+                    weights.push(fse.entries[states[i]].symbol);
+                    states[i] = fse.entries[states[i]].next(&mut bits);
+                    if bits.pos < 0 {
+                        weights.push(fse.entries[states[1 - i]].symbol);
+                        break 'decode;
+                    }
+                }
+                if weights.len() > 255 {
+                    return Err(CORRUPTED);
+                }
+            }
+            (weights, 1 + header)
+        } else {
+            let count = header - 127;
+            let data = data.get(1..1 + count.div_ceil(2)).ok_or(TRUNCATED)?;
+            let weights = (0..count).map(|i| if i % 2 == 0 { data[i / 2] >> 4 } else { data[i / 2] & 15 }).collect();
+            (weights, 1 + data.len())
+        };
+        if weights.len() > 255 || weights.iter().any(|&w| w > 11) {
+            return Err(CORRUPTED);
+        }
+        let total: u32 = weights.iter().filter(|&&w| w > 0).map(|&w| 1 << (w - 1)).sum();
+        if total == 0 {
+            return Err(CORRUPTED);
+        }
+        // the last weight makes the total a power of two
+        let max_bits = 32 - total.leading_zeros();
+        let left = (1 << max_bits) - total;
+        if max_bits > 11 || !left.is_power_of_two() {
+            return Err(CORRUPTED);
+        }
+        weights.push(left.trailing_zeros() as u8 + 1);
+
+        let bit_lengths: Vec<u32> = weights.iter().map(|&w| if w > 0 { max_bits + 1 - u32::from(w) } else { 0 }).collect();
+        let mut next_code = vec![0usize; max_bits as usize + 2];
+        for bits in (1..=max_bits).rev() {
+            let count = bit_lengths.iter().filter(|&&b| b == bits).count();
+            next_code[bits as usize - 1] = next_code[bits as usize] + (count << (max_bits - bits));
+        }
+        let mut entries = vec![(0, 0); 1 << max_bits];
+        for (symbol, &bits) in bit_lengths.iter().enumerate() {
+            if bits == 0 {
+                continue;
+            }
+            let start = next_code[bits as usize];
+            let len = 1 << (max_bits - bits);
+            entries[start..start + len].fill((symbol as u8, bits as u8));
+            next_code[bits as usize] += len;
+        }
+        Ok((Self { max_bits, entries }, len))
+    }
+
+    fn decode(&self, stream: &[u8], count: usize, out: &mut Vec<u8>) -> CDResult<()> {
+        let mut bits = BackwardBits::new(stream)?;
+        for _ in 0..count {
+            let (symbol, len) = self.entries[bits.peek(self.max_bits) as usize];
+            bits.pos -= isize::from(len);
+            out.push(symbol);
+        }
+        if !bits.is_empty() {
+            return Err(CORRUPTED);
+        }
+        Ok(())
+    }
+}
+
+/// Little-endian bits, read from the start
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl ForwardBits<'_> {
+    fn read(&mut self, count: u32) -> CDResult<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.pos / 8).ok_or(TRUNCATED)?;
+            value |= u32::from(byte >> (self.pos % 8) & 1) << i;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Bits read from the end, where the highest set bit of the last byte marks the start.
+/// Bits before the start of the data read as zeros, which is valid for peeking past the end.
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    pos: isize,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> CDResult<Self> {
+        let last = *data.last().ok_or(TRUNCATED)?;
+        if last == 0 {
+            return Err(CORRUPTED);
+        }
+        Ok(Self { data, pos: (data.len() * 8 - 8) as isize + 7 - last.leading_zeros() as isize })
+    }
+
+    /// The `count` bits before the position, at most 56
+    fn peek(&self, count: u32) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+        let start = self.pos - count as isize;
+        let (start, shift_left) = if start < 0 { (0, (-start) as u32) } else { (start as usize, 0) };
+        let available = count - shift_left.min(count);
+        if available == 0 {
+            return 0;
+        }
+        let byte = start / 8;
+        let mut buf = [0; 8];
+        let end = (byte + 8).min(self.data.len());
+        buf[..end - byte].copy_from_slice(&self.data[byte..end]);
+        let value = (u64::from_le_bytes(buf) >> (start % 8)) & ((1 << available) - 1);
+        value << shift_left
+    }
+
+    fn read(&mut self, count: u32) -> u64 {
+        let value = self.peek(count);
+        self.pos -= count as isize;
+        value
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == 0
+    }
+}
+
+/// Base value and number of extra bits of each literal length code
+const LITERAL_LENGTH_CODES: [(u32, u32); 36] = [
+    (0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0), (11, 0),
+    (12, 0), (13, 0), (14, 0), (15, 0), (16, 1), (18, 1), (20, 1), (22, 1), (24, 2), (28, 2), (32, 3), (40, 3),
+    (48, 4), (64, 6), (128, 7), (256, 8), (512, 9), (1024, 10), (2048, 11), (4096, 12), (8192, 13), (16384, 14), (32768, 15), (65536, 16),
+];
+
+/// Base value and number of extra bits of each match length code
+const MATCH_LENGTH_CODES: [(u32, u32); 53] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0), (11, 0), (12, 0), (13, 0), (14, 0),
+    (15, 0), (16, 0), (17, 0), (18, 0), (19, 0), (20, 0), (21, 0), (22, 0), (23, 0), (24, 0), (25, 0), (26, 0),
+    (27, 0), (28, 0), (29, 0), (30, 0), (31, 0), (32, 0), (33, 0), (34, 0), (35, 1), (37, 1), (39, 1), (41, 1),
+    (43, 2), (47, 2), (51, 3), (59, 3), (67, 4), (83, 4), (99, 5), (131, 7), (259, 8), (515, 9), (1027, 10), (2051, 11),
+    (4099, 12), (8195, 13), (16387, 14), (32771, 15), (65539, 16),
+];
+
+const LITERAL_LENGTHS_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1, -1, -1, -1, -1,
+];
+
+const MATCH_LENGTHS_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+
+const OFFSETS_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+/// XXH64 with seed 0, used for the frame checksum
+fn xxh64(data: &[u8]) -> u64 {
+    const P1: u64 = 0x9E37_79B1_85EB_CA87;
+    const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+    const P3: u64 = 0x1656_67B1_9E37_79F9;
+    const P4: u64 = 0x85EB_CA77_C2B2_AE63;
+    const P5: u64 = 0x27D4_EB2F_1656_67C5;
+    fn round(acc: u64, input: u64) -> u64 {
+        acc.wrapping_add(input.wrapping_mul(P2)).rotate_left(31).wrapping_mul(P1)
+    }
+    fn lane(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    let mut stripes = data.chunks_exact(32);
+    let mut hash = if data.len() >= 32 {
+        let mut v = [P1.wrapping_add(P2), P2, 0, 0u64.wrapping_sub(P1)];
+        for stripe in &mut stripes {
+            for (v, bytes) in v.iter_mut().zip(stripe.chunks_exact(8)) {
+                *v = round(*v, lane(bytes));
+            }
+        }
+        let mut hash = v[0].rotate_left(1).wrapping_add(v[1].rotate_left(7)).wrapping_add(v[2].rotate_left(12)).wrapping_add(v[3].rotate_left(18));
+        for v in v {
+            hash = (hash ^ round(0, v)).wrapping_mul(P1).wrapping_add(P4);
+        }
+        hash
+    } else {
+        P5
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+    let mut rest = stripes.remainder();
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, lane(&rest[..8]))).rotate_left(27).wrapping_mul(P1).wrapping_add(P4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        hash = (hash ^ u64::from(u32::from_le_bytes(rest[..4].try_into().unwrap())).wrapping_mul(P1)).rotate_left(23).wrapping_mul(P2).wrapping_add(P3);
+        rest = &rest[4..];
+    }
+    for &b in rest {
+        hash = (hash ^ u64::from(b).wrapping_mul(P5)).rotate_left(11).wrapping_mul(P1);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(P2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(P3);
+    hash ^ (hash >> 32)
+}
+
+#[test]
+fn unzstd_cli_output() {
+    // `zstd -19` of two similar sentences, with compressed literals and a checksum
+    let compressed = [
+        0x28, 0xb5, 0x2f, 0xfd, 0x24, 0x6f, 0xb5, 0x01, 0x00, 0xf2, 0x02, 0x0a, 0x10, 0xd0, 0xe7, 0x50, 0x54, 0x66, 0x18, 0x82, 0xa8, 0x9d,
+        0x64, 0x00, 0x5c, 0x69, 0x3a, 0xcb, 0x08, 0xa0, 0x50, 0xc5, 0xb1, 0xe1, 0xb0, 0xd5, 0x9e, 0xa9, 0xbe, 0x97, 0x60, 0x9f, 0x7e, 0xab,
+        0x59, 0x9a, 0xe5, 0xde, 0x50, 0x34, 0xc6, 0x17, 0x03, 0x00, 0x5b, 0xdd, 0xd8, 0x5a, 0x81, 0x97, 0xb8, 0x14, 0x01, 0x95, 0xe5, 0x7e, 0xa9,
+    ];
+    let text = &b"hello hello hello, this is a test of the zstd decoder. hello hello hello, this was a test of the zstd decoder.\n"[..];
+    assert_eq!(text, &unzstd(&compressed).unwrap()[..]);
+    let mut corrupted = compressed;
+    corrupted[40] ^= 1;
+    assert!(unzstd(&corrupted).is_err());
+
+    // a raw block, after a skippable frame
+    let mut raw = vec![0x50, 0x2a, 0x4d, 0x18, 2, 0, 0, 0, 1, 2];
+    raw.extend_from_slice(&[0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x19, 0x00, 0x00, 0x61, 0x62, 0x63, 0x99, 0x09, 0x77, 0xad]);
+    assert_eq!(b"abc", &unzstd(&raw).unwrap()[..]);
+    assert!(unzstd(b"not zstd").is_err());
+    assert_eq!(0xEF46_DB37_51D8_E999, xxh64(b""));
+}
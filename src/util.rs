@@ -12,7 +12,7 @@ pub(crate) fn fname_from_path(path: &Path) -> String {
 pub(crate) use tests::is_path_file;
 
 #[cfg(not(test))]
-pub(crate) fn is_path_file(path: &PathBuf) -> bool {
+pub(crate) fn is_path_file(path: &Path) -> bool {
     path.is_file()
 }
 
@@ -28,16 +28,70 @@ pub(crate) fn read_file_to_string(path: PathBuf) -> std::io::Result<String> {
 pub(crate) use tests::read_file_to_bytes;
 
 #[cfg(not(test))]
-pub(crate) fn read_file_to_bytes(path: &PathBuf) -> std::io::Result<Vec<u8>> {
+pub(crate) fn read_file_to_bytes(path: &Path) -> std::io::Result<Vec<u8>> {
     std::fs::read(path)
 }
 
+/// Current time in seconds since the UNIX epoch, clamped to `SOURCE_DATE_EPOCH` if it's set,
+/// per <https://reproducible-builds.org/specs/source-date-epoch/>
+#[must_use]
+pub fn clamped_timestamp() -> u64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .map_or(now, |epoch: u64| epoch.min(now))
+}
+
+/// Formats a UNIX timestamp as an RFC 2822 date in UTC, as used by Debian changelogs,
+/// e.g. `Thu, 01 Jan 1970 00:00:00 +0000`
+pub(crate) fn rfc2822_date(timestamp: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = timestamp / 86400;
+    let secs = timestamp % 86400;
+    let (year, month, day) = civil_date(days);
+
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[(days % 7) as usize], day, MONTHS[(month - 1) as usize], year,
+        secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Formats a UNIX timestamp as an RFC 3339 date in UTC, e.g. `1970-01-01T00:00:00Z`
+pub(crate) fn rfc3339_date(timestamp: u64) -> String {
+    let (year, month, day) = civil_date(timestamp / 86400);
+    let secs = timestamp % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Formats a UNIX timestamp as a `YYYYMMDD` date in UTC
+pub(crate) fn compact_date(timestamp: u64) -> String {
+    let (year, month, day) = civil_date(timestamp / 86400);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+/// Year, month and day from days since the epoch, see <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
 /// Create a HashMap from one or more key => value pairs in a single statement.
 ///
 /// # Usage
 ///
 /// Any types supported by HashMap for keys and values are supported:
-/// ```
+/// ```text
 /// let mut one = std::collections::HashMap::new();
 /// one.insert(1, 'a');
 /// assert_eq!(one, map!{ 1 => 'a' });
@@ -140,7 +194,7 @@ pub(crate) mod tests {
         fn new(filename: &'static str, contents: String) -> Self {
             TestPath {
                 _filename: filename,
-                contents: contents,
+                contents,
                 read_count: 0,
             }
         }
@@ -182,7 +236,7 @@ pub(crate) mod tests {
         MOCK_FS.with(|fs| callback(&mut fs.lock().unwrap()))
     }
 
-    pub(crate) fn is_path_file(path: &PathBuf) -> bool {
+    pub(crate) fn is_path_file(path: &Path) -> bool {
         with_test_fs(|fs| {
             fs.contains_key(&path.to_str().unwrap())
         })
@@ -224,8 +278,12 @@ pub(crate) mod tests {
         })
     }
 
-    pub(crate) fn read_file_to_bytes(path: &PathBuf) -> std::io::Result<Vec<u8>> {
-        match read_file_to_string(path.clone()) {
+    /// Files that the test didn't add, e.g. the crate's own `LICENSE`, are read from the real filesystem
+    pub(crate) fn read_file_to_bytes(path: &Path) -> std::io::Result<Vec<u8>> {
+        if !is_path_file(path) {
+            return std::fs::read(path);
+        }
+        match read_file_to_string(path.to_path_buf()) {
             Ok(contents) => Ok(Vec::from(contents.as_bytes())),
             Err(x) => Err(x),
         }
@@ -235,6 +293,14 @@ pub(crate) mod tests {
     // End: test virtual filesystem
     // ---------------------------------------------------------------------
 
+    /// `Config` of this crate's `Cargo.toml`, and a listener that accepts info messages
+    pub(crate) fn test_config() -> (crate::Config, crate::listener::MockListener) {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let config = crate::Config::from_manifest(crate::manifest::ManifestOptions::default(), &mock_listener).unwrap();
+        (config, mock_listener)
+    }
+
     use super::*;
 
     #[test]
@@ -261,6 +327,16 @@ pub(crate) mod tests {
         assert_eq!("", fname_from_path(Path::new("/a/")));
     }
 
+    #[test]
+    fn rfc2822_dates() {
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 +0000", rfc2822_date(0));
+        assert_eq!("Tue, 29 Feb 2000 23:59:59 +0000", rfc2822_date(951_868_799));
+        assert_eq!("Wed, 14 Oct 2026 12:34:56 +0000", rfc2822_date(1_791_981_296));
+        assert_eq!("20000229", compact_date(951_868_799));
+        assert_eq!("20261014", compact_date(1_791_981_296));
+        assert_eq!("2026-10-14T12:34:56Z", rfc3339_date(1_791_981_296));
+    }
+
     #[test]
     fn map_macro() {
         let mut one = std::collections::HashMap::new();
@@ -0,0 +1,411 @@
+use crate::reader::{decompress, read_package, tar_entries, Member, Package, TarEntry};
+use crate::error::*;
+use crate::listener::{Listener, Warning};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const SCRIPTS: [&str; 5] = ["preinst", "postinst", "prerm", "postrm", "config"];
+
+/// Checks integrity of a built package: the archive structure, the control file syntax,
+/// checksums of all files listed in `md5sums`/`sha256sums`, and permissions of maintainer scripts.
+pub fn verify_package(path: &Path, listener: &dyn Listener) -> CDResult<()> {
+    verify_members(path, &read_package(path)?, listener)
+}
+
+fn verify_members(path: &Path, members: &[Member], listener: &dyn Listener) -> CDResult<()> {
+    let mut problems = Vec::new();
+
+    match members.first() {
+        Some(m) if m.name == "debian-binary" => {
+            if !m.data.starts_with(b"2.") || !m.data.ends_with(b"\n") {
+                problems.push(format!("unsupported debian-binary version {:?}", String::from_utf8_lossy(&m.data)));
+            }
+        },
+        _ => problems.push("debian-binary must be the first member".to_owned()),
+    }
+    let control_member = find_member(members, "control.tar");
+    let data_member = find_member(members, "data.tar");
+    let (control_member, data_member) = match (control_member, data_member) {
+        (Some((c_pos, c)), Some((d_pos, d))) => {
+            // opkg doesn't care about the order
+            if c_pos > d_pos && path.extension().is_none_or(|e| e != "ipk") {
+                problems.push("control.tar must come before data.tar".to_owned());
+            }
+            (c, d)
+        },
+        _ => {
+            problems.push("control.tar or data.tar member is missing".to_owned());
+            return Err(CargoDebError::VerificationFailed(path.to_owned(), problems.join("\n")));
+        },
+    };
+
+    let control_entries = tar_entries(&decompress(control_member)?)?;
+    let control_files: HashMap<&str, &TarEntry> = control_entries.iter()
+        .filter(|e| e.entry_type.is_file())
+        .map(|e| (e.path.as_str(), e))
+        .collect();
+    let data_entries = tar_entries(&decompress(data_member)?)?;
+    let mut data_files: HashMap<&str, &TarEntry> = data_entries.iter()
+        .filter(|e| e.entry_type.is_file())
+        .map(|e| (e.path.as_str(), e))
+        .collect();
+    // hardlinks are checked against the data of the file they link to
+    for link in data_entries.iter().filter(|e| e.entry_type.is_hard_link()) {
+        let target = link.link_name.as_deref().unwrap_or_default().trim_start_matches("./");
+        match data_files.get(target).copied() {
+            Some(file) => { data_files.insert(link.path.as_str(), file); },
+            None => problems.push(format!("hardlink /{} points to /{}, which is not in the package", link.path, target)),
+        }
+    }
+    listener.info(format!("{} files in the data archive", data_files.len()));
+
+    match control_files.get("control") {
+        Some(control) => problems.extend(control_syntax_problems(&String::from_utf8_lossy(&control.data))),
+        None => problems.push("control file is missing".to_owned()),
+    }
+
+    let conffiles: HashSet<&str> = control_files.get("conffiles")
+        .map(|c| std::str::from_utf8(&c.data).unwrap_or_default())
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().trim_start_matches('/'))
+        .filter(|l| !l.is_empty())
+        .collect();
+    for conffile in &conffiles {
+        if !data_files.contains_key(conffile) {
+            problems.push(format!("conffile /{} is not in the package", conffile));
+        }
+    }
+
+    let mut has_checksums = false;
+    if let Some(md5sums) = control_files.get("md5sums") {
+        has_checksums = true;
+        problems.extend(checksum_problems("md5sums", &md5sums.data, &data_files, &conffiles, |data| format!("{:x}", md5::compute(data))));
+    }
+    if let Some(sha256sums) = control_files.get("sha256sums") {
+        has_checksums = true;
+        problems.extend(checksum_problems("sha256sums", &sha256sums.data, &data_files, &conffiles, crate::sha256::sha256_hex));
+    }
+    if !has_checksums {
+        listener.warning(Warning::NoMd5sums, "the package has no md5sums".to_owned());
+    }
+
+    for (name, file) in &control_files {
+        let executable = file.mode & 0o111 != 0;
+        if SCRIPTS.contains(name) {
+            if !executable {
+                problems.push(format!("maintainer script {} is not executable (mode {:o})", name, file.mode));
+            }
+            if !file.data.starts_with(b"#!") && !file.data.starts_with(b"\x7fELF") {
+                problems.push(format!("maintainer script {} has no #! interpreter line", name));
+            }
+        } else if executable {
+            problems.push(format!("control file {} should not be executable (mode {:o})", name, file.mode));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(CargoDebError::VerificationFailed(path.to_owned(), problems.join("\n")))
+    }
+}
+
+/// Compares the files installed under `root` with the package's files, like `dpkg -V`.
+///
+/// Returns a line for every file that differs, with flags in the rpm/dpkg format: `S` size, `M` mode,
+/// `5` checksum and `L` symlink target changed, `?` for checks that couldn't be made,
+/// followed by `c` for conffiles, which are expected to be edited.
+pub fn verify_installed(package: &Package, root: &Path, listener: &dyn Listener) -> CDResult<Vec<String>> {
+    let control_entries = package.control_entries()?;
+    let conffiles: HashSet<&str> = control_entries.iter()
+        .filter(|e| e.path == "conffiles")
+        .flat_map(|e| std::str::from_utf8(&e.data).unwrap_or_default().lines())
+        .map(|l| l.trim().trim_start_matches('/'))
+        .collect();
+    let data_entries = package.data_entries()?;
+    let files: HashMap<&str, &TarEntry> = data_entries.iter().map(|e| (e.path.as_str(), e)).collect();
+
+    let mut drift = Vec::new();
+    let mut checked = 0;
+    for entry in data_entries.iter().filter(|e| !e.path.is_empty() && !e.entry_type.is_dir()) {
+        checked += 1;
+        let installed_path = root.join(&entry.path);
+        let conffile = if conffiles.contains(entry.path.as_str()) { 'c' } else { ' ' };
+        let Ok(metadata) = fs::symlink_metadata(&installed_path) else {
+            drift.push(format!("missing   {} /{}", conffile, entry.path));
+            continue;
+        };
+        let mut flags = *b"?????????";
+        if entry.entry_type.is_symlink() {
+            let expected = entry.link_name.as_deref().unwrap_or_default();
+            flags[4] = match fs::read_link(&installed_path) {
+                Ok(target) if target == Path::new(expected) => b'.',
+                _ => b'L',
+            };
+        } else {
+            // hardlinks have the data of the file they link to
+            let expected = if entry.entry_type.is_hard_link() {
+                let target = entry.link_name.as_deref().unwrap_or_default().trim_start_matches("./");
+                files.get(target).map_or(&entry.data, |target| &target.data)
+            } else {
+                &entry.data
+            };
+            if metadata.file_type().is_file() {
+                flags[0] = if metadata.len() == expected.len() as u64 { b'.' } else { b'S' };
+                // unreadable files, e.g. private keys, are left as `?`
+                if let Ok(installed) = fs::read(&installed_path) {
+                    flags[2] = if installed == *expected { b'.' } else { b'5' };
+                }
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    flags[1] = if metadata.permissions().mode() & 0o7777 == entry.mode & 0o7777 { b'.' } else { b'M' };
+                }
+            } else {
+                // replaced with a directory or a symlink
+                flags[2] = b'5';
+            }
+        }
+        if flags.iter().any(u8::is_ascii_alphanumeric) {
+            drift.push(format!("{} {} /{}", String::from_utf8_lossy(&flags), conffile, entry.path));
+        }
+    }
+    listener.info(format!("checked {} installed files", checked));
+    Ok(drift)
+}
+
+fn find_member<'a>(members: &'a [Member], prefix: &str) -> Option<(usize, &'a Member)> {
+    members.iter().enumerate().find(|(_, m)| m.name.starts_with(prefix))
+}
+
+/// Lines of the checksums file are `<hex digest>  <path>`
+fn checksum_problems(list_name: &str, list: &[u8], data_files: &HashMap<&str, &TarEntry>, conffiles: &HashSet<&str>, digest: impl Fn(&[u8]) -> String) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut listed = HashSet::new();
+    for line in String::from_utf8_lossy(list).lines().filter(|l| !l.trim().is_empty()) {
+        let (expected, path) = match line.split_once(' ') {
+            Some((hash, path)) => (hash, path.trim_start().trim_start_matches("./")),
+            None => {
+                problems.push(format!("invalid line in {}: {}", list_name, line));
+                continue;
+            },
+        };
+        listed.insert(path.to_owned());
+        match data_files.get(path) {
+            Some(file) => {
+                let actual = digest(&file.data);
+                if !actual.eq_ignore_ascii_case(expected) {
+                    problems.push(format!("{} mismatch for /{}: expected {}, got {}", list_name, path, expected, actual));
+                }
+            },
+            None => problems.push(format!("/{} is listed in {}, but is not in the package", path, list_name)),
+        }
+    }
+    let mut unlisted: Vec<_> = data_files.keys()
+        .filter(|path| !listed.contains(**path) && !conffiles.contains(*path))
+        .collect();
+    unlisted.sort();
+    for path in unlisted {
+        problems.push(format!("/{} is missing from {}", path, list_name));
+    }
+    problems
+}
+
+/// Checks the deb822 syntax of a binary package's control file, and presence of required fields
+fn control_syntax_problems(control: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut fields = HashMap::new();
+    let mut current_field: Option<String> = None;
+    let lines: Vec<_> = control.trim_end_matches('\n').split('\n').collect();
+    if !control.ends_with('\n') {
+        problems.push("control file must end with a newline".to_owned());
+    }
+    for (line_no, line) in lines.iter().enumerate() {
+        let line_no = line_no + 1;
+        if line.starts_with(' ') || line.starts_with('\t') {
+            match current_field {
+                Some(ref name) => {
+                    let value: &mut String = fields.get_mut(name).unwrap();
+                    value.push('\n');
+                    value.push_str(line);
+                },
+                None => problems.push(format!("control line {}: continuation line without a field", line_no)),
+            }
+            if line.trim().is_empty() {
+                problems.push(format!("control line {}: empty continuation line (use \" .\")", line_no));
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            problems.push(format!("control line {}: empty line in the control file", line_no));
+            continue;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some(split) => split,
+            None => {
+                problems.push(format!("control line {}: expected 'Field: value', got {:?}", line_no, line));
+                continue;
+            },
+        };
+        if name.is_empty() || name.starts_with('#') || name.starts_with('-') || !name.bytes().all(|b| b.is_ascii_graphic()) {
+            problems.push(format!("control line {}: invalid field name {:?}", line_no, name));
+        }
+        let key = name.to_ascii_lowercase();
+        if fields.contains_key(&key) {
+            problems.push(format!("control line {}: duplicate field {}", line_no, name));
+        } else {
+            fields.insert(key.clone(), value.trim().to_owned());
+        }
+        current_field = Some(key);
+    }
+
+    for required in &["Package", "Version", "Architecture", "Maintainer", "Description"] {
+        match fields.get(&required.to_ascii_lowercase()) {
+            Some(value) if !value.is_empty() => {},
+            _ => problems.push(format!("control field {} is missing", required)),
+        }
+    }
+    if let Some(name) = fields.get("package") {
+        let mut chars = name.chars();
+        let valid = name.len() >= 2
+            && chars.next().is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+        if !valid {
+            problems.push(format!("invalid package name {:?}", name));
+        }
+    }
+    if let Some(version) = fields.get("version") {
+        let upstream = version.split_once(':').map_or(version.as_str(), |(epoch, rest)| {
+            if !epoch.bytes().all(|b| b.is_ascii_digit()) {
+                problems.push(format!("invalid epoch in version {:?}", version));
+            }
+            rest
+        });
+        if !upstream.starts_with(|c: char| c.is_ascii_digit()) || upstream.contains(|c: char| c.is_whitespace()) {
+            problems.push(format!("invalid version {:?}", version));
+        }
+    }
+    problems
+}
+
+#[test]
+fn control_syntax() {
+    let valid = "Package: foo\nVersion: 1:1.0-1\nArchitecture: amd64\nMaintainer: me\nDescription: short\n long\n .\n more\n";
+    assert!(control_syntax_problems(valid).is_empty(), "{:?}", control_syntax_problems(valid));
+
+    let problems = control_syntax_problems(" orphan\nPackage: Foo\nVersion: v1\npackage: foo\n\nbroken line");
+    assert_eq!(problems, vec![
+        "control file must end with a newline",
+        "control line 1: continuation line without a field",
+        "control line 4: duplicate field package",
+        "control line 5: empty line in the control file",
+        "control line 6: expected 'Field: value', got \"broken line\"",
+        "control field Architecture is missing",
+        "control field Maintainer is missing",
+        "control field Description is missing",
+        "invalid package name \"Foo\"",
+        "invalid version \"v1\"",
+    ]);
+}
+
+#[test]
+fn checksums() {
+    let file = TarEntry { path: "usr/bin/foo".into(), mode: 0o755, entry_type: tar::EntryType::Regular, link_name: None, data: b"hello\n".to_vec() };
+    let conf = TarEntry { path: "etc/foo.conf".into(), mode: 0o644, entry_type: tar::EntryType::Regular, link_name: None, data: Vec::new() };
+    let data_files: HashMap<&str, &TarEntry> = vec![("usr/bin/foo", &file), ("etc/foo.conf", &conf)].into_iter().collect();
+    let conffiles: HashSet<&str> = vec!["etc/foo.conf"].into_iter().collect();
+    let md5 = |data: &[u8]| format!("{:x}", md5::compute(data));
+
+    assert!(checksum_problems("md5sums", b"b1946ac92492d2347c6235b4d2611184  usr/bin/foo\n", &data_files, &conffiles, md5).is_empty());
+    assert_eq!(checksum_problems("md5sums", b"00000000000000000000000000000000  usr/bin/foo\nb1946ac92492d2347c6235b4d2611184  usr/bin/bar\n", &data_files, &HashSet::new(), md5), vec![
+        "md5sums mismatch for /usr/bin/foo: expected 00000000000000000000000000000000, got b1946ac92492d2347c6235b4d2611184",
+        "/usr/bin/bar is listed in md5sums, but is not in the package",
+        "/etc/foo.conf is missing from md5sums",
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn installed_files() {
+    use crate::manifest::TarFormat;
+    use crate::reader::Member;
+    use crate::tararchive::Archive;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut control = Archive::new(0, TarFormat::Gnu);
+    control.file("./conffiles", b"/etc/foo.conf\n", 0o644).unwrap();
+    let mut data = Archive::new(0, TarFormat::Gnu);
+    data.file("usr/bin/foo", b"binary", 0o755).unwrap();
+    data.hardlink(Path::new("usr/bin/foo-alias"), Path::new("usr/bin/foo"), 0o755).unwrap();
+    data.file("usr/share/foo/data", b"data", 0o644).unwrap();
+    data.symlink(Path::new("usr/share/foo/link"), Path::new("data")).unwrap();
+    data.file("etc/foo.conf", b"setting = 1", 0o644).unwrap();
+    data.file("usr/share/foo/removed", b"", 0o644).unwrap();
+    let package = Package { members: vec![
+        Member { name: "control.tar".into(), data: control.into_inner().unwrap() },
+        Member { name: "data.tar".into(), data: data.into_inner().unwrap() },
+    ] };
+
+    let root = tempfile::tempdir().unwrap();
+    let root = root.path();
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::create_dir_all(root.join("usr/share/foo")).unwrap();
+    fs::create_dir_all(root.join("etc")).unwrap();
+    fs::write(root.join("usr/bin/foo"), "binary").unwrap();
+    fs::set_permissions(root.join("usr/bin/foo"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(root.join("usr/bin/foo-alias"), "binarY").unwrap();
+    fs::set_permissions(root.join("usr/bin/foo-alias"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(root.join("usr/share/foo/data"), "data").unwrap();
+    fs::set_permissions(root.join("usr/share/foo/data"), fs::Permissions::from_mode(0o666)).unwrap();
+    std::os::unix::fs::symlink("elsewhere", root.join("usr/share/foo/link")).unwrap();
+    fs::write(root.join("etc/foo.conf"), "setting = 2\n").unwrap();
+    fs::set_permissions(root.join("etc/foo.conf"), fs::Permissions::from_mode(0o644)).unwrap();
+
+    let mut mock_listener = crate::listener::MockListener::new();
+    mock_listener.expect_info().return_const(());
+    assert_eq!(verify_installed(&package, root, &mock_listener).unwrap(), vec![
+        "..5??????   /usr/bin/foo-alias",
+        ".M.??????   /usr/share/foo/data",
+        "????L????   /usr/share/foo/link",
+        "S.5?????? c /etc/foo.conf",
+        "missing     /usr/share/foo/removed",
+    ]);
+}
+
+#[test]
+fn verify_hardlinks() {
+    use crate::manifest::TarFormat;
+    use crate::tararchive::Archive;
+
+    let members = |md5sums: &str, dangling: bool| {
+        let mut control = Archive::new(0, TarFormat::Gnu);
+        control.file("./control", b"Package: hl\nVersion: 1.0\nArchitecture: amd64\nMaintainer: me\nDescription: hardlinks\n", 0o644).unwrap();
+        control.file("./md5sums", md5sums.as_bytes(), 0o644).unwrap();
+        let mut data = Archive::new(0, TarFormat::Gnu);
+        data.file("usr/bin/hl", b"binary", 0o755).unwrap();
+        data.hardlink(Path::new("usr/bin/hl-alias"), Path::new("usr/bin/hl"), 0o755).unwrap();
+        if dangling {
+            data.hardlink(Path::new("usr/bin/hl-dangling"), Path::new("usr/bin/missing"), 0o755).unwrap();
+        }
+        vec![
+            Member { name: "debian-binary".into(), data: b"2.0\n".to_vec() },
+            Member { name: "control.tar".into(), data: control.into_inner().unwrap() },
+            Member { name: "data.tar".into(), data: data.into_inner().unwrap() },
+        ]
+    };
+    let mut mock_listener = crate::listener::MockListener::new();
+    mock_listener.expect_info().return_const(());
+
+    let md5 = format!("{:x}", md5::compute(b"binary"));
+    let md5sums = format!("{0}  usr/bin/hl\n{0}  usr/bin/hl-alias\n", md5);
+    verify_members(Path::new("hl.deb"), &members(&md5sums, false), &mock_listener).unwrap();
+
+    let err = verify_members(Path::new("hl.deb"), &members(&md5sums, true), &mock_listener).unwrap_err();
+    let CargoDebError::VerificationFailed(_, problems) = err else { panic!("{}", err) };
+    assert_eq!(problems, "hardlink /usr/bin/hl-dangling points to /usr/bin/missing, which is not in the package");
+
+    let err = verify_members(Path::new("hl.deb"), &members(&format!("{}  usr/bin/hl\n00000000000000000000000000000000  usr/bin/hl-alias\n", md5), false), &mock_listener).unwrap_err();
+    assert!(err.to_string().contains(&format!("md5sums mismatch for /usr/bin/hl-alias: expected 00000000000000000000000000000000, got {}", md5)), "{}", err);
+}
@@ -0,0 +1,233 @@
+//! Filesystem access of asset resolution and archive building.
+//!
+//! `Config::fs` is the real filesystem, and can be replaced with a `MemoryFs` to build packages
+//! from files that exist only in memory, e.g. in tests comparing them to golden archives.
+use crate::error::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Like `read`, for UTF-8 text
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// For reading large files in chunks
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send + '_>>;
+
+    /// Size of the file, following symlinks
+    fn file_len(&self, path: &Path) -> io::Result<u64>;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Target of the symlink, or `None` if it's not a symlink
+    fn read_link(&self, path: &Path) -> io::Result<Option<PathBuf>>;
+
+    /// Files (not directories) matching the glob pattern, sorted
+    fn glob(&self, pattern: &str) -> CDResult<Vec<PathBuf>>;
+}
+
+/// `std::fs`, and the `glob` crate
+#[derive(Debug, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        crate::util::read_file_to_bytes(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send + '_>> {
+        Ok(Box::new(io::BufReader::new(fs::File::open(path)?)))
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        Ok(fs::metadata(path)?.len())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        if fs::symlink_metadata(path)?.file_type().is_symlink() {
+            fs::read_link(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn glob(&self, pattern: &str) -> CDResult<Vec<PathBuf>> {
+        let mut matches = Vec::new();
+        for entry in glob::glob(pattern)? {
+            let path = entry?;
+            if !path.is_dir() {
+                matches.push(path);
+            }
+        }
+        if is_glob_pattern(pattern) {
+            let found = non_utf8_matches(pattern)?;
+            matches.extend(found.into_iter().filter(|path| !matches.contains(path)).collect::<Vec<_>>());
+        }
+        Ok(matches)
+    }
+}
+
+/// The glob crate skips file names that aren't UTF-8, which would silently leave them out of the package.
+/// This finds them by walking the directories under the pattern's literal prefix, and matching their lossy names,
+/// where `?` and `*` match the replacement characters. Symlinks to directories aren't followed, to avoid loops.
+fn non_utf8_matches(pattern: &str) -> CDResult<Vec<PathBuf>> {
+    let source_prefix: PathBuf = Path::new(pattern).iter()
+        .take_while(|part| !part.to_str().is_some_and(is_glob_pattern))
+        .collect();
+    // Without `**`, matches can't be deeper than the pattern
+    let max_depth = if pattern.contains("**") { usize::MAX } else { Path::new(pattern).iter().count() - source_prefix.iter().count() };
+    let pattern = glob::Pattern::new(pattern)?;
+    let options = glob::MatchOptions { require_literal_separator: true, ..glob::MatchOptions::new() };
+    let mut matches = Vec::new();
+    let mut dirs = vec![(source_prefix, 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                if depth + 1 < max_depth {
+                    dirs.push((path, depth + 1));
+                }
+            } else if path.to_str().is_none() && !path.is_dir() && pattern.matches_with(&path.to_string_lossy(), options) {
+                matches.push(path);
+            }
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+pub(crate) fn is_glob_pattern(s: &str) -> bool {
+    s.contains('*') || s.contains('[') || s.contains(']') || s.contains('!')
+}
+
+#[derive(Debug, Clone)]
+enum MemoryEntry {
+    File(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+/// Files and symlinks kept in memory. Directories exist implicitly, as parents of the files.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFs {
+    entries: BTreeMap<PathBuf, MemoryEntry>,
+}
+
+impl MemoryFs {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, path: impl Into<PathBuf>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.entries.insert(path.into(), MemoryEntry::File(data.into()));
+        self
+    }
+
+    /// The target is relative to the link's directory, like in a real filesystem
+    pub fn add_symlink(&mut self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> &mut Self {
+        self.entries.insert(path.into(), MemoryEntry::Symlink(target.into()));
+        self
+    }
+
+    /// File contents, following symlinks
+    fn file(&self, path: &Path) -> io::Result<&[u8]> {
+        let mut path = path.to_owned();
+        // a limit, like the kernel's, in case of symlink loops
+        for _ in 0..40 {
+            match self.entries.get(&path) {
+                Some(MemoryEntry::File(data)) => return Ok(data),
+                Some(MemoryEntry::Symlink(target)) => path = path.parent().unwrap_or(Path::new("")).join(target),
+                None => break,
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in the in-memory filesystem", path.display())))
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.file(path).map(<[u8]>::to_vec)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send + '_>> {
+        Ok(Box::new(self.file(path)?))
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        self.file(path).map(|data| data.len() as u64)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.entries.keys().any(|p| p != path && p.starts_with(path))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        match self.entries.get(path) {
+            Some(MemoryEntry::Symlink(target)) => Ok(Some(target.clone())),
+            Some(MemoryEntry::File(_)) => Ok(None),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in the in-memory filesystem", path.display()))),
+        }
+    }
+
+    fn glob(&self, pattern: &str) -> CDResult<Vec<PathBuf>> {
+        let pattern = glob::Pattern::new(pattern)?;
+        let options = glob::MatchOptions { require_literal_separator: true, ..glob::MatchOptions::new() };
+        Ok(self.entries.keys()
+            .filter(|path| pattern.matches_path_with(path, options))
+            .cloned()
+            .collect())
+    }
+}
+
+#[test]
+fn memory_fs() {
+    let mut fs = MemoryFs::new();
+    fs.add_file("/src/web/index.html", "<html>")
+        .add_file("/src/web/js/app.js", "app()")
+        .add_symlink("/src/web/start.html", "index.html");
+    assert_eq!(b"<html>", &fs.read(Path::new("/src/web/start.html")).unwrap()[..]);
+    assert_eq!(Some(PathBuf::from("index.html")), fs.read_link(Path::new("/src/web/start.html")).unwrap());
+    assert_eq!(5, fs.file_len(Path::new("/src/web/js/app.js")).unwrap());
+    assert!(fs.is_dir(Path::new("/src/web/js")));
+    assert!(!fs.is_dir(Path::new("/src/web/index.html")));
+    assert!(fs.read(Path::new("/src/missing")).is_err());
+    assert_eq!(vec![PathBuf::from("/src/web/index.html"), PathBuf::from("/src/web/start.html")], fs.glob("/src/web/*.html").unwrap());
+    assert_eq!(3, fs.glob("/src/web/**/*").unwrap().len());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_file_names() {
+    use std::os::unix::ffi::OsStrExt;
+    let dir = tempfile::tempdir().unwrap();
+    let latin1 = dir.path().join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt"));
+    fs::write(&latin1, "").unwrap();
+    fs::write(dir.path().join("tea.txt"), "").unwrap();
+    fs::write(dir.path().join("tea.md"), "").unwrap();
+    let matches = RealFs.glob(&format!("{}/*.txt", dir.path().display())).unwrap();
+    assert_eq!(vec![dir.path().join("tea.txt"), latin1], matches);
+    assert_eq!(1, RealFs.glob(&format!("{}/*.md", dir.path().display())).unwrap().len());
+
+    // in directories without other matches, and in directories that aren't UTF-8
+    let sub = dir.path().join("docs/caf\u{e9}");
+    let latin1_dir = dir.path().join("docs").join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+    fs::create_dir_all(&sub).unwrap();
+    fs::create_dir_all(&latin1_dir).unwrap();
+    fs::write(sub.join(std::ffi::OsStr::from_bytes(b"na\xefve.txt")), "").unwrap();
+    fs::write(latin1_dir.join("notes.txt"), "").unwrap();
+    let matches = RealFs.glob(&format!("{}/docs/**/*.txt", dir.path().display())).unwrap();
+    assert_eq!(vec![latin1_dir.join("notes.txt"), sub.join(std::ffi::OsStr::from_bytes(b"na\xefve.txt"))], matches);
+    assert!(RealFs.glob(&format!("{}/*/*.txt", dir.path().display())).unwrap().is_empty());
+}
@@ -9,9 +9,8 @@ impl WordSplit for str {
     //   If you violate this rule, `dpkg -i` will fail.
     // * Extended description line must not have any tab character.
     //   If you violate this rule, the effect is not predictable.
-    //
-    // NOTE: as for extended description, this splitting might not be necessary in the first place?
-    // (debian policy seems to say nothing about line length of extended description)
+    // * Lines should fit in 80 columns, because frontends display them as-is.
+    //   Words longer than that are kept whole.
     fn split_by_chars(&self, length: usize) -> Vec<String> {
         let output_capacity = self.len() + self.len() % length + 1;
         let mut lines: Vec<String> = Vec::with_capacity(output_capacity);
@@ -44,7 +43,7 @@ impl WordSplit for str {
                 }
 
                 // now current_line has non-whitespace character
-                if current_line.len() + word.len() >= length {
+                if current_line.chars().count() + word.chars().count() >= length {
                     // skip a space
                     let to_push = std::mem::replace(&mut current_line, word.to_owned());
                     // if character length met or exceeded
@@ -17,7 +17,7 @@ fn run_cargo_deb_command_on_example_dir() {
     }
 
     // prints deb path on the last line
-    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').last().unwrap();
+    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').next_back().unwrap();
     let deb_path = Path::new(::std::str::from_utf8(last_line).unwrap());
     assert!(deb_path.exists());
 
@@ -76,7 +76,7 @@ fn run_cargo_deb_command_on_example_dir() {
 }
 
 #[test]
-#[cfg(all(feature = "lzma"))]
+#[cfg(feature = "lzma")]
 fn run_cargo_deb_command_on_example_dir_with_variant() {
     let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
     let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
@@ -99,7 +99,7 @@ fn run_cargo_deb_command_on_example_dir_with_variant() {
     }
 
     // prints deb path on the last line
-    let last_line = output.stdout[..output.stdout.len()-1].split(|&c| c==b'\n').last().unwrap();
+    let last_line = output.stdout[..output.stdout.len()-1].split(|&c| c==b'\n').next_back().unwrap();
     let printed_deb_path = Path::new(::std::str::from_utf8(last_line).unwrap());
     assert_eq!(printed_deb_path, deb_path);
     assert!(deb_path.exists());
@@ -168,12 +168,12 @@ fn run_cargo_deb_command_on_example_dir_with_version() {
             "--manifest-path={}",
             root.join("example/Cargo.toml").display()
         ))
-        .arg(format!("--deb-version=my-custom-version"))
+        .arg("--deb-version=my-custom-version")
         .output().unwrap();
     assert!(output.status.success());
 
     // prints deb path on the last line
-    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').last().unwrap();
+    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').next_back().unwrap();
     let deb_path = Path::new(::std::str::from_utf8(last_line).unwrap());
     assert!(deb_path.exists());
 
@@ -230,3 +230,305 @@ fn run_cargo_deb_command_on_example_dir_with_version() {
         &fs::read(ddir.path().join("usr/share/doc/example/changelog.Debian.gz")).unwrap()[..2]
     );
 }
+
+#[test]
+#[cfg(target_os = "linux")]
+fn run_cargo_deb_command_on_example_dir_with_ipk_format() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join("target/debug/cargo-deb");
+    assert!(cmd_path.exists());
+    let cargo_dir = tempfile::tempdir().unwrap();
+    let ipk_path = cargo_dir.path().join("test.ipk");
+    let output = Command::new(cmd_path)
+        .env("CARGO_TARGET_DIR", cargo_dir.path()) // otherwise tests overwrite each other
+        .arg("--format=ipk")
+        .arg(format!("--output={}", ipk_path.display()))
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .output().unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+    assert!(ipk_path.exists());
+
+    let verify = Command::new(root.join("target/debug/cargo-deb"))
+        .arg("verify")
+        .arg(&ipk_path)
+        .output().unwrap();
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stderr));
+
+    // ipk is a gzipped tarball rather than an ar archive
+    let ipkdir = tempfile::tempdir().unwrap();
+    assert!(Command::new("tar")
+        .arg("xzf")
+        .current_dir(ipkdir.path())
+        .arg(&ipk_path)
+        .status().unwrap().success());
+
+    assert_eq!("2.0\n", fs::read_to_string(ipkdir.path().join("debian-binary")).unwrap());
+    assert!(ipkdir.path().join("data.tar.gz").exists());
+    assert!(ipkdir.path().join("control.tar.gz").exists());
+
+    let cdir = tempfile::tempdir().unwrap();
+    assert!(Command::new("tar")
+        .arg("xzf")
+        .current_dir(cdir.path())
+        .arg(ipkdir.path().join("control.tar.gz"))
+        .status().unwrap().success());
+
+    let control = fs::read_to_string(cdir.path().join("control")).unwrap();
+    assert!(control.contains("Package: example\n"));
+    assert!(control.contains(&format!("Architecture: {}\n", env::consts::ARCH)), "Control is: {:?}", control);
+    assert!(!control.contains("Architecture: amd64\n"));
+
+    let ddir = tempfile::tempdir().unwrap();
+    assert!(Command::new("tar")
+        .arg("xzf")
+        .current_dir(ddir.path())
+        .arg(ipkdir.path().join("data.tar.gz"))
+        .status().unwrap().success());
+    assert!(ddir.path().join("usr/bin/example").exists());
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_with_several_variants() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let cargo_dir = tempfile::tempdir().unwrap();
+    let output = Command::new(cmd_path)
+        .env("CARGO_TARGET_DIR", cargo_dir.path())
+        .arg("--variant=debug")
+        .arg("--variant=meta")
+        .arg("--no-strip")
+        .arg(format!("--output={}/", cargo_dir.path().display()))
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+
+    // prints a path for each package
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let deb_paths: Vec<_> = stdout.lines().collect();
+    assert_eq!(2, deb_paths.len(), "{}", stdout);
+    assert!(deb_paths[0].contains("example-debug_0.1.0"), "{}", stdout);
+    assert!(deb_paths[1].contains("example-meta_0.1.0"), "{}", stdout);
+    assert!(deb_paths.iter().all(|path| Path::new(path).exists()));
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_frozen() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let output = Command::new(cmd_path)
+        .arg("--frozen")
+        .arg("--print-config=json")
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .arg("--")
+        .arg("--offline")
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+    let config: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(serde_json::json!(["--offline", "--frozen"]), config["cargo-build-flags"]);
+}
+
+fn example_config_with_compress_type(compress_type: &str, args: &[&str]) -> serde_json::Value {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let output = Command::new(cmd_path)
+        .env("CARGO_DEB_COMPRESS_TYPE", compress_type)
+        .args(args)
+        .arg("--print-config=json")
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_reports_all_errors() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let cdir = tempfile::tempdir().unwrap();
+    let deb_config = cdir.path().join("deb.toml");
+    fs::write(&deb_config, r#"
+bugs = "not a url"
+assets = [["missing.txt", "usr/share/example/", "644"]]
+systemd-units = { enable = false }
+
+[arch-map]
+x86_64-unknown-linux-gnu = "Not Valid"
+
+[systemd]
+enable = false
+"#).unwrap();
+    let output = Command::new(cmd_path)
+        .arg("--print-config=json")
+        .arg("--target=x86_64-unknown-linux-gnu")
+        .arg(format!("--config={}", deb_config.display()))
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for code in ["CD0219", "CD0225", "CD0212"] {
+        assert!(stderr.contains(&format!("error[{}]", code)), "{} missing in {}", code, stderr);
+    }
+    assert!(stderr.contains("not both"), "{}", stderr);
+    assert!(stderr.contains("stopped because of 4 errors"), "{}", stderr);
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_oldstable_forces_gzip() {
+    assert_eq!("gz", example_config_with_compress_type("xz", &["--compat", "oldstable"])["compress-type"]);
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_ipk_forces_gzip() {
+    assert_eq!("gz", example_config_with_compress_type("zstd", &["--format", "ipk"])["compress-type"]);
+    assert_eq!("zstd", example_config_with_compress_type("zstd", &[])["compress-type"]);
+}
+
+#[test]
+fn run_cargo_deb_command_on_example_dir_with_target_dir() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let cargo_dir = tempfile::tempdir().unwrap();
+    let output = Command::new(cmd_path)
+        .current_dir(cargo_dir.path())
+        .env_remove("CARGO_TARGET_DIR")
+        .arg("--no-strip")
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .arg("--")
+        .arg("--target-dir=out")
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+
+    // relative to the current directory, like in cargo
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let deb_path = Path::new(stdout.trim_end());
+    assert!(deb_path.starts_with(cargo_dir.path().join("out/debian")), "{}", stdout);
+    assert!(deb_path.exists());
+    assert!(cargo_dir.path().join("out/release/example").exists());
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn run_cargo_deb_command_on_example_dir_from_another_dir() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let cargo_dir = tempfile::tempdir().unwrap();
+    let deb_path = cargo_dir.path().join("test.deb");
+    let output = Command::new(cmd_path)
+        .current_dir(root.join("tests"))
+        .env("CARGO_TARGET_DIR", cargo_dir.path())
+        .arg("--no-strip")
+        .arg(format!("--output={}", deb_path.display()))
+        .arg("--manifest-path=../example/Cargo.toml")
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+    assert!(deb_path.exists());
+
+    let ardir = tempfile::tempdir().unwrap();
+    assert!(Command::new("ar")
+        .current_dir(ardir.path())
+        .arg("-x")
+        .arg(&deb_path)
+        .status().unwrap().success());
+    let ddir = tempfile::tempdir().unwrap();
+    assert!(Command::new("tar")
+        .arg("xJf")
+        .current_dir(ddir.path())
+        .arg(ardir.path().join("data.tar.xz"))
+        .status().unwrap().success());
+
+    // paths in the manifest are relative to its directory, not the current one
+    assert!(ddir.path().join("var/lib/example/3.txt").exists());
+    assert!(ddir.path().join("usr/share/doc/example/copyright").exists());
+    assert!(ddir.path().join("usr/share/doc/example/changelog.Debian.gz").exists());
+    assert!(ddir.path().join("usr/bin/example").exists());
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn run_cargo_deb_command_on_example_dir_with_meta_package() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let cmd_path = root.join(format!("target/debug/cargo-deb{}", std::env::consts::EXE_SUFFIX));
+    assert!(cmd_path.exists());
+    let cargo_dir = tempfile::tempdir().unwrap();
+    let deb_path = cargo_dir.path().join("test.deb");
+    let output = Command::new(cmd_path)
+        .env("CARGO_TARGET_DIR", cargo_dir.path())
+        .arg("--variant=meta")
+        .arg(format!("--output={}", deb_path.display()))
+        .arg(format!(
+            "--manifest-path={}",
+            root.join("example/Cargo.toml").display()
+        ))
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!("Cmd failed: {}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    }
+    // there's nothing to build
+    assert!(!cargo_dir.path().join("release").exists());
+
+    let ardir = tempfile::tempdir().unwrap();
+    assert!(Command::new("ar")
+        .current_dir(ardir.path())
+        .arg("-x")
+        .arg(deb_path)
+        .status().unwrap().success());
+
+    let cdir = tempfile::tempdir().unwrap();
+    assert!(Command::new("tar")
+        .arg("xJf")
+        .current_dir(cdir.path())
+        .arg(ardir.path().join("control.tar.xz"))
+        .status().unwrap().success());
+
+    let control = fs::read_to_string(cdir.path().join("control")).unwrap();
+    assert!(control.contains("Package: example-meta\n"), "Control is: {:?}", control);
+    assert!(control.contains("Architecture: all\n"));
+    assert!(control.contains("Depends: example, example-debug\n") || control.contains("Depends: example-debug, example\n"));
+
+    let md5sums = fs::read_to_string(cdir.path().join("md5sums")).unwrap();
+    assert!(!md5sums.contains(" usr/bin/"));
+    assert!(md5sums.contains(" usr/share/doc/example-meta/copyright\n"));
+}